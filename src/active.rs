@@ -0,0 +1,52 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// `~/.texman/active` marks the active profile. On Unix it is a symlink to the profile, so
+// `~/.texman/active/bin/<platform>` can go on PATH; Windows only lets administrators (or
+// developer mode) create symlinks, so there it is a file holding the profile's name. Either
+// form is read everywhere, so a texman directory on a shared drive works from both.
+fn path(texman_dir: &Path) -> PathBuf {
+    texman_dir.join("active")
+}
+
+fn profile_dir(texman_dir: &Path, name: &str) -> PathBuf {
+    texman_dir.join("profiles").join(name)
+}
+
+// The active profile's name, as long as the profile still exists.
+pub fn get(texman_dir: &Path) -> anyhow::Result<Option<String>> {
+    let path = path(texman_dir);
+    let Ok(metadata) = fs::symlink_metadata(&path) else { return Ok(None) };
+    let name = if metadata.file_type().is_symlink() {
+        fs::read_link(&path)?.file_name().map(|name| name.to_string_lossy().to_string())
+    } else {
+        Some(fs::read_to_string(&path)?.trim().to_string())
+    };
+    Ok(name.filter(|name| !name.is_empty() && profile_dir(texman_dir, name).is_dir()))
+}
+
+pub fn name(texman_dir: &Path) -> anyhow::Result<String> {
+    get(texman_dir)?.ok_or_else(|| anyhow::anyhow!("No active profile set. Install a package or switch to a profile first."))
+}
+
+pub fn dir(texman_dir: &Path) -> anyhow::Result<PathBuf> {
+    Ok(profile_dir(texman_dir, &name(texman_dir)?))
+}
+
+pub fn is_set(texman_dir: &Path) -> bool {
+    get(texman_dir).is_ok_and(|name| name.is_some())
+}
+
+pub fn set(texman_dir: &Path, profile: &str) -> anyhow::Result<()> {
+    let path = path(texman_dir);
+    // A link left dangling by a removed profile counts as not set, but is still in the way.
+    if fs::symlink_metadata(&path).is_ok() {
+        fs::remove_file(&path)?;
+    }
+    if cfg!(windows) {
+        fs::write(&path, profile)?;
+    } else {
+        crate::os::symlink(&Path::new("profiles").join(profile), &path)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,148 @@
+use crate::Package;
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256, Sha512};
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+
+// Each attestation's hash covers the previous hash and its own record, so editing or
+// dropping an earlier entry breaks every later link of the chain.
+const GENESIS_HASH: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+#[derive(Serialize, Deserialize)]
+pub struct Record {
+    pub action: String,
+    pub profile: String,
+    pub created_at: String,
+    pub repository: String,
+    pub tlpdb_sha256: Option<String>,
+    pub tlpdb_revision: Option<u64>,
+    pub changes: Vec<String>,
+    pub packages: Vec<AttestedPackage>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct AttestedPackage {
+    pub name: String,
+    pub revision: String,
+    pub containers: Vec<Container>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Container {
+    pub file: String,
+    pub sha512: String,
+}
+
+#[derive(Serialize)]
+struct Attestation {
+    record: serde_json::Value,
+    prev_hash: String,
+    hash: String,
+}
+
+// Shared-cache copies carry the revision in their file name; record the name the mirror uses.
+pub fn record_containers(conn: &Connection, pkg: &Package, download_paths: &[PathBuf]) -> anyhow::Result<()> {
+    for path in download_paths {
+        let file_name = path.file_name().unwrap_or_default().to_string_lossy().to_string();
+        let container = file_name.replace(&format!(".r{}.tar.xz", pkg.revision), ".tar.xz");
+        let mut hasher = Sha512::new();
+        std::io::copy(&mut File::open(path)?, &mut hasher)?;
+        conn.execute(
+            "INSERT OR REPLACE INTO container_checksums (name, revision, container, sha512) VALUES (?1, ?2, ?3, ?4)",
+            params![pkg.name, pkg.revision, container, format!("{:x}", hasher.finalize())],
+        )?;
+    }
+    Ok(())
+}
+
+pub fn packages(conn: &Connection, installed: &HashMap<String, String>) -> anyhow::Result<Vec<AttestedPackage>> {
+    let mut stmt = conn.prepare(
+        "SELECT container, sha512 FROM container_checksums WHERE name = ?1 AND revision = ?2 ORDER BY container",
+    )?;
+    let mut packages = Vec::new();
+    for (name, revision) in installed {
+        let containers = stmt
+            .query_map(params![name, revision], |row| Ok(Container { file: row.get(0)?, sha512: row.get(1)? }))?
+            .collect::<Result<Vec<_>, _>>()?;
+        packages.push(AttestedPackage { name: name.clone(), revision: revision.clone(), containers });
+    }
+    packages.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(packages)
+}
+
+// Prefers the revision the repository declares in 00texlive.config over the newest package revision.
+pub fn tlpdb_identity(tlpdb: &HashMap<String, Package>) -> (Option<String>, Option<u64>) {
+    let Some(texman_dir) = dirs::home_dir().map(|home| home.join(".texman")) else {
+        return (None, None);
+    };
+    let hash = fs::read_to_string(texman_dir.join("db").join("tlpdb.sha256")).ok().map(|h| h.trim().to_string());
+    let revision = crate::repo::load(&texman_dir)
+        .and_then(|repository| repository.revision)
+        .or_else(|| tlpdb.values().filter_map(|p| p.revision.parse::<u64>().ok()).max());
+    (hash, revision)
+}
+
+fn chain_hash(prev_hash: &str, record: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(prev_hash.as_bytes());
+    hasher.update(record.as_bytes());
+    format!("{:x}", hasher.finalize())
+}
+
+pub fn append(conn: &Connection, record: &Record) -> anyhow::Result<()> {
+    let record = serde_json::to_string(record)?;
+    let prev_hash: String = conn
+        .query_row("SELECT hash FROM attestations ORDER BY id DESC LIMIT 1", [], |row| row.get(0))
+        .optional()?
+        .unwrap_or_else(|| GENESIS_HASH.to_string());
+    let hash = chain_hash(&prev_hash, &record);
+    conn.execute(
+        "INSERT INTO attestations (record, prev_hash, hash) VALUES (?1, ?2, ?3)",
+        params![record, prev_hash, hash],
+    )?;
+    Ok(())
+}
+
+pub fn attest(output: Option<&Path>, verify: bool) -> anyhow::Result<()> {
+    let texman_dir = dirs::home_dir()
+        .ok_or_else(|| anyhow::anyhow!("Cannot find home directory"))?
+        .join(".texman");
+    let conn = crate::init_db(&texman_dir)?;
+
+    if verify {
+        let mut stmt = conn.prepare("SELECT id, record, prev_hash, hash FROM attestations ORDER BY id")?;
+        let rows = stmt
+            .query_map([], |row| Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?, row.get::<_, String>(2)?, row.get::<_, String>(3)?)))?
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut expected_prev = GENESIS_HASH.to_string();
+        for (id, record, prev_hash, hash) in &rows {
+            if *prev_hash != expected_prev || *hash != chain_hash(prev_hash, record) {
+                anyhow::bail!("Attestation chain is broken at entry {}", id);
+            }
+            expected_prev = hash.clone();
+        }
+        println!("Attestation chain intact: {} entries", rows.len());
+        return Ok(());
+    }
+
+    let Some((record, prev_hash, hash)) = conn
+        .query_row("SELECT record, prev_hash, hash FROM attestations ORDER BY id DESC LIMIT 1", [], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?, row.get::<_, String>(2)?))
+        })
+        .optional()?
+    else {
+        anyhow::bail!("No transactions recorded yet; install, update, or remove a package first.");
+    };
+    let attestation = Attestation { record: serde_json::from_str(&record)?, prev_hash, hash };
+    let json = serde_json::to_string_pretty(&attestation)?;
+    match output {
+        Some(path) => {
+            fs::write(path, format!("{}\n", json))?;
+            println!("Wrote attestation to {:?}", path);
+        }
+        None => println!("{}", json),
+    }
+    Ok(())
+}
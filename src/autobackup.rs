@@ -0,0 +1,71 @@
+use crate::config::Config;
+use rusqlite::params;
+
+// Placeholders in `autobackup_name`: {operation} (update, restore, remove), {profile}, and
+// {timestamp} (local time, e.g. 20250301-142530).
+fn render(template: &str, operation: &str, profile: &str) -> anyhow::Result<String> {
+    let timestamp = chrono::Local::now().format("%Y%m%d-%H%M%S").to_string();
+    let name = template
+        .replace("{operation}", operation)
+        .replace("{profile}", profile)
+        .replace("{timestamp}", &timestamp);
+    if name.is_empty() || name.contains('/') || name.starts_with('.') {
+        anyhow::bail!("autobackup_name '{}' gives the invalid backup name '{}'", template, name);
+    }
+    Ok(name)
+}
+
+// Snapshots the active profile before a risky `operation` when `autobackup` is on, then drops
+// the oldest automatic backups of that profile beyond `autobackup_keep` (0 keeps all of them).
+// Backups made with `texman backup create` are never removed here.
+pub fn snapshot(operation: &str, config: &Config) -> anyhow::Result<()> {
+    if !config.autobackup {
+        return Ok(());
+    }
+    let texman_dir = dirs::home_dir()
+        .ok_or_else(|| anyhow::anyhow!("Cannot find home directory"))?
+        .join(".texman");
+    if !crate::active::is_set(&texman_dir) {
+        return Ok(());
+    }
+    let profile = crate::active::name(&texman_dir)?;
+    let base = render(&config.autobackup_name, operation, &profile)?;
+    let conn = crate::init_db(&texman_dir)?;
+    let taken = |name: &str| -> anyhow::Result<bool> {
+        let recorded: i64 = conn.query_row("SELECT COUNT(*) FROM backups WHERE backup_name = ?1", params![name], |row| row.get(0))?;
+        Ok(recorded > 0 || texman_dir.join("backups").join(name).exists())
+    };
+    // Two operations within the same second would otherwise share a name.
+    let mut name = base.clone();
+    let mut suffix = 2;
+    while taken(&name)? {
+        name = format!("{}-{}", base, suffix);
+        suffix += 1;
+    }
+
+    crate::backup_profile(&name)?;
+    conn.execute(
+        "INSERT INTO autobackups (backup_name, profile, operation) VALUES (?1, ?2, ?3)",
+        params![name, profile, operation],
+    )?;
+    println!("Backed up profile '{}' as '{}'; undo with 'texman restore {}'", profile, name, name);
+
+    if config.autobackup_keep > 0 {
+        let mut stmt = conn.prepare("SELECT backup_name FROM autobackups WHERE profile = ?1 ORDER BY created_at DESC, rowid DESC")?;
+        let expired = stmt
+            .query_map(params![profile], |row| row.get::<_, String>(0))?
+            .collect::<Result<Vec<_>, _>>()?
+            .into_iter()
+            .skip(config.autobackup_keep);
+        for old in expired {
+            match crate::remove_backup(&old) {
+                Ok(()) => log::info!("Removed automatic backup '{}' (autobackup_keep = {})", old, config.autobackup_keep),
+                Err(e) => {
+                    log::warn!("Cannot remove automatic backup '{}': {}", old, e);
+                    conn.execute("DELETE FROM autobackups WHERE backup_name = ?1", params![old])?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
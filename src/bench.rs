@@ -0,0 +1,109 @@
+use crate::output::format_size;
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use xz2::read::XzDecoder;
+
+// The first run of each step is reported as cold: the TLPDB text, the cache file, and the
+// allocator are all untouched at that point. Warm is the median of the remaining runs.
+struct Timing {
+    cold: Duration,
+    warm: Duration,
+}
+
+fn measure<T>(iterations: usize, mut step: impl FnMut() -> anyhow::Result<T>) -> anyhow::Result<(Timing, T)> {
+    let started = Instant::now();
+    let mut result = step()?;
+    let cold = started.elapsed();
+    let mut runs = Vec::new();
+    for _ in 1..iterations {
+        let started = Instant::now();
+        result = step()?;
+        runs.push(started.elapsed());
+    }
+    runs.sort();
+    let warm = runs.get(runs.len() / 2).copied().unwrap_or(cold);
+    Ok((Timing { cold, warm }, result))
+}
+
+fn print_timing(label: &str, timing: &Timing) {
+    println!(
+        "  {:<40} cold {:>9.1} ms   warm {:>9.1} ms",
+        label,
+        timing.cold.as_secs_f64() * 1000.0,
+        timing.warm.as_secs_f64() * 1000.0
+    );
+}
+
+fn read_tlpdb(path: &Path) -> anyhow::Result<String> {
+    let bytes = fs::read(path).map_err(|e| anyhow::anyhow!("Cannot read {:?}: {}", path, e))?;
+    if path.extension().is_some_and(|extension| extension == "xz") {
+        let mut text = String::new();
+        XzDecoder::new(bytes.as_slice()).read_to_string(&mut text)?;
+        Ok(text)
+    } else {
+        Ok(String::from_utf8(bytes).map_err(|e| anyhow::anyhow!("Invalid UTF-8 in {:?}: {}", path, e))?)
+    }
+}
+
+// Times the steps every command pays for: reading and parsing the TLPDB, loading the parsed
+// cache, and resolving a scheme's dependencies. Nothing is fetched, and the user's cache is left
+// alone; the cache step writes its own copy to a temporary file.
+pub fn run(tlpdb_path: Option<PathBuf>, scheme: &str, iterations: usize) -> anyhow::Result<()> {
+    let texman_dir = dirs::home_dir()
+        .ok_or_else(|| anyhow::anyhow!("Cannot find home directory"))?
+        .join(".texman");
+    let tlpdb_path = tlpdb_path.unwrap_or_else(|| texman_dir.join("db").join("tlpdb.txt"));
+    if !tlpdb_path.exists() {
+        anyhow::bail!("No TLPDB at {:?}; run any texman command to fetch one, or pass --tlpdb", tlpdb_path);
+    }
+    let iterations = iterations.max(2);
+
+    let (read, text) = measure(iterations, || read_tlpdb(&tlpdb_path))?;
+    let (parse, (tlpdb, _)) = measure(iterations, || crate::parse_tlpdb(&text))?;
+
+    let cache_path = std::env::temp_dir().join(format!("texman-bench-{}.bin", std::process::id()));
+    let mut bin = Vec::new();
+    crate::tlpdb::write_cache(&mut bin, &tlpdb)?;
+    fs::write(&cache_path, &bin)?;
+    let cache = measure(iterations, || {
+        crate::load_tlpdb_bin(&cache_path).ok_or_else(|| anyhow::anyhow!("Failed to load {:?}", cache_path))
+    });
+    let _ = fs::remove_file(&cache_path);
+    let (cache, _) = cache?;
+
+    // Test repositories and partial TLPDBs may lack the scheme; every package is the next best
+    // stand-in for a full install.
+    let roots: Vec<String> = if tlpdb.contains_key(scheme) {
+        vec![scheme.to_string()]
+    } else {
+        log::warn!("{} is not in the TLPDB; resolving every package instead", scheme);
+        let mut names: Vec<String> = tlpdb.keys().cloned().collect();
+        names.sort();
+        names
+    };
+    let (resolve, resolved) = measure(iterations, || {
+        let mut resolved = Vec::new();
+        let mut visited = Vec::new();
+        for root in &roots {
+            crate::resolve_dependencies(root, &tlpdb, &mut resolved, &mut visited)?;
+        }
+        Ok(resolved)
+    })?;
+
+    println!(
+        "TLPDB {:?}: {} text, {} packages, {} cache, {} runs",
+        tlpdb_path,
+        format_size(text.len() as u64),
+        tlpdb.len(),
+        format_size(bin.len() as u64),
+        iterations
+    );
+    print_timing("read", &read);
+    print_timing("parse", &parse);
+    print_timing("load cache", &cache);
+    let label = if roots.len() == 1 { format!("resolve {}", scheme) } else { "resolve all packages".to_string() };
+    print_timing(&format!("{} ({} packages)", label, resolved.len()), &resolve);
+    Ok(())
+}
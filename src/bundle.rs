@@ -0,0 +1,361 @@
+use crate::config::{Config, ConflictPolicy};
+use crate::{tree, Package};
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use rusqlite::params;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File};
+use std::io::{Seek, Write};
+use std::path::Path;
+
+const MANIFEST_NAME: &str = "TEXMAN-BUNDLE.json";
+
+#[derive(Serialize, Deserialize)]
+struct Manifest {
+    profile: String,
+    created_at: i64,
+    packages: Vec<BundlePackage>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct BundlePackage {
+    name: String,
+    revision: String,
+    files: Vec<String>,
+}
+
+// The bundle is an uncompressed tar so files can be read at an offset, plus a
+// `<out>.index.gz` listing `name offset length` per file in the format tectonic's
+// indexed-tar bundles use.
+pub fn create_bundle(out: &Path) -> anyhow::Result<()> {
+    let texman_dir = dirs::home_dir()
+        .ok_or_else(|| anyhow::anyhow!("Cannot find home directory"))?
+        .join(".texman");
+
+    let active_dir = crate::active::dir(&texman_dir)?;
+    let active_profile = crate::active::name(&texman_dir)?;
+
+    let conn = crate::init_db(&texman_dir)?;
+    let mut stmt = conn.prepare("SELECT name, revision FROM installed_packages WHERE profile = ?1 ORDER BY name")?;
+    let installed = stmt
+        .query_map(params![active_profile], |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)))?
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut packages = Vec::new();
+    for (name, revision) in installed {
+        let mut stmt = conn.prepare("SELECT path FROM profile_files WHERE profile = ?1 AND package = ?2 ORDER BY path")?;
+        let files = stmt
+            .query_map(params![active_profile, name], |row| row.get::<_, String>(0))?
+            .collect::<Result<Vec<_>, _>>()?;
+        packages.push(BundlePackage { name, revision, files });
+    }
+    let manifest = Manifest {
+        profile: active_profile.clone(),
+        created_at: chrono::Utc::now().timestamp(),
+        packages,
+    };
+
+    let mut builder = tar::Builder::new(File::create(out)?);
+    let manifest_json = serde_json::to_vec_pretty(&manifest)?;
+    let mut header = tar::Header::new_gnu();
+    header.set_size(manifest_json.len() as u64);
+    header.set_mode(0o644);
+    header.set_mtime(manifest.created_at as u64);
+    header.set_cksum();
+    builder.append_data(&mut header, MANIFEST_NAME, manifest_json.as_slice())?;
+
+    let mut index = String::new();
+    let mut indexed = HashSet::new();
+    let mut file_count = 0;
+    for rel_path in tree::walk_files(&active_dir)? {
+        let src_path = active_dir.join(&rel_path);
+        let Ok(metadata) = fs::metadata(&src_path) else {
+            log::warn!("Skipping dangling link {:?}", src_path);
+            continue;
+        };
+        builder.append_path_with_name(&src_path, &rel_path)?;
+        file_count += 1;
+
+        // The data of the entry just written ends at the current position, padded to 512 bytes.
+        let end = builder.get_mut().stream_position()?;
+        let offset = end - metadata.len().div_ceil(512) * 512;
+        let file_name = rel_path.file_name().unwrap_or_default().to_string_lossy().to_string();
+        if indexed.insert(file_name.clone()) {
+            index.push_str(&format!("{} {} {}\n", file_name, offset, metadata.len()));
+        } else {
+            log::debug!("{} is shadowed in the bundle index by an earlier file of the same name", rel_path.display());
+        }
+    }
+    builder.into_inner()?.sync_all()?;
+
+    let mut index_path = out.as_os_str().to_os_string();
+    index_path.push(".index.gz");
+    let mut encoder = GzEncoder::new(File::create(&index_path)?, Compression::default());
+    encoder.write_all(index.as_bytes())?;
+    encoder.finish()?;
+
+    println!(
+        "Created bundle {:?} from profile '{}': {} files, {} packages (index: {:?})",
+        out, active_profile, file_count, manifest.packages.len(), index_path
+    );
+    Ok(())
+}
+
+pub fn install_bundle(bundle: &Path, profile: &str, config: &Config) -> anyhow::Result<()> {
+    let texman_dir = dirs::home_dir()
+        .ok_or_else(|| anyhow::anyhow!("Cannot find home directory"))?
+        .join(".texman");
+    let staging_dir = texman_dir.join("staging").join(format!("bundle-{}", std::process::id()));
+    if staging_dir.exists() {
+        fs::remove_dir_all(&staging_dir)?;
+    }
+    fs::create_dir_all(&staging_dir)?;
+    let result = install_from_staging(bundle, profile, config, &texman_dir, &staging_dir);
+    fs::remove_dir_all(&staging_dir)?;
+    result
+}
+
+fn install_from_staging(bundle: &Path, profile: &str, config: &Config, texman_dir: &Path, staging_dir: &Path) -> anyhow::Result<()> {
+    let spinner = crate::ui::spinner(&format!("Unpacking {}", bundle.display()));
+    tar::Archive::new(File::open(bundle)?).unpack(staging_dir)?;
+    drop(spinner);
+    let manifest_path = staging_dir.join(MANIFEST_NAME);
+    if !manifest_path.exists() {
+        anyhow::bail!("{:?} is not a texman bundle (no {} inside)", bundle, MANIFEST_NAME);
+    }
+    let manifest: Manifest = serde_json::from_str(&fs::read_to_string(&manifest_path)?)
+        .map_err(|e| anyhow::anyhow!("Invalid bundle manifest in {:?}: {}", bundle, e))?;
+    fs::remove_file(&manifest_path)?;
+
+    let profile_dir = texman_dir.join("profiles").join(profile);
+    fs::create_dir_all(&profile_dir)?;
+    let conn = crate::init_db(texman_dir)?;
+    let policy = crate::conflict_policy(&conn, profile, config, None)?;
+    let strategy = crate::install_strategy(&conn, profile, config)?;
+
+    let mut managed = HashSet::new();
+    for pkg in &manifest.packages {
+        let store = crate::store_path(texman_dir, &pkg.name, &pkg.revision);
+        if !crate::store::ensure_expanded(&store)? {
+            for file in &pkg.files {
+                let dest = store.join(file);
+                if let Some(parent) = dest.parent() {
+                    fs::create_dir_all(parent)?;
+                }
+                fs::copy(staging_dir.join(file), &dest)?;
+            }
+            log::info!("Added {} r{} to the store from the bundle", pkg.name, pkg.revision);
+        }
+        tree::link_package(&conn, profile, &profile_dir, &pkg.name, &store, policy, strategy)?;
+        conn.execute(
+            "INSERT OR REPLACE INTO installed_packages (profile, name, revision) VALUES (?1, ?2, ?3)",
+            params![profile, pkg.name, pkg.revision],
+        )?;
+        conn.execute(
+            "INSERT OR REPLACE INTO package_origins (profile, name, repository) VALUES (?1, ?2, ?3)",
+            params![profile, pkg.name, crate::repo::BUNDLE],
+        )?;
+        managed.extend(pkg.files.iter().map(String::as_str));
+    }
+
+    let mut unmanaged = 0;
+    for rel_path in tree::walk_files(staging_dir)? {
+        if managed.contains(tree::path_key(&rel_path)?.as_str()) {
+            continue;
+        }
+        let dest = profile_dir.join(&rel_path);
+        if dest.symlink_metadata().is_ok() {
+            continue;
+        }
+        if let Some(parent) = dest.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::copy(staging_dir.join(&rel_path), &dest)?;
+        unmanaged += 1;
+    }
+    tree::refresh(&conn, profile, &profile_dir, &HashMap::new())?;
+
+    if !crate::active::is_set(texman_dir) {
+        crate::active::set(texman_dir, profile)?;
+    }
+
+    println!(
+        "Installed bundle {:?} (from profile '{}') into profile '{}': {} packages, {} extra files",
+        bundle, manifest.profile, profile, manifest.packages.len(), unmanaged
+    );
+    Ok(())
+}
+
+const EXPORT_MANIFEST_NAME: &str = "TEXMAN-EXPORT.json";
+
+// An export carries what a machine without any network needs to install `packages`: the TLPDB
+// blocks of their dependency closure and the archives, laid out like a shared cache.
+#[derive(Serialize, Deserialize)]
+struct ExportManifest {
+    repository: String,
+    created_at: i64,
+    packages: Vec<String>,
+    docs: bool,
+    sources: bool,
+}
+
+pub async fn export(requested: &[String], out: &Path, tlpdb: &HashMap<String, Package>, config: &Config) -> anyhow::Result<()> {
+    let texman_dir = dirs::home_dir()
+        .ok_or_else(|| anyhow::anyhow!("Cannot find home directory"))?
+        .join(".texman");
+    let mut resolved = Vec::new();
+    let mut visited = Vec::new();
+    for package in requested {
+        crate::resolve_dependencies(package, tlpdb, &mut resolved, &mut visited)?;
+    }
+    let packages: Vec<Package> = resolved.iter().map(|name| tlpdb[name].clone()).collect();
+
+    let staging_dir = texman_dir.join("staging").join(format!("export-{}", std::process::id()));
+    if staging_dir.exists() {
+        fs::remove_dir_all(&staging_dir)?;
+    }
+    fs::create_dir_all(&staging_dir)?;
+    let result = export_from_staging(requested, out, &packages, config, &texman_dir, &staging_dir).await;
+    fs::remove_dir_all(&staging_dir)?;
+    result
+}
+
+async fn export_from_staging(
+    requested: &[String],
+    out: &Path,
+    packages: &[Package],
+    config: &Config,
+    texman_dir: &Path,
+    staging_dir: &Path,
+) -> anyhow::Result<()> {
+    // Downloading into a shared cache rooted in the staging directory leaves the archives in the
+    // layout `import` points `shared_cache` at; archives already in the user's caches are copied.
+    let mut export_config = config.clone();
+    export_config.shared_cache = Some(staging_dir.to_path_buf());
+    export_config.keep_going = false;
+    for pkg in packages {
+        for (archive, _) in crate::package_archives(pkg, &export_config) {
+            let name = crate::cache::archive_name(&archive, &pkg.revision);
+            let cached = config
+                .shared_cache
+                .as_ref()
+                .map(|shared| shared.join("archives").join(&name))
+                .into_iter()
+                .chain(std::iter::once(texman_dir.join(&name)))
+                .find(|path| path.exists());
+            if let Some(cached) = cached {
+                fs::create_dir_all(staging_dir.join("archives"))?;
+                fs::copy(cached, staging_dir.join("archives").join(&name))?;
+            }
+        }
+    }
+    crate::download_packages(packages, texman_dir, &export_config).await?;
+
+    let text = fs::read_to_string(texman_dir.join("db").join("tlpdb.txt"))?;
+    let keep: HashSet<&str> = packages.iter().map(|pkg| pkg.name.as_str()).collect();
+    let slice = crate::mirror::filter_tlpdb(&text, &keep);
+    let manifest = ExportManifest {
+        repository: config.repository.clone(),
+        created_at: chrono::Utc::now().timestamp(),
+        packages: requested.to_vec(),
+        docs: config.docs,
+        sources: config.sources,
+    };
+
+    let mut builder = tar::Builder::new(File::create(out)?);
+    for (name, data) in [
+        (EXPORT_MANIFEST_NAME, serde_json::to_vec_pretty(&manifest)?),
+        ("tlpkg/texlive.tlpdb", slice.into_bytes()),
+    ] {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(data.len() as u64);
+        header.set_mode(0o644);
+        header.set_mtime(manifest.created_at as u64);
+        header.set_cksum();
+        builder.append_data(&mut header, name, data.as_slice())?;
+    }
+    // Lock files left by the downloads stay behind.
+    for entry in fs::read_dir(staging_dir.join("archives"))? {
+        let entry = entry?;
+        if entry.file_name().to_string_lossy().ends_with(".tar.xz") {
+            builder.append_path_with_name(entry.path(), Path::new("archives").join(entry.file_name()))?;
+        }
+    }
+    builder.into_inner()?.sync_all()?;
+
+    println!(
+        "Exported {} ({} packages with dependencies, {}) to {:?}",
+        requested.join(", "),
+        packages.len(),
+        crate::output::format_size(fs::metadata(out)?.len()),
+        out
+    );
+    Ok(())
+}
+
+// Installs what an export carries without touching the network; the TLPDB comes from the export
+// too, so the machine needs no cached TLPDB of its own.
+pub async fn import(
+    bundle: &Path,
+    profile: &str,
+    config: &Config,
+    on_conflict: Option<ConflictPolicy>,
+    allow_scripts: bool,
+) -> anyhow::Result<Vec<(String, String)>> {
+    let texman_dir = dirs::home_dir()
+        .ok_or_else(|| anyhow::anyhow!("Cannot find home directory"))?
+        .join(".texman");
+    let staging_dir = texman_dir.join("staging").join(format!("import-{}", std::process::id()));
+    if staging_dir.exists() {
+        fs::remove_dir_all(&staging_dir)?;
+    }
+    fs::create_dir_all(&staging_dir)?;
+    let result = import_from_staging(bundle, profile, config, on_conflict, allow_scripts, &texman_dir, &staging_dir).await;
+    fs::remove_dir_all(&staging_dir)?;
+    result
+}
+
+async fn import_from_staging(
+    bundle: &Path,
+    profile: &str,
+    config: &Config,
+    on_conflict: Option<ConflictPolicy>,
+    allow_scripts: bool,
+    texman_dir: &Path,
+    staging_dir: &Path,
+) -> anyhow::Result<Vec<(String, String)>> {
+    let spinner = crate::ui::spinner(&format!("Unpacking {}", bundle.display()));
+    tar::Archive::new(File::open(bundle)?).unpack(staging_dir)?;
+    drop(spinner);
+    let manifest_path = staging_dir.join(EXPORT_MANIFEST_NAME);
+    if !manifest_path.exists() {
+        anyhow::bail!("{:?} is not a texman export (no {} inside); use 'texman bundle install' for profile bundles", bundle, EXPORT_MANIFEST_NAME);
+    }
+    let manifest: ExportManifest = serde_json::from_str(&fs::read_to_string(&manifest_path)?)
+        .map_err(|e| anyhow::anyhow!("Invalid export manifest in {:?}: {}", bundle, e))?;
+    let (tlpdb, _) = crate::parse_tlpdb(&fs::read_to_string(staging_dir.join("tlpkg").join("texlive.tlpdb"))?)?;
+
+    let mut config = config.clone();
+    config.offline = true;
+    config.shared_cache = Some(staging_dir.to_path_buf());
+    if config.docs && !manifest.docs {
+        log::warn!("The export carries no documentation; installing without it");
+        config.docs = false;
+    }
+    if config.sources && !manifest.sources {
+        log::warn!("The export carries no sources; installing without them");
+        config.sources = false;
+    }
+
+    println!(
+        "Importing {} from {:?} (exported from {})",
+        manifest.packages.join(", "),
+        bundle,
+        manifest.repository
+    );
+    let profiles = [profile.to_string()];
+    let transaction = crate::summary::Transaction::begin("install", profile, texman_dir)?;
+    let failed = crate::install_package(&manifest.packages, &profiles, &tlpdb, &config, on_conflict, None, allow_scripts).await?;
+    transaction.finish(&config, &tlpdb)?;
+    Ok(failed)
+}
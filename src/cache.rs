@@ -0,0 +1,100 @@
+use crate::config::Config;
+use chrono::{DateTime, Duration, Utc};
+use sha2::{Digest, Sha256};
+use crate::os;
+use std::fs::{self, File, OpenOptions};
+use std::path::{Path, PathBuf};
+
+// Shared directories are group-writable and setgid so every member of the owning
+// group (e.g. all accounts on a lab machine) can add to the cache.
+const SHARED_DIR_MODE: u32 = 0o2775;
+const SHARED_FILE_MODE: u32 = 0o664;
+
+pub struct SharedDir {
+    pub path: PathBuf,
+    pub writable: bool,
+}
+
+pub fn shared_dir(config: &Config, kind: &str) -> Option<SharedDir> {
+    let root = config.shared_cache.as_ref()?;
+    let path = root.join(kind);
+    if !path.exists() {
+        let created_root = !root.exists();
+        if let Err(e) = os::create_dir_all_with_mode(&path, SHARED_DIR_MODE) {
+            log::warn!("Cannot create shared cache {:?} ({}); using the per-user cache", path, e);
+            return None;
+        }
+        // The umask strips group write and setgid from the directories we just created.
+        let _ = os::set_mode(&path, SHARED_DIR_MODE);
+        if created_root {
+            let _ = os::set_mode(root, SHARED_DIR_MODE);
+        }
+    }
+
+    let probe = path.join(format!(".texman-probe-{}", std::process::id()));
+    let writable = File::create(&probe).is_ok();
+    let _ = fs::remove_file(&probe);
+    if !writable {
+        log::warn!("Shared cache {:?} is read-only for this user; new downloads go to the per-user cache", path);
+    }
+    Some(SharedDir { path, writable })
+}
+
+// Archives are keyed by revision, so users on different TLPDB snapshots never pick up each other's containers.
+pub fn archive_name(archive_name: &str, revision: &str) -> String {
+    format!("{}.r{}.tar.xz", archive_name.trim_end_matches(".tar.xz"), revision)
+}
+
+pub fn tlpdb_name(repository: &str) -> String {
+    let hash = format!("{:x}", Sha256::digest(repository.trim_end_matches('/').as_bytes()));
+    format!("texlive-{}.tlpdb", &hash[..16])
+}
+
+// Holds an exclusive lock on `<path>.lock` until the returned file is dropped.
+pub async fn lock(path: &Path) -> anyhow::Result<File> {
+    let path = path.to_path_buf();
+    tokio::task::spawn_blocking(move || lock_blocking(&path)).await?
+}
+
+// `lock` for code that doesn't run on the async runtime.
+pub fn lock_blocking(path: &Path) -> anyhow::Result<File> {
+    let mut lock_path = path.as_os_str().to_os_string();
+    lock_path.push(".lock");
+    let lock_path = PathBuf::from(lock_path);
+    if let Some(parent) = lock_path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let file = OpenOptions::new().create(true).truncate(false).write(true).open(&lock_path)?;
+    let _ = os::set_mode(&lock_path, SHARED_FILE_MODE);
+    if file.try_lock().is_err() {
+        log::info!("Waiting for another texman process to release {:?}", lock_path);
+        file.lock()?;
+    }
+    Ok(file)
+}
+
+// Whether another process holds the lock `lock` takes on `path`.
+pub fn is_locked(path: &Path) -> bool {
+    let mut lock_path = path.as_os_str().to_os_string();
+    lock_path.push(".lock");
+    File::open(lock_path).is_ok_and(|file| file.try_lock().is_err())
+}
+
+pub fn is_fresh(path: &Path, max_age: Duration) -> bool {
+    let Ok(modified) = fs::metadata(path).and_then(|m| m.modified()) else { return false };
+    let modified: DateTime<Utc> = modified.into();
+    Utc::now() - modified <= max_age
+}
+
+// Moves a finished download into the shared cache atomically, so readers never see a partial file.
+pub fn publish(staged: &Path, cached: &Path) -> anyhow::Result<()> {
+    let _ = os::set_mode(staged, SHARED_FILE_MODE);
+    fs::rename(staged, cached)?;
+    Ok(())
+}
+
+pub fn staging_path(cached: &Path) -> PathBuf {
+    let mut staged = cached.as_os_str().to_os_string();
+    staged.push(format!(".part.{}", std::process::id()));
+    PathBuf::from(staged)
+}
@@ -0,0 +1,112 @@
+use crate::Package;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+struct Coupling {
+    packages: (&'static str, &'static str),
+    reason: &'static str,
+    compatible: fn(&str, &str) -> bool,
+}
+
+const COUPLINGS: &[Coupling] = &[Coupling {
+    packages: ("biblatex", "biber"),
+    reason: "biber only understands the data model of the matching biblatex release (biblatex 3.N pairs with biber 2.N)",
+    compatible: same_minor_version,
+}];
+
+pub const ENGINES: &[&str] = &["pdftex", "xetex", "luatex", "luahbtex", "euptex", "eptex", "ptex", "uptex", "aleph"];
+
+pub struct Incompatibility {
+    pub updated: String,
+    pub partner: String,
+    pub reason: &'static str,
+    pub updated_version: String,
+    pub partner_version: String,
+    pub partner_update: Option<Package>,
+}
+
+// `installed` maps installed package names to the catalogue version of their installed revision.
+pub fn find_incompatibilities(
+    installed: &HashMap<String, Option<String>>,
+    updates: &[Package],
+    tlpdb: &HashMap<String, Package>,
+) -> Vec<Incompatibility> {
+    let version_after = |name: &str| -> Option<String> {
+        match updates.iter().find(|p| p.name == name) {
+            Some(pkg) => pkg.catalogue_version.clone(),
+            None => installed.get(name).cloned().flatten(),
+        }
+    };
+
+    let mut incompatibilities = Vec::new();
+    for coupling in COUPLINGS {
+        let (first, second) = coupling.packages;
+        if !installed.contains_key(first) || !installed.contains_key(second) {
+            continue;
+        }
+        let (Some(first_version), Some(second_version)) = (version_after(first), version_after(second)) else {
+            continue;
+        };
+        if (coupling.compatible)(&first_version, &second_version) {
+            continue;
+        }
+
+        let first_updated = updates.iter().any(|p| p.name == first);
+        let second_updated = updates.iter().any(|p| p.name == second);
+        let (updated, partner, updated_version, partner_version) = match (first_updated, second_updated) {
+            (true, false) => (first, second, first_version, second_version),
+            (false, true) => (second, first, second_version, first_version),
+            _ => continue,
+        };
+
+        let partner_update = tlpdb
+            .get(partner)
+            .filter(|p| p.catalogue_version.as_deref().is_some_and(|v| {
+                if updated == first { (coupling.compatible)(&updated_version, v) } else { (coupling.compatible)(v, &updated_version) }
+            }))
+            .cloned();
+
+        incompatibilities.push(Incompatibility {
+            updated: updated.to_string(),
+            partner: partner.to_string(),
+            reason: coupling.reason,
+            updated_version,
+            partner_version,
+            partner_update,
+        });
+    }
+    incompatibilities
+}
+
+pub fn stale_format_engines(profile_dir: &Path, updates: &[Package]) -> Vec<String> {
+    let web2c = profile_dir.join("texmf-var").join("web2c");
+    if !has_formats(&web2c) {
+        return Vec::new();
+    }
+    updates
+        .iter()
+        .filter(|p| ENGINES.contains(&p.name.as_str()))
+        .map(|p| p.name.clone())
+        .collect()
+}
+
+fn has_formats(dir: &Path) -> bool {
+    let Ok(entries) = fs::read_dir(dir) else { return false };
+    entries.flatten().any(|entry| {
+        let path = entry.path();
+        if path.is_dir() {
+            has_formats(&path)
+        } else {
+            path.extension().and_then(|e| e.to_str()) == Some("fmt")
+        }
+    })
+}
+
+fn same_minor_version(first: &str, second: &str) -> bool {
+    let minor = |v: &str| v.split('.').nth(1).and_then(|m| m.parse::<u32>().ok());
+    match (minor(first), minor(second)) {
+        (Some(a), Some(b)) => a == b,
+        _ => true,
+    }
+}
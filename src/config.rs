@@ -0,0 +1,335 @@
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+pub const DEFAULT_REPOSITORY: &str = "https://mirror.ctan.org/systems/texlive/tlnet";
+// The default before downloads switched to HTTPS; config files written by `texman setup` may still name it.
+const OLD_DEFAULT_REPOSITORY: &str = "http://mirror.ctan.org/systems/texlive/tlnet";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum ConflictPolicy {
+    Error,
+    Skip,
+    Overwrite,
+    Backup,
+}
+
+impl ConflictPolicy {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ConflictPolicy::Error => "error",
+            ConflictPolicy::Skip => "skip",
+            ConflictPolicy::Overwrite => "overwrite",
+            ConflictPolicy::Backup => "backup",
+        }
+    }
+
+    pub fn parse(value: &str) -> anyhow::Result<ConflictPolicy> {
+        <ConflictPolicy as ValueEnum>::from_str(value, true)
+            .map_err(|_| anyhow::anyhow!("Invalid conflict policy '{}': expected error, skip, overwrite or backup", value))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum InstallStrategy {
+    Symlink,
+    Hardlink,
+    Copy,
+}
+
+impl InstallStrategy {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            InstallStrategy::Symlink => "symlink",
+            InstallStrategy::Hardlink => "hardlink",
+            InstallStrategy::Copy => "copy",
+        }
+    }
+
+    pub fn parse(value: &str) -> anyhow::Result<InstallStrategy> {
+        <InstallStrategy as ValueEnum>::from_str(value, true)
+            .map_err(|_| anyhow::anyhow!("Invalid install strategy '{}': expected symlink, hardlink or copy", value))
+    }
+}
+
+// How modes from package archives are treated when unpacking: `normalize` gives 0644 files and
+// 0755 directories and executables, `umask` applies the user's umask to 0666/0777 instead, and
+// `preserve` keeps whatever the archive says.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum PermissionPolicy {
+    Normalize,
+    Umask,
+    Preserve,
+}
+
+impl PermissionPolicy {
+    pub fn parse(value: &str) -> anyhow::Result<PermissionPolicy> {
+        <PermissionPolicy as ValueEnum>::from_str(value, true)
+            .map_err(|_| anyhow::anyhow!("Invalid permissions policy '{}': expected normalize, umask or preserve", value))
+    }
+}
+
+// Resolution order: command-line flag > TEXMAN_* environment variable > config file > default.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub repository: String,
+    pub mirrors: Vec<String>,
+    pub jobs: usize,
+    pub offline: bool,
+    pub profile: String,
+    pub docs: bool,
+    pub sources: bool,
+    pub on_conflict: ConflictPolicy,
+    pub install_strategy: InstallStrategy,
+    pub shared_cache: Option<PathBuf>,
+    pub history_log: bool,
+    pub update_notice: bool,
+    pub ca_bundle: Option<PathBuf>,
+    pub keep_going: bool,
+    pub permissions: PermissionPolicy,
+    pub retries: u32,
+    pub retry_delay_ms: u64,
+    pub retry_jitter: bool,
+    pub proxy: Option<String>,
+    pub autobackup: bool,
+    pub autobackup_name: String,
+    pub autobackup_keep: usize,
+    pub repositories: BTreeMap<String, String>,
+    pub repository_priority: Vec<String>,
+    pub low_memory: bool,
+    pub renames: BTreeMap<String, String>,
+    pub smoke_tests: bool,
+    pub build_formats: bool,
+    pub sync_fontmaps: bool,
+    // Unused revisions of each package kept in the store for `rollback` and `undo`.
+    pub keep_revisions: usize,
+    // Only set by `--no-depends-at-all`, for the one command.
+    #[serde(skip)]
+    pub no_depends_at_all: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            repository: DEFAULT_REPOSITORY.to_string(),
+            mirrors: Vec::new(),
+            jobs: 8,
+            offline: false,
+            profile: "default".to_string(),
+            docs: false,
+            sources: false,
+            on_conflict: ConflictPolicy::Error,
+            // Windows only lets administrators, or developer mode, create symlinks.
+            install_strategy: if cfg!(windows) { InstallStrategy::Hardlink } else { InstallStrategy::Symlink },
+            shared_cache: None,
+            history_log: false,
+            update_notice: false,
+            ca_bundle: None,
+            keep_going: false,
+            permissions: PermissionPolicy::Normalize,
+            retries: 3,
+            retry_delay_ms: 500,
+            retry_jitter: true,
+            proxy: None,
+            autobackup: false,
+            autobackup_name: "auto-{operation}-{timestamp}".to_string(),
+            autobackup_keep: 10,
+            repositories: BTreeMap::new(),
+            repository_priority: Vec::new(),
+            low_memory: false,
+            renames: BTreeMap::new(),
+            smoke_tests: true,
+            build_formats: true,
+            sync_fontmaps: true,
+            keep_revisions: 2,
+            no_depends_at_all: false,
+        }
+    }
+}
+
+impl Config {
+    pub fn path(texman_dir: &Path) -> PathBuf {
+        texman_dir.join("config.toml")
+    }
+
+    pub fn load(texman_dir: &Path) -> anyhow::Result<Config> {
+        let mut config = Config::load_file(texman_dir)?;
+        config.apply_env()?;
+        if config.jobs == 0 {
+            anyhow::bail!("jobs must be at least 1");
+        }
+        Ok(config)
+    }
+
+    pub fn load_file(texman_dir: &Path) -> anyhow::Result<Config> {
+        let path = Config::path(texman_dir);
+        if !path.exists() {
+            return Ok(Config::default());
+        }
+        let text = fs::read_to_string(&path)?;
+        let mut config: Config = toml::from_str(&text).map_err(|e| anyhow::anyhow!("Invalid config file {:?}: {}", path, e))?;
+        if config.repository == OLD_DEFAULT_REPOSITORY {
+            config.repository = DEFAULT_REPOSITORY.to_string();
+        }
+        Ok(config)
+    }
+
+    pub fn save(&self, texman_dir: &Path) -> anyhow::Result<()> {
+        fs::create_dir_all(texman_dir)?;
+        let text = toml::to_string_pretty(self)
+            .map_err(|e| anyhow::anyhow!("Failed to serialize config: {}", e))?;
+        fs::write(Config::path(texman_dir), text)?;
+        Ok(())
+    }
+
+    fn apply_env(&mut self) -> anyhow::Result<()> {
+        if let Some(repository) = env_var("TEXMAN_REPOSITORY") {
+            self.repository = repository;
+        }
+        if let Some(mirrors) = env_var("TEXMAN_MIRRORS") {
+            self.mirrors = mirrors.split(',').map(|mirror| mirror.trim().to_string()).filter(|m| !m.is_empty()).collect();
+        }
+        if let Some(jobs) = env_var("TEXMAN_JOBS") {
+            self.jobs = jobs.parse()
+                .map_err(|e| anyhow::anyhow!("Invalid TEXMAN_JOBS '{}': {}", jobs, e))?;
+        }
+        if let Some(offline) = env_var("TEXMAN_OFFLINE") {
+            self.offline = parse_bool("TEXMAN_OFFLINE", &offline)?;
+        }
+        if let Some(profile) = env_var("TEXMAN_PROFILE") {
+            self.profile = profile;
+        }
+        if let Some(docs) = env_var("TEXMAN_DOCS") {
+            self.docs = parse_bool("TEXMAN_DOCS", &docs)?;
+        }
+        if let Some(sources) = env_var("TEXMAN_SOURCES") {
+            self.sources = parse_bool("TEXMAN_SOURCES", &sources)?;
+        }
+        if let Some(policy) = env_var("TEXMAN_ON_CONFLICT") {
+            self.on_conflict = ConflictPolicy::parse(&policy)?;
+        }
+        if let Some(strategy) = env_var("TEXMAN_INSTALL_STRATEGY") {
+            self.install_strategy = InstallStrategy::parse(&strategy)?;
+        }
+        if let Some(shared_cache) = env_var("TEXMAN_SHARED_CACHE") {
+            self.shared_cache = Some(PathBuf::from(shared_cache));
+        }
+        if let Some(history_log) = env_var("TEXMAN_HISTORY_LOG") {
+            self.history_log = parse_bool("TEXMAN_HISTORY_LOG", &history_log)?;
+        }
+        if let Some(update_notice) = env_var("TEXMAN_UPDATE_NOTICE") {
+            self.update_notice = parse_bool("TEXMAN_UPDATE_NOTICE", &update_notice)?;
+        }
+        if let Some(ca_bundle) = env_var("TEXMAN_CA_BUNDLE") {
+            self.ca_bundle = Some(PathBuf::from(ca_bundle));
+        }
+        if let Some(keep_going) = env_var("TEXMAN_KEEP_GOING") {
+            self.keep_going = parse_bool("TEXMAN_KEEP_GOING", &keep_going)?;
+        }
+        if let Some(permissions) = env_var("TEXMAN_PERMISSIONS") {
+            self.permissions = PermissionPolicy::parse(&permissions)?;
+        }
+        if let Some(retries) = env_var("TEXMAN_RETRIES") {
+            self.retries = retries.parse()
+                .map_err(|e| anyhow::anyhow!("Invalid TEXMAN_RETRIES '{}': {}", retries, e))?;
+        }
+        if let Some(delay) = env_var("TEXMAN_RETRY_DELAY_MS") {
+            self.retry_delay_ms = delay.parse()
+                .map_err(|e| anyhow::anyhow!("Invalid TEXMAN_RETRY_DELAY_MS '{}': {}", delay, e))?;
+        }
+        if let Some(jitter) = env_var("TEXMAN_RETRY_JITTER") {
+            self.retry_jitter = parse_bool("TEXMAN_RETRY_JITTER", &jitter)?;
+        }
+        if let Some(proxy) = env_var("TEXMAN_PROXY") {
+            self.proxy = Some(proxy);
+        }
+        if let Some(repositories) = env_var("TEXMAN_REPOSITORIES") {
+            self.repositories = repositories
+                .split(',')
+                .filter(|entry| !entry.trim().is_empty())
+                .map(|entry| {
+                    let (name, url) = entry
+                        .split_once('=')
+                        .ok_or_else(|| anyhow::anyhow!("Invalid TEXMAN_REPOSITORIES entry '{}': expected name=url", entry))?;
+                    Ok((name.trim().to_string(), url.trim().trim_end_matches('/').to_string()))
+                })
+                .collect::<anyhow::Result<_>>()?;
+        }
+        if let Some(priority) = env_var("TEXMAN_REPOSITORY_PRIORITY") {
+            self.repository_priority = priority.split(',').map(|name| name.trim().to_string()).filter(|name| !name.is_empty()).collect();
+        }
+        if let Some(autobackup) = env_var("TEXMAN_AUTOBACKUP") {
+            self.autobackup = parse_bool("TEXMAN_AUTOBACKUP", &autobackup)?;
+        }
+        if let Some(name) = env_var("TEXMAN_AUTOBACKUP_NAME") {
+            self.autobackup_name = name;
+        }
+        if let Some(keep) = env_var("TEXMAN_AUTOBACKUP_KEEP") {
+            self.autobackup_keep = keep.parse()
+                .map_err(|e| anyhow::anyhow!("Invalid TEXMAN_AUTOBACKUP_KEEP '{}': {}", keep, e))?;
+        }
+        if let Some(low_memory) = env_var("TEXMAN_LOW_MEMORY") {
+            self.low_memory = parse_bool("TEXMAN_LOW_MEMORY", &low_memory)?;
+        }
+        if let Some(renames) = env_var("TEXMAN_RENAMES") {
+            self.renames = renames
+                .split(',')
+                .filter(|entry| !entry.trim().is_empty())
+                .map(|entry| {
+                    let (old, new) = entry
+                        .split_once('=')
+                        .ok_or_else(|| anyhow::anyhow!("Invalid TEXMAN_RENAMES entry '{}': expected old=new", entry))?;
+                    Ok((old.trim().to_string(), new.trim().to_string()))
+                })
+                .collect::<anyhow::Result<_>>()?;
+        }
+        if let Some(smoke_tests) = env_var("TEXMAN_SMOKE_TESTS") {
+            self.smoke_tests = parse_bool("TEXMAN_SMOKE_TESTS", &smoke_tests)?;
+        }
+        if let Some(build_formats) = env_var("TEXMAN_BUILD_FORMATS") {
+            self.build_formats = parse_bool("TEXMAN_BUILD_FORMATS", &build_formats)?;
+        }
+        if let Some(sync_fontmaps) = env_var("TEXMAN_SYNC_FONTMAPS") {
+            self.sync_fontmaps = parse_bool("TEXMAN_SYNC_FONTMAPS", &sync_fontmaps)?;
+        }
+        if let Some(keep) = env_var("TEXMAN_KEEP_REVISIONS") {
+            self.keep_revisions = keep.parse()
+                .map_err(|e| anyhow::anyhow!("Invalid TEXMAN_KEEP_REVISIONS '{}': {}", keep, e))?;
+        }
+        Ok(())
+    }
+
+    pub fn repository_url(&self, path: &str) -> String {
+        format!("{}/{}", self.repository.trim_end_matches('/'), path)
+    }
+
+    // The repository first, then the fallback mirrors in the order given.
+    pub fn repository_urls(&self, path: &str) -> Vec<String> {
+        let mut urls = vec![self.repository_url(path)];
+        for mirror in &self.mirrors {
+            let url = format!("{}/{}", mirror.trim_end_matches('/'), path);
+            if !urls.contains(&url) {
+                urls.push(url);
+            }
+        }
+        urls
+    }
+}
+
+fn env_var(name: &str) -> Option<String> {
+    std::env::var(name).ok().filter(|v| !v.is_empty())
+}
+
+fn parse_bool(name: &str, value: &str) -> anyhow::Result<bool> {
+    match value.to_lowercase().as_str() {
+        "1" | "true" | "yes" | "on" => Ok(true),
+        "0" | "false" | "no" | "off" => Ok(false),
+        _ => anyhow::bail!("Invalid {} '{}': expected true or false", name, value),
+    }
+}
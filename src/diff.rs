@@ -0,0 +1,101 @@
+use crate::config::Config;
+use crate::Package;
+use rusqlite::{params, OptionalExtension};
+use std::collections::{BTreeSet, HashMap};
+use std::fs;
+use std::path::Path;
+
+// Installed files the TLPDB's runfiles and binfiles don't describe: TeX Live's own metadata and
+// the doc and source containers.
+fn is_compared(path: &str) -> bool {
+    !path.starts_with("tlpkg/") && !path.starts_with("texmf-dist/doc/") && !path.starts_with("texmf-dist/source/")
+}
+
+fn same_content(a: &Path, b: &Path) -> bool {
+    match (fs::metadata(a), fs::metadata(b)) {
+        (Ok(ma), Ok(mb)) if ma.len() == mb.len() => matches!((fs::read(a), fs::read(b)), (Ok(x), Ok(y)) if x == y),
+        _ => false,
+    }
+}
+
+// Compares what the active profile has installed for `package` with the TLPDB's current
+// revision. File contents are compared against that revision's store copy, which is downloaded
+// if needed; offline, only the file lists are compared.
+pub async fn diff_remote(package: &str, tlpdb: &HashMap<String, Package>, config: &Config) -> anyhow::Result<()> {
+    let texman_dir = dirs::home_dir()
+        .ok_or_else(|| anyhow::anyhow!("Cannot find home directory"))?
+        .join(".texman");
+    let pkg = tlpdb.get(package).ok_or_else(|| anyhow::anyhow!("Package '{}' not found in TLPDB", package))?;
+    let profile = crate::active::name(&texman_dir)?;
+    let profile_dir = texman_dir.join("profiles").join(&profile);
+    let conn = crate::init_db(&texman_dir)?;
+
+    let installed_revision: Option<String> = conn
+        .query_row(
+            "SELECT revision FROM installed_packages WHERE profile = ?1 AND name = ?2",
+            params![profile, package],
+            |row| row.get(0),
+        )
+        .optional()?;
+    let Some(installed_revision) = installed_revision else {
+        println!("{} is not installed in profile '{}'; installing r{} would add {} files.", package, profile, pkg.revision, pkg.runfiles.len() + pkg.binfiles.len());
+        return Ok(());
+    };
+
+    let mut stmt = conn.prepare("SELECT path FROM profile_files WHERE profile = ?1 AND package = ?2")?;
+    let installed: BTreeSet<String> = stmt
+        .query_map(params![profile, package], |row| row.get::<_, String>(0))?
+        .collect::<Result<Vec<_>, _>>()?
+        .into_iter()
+        .filter(|path| is_compared(path))
+        .collect();
+    let remote: BTreeSet<String> = pkg
+        .runfiles
+        .iter()
+        .chain(&pkg.binfiles)
+        .map(|path| crate::tree::fold_key(path, false))
+        .collect();
+
+    let store_path = crate::store_path(&texman_dir, &pkg.name, &pkg.revision);
+    let have_store = if crate::store::ensure_expanded(&store_path)? {
+        true
+    } else if config.offline {
+        false
+    } else {
+        log::info!("Fetching {} r{} to compare file contents", pkg.name, pkg.revision);
+        crate::populate_store(&conn, &texman_dir, std::slice::from_ref(pkg), config, &[]).await?;
+        store_path.exists()
+    };
+
+    println!("{}: installed r{} in profile '{}', remote r{}", package, installed_revision, profile, pkg.revision);
+    let mut added = 0;
+    let mut removed = 0;
+    let mut changed = 0;
+    for path in remote.difference(&installed) {
+        println!("  + {}", path);
+        added += 1;
+    }
+    for path in installed.difference(&remote) {
+        println!("  - {}", path);
+        removed += 1;
+    }
+    let kept: Vec<&String> = remote.intersection(&installed).collect();
+    if have_store {
+        for path in &kept {
+            if !same_content(&profile_dir.join(path), &store_path.join(path)) {
+                println!("  ~ {}", path);
+                changed += 1;
+            }
+        }
+        println!("{} added, {} removed, {} changed, {} unchanged", added, removed, changed, kept.len() - changed);
+    } else {
+        println!(
+            "{} added, {} removed, {} in both (contents not compared: r{} is not in the store and texman is offline)",
+            added,
+            removed,
+            kept.len(),
+            pkg.revision
+        );
+    }
+    Ok(())
+}
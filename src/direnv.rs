@@ -0,0 +1,53 @@
+use std::fs;
+use std::path::Path;
+
+const HEADER: &str = "# Generated by texman direnv";
+
+fn envrc(profile: &str, profile_dir: &Path) -> String {
+    format!(
+        "{header} for profile '{profile}'\n\
+         texman_profile_dir=\"{dir}\"\n\
+         for dir in \"$texman_profile_dir\"/bin/*/; do\n    [ -d \"$dir\" ] && PATH_add \"${{dir%/}}\"\ndone\n\
+         export TEXMAN_PROFILE=\"{profile}\"\n\
+         export TEXMFROOT=\"$texman_profile_dir\"\n\
+         export TEXMFDIST=\"$texman_profile_dir/texmf-dist\"\n\
+         export TEXMFVAR=\"$texman_profile_dir/texmf-var\"\n\
+         export TEXMFCONFIG=\"$texman_profile_dir/texmf-config\"\n\
+         export TEXMFSYSVAR=\"$TEXMFVAR\"\n\
+         export TEXMFSYSCONFIG=\"$TEXMFCONFIG\"\n",
+        header = HEADER,
+        profile = profile,
+        dir = profile_dir.display()
+    )
+}
+
+// Writes `.envrc` into `dir`, or prints it. An existing `.envrc` that texman didn't generate is
+// only replaced with `force`, since it may hold the project's own settings.
+pub fn generate(profile: &str, dir: &Path, print: bool, force: bool) -> anyhow::Result<()> {
+    let texman_dir = dirs::home_dir()
+        .ok_or_else(|| anyhow::anyhow!("Cannot find home directory"))?
+        .join(".texman");
+    let profile_dir = texman_dir.join("profiles").join(profile);
+    if !profile_dir.exists() {
+        anyhow::bail!("Profile '{}' does not exist", profile);
+    }
+    let text = envrc(profile, &profile_dir);
+    if print {
+        print!("{}", text);
+        return Ok(());
+    }
+
+    let envrc_path = dir.join(".envrc");
+    if let Ok(existing) = fs::read_to_string(&envrc_path)
+        && !existing.starts_with(HEADER)
+        && !force
+    {
+        anyhow::bail!(
+            "{:?} already exists and was not generated by texman; use --print to add the snippet yourself, or --force to replace it",
+            envrc_path
+        );
+    }
+    fs::write(&envrc_path, text)?;
+    println!("Wrote {:?} for profile '{}'. Run 'direnv allow' to activate it.", envrc_path, profile);
+    Ok(())
+}
@@ -0,0 +1,185 @@
+use crate::Package;
+use rusqlite::{params, Connection};
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::fs;
+use std::path::Path;
+
+// A package's `execute` lines register its font maps and formats:
+//   execute addMap cmextra.map
+//   execute AddFormat name=pdflatex engine=pdftex patterns=language.dat options="-etex pdflatex.ini"
+// tlmgr collects them from every installed package into updmap.cfg and fmtutil.cnf, which
+// updmap and fmtutil read; without them the fonts and formats on disk are never used. texman
+// keeps the same two files in each profile.
+pub const UPDMAP_CFG: &str = "texmf-dist/web2c/updmap.cfg";
+pub const FMTUTIL_CNF: &str = "texmf-dist/web2c/fmtutil.cnf";
+
+pub struct MapLine {
+    // Map, MixedMap, or KanjiMap, as updmap.cfg spells them.
+    pub kind: &'static str,
+    pub file: String,
+}
+
+pub struct Format {
+    pub name: String,
+    pub engine: String,
+    pub patterns: Option<String>,
+    pub options: String,
+    // `mode=disabled`: listed, but commented out so fmtutil doesn't build it.
+    pub disabled: bool,
+}
+
+pub fn maps(executes: &[String]) -> Vec<MapLine> {
+    executes
+        .iter()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            let kind = match fields.next()? {
+                "addMap" => "Map",
+                "addMixedMap" => "MixedMap",
+                "addKanjiMap" => "KanjiMap",
+                _ => return None,
+            };
+            Some(MapLine { kind, file: fields.next()?.to_string() })
+        })
+        .collect()
+}
+
+// `key=value` fields, where a value may be double-quoted to hold spaces.
+fn fields(text: &str) -> HashMap<&str, &str> {
+    let mut fields = HashMap::new();
+    let mut rest = text.trim_start();
+    while let Some((key, after)) = rest.split_once('=') {
+        let (value, next) = match after.strip_prefix('"') {
+            Some(quoted) => quoted.split_once('"').unwrap_or((quoted, "")),
+            None => after.split_once(char::is_whitespace).unwrap_or((after, "")),
+        };
+        fields.insert(key.trim(), value);
+        rest = next.trim_start();
+    }
+    fields
+}
+
+pub fn formats(executes: &[String]) -> Vec<Format> {
+    executes
+        .iter()
+        .filter_map(|line| {
+            let fields = fields(line.strip_prefix("AddFormat ")?);
+            Some(Format {
+                name: fields.get("name")?.to_string(),
+                engine: fields.get("engine")?.to_string(),
+                patterns: fields.get("patterns").map(|patterns| patterns.to_string()),
+                options: fields.get("options").copied().unwrap_or_default().to_string(),
+                disabled: fields.get("mode") == Some(&"disabled"),
+            })
+        })
+        .collect()
+}
+
+// Kept per revision, so the files can be regenerated when the TLPDB isn't loaded, e.g. by `remove`.
+pub fn record(conn: &Connection, pkg: &Package) -> anyhow::Result<()> {
+    let mut stmt = conn.prepare("INSERT OR IGNORE INTO package_executes (name, revision, position, line) VALUES (?1, ?2, ?3, ?4)")?;
+    for (position, line) in pkg.executes.iter().enumerate() {
+        stmt.execute(params![pkg.name, pkg.revision, position as i64, line])?;
+    }
+    Ok(())
+}
+
+// The execute lines of every package installed in the profile, by package name. Revisions
+// installed before texman recorded them are taken from the TLPDB when it has the same revision.
+pub fn installed_executes(conn: &Connection, profile: &str, tlpdb: &HashMap<String, Package>) -> anyhow::Result<Vec<(String, Vec<String>)>> {
+    let mut stmt = conn.prepare("SELECT name, revision FROM installed_packages WHERE profile = ?1 ORDER BY name")?;
+    let installed = stmt
+        .query_map(params![profile], |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)))?
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut lines_stmt = conn.prepare("SELECT line FROM package_executes WHERE name = ?1 AND revision = ?2 ORDER BY position")?;
+    let mut executes = Vec::new();
+    for (name, revision) in installed {
+        let mut lines = lines_stmt
+            .query_map(params![name, revision], |row| row.get::<_, String>(0))?
+            .collect::<Result<Vec<_>, _>>()?;
+        if lines.is_empty()
+            && let Some(pkg) = tlpdb.get(&name).filter(|pkg| pkg.revision == revision)
+        {
+            lines = pkg.executes.clone();
+        }
+        if !lines.is_empty() {
+            executes.push((name, lines));
+        }
+    }
+    Ok(executes)
+}
+
+// The header texlive.infra ships for the generated file, or a short one of our own.
+fn header(profile_dir: &Path, header_file: &str, generated: &str) -> String {
+    fs::read_to_string(profile_dir.join("texmf-dist/web2c").join(header_file))
+        .unwrap_or_else(|_| format!("# Generated by texman from the execute lines of the installed packages.\n# {} is rewritten on every install and remove; put local changes elsewhere.\n", generated))
+}
+
+fn write_generated(conn: &Connection, profile: &str, profile_dir: &Path, rel_path: &str, text: &str) -> anyhow::Result<()> {
+    let owner: Option<String> = conn
+        .query_row("SELECT package FROM profile_files WHERE profile = ?1 AND path = ?2", params![profile, rel_path], |row| row.get(0))
+        .ok();
+    if let Some(owner) = owner {
+        log::warn!("Not regenerating {} in profile '{}': it belongs to package {}", rel_path, profile, owner);
+        return Ok(());
+    }
+    let path = profile_dir.join(rel_path);
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    if fs::read_to_string(&path).is_ok_and(|current| current == text) {
+        return Ok(());
+    }
+    fs::write(&path, text)?;
+    log::info!("Updated {}", path.display());
+    Ok(())
+}
+
+// Rewrites the profile's updmap.cfg and fmtutil.cnf from its installed packages, after they
+// were installed, updated, or removed.
+pub fn regenerate(conn: &Connection, profile: &str, profile_dir: &Path, tlpdb: &HashMap<String, Package>) -> anyhow::Result<()> {
+    let executes = installed_executes(conn, profile, tlpdb)?;
+
+    let mut updmap = String::new();
+    let mut map_count = 0;
+    for (name, lines) in &executes {
+        let maps = maps(lines);
+        if maps.is_empty() {
+            continue;
+        }
+        writeln!(updmap, "# from {}:", name)?;
+        for map in &maps {
+            writeln!(updmap, "{} {}", map.kind, map.file)?;
+        }
+        map_count += maps.len();
+    }
+
+    let mut fmtutil = String::new();
+    let mut format_count = 0;
+    for (name, lines) in &executes {
+        let formats = formats(lines);
+        if formats.is_empty() {
+            continue;
+        }
+        writeln!(fmtutil, "# from {}:", name)?;
+        for format in &formats {
+            // fmtutil.cnf columns: format, engine, hyphenation patterns (- for none), arguments.
+            let disabled = if format.disabled { "#! " } else { "" };
+            let patterns = format.patterns.as_deref().unwrap_or("-");
+            writeln!(fmtutil, "{}{} {} {} {}", disabled, format.name, format.engine, patterns, format.options)?;
+        }
+        format_count += formats.len();
+    }
+
+    // A profile that never had maps or formats gets no empty files.
+    for (rel_path, header_file, body, count) in [(UPDMAP_CFG, "updmap-hdr.cfg", updmap, map_count), (FMTUTIL_CNF, "fmtutil-hdr.cnf", fmtutil, format_count)] {
+        if count == 0 && !profile_dir.join(rel_path).exists() {
+            continue;
+        }
+        let file_name = Path::new(rel_path).file_name().unwrap_or_default().to_string_lossy();
+        let text = format!("{}{}", header(profile_dir, header_file, &file_name), body);
+        write_generated(conn, profile, profile_dir, rel_path, &text)?;
+    }
+    Ok(())
+}
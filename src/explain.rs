@@ -0,0 +1,143 @@
+use crate::config::Config;
+use crate::output::format_size;
+use crate::space::download_size;
+use crate::Package;
+use std::collections::{HashMap, VecDeque};
+
+// Length of the longest dependency chain starting at `name`, and the next package on it. The
+// resolver has already rejected cycles, so the recursion ends.
+fn longest_chain<'a>(
+    name: &'a str,
+    tlpdb: &'a HashMap<String, Package>,
+    memo: &mut HashMap<&'a str, (usize, Option<&'a str>)>,
+) -> usize {
+    if let Some((length, _)) = memo.get(name) {
+        return *length;
+    }
+    let mut best = (1, None);
+    if let Some(pkg) = tlpdb.get(name) {
+        for dep in crate::dependencies(pkg, tlpdb) {
+            let length = longest_chain(dep, tlpdb, memo) + 1;
+            if length > best.0 {
+                best = (length, Some(dep));
+            }
+        }
+    }
+    memo.insert(name, best);
+    best.0
+}
+
+// Prints how `requested` grows into the packages an install would pull in: what the collections
+// and schemes account for versus plain dependencies, the deepest chain, and where the bytes go.
+pub fn explain(requested: &[String], tlpdb: &HashMap<String, Package>, config: &Config) -> anyhow::Result<()> {
+    let mut closure = Vec::new();
+    let mut visited = Vec::new();
+    for package in requested {
+        crate::resolve_dependencies(package, tlpdb, &mut closure, &mut visited)?;
+    }
+
+    // Breadth first, so each package is credited to the shortest path that reaches it.
+    let mut parent: HashMap<&str, Option<&str>> = HashMap::new();
+    let mut queue = VecDeque::new();
+    for package in requested {
+        if parent.insert(package.as_str(), None).is_none() {
+            queue.push_back(package.as_str());
+        }
+    }
+    while let Some(name) = queue.pop_front() {
+        let Some(pkg) = tlpdb.get(name) else { continue };
+        for dep in crate::dependencies(pkg, tlpdb) {
+            if !parent.contains_key(dep) {
+                parent.insert(dep, Some(name));
+                queue.push_back(dep);
+            }
+        }
+    }
+
+    // The collection or scheme a package came in through: the nearest one on its path, itself included.
+    let grouping = |name: &'_ str| -> Option<String> {
+        let mut current = Some(name);
+        while let Some(name) = current {
+            if crate::is_grouping(name) {
+                return Some(name.to_string());
+            }
+            current = parent.get(name).copied().flatten();
+        }
+        None
+    };
+
+    let mut requested_count = 0;
+    let mut groupings = 0;
+    let mut through_groupings = 0;
+    let mut dependencies = 0;
+    let mut by_grouping: HashMap<String, (usize, u64)> = HashMap::new();
+    let mut total = 0;
+    for name in &closure {
+        let size = tlpdb.get(name).map(|pkg| download_size(pkg, config)).unwrap_or(0);
+        total += size;
+        let via = grouping(name);
+        if requested.contains(name) {
+            requested_count += 1;
+        } else if crate::is_grouping(name) {
+            groupings += 1;
+        } else if via.is_some() {
+            through_groupings += 1;
+        } else {
+            dependencies += 1;
+        }
+        if let Some(via) = via {
+            let entry = by_grouping.entry(via).or_default();
+            entry.0 += 1;
+            entry.1 += size;
+        }
+    }
+
+    println!(
+        "{} resolves to {} packages, {} to download:",
+        requested.join(", "),
+        closure.len(),
+        format_size(total)
+    );
+    println!("  {:>6}  requested", requested_count);
+    println!("  {:>6}  collections and schemes", groupings);
+    println!("  {:>6}  pulled in through collections and schemes", through_groupings);
+    println!("  {:>6}  dependencies of other packages", dependencies);
+
+    let mut memo = HashMap::new();
+    let deepest = requested
+        .iter()
+        .max_by_key(|root| longest_chain(root, tlpdb, &mut memo))
+        .map(String::as_str);
+    if let Some(root) = deepest {
+        let mut chain = vec![root];
+        while let Some((_, Some(next))) = memo.get(chain[chain.len() - 1]) {
+            chain.push(next);
+        }
+        println!("Longest dependency chain ({} packages):", chain.len());
+        println!("  {}", chain.join(" -> "));
+    }
+
+    if !by_grouping.is_empty() {
+        let mut by_grouping: Vec<(String, (usize, u64))> = by_grouping.into_iter().collect();
+        by_grouping.sort_by(|a, b| b.1.1.cmp(&a.1.1).then_with(|| a.0.cmp(&b.0)));
+        println!("Collections and schemes by what they bring in:");
+        for (name, (count, size)) in by_grouping.iter().take(10) {
+            println!("  {:<40} {:>6} packages  {:>10}", name, count, format_size(*size));
+        }
+    }
+
+    let mut largest: Vec<(&String, u64)> = closure
+        .iter()
+        .filter_map(|name| tlpdb.get(name).map(|pkg| (name, download_size(pkg, config))))
+        .filter(|(_, size)| *size > 0)
+        .collect();
+    largest.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+    if !largest.is_empty() {
+        println!("Largest packages:");
+        for (name, size) in largest.iter().take(10) {
+            let via = grouping(name).filter(|via| via != *name).map(|via| format!("  (via {})", via)).unwrap_or_default();
+            println!("  {:<40} {:>10}{}", name, format_size(*size), via);
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,368 @@
+use crate::Package;
+use std::collections::HashMap;
+
+// Grammar, loosest binding first:
+//   expr := and ("OR" and)*
+//   and  := not (["AND"] not)*
+//   not  := "NOT" not | "(" expr ")" | term
+//   term := word | field op value      op := ":" "=" "!=" ">" ">=" "<" "<="
+pub const FIELDS: &[&str] = &[
+    "name", "category", "depends", "desc", "longdesc", "file", "revision", "size", "version", "catalogue", "installed",
+];
+
+#[derive(Debug)]
+pub enum Expr {
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+    Term(Term),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Op {
+    Match,
+    Eq,
+    Ne,
+    Gt,
+    Ge,
+    Lt,
+    Le,
+}
+
+#[derive(Debug)]
+pub struct Term {
+    field: Option<String>,
+    op: Op,
+    value: String,
+    number: Option<u64>,
+}
+
+#[derive(Debug, PartialEq)]
+enum Token {
+    Open,
+    Close,
+    Word(String),
+}
+
+fn tokenize(input: &str) -> anyhow::Result<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut chars = input.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        match c {
+            c if c.is_whitespace() => {
+                chars.next();
+            }
+            '(' => {
+                chars.next();
+                tokens.push(Token::Open);
+            }
+            ')' => {
+                chars.next();
+                tokens.push(Token::Close);
+            }
+            _ => {
+                let mut word = String::new();
+                while let Some(&c) = chars.peek() {
+                    if c.is_whitespace() || c == '(' || c == ')' {
+                        break;
+                    }
+                    chars.next();
+                    if c == '"' {
+                        let mut closed = false;
+                        for c in chars.by_ref() {
+                            if c == '"' {
+                                closed = true;
+                                break;
+                            }
+                            word.push(c);
+                        }
+                        if !closed {
+                            anyhow::bail!("Unterminated quote in query");
+                        }
+                    } else {
+                        word.push(c);
+                    }
+                }
+                tokens.push(Token::Word(word));
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+pub fn parse(input: &str) -> anyhow::Result<Expr> {
+    let tokens = tokenize(input)?;
+    if tokens.is_empty() {
+        anyhow::bail!("Empty query");
+    }
+    let mut pos = 0;
+    let expr = parse_or(&tokens, &mut pos)?;
+    if pos < tokens.len() {
+        anyhow::bail!("Unexpected {:?} in query", tokens[pos]);
+    }
+    Ok(expr)
+}
+
+fn is_keyword(token: Option<&Token>, keyword: &str) -> bool {
+    matches!(token, Some(Token::Word(w)) if w == keyword)
+}
+
+fn parse_or(tokens: &[Token], pos: &mut usize) -> anyhow::Result<Expr> {
+    let mut left = parse_and(tokens, pos)?;
+    while is_keyword(tokens.get(*pos), "OR") {
+        *pos += 1;
+        let right = parse_and(tokens, pos)?;
+        left = Expr::Or(Box::new(left), Box::new(right));
+    }
+    Ok(left)
+}
+
+fn parse_and(tokens: &[Token], pos: &mut usize) -> anyhow::Result<Expr> {
+    let mut left = parse_not(tokens, pos)?;
+    loop {
+        if is_keyword(tokens.get(*pos), "AND") {
+            *pos += 1;
+        } else if matches!(tokens.get(*pos), None | Some(Token::Close)) || is_keyword(tokens.get(*pos), "OR") {
+            break;
+        }
+        let right = parse_not(tokens, pos)?;
+        left = Expr::And(Box::new(left), Box::new(right));
+    }
+    Ok(left)
+}
+
+fn parse_not(tokens: &[Token], pos: &mut usize) -> anyhow::Result<Expr> {
+    match tokens.get(*pos) {
+        Some(Token::Word(w)) if w == "NOT" => {
+            *pos += 1;
+            Ok(Expr::Not(Box::new(parse_not(tokens, pos)?)))
+        }
+        Some(Token::Open) => {
+            *pos += 1;
+            let expr = parse_or(tokens, pos)?;
+            if tokens.get(*pos) != Some(&Token::Close) {
+                anyhow::bail!("Missing ')' in query");
+            }
+            *pos += 1;
+            Ok(expr)
+        }
+        Some(Token::Word(word)) => {
+            *pos += 1;
+            Ok(Expr::Term(parse_term(word)?))
+        }
+        Some(Token::Close) => anyhow::bail!("Unexpected ')' in query"),
+        None => anyhow::bail!("Query ends where a condition was expected"),
+    }
+}
+
+fn parse_term(word: &str) -> anyhow::Result<Term> {
+    const OPS: &[(&str, Op)] = &[(">=", Op::Ge), ("<=", Op::Le), ("!=", Op::Ne), (":", Op::Match), ("=", Op::Eq), (">", Op::Gt), ("<", Op::Lt)];
+    let split = OPS
+        .iter()
+        .filter_map(|(symbol, op)| word.find(symbol).map(|i| (i, *symbol, *op)))
+        .min_by_key(|(i, symbol, _)| (*i, std::cmp::Reverse(symbol.len())));
+    let Some((index, symbol, op)) = split else {
+        return Ok(Term { field: None, op: Op::Match, value: word.to_lowercase(), number: None });
+    };
+
+    let field = word[..index].to_lowercase();
+    let value = word[index + symbol.len()..].to_string();
+    if !FIELDS.contains(&field.as_str()) {
+        anyhow::bail!("Unknown field '{}' in query; available fields: {}", field, FIELDS.join(", "));
+    }
+    let number = match field.as_str() {
+        "size" => Some(parse_size(&value)?),
+        "revision" => Some(value.parse().map_err(|_| anyhow::anyhow!("Invalid revision '{}' in query", value))?),
+        _ => {
+            if !matches!(op, Op::Match | Op::Eq | Op::Ne) {
+                anyhow::bail!("Field '{}' only supports ':', '=' and '!='", field);
+            }
+            None
+        }
+    };
+    Ok(Term { field: Some(field), op, value: value.to_lowercase(), number })
+}
+
+// Units are binary, matching how texman prints sizes: 1KB = 1024 bytes.
+fn parse_size(value: &str) -> anyhow::Result<u64> {
+    let upper = value.to_uppercase();
+    let digits_end = upper.find(|c: char| !c.is_ascii_digit() && c != '.').unwrap_or(upper.len());
+    let (number, unit) = upper.split_at(digits_end);
+    let multiplier: u64 = match unit {
+        "" | "B" => 1,
+        "K" | "KB" | "KIB" => 1 << 10,
+        "M" | "MB" | "MIB" => 1 << 20,
+        "G" | "GB" | "GIB" => 1 << 30,
+        _ => anyhow::bail!("Unknown size unit '{}' in query", unit),
+    };
+    let number: f64 = number.parse().map_err(|_| anyhow::anyhow!("Invalid size '{}' in query", value))?;
+    Ok((number * multiplier as f64) as u64)
+}
+
+impl Expr {
+    pub fn matches(&self, pkg: &Package, installed: &HashMap<String, String>) -> bool {
+        match self {
+            Expr::And(a, b) => a.matches(pkg, installed) && b.matches(pkg, installed),
+            Expr::Or(a, b) => a.matches(pkg, installed) || b.matches(pkg, installed),
+            Expr::Not(e) => !e.matches(pkg, installed),
+            Expr::Term(term) => term.matches(pkg, installed),
+        }
+    }
+
+    pub fn uses_installed(&self) -> bool {
+        match self {
+            Expr::And(a, b) | Expr::Or(a, b) => a.uses_installed() || b.uses_installed(),
+            Expr::Not(e) => e.uses_installed(),
+            Expr::Term(term) => term.field.as_deref() == Some("installed"),
+        }
+    }
+}
+
+impl Term {
+    fn matches(&self, pkg: &Package, installed: &HashMap<String, String>) -> bool {
+        let Some(field) = self.field.as_deref() else {
+            return pkg.name.to_lowercase().contains(&self.value)
+                || pkg.description.as_ref().is_some_and(|d| d.to_lowercase().contains(&self.value));
+        };
+        let matched = match field {
+            "name" => self.text(Some(&pkg.name)),
+            "category" => self.text(pkg.category.as_deref()),
+            "depends" => pkg.depends.iter().any(|d| d.eq_ignore_ascii_case(&self.value)),
+            "desc" => self.text(pkg.description.as_deref()),
+            "longdesc" => self.text(pkg.longdesc.as_deref()),
+            "file" => pkg.runfiles.iter().chain(&pkg.binfiles).any(|f| self.text(Some(f))),
+            "version" => self.text(pkg.catalogue_version.as_deref()),
+            "catalogue" => self.text(pkg.catalogue.as_deref().or(Some(&pkg.name))),
+            "installed" => {
+                let want = matches!(self.value.as_str(), "true" | "yes" | "1");
+                installed.contains_key(&pkg.name) == want
+            }
+            "revision" => return self.compare(pkg.revision.parse().ok()),
+            "size" => return self.compare(pkg.container_size),
+            _ => false,
+        };
+        if self.op == Op::Ne { !matched } else { matched }
+    }
+
+    // ':' is a substring match and '='/'!=' compare the whole value, all case-insensitively.
+    fn text(&self, value: Option<&str>) -> bool {
+        let Some(value) = value else { return false };
+        let value = value.to_lowercase();
+        match self.op {
+            Op::Match => value.contains(&self.value),
+            _ => value == self.value,
+        }
+    }
+
+    fn compare(&self, actual: Option<u64>) -> bool {
+        let (Some(actual), Some(expected)) = (actual, self.number) else { return false };
+        match self.op {
+            Op::Match | Op::Eq => actual == expected,
+            Op::Ne => actual != expected,
+            Op::Gt => actual > expected,
+            Op::Ge => actual >= expected,
+            Op::Lt => actual < expected,
+            Op::Le => actual <= expected,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ALPHA: &str = "\
+name alpha
+category Package
+revision 100
+shortdesc Alpha fonts for LaTeX
+depend beta
+containersize 20971520
+catalogue-version 1.2
+runfiles size=1
+ texmf-dist/tex/latex/alpha/alpha.sty
+";
+
+    const BETA: &str = "\
+name beta
+category Collection
+revision 5
+shortdesc Beta tools
+containersize 1024
+runfiles size=1
+ texmf-dist/tex/latex/beta/beta.sty
+";
+
+    // The fixture packages matching `query`, with alpha installed and beta not.
+    fn hits(query: &str) -> Vec<String> {
+        let expr = parse(query).unwrap();
+        let installed = HashMap::from([("alpha".to_string(), "100".to_string())]);
+        [ALPHA, BETA]
+            .iter()
+            .map(|block| crate::tlpdb::parse_package_block(block).unwrap())
+            .filter(|pkg| expr.matches(pkg, &installed))
+            .map(|pkg| pkg.name)
+            .collect()
+    }
+
+    #[test]
+    fn and_binds_tighter_than_or() {
+        let expr = parse("a OR b AND c").unwrap();
+        assert!(matches!(&expr, Expr::Or(left, right) if matches!(**left, Expr::Term(_)) && matches!(**right, Expr::And(..))));
+        assert_eq!(hits("name:alpha OR name:beta AND category:package"), ["alpha"]);
+        assert_eq!(hits("(name:alpha OR name:beta) AND category:collection"), ["beta"]);
+    }
+
+    #[test]
+    fn adjacent_terms_are_anded() {
+        assert!(matches!(parse("a b").unwrap(), Expr::And(..)));
+        assert_eq!(hits("name:a category:package"), ["alpha"]);
+        assert!(hits("name:alpha category:collection").is_empty());
+    }
+
+    #[test]
+    fn quotes_keep_spaces_in_values() {
+        assert_eq!(tokenize(r#"desc:"alpha fonts" x"#).unwrap(), [Token::Word("desc:alpha fonts".to_string()), Token::Word("x".to_string())]);
+        assert_eq!(hits(r#"desc:"alpha fonts""#), ["alpha"]);
+        assert!(parse(r#"desc:"alpha"#).is_err());
+    }
+
+    #[test]
+    fn sizes_take_binary_units() {
+        assert_eq!(parse_size("10MB").unwrap(), 10 << 20);
+        assert_eq!(parse_size("1.5k").unwrap(), 1536);
+        assert_eq!(parse_size("512").unwrap(), 512);
+        assert!(parse_size("10XB").is_err());
+        assert_eq!(hits("size>10MB"), ["alpha"]);
+        assert_eq!(hits("size<=1KB"), ["beta"]);
+    }
+
+    #[test]
+    fn not_negates_what_follows() {
+        assert_eq!(hits("NOT name:alpha"), ["beta"]);
+        assert_eq!(hits("NOT NOT name:alpha"), ["alpha"]);
+        assert_eq!(hits("NOT (name:alpha OR name:beta)"), Vec::<String>::new());
+    }
+
+    #[test]
+    fn not_equal_negates_every_field() {
+        assert_eq!(hits("name!=alpha"), ["beta"]);
+        assert_eq!(hits("category!=package"), ["beta"]);
+        assert_eq!(hits("desc!=\"beta tools\""), ["alpha"]);
+        assert_eq!(hits("depends!=beta"), ["beta"]);
+        assert_eq!(hits("file!=texmf-dist/tex/latex/beta/beta.sty"), ["alpha"]);
+        assert_eq!(hits("version!=1.2"), ["beta"]);
+        assert_eq!(hits("revision!=100"), ["beta"]);
+        assert_eq!(hits("size!=1024"), ["alpha"]);
+        assert_eq!(hits("installed!=true"), ["beta"]);
+        assert_eq!(hits("installed!=false"), ["alpha"]);
+    }
+
+    #[test]
+    fn rejects_unknown_fields_and_ordering_on_text() {
+        assert!(parse("colour:red").is_err());
+        assert!(parse("name>alpha").is_err());
+        assert!(parse("a OR").is_err());
+        assert!(parse("(a").is_err());
+    }
+}
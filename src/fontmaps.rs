@@ -0,0 +1,47 @@
+use crate::config::Config;
+use crate::{execute, tool, Package};
+use std::path::Path;
+
+// dvips, pdftex, and dvipdfmx find fonts through merged map files (`psfonts.map`, `pdftex.map`,
+// `kanjix.map`) that updmap builds from the maps updmap.cfg lists. A font package's map only
+// takes effect once updmap has run again; texman runs it against the profile's updmap.cfg and
+// writes the merged maps to `texmf-var/fonts/map/`.
+
+// updmap-sys is updmap writing to the system trees, which for a profile are its own.
+const PROGRAMS: &[(&str, &[&str])] = &[("updmap-sys", &[]), ("updmap", &["--sys"])];
+
+// Rebuilds the profile's merged maps. Fails when updmap is missing or fails.
+pub fn sync(profile_dir: &Path) -> anyhow::Result<()> {
+    if !profile_dir.join(execute::UPDMAP_CFG).exists() {
+        anyhow::bail!("No font maps are registered in {:?}; install a package that provides them, e.g. cm-super", profile_dir);
+    }
+    // texman writes ls-R itself, below, instead of updmap calling mktexlsr.
+    let args = ["--cnffile".to_string(), profile_dir.join(execute::UPDMAP_CFG).to_string_lossy().to_string(), "--nohash".to_string()];
+    log::info!("Rebuilding font maps in {:?}", profile_dir);
+    let output = {
+        let _spinner = crate::ui::spinner("Rebuilding font maps");
+        tool::run(profile_dir, PROGRAMS, &args)?
+    };
+    let Some(output) = output else {
+        anyhow::bail!("Neither updmap-sys nor updmap is installed; install texlive-scripts or put TeX Live's bin directory on PATH");
+    };
+    if !output.status.success() {
+        anyhow::bail!("Rebuilding font maps failed ({})", tool::failure(&output));
+    }
+    crate::lsr::regenerate(profile_dir)
+}
+
+// After an install or update that brought map files: rebuilds the merged maps, so the new fonts
+// show up in dvips and pdftex output right away. Failures are warnings; the packages are
+// installed either way.
+pub fn after_change(config: &Config, profile_dir: &Path, packages: &[Package]) {
+    if !config.sync_fontmaps || crate::platform::is_foreign() {
+        return;
+    }
+    if packages.iter().all(|pkg| execute::maps(&pkg.executes).is_empty()) {
+        return;
+    }
+    if let Err(e) = sync(profile_dir) {
+        log::warn!("{}; retry with 'texman fontmaps sync'", e);
+    }
+}
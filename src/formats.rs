@@ -0,0 +1,94 @@
+use crate::config::Config;
+use crate::{execute, tool, Package};
+use rusqlite::Connection;
+use std::collections::{BTreeSet, HashMap};
+use std::path::Path;
+
+// Formats (`pdflatex.fmt` and the like) are memory dumps an engine loads before the document, so
+// `pdflatex` doesn't work until fmtutil has built them. texman runs fmtutil against the
+// profile's own trees and fmtutil.cnf, and writes the dumps to `texmf-var/web2c/<engine>/`.
+
+// fmtutil-sys is fmtutil writing to the system trees, which for a profile are its own.
+const PROGRAMS: &[(&str, &[&str])] = &[("fmtutil-sys", &[]), ("fmtutil", &["--sys"])];
+
+// Builds `formats` in the profile, or every format in its fmtutil.cnf when empty. Fails when
+// fmtutil is missing or a format doesn't build.
+pub fn rebuild(profile_dir: &Path, formats: &[String]) -> anyhow::Result<()> {
+    if !profile_dir.join(execute::FMTUTIL_CNF).exists() {
+        anyhow::bail!("No formats are registered in {:?}; install a package that provides them, e.g. latex-bin", profile_dir);
+    }
+    // fmtutil takes one --byfmt at a time.
+    let targets: Vec<Option<&String>> = if formats.is_empty() { vec![None] } else { formats.iter().map(Some).collect() };
+    let mut failed = Vec::new();
+    for format in targets {
+        let target = format.map_or("all formats", String::as_str);
+        let mut args = vec![
+            "--cnffile".to_string(),
+            profile_dir.join(execute::FMTUTIL_CNF).to_string_lossy().to_string(),
+            "--fmtdir".to_string(),
+            profile_dir.join("texmf-var/web2c").to_string_lossy().to_string(),
+        ];
+        match format {
+            Some(format) => args.extend(["--byfmt".to_string(), format.clone()]),
+            None => args.push("--all".to_string()),
+        }
+        log::info!("Building {}", target);
+        let _spinner = crate::ui::spinner(&format!("Building {}", target));
+        let Some(output) = tool::run(profile_dir, PROGRAMS, &args)? else {
+            anyhow::bail!("Neither fmtutil-sys nor fmtutil is installed; install texlive-scripts or put TeX Live's bin directory on PATH");
+        };
+        if !output.status.success() {
+            // e.g. "alphatex (exit status: 1: fmtutil [ERROR]: failed to build: alphatex.fmt)"
+            failed.push(format!("{} ({})", target, tool::failure(&output)));
+        }
+    }
+    if !failed.is_empty() {
+        anyhow::bail!("Building formats failed: {}", failed.join("; "));
+    }
+    Ok(())
+}
+
+// The formats a change to `packages` makes stale: the ones they register, the ones run by an
+// engine among their binaries, and all of them when hyphenation patterns changed, since every
+// format built with patterns embeds them.
+fn affected(installed: &[execute::Format], packages: &[Package]) -> Vec<String> {
+    let mut names = BTreeSet::new();
+    for pkg in packages {
+        if pkg.executes.iter().any(|line| line.starts_with("AddHyphen ")) {
+            names.extend(installed.iter().filter(|format| format.patterns.is_some()).map(|format| format.name.clone()));
+            continue;
+        }
+        names.extend(execute::formats(&pkg.executes).into_iter().filter(|format| !format.disabled).map(|format| format.name));
+        let programs: Vec<&str> = pkg
+            .binfiles
+            .iter()
+            .filter_map(|file| Path::new(file).file_name().and_then(|name| name.to_str()))
+            .map(|name| name.strip_suffix(".exe").unwrap_or(name))
+            .collect();
+        names.extend(installed.iter().filter(|format| programs.contains(&format.engine.as_str())).map(|format| format.name.clone()));
+    }
+    // Only formats the profile still has; a removed package's are gone from fmtutil.cnf.
+    names.into_iter().filter(|name| installed.iter().any(|format| &format.name == name && !format.disabled)).collect()
+}
+
+// After an install or update: rebuilds the formats it affected, so e.g. `pdflatex` works right
+// after `texman install latex`. Failures are warnings; the packages are installed either way.
+pub fn after_change(conn: &Connection, config: &Config, profile: &str, profile_dir: &Path, tlpdb: &HashMap<String, Package>, packages: &[Package]) {
+    if !config.build_formats || crate::platform::is_foreign() {
+        return;
+    }
+    let installed: Vec<execute::Format> = match execute::installed_executes(conn, profile, tlpdb) {
+        Ok(executes) => executes.iter().flat_map(|(_, lines)| execute::formats(lines)).collect(),
+        Err(e) => {
+            log::warn!("Cannot read the formats of profile '{}': {}", profile, e);
+            return;
+        }
+    };
+    let formats = affected(&installed, packages);
+    if formats.is_empty() {
+        return;
+    }
+    if let Err(e) = rebuild(profile_dir, &formats) {
+        log::warn!("{}; retry with 'texman formats rebuild'", e);
+    }
+}
@@ -0,0 +1,160 @@
+use crate::config::Config;
+use crate::journal::{self, Entry, Step, Target};
+use crate::Package;
+use chrono::{DateTime, Utc};
+use rusqlite::{params, OptionalExtension};
+use std::collections::HashMap;
+
+// `texman history` lists what the journal recorded, newest first; `texman undo` reverts one
+// transaction by putting its packages back to the revisions they had before.
+
+fn format_time(timestamp: i64) -> String {
+    DateTime::<Utc>::from_timestamp(timestamp, 0)
+        .map(|time| time.format("%Y-%m-%d %H:%M:%S UTC").to_string())
+        .unwrap_or_default()
+}
+
+// In the notation of the summary after each command: +added, -removed, ~updated.
+fn change(step: &Step) -> Option<String> {
+    match (&step.previous, &step.revision) {
+        (None, Some(revision)) => Some(format!("+{} r{}", step.name, revision)),
+        (Some(previous), None) => Some(format!("-{} r{}", step.name, previous)),
+        (Some(previous), Some(revision)) if previous != revision => Some(format!("~{} r{} -> r{}", step.name, previous, revision)),
+        _ => None,
+    }
+}
+
+fn state_note(entry: &Entry, texman_dir: &std::path::Path) -> String {
+    match entry.state.as_str() {
+        "committed" => String::new(),
+        "pending" if journal::is_running(texman_dir, &entry.profile) => " [running]".to_string(),
+        "pending" => " [interrupted]".to_string(),
+        state => format!(" [{}]", state),
+    }
+}
+
+pub fn show(id: Option<i64>, profile: Option<&str>) -> anyhow::Result<()> {
+    let texman_dir = dirs::home_dir()
+        .ok_or_else(|| anyhow::anyhow!("Cannot find home directory"))?
+        .join(".texman");
+    let conn = crate::init_db(&texman_dir)?;
+
+    if let Some(id) = id {
+        let entry = journal::load(&conn, id)?.ok_or_else(|| anyhow::anyhow!("No transaction #{} in the history", id))?;
+        println!(
+            "#{} {} of {} in profile '{}', {}{}",
+            entry.id,
+            entry.action,
+            entry.requested,
+            entry.profile,
+            format_time(entry.started_at),
+            state_note(&entry, &texman_dir)
+        );
+        for step in &entry.steps {
+            if let Some(change) = change(step) {
+                let applied = if step.committed { "" } else { " (not applied)" };
+                println!("  {}{}", change, applied);
+            }
+        }
+        return Ok(());
+    }
+
+    let mut stmt = conn.prepare("SELECT id FROM journal WHERE ?1 IS NULL OR profile = ?1 ORDER BY id DESC")?;
+    let ids = stmt.query_map(params![profile], |row| row.get::<_, i64>(0))?.collect::<Result<Vec<_>, _>>()?;
+    if ids.is_empty() {
+        println!("No transactions recorded yet.");
+        return Ok(());
+    }
+    for id in ids {
+        let Some(entry) = journal::load(&conn, id)? else { continue };
+        let changes = entry.steps.iter().filter(|step| step.committed && change(step).is_some()).count();
+        println!(
+            "#{:<5} {}  {:<7} {:<12} {} ({} changed){}",
+            entry.id,
+            format_time(entry.started_at),
+            entry.action,
+            entry.profile,
+            entry.requested,
+            changes,
+            state_note(&entry, &texman_dir)
+        );
+    }
+    println!();
+    println!("Show a transaction's packages with 'texman history <ID>', revert it with 'texman undo <ID>'.");
+    Ok(())
+}
+
+// Reverts transaction `id`, or the latest finished one. Packages changed again by a later
+// transaction are left alone. The undo is journaled like any other change, so it can be
+// resumed, rolled back, or undone in turn.
+pub async fn undo(id: Option<i64>, tlpdb: &HashMap<String, Package>, config: &Config) -> anyhow::Result<()> {
+    let texman_dir = dirs::home_dir()
+        .ok_or_else(|| anyhow::anyhow!("Cannot find home directory"))?
+        .join(".texman");
+    let conn = crate::init_db(&texman_dir)?;
+
+    if let Some(pending) = journal::latest_pending(&conn)? {
+        anyhow::bail!(
+            "The {} of {} in profile '{}' is unfinished; finish it with 'texman resume' or undo it with 'texman resume --rollback' first",
+            pending.action,
+            pending.requested,
+            pending.profile
+        );
+    }
+    let id = match id {
+        Some(id) => id,
+        None => {
+            let latest: Option<i64> = conn
+                .query_row("SELECT id FROM journal WHERE state = 'committed' ORDER BY id DESC LIMIT 1", [], |row| row.get(0))
+                .optional()?;
+            let Some(latest) = latest else {
+                println!("Nothing to undo.");
+                return Ok(());
+            };
+            latest
+        }
+    };
+    let entry = journal::load(&conn, id)?.ok_or_else(|| anyhow::anyhow!("No transaction #{} in the history", id))?;
+    if entry.state != "committed" {
+        anyhow::bail!("Transaction #{} is {}; only finished transactions can be undone", id, entry.state);
+    }
+
+    let _locks = crate::lock_profiles(&texman_dir, &[&entry.profile]).await?;
+    let target = Target::new(&conn, &texman_dir, config, &entry.profile)?;
+    let mut steps = Vec::new();
+    for step in entry.steps.iter().rev().filter(|step| step.committed && change(step).is_some()) {
+        let current = target.installed(&step.name)?;
+        if current != step.revision {
+            let now = current.map_or("not installed".to_string(), |revision| format!("now r{}", revision));
+            println!("Leaving {} alone: a later transaction changed it ({})", step.name, now);
+            continue;
+        }
+        steps.push(step);
+    }
+    if steps.is_empty() {
+        println!("Nothing of transaction #{} is left to undo.", id);
+        return Ok(());
+    }
+    // Every earlier revision is made available first, so a missing one stops the undo before
+    // it changed anything.
+    for step in &steps {
+        if let Some(previous) = &step.previous {
+            crate::restore_revision(&conn, &texman_dir, config, &step.name, previous, journal::template(step, tlpdb))
+                .map_err(|e| anyhow::anyhow!("Cannot undo #{}: {}", id, e))?;
+        }
+    }
+
+    println!("Undoing #{}: {} of {} in profile '{}'", id, entry.action, entry.requested, entry.profile);
+    let transaction = crate::summary::Transaction::begin("undo", &entry.profile, &texman_dir)?;
+    let journal_steps: Vec<(&str, Option<&str>, Option<&Package>)> = steps.iter().map(|step| (step.name.as_str(), step.previous.as_deref(), None)).collect();
+    let undo_id = journal::begin(&conn, "undo", &entry.profile, &[format!("#{}", id)], entry.policy, &journal_steps)?;
+    for step in &steps {
+        target.set_revision(&step.name, step.previous.as_deref(), journal::template(step, tlpdb))?;
+        journal::commit(&conn, undo_id, &step.name)?;
+    }
+    let packages: Vec<Package> = steps.iter().filter_map(|step| journal::template(step, tlpdb).cloned()).collect();
+    target.refresh(tlpdb, &packages)?;
+    journal::finish(&conn, undo_id)?;
+    journal::set_state(&conn, id, "undone")?;
+    transaction.finish(config, tlpdb)
+}
@@ -0,0 +1,37 @@
+use rusqlite::{params, Connection, OptionalExtension};
+use std::collections::HashSet;
+use std::path::Path;
+
+// `texman pin <pkg>` without `--repo` holds a package of the active profile at its installed
+// revision: `update` leaves it alone and says so, until `texman unpin <pkg>`. Holds are per
+// profile, unlike repository pins (`--repo`), since each profile has its own revisions.
+
+pub fn held(conn: &Connection, profile: &str) -> anyhow::Result<HashSet<String>> {
+    let mut stmt = conn.prepare("SELECT name FROM package_holds WHERE profile = ?1")?;
+    let names = stmt.query_map(params![profile], |row| row.get(0))?.collect::<Result<_, _>>()?;
+    Ok(names)
+}
+
+pub fn pin(texman_dir: &Path, package: &str) -> anyhow::Result<()> {
+    let conn = crate::init_db(texman_dir)?;
+    let profile = crate::active::name(texman_dir)?;
+    let revision: Option<String> = conn
+        .query_row("SELECT revision FROM installed_packages WHERE profile = ?1 AND name = ?2", params![profile, package], |row| row.get(0))
+        .optional()?;
+    let Some(revision) = revision else {
+        anyhow::bail!("Package '{}' is not installed in profile '{}'", package, profile);
+    };
+    conn.execute("INSERT OR IGNORE INTO package_holds (profile, name) VALUES (?1, ?2)", params![profile, package])?;
+    println!("Pinned {} at r{} in profile '{}'; 'texman update' leaves it alone until 'texman unpin {}'", package, revision, profile, package);
+    Ok(())
+}
+
+pub fn unpin(texman_dir: &Path, package: &str) -> anyhow::Result<()> {
+    let conn = crate::init_db(texman_dir)?;
+    let profile = crate::active::name(texman_dir)?;
+    if conn.execute("DELETE FROM package_holds WHERE profile = ?1 AND name = ?2", params![profile, package])? == 0 {
+        anyhow::bail!("{} is not pinned in profile '{}'; remove a repository pin with 'texman unpin {} --repo'", package, profile, package);
+    }
+    println!("Unpinned {} in profile '{}'; 'texman update' updates it again", package, profile);
+    Ok(())
+}
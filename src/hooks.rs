@@ -0,0 +1,252 @@
+use crate::output::{format_size, SchemaVersion};
+use indicatif::ProgressBar;
+use serde::Serialize;
+use std::future::Future;
+use std::io::{IsTerminal, Write};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+// Downloads report progress and the installer asks its questions through these traits instead of
+// driving indicatif and stdin directly, so another frontend only needs its own implementations.
+pub trait ProgressObserver: Send + Sync {
+    // `done` is nonzero when a download resumes part way through.
+    fn start(&self, label: &str, total: Option<u64>, done: u64) -> Box<dyn Progress>;
+}
+
+pub trait Progress: Send {
+    fn advance(&self, bytes: u64);
+    fn finish(&self);
+    // The transfer broke off and may be retried under a new `start`.
+    fn abandon(&self);
+}
+
+pub trait Prompter: Send + Sync {
+    fn confirm(&self, question: &str, default: bool) -> anyhow::Result<bool>;
+}
+
+struct TerminalObserver;
+
+impl ProgressObserver for TerminalObserver {
+    fn start(&self, label: &str, total: Option<u64>, done: u64) -> Box<dyn Progress> {
+        Box::new(TerminalProgress(crate::ui::download_bar(label, total, done)))
+    }
+}
+
+struct TerminalProgress(ProgressBar);
+
+impl Progress for TerminalProgress {
+    fn advance(&self, bytes: u64) {
+        self.0.inc(bytes);
+    }
+
+    fn finish(&self) {
+        self.0.finish();
+    }
+
+    fn abandon(&self) {
+        self.0.abandon();
+    }
+}
+
+// Without a terminal (CI logs, pipes), bars would only add control characters; a line per
+// finished download is enough.
+struct LogObserver;
+
+impl ProgressObserver for LogObserver {
+    fn start(&self, label: &str, _total: Option<u64>, done: u64) -> Box<dyn Progress> {
+        Box::new(LogProgress { label: label.to_string(), bytes: AtomicU64::new(done) })
+    }
+}
+
+struct LogProgress {
+    label: String,
+    bytes: AtomicU64,
+}
+
+impl Progress for LogProgress {
+    fn advance(&self, bytes: u64) {
+        self.bytes.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    fn finish(&self) {
+        log::info!("Downloaded {} ({})", self.label, format_size(self.bytes.load(Ordering::Relaxed)));
+    }
+
+    fn abandon(&self) {}
+}
+
+// `--json-progress`: one JSON object per line on stderr, for editors and dashboards that show
+// their own progress.
+struct JsonObserver;
+
+const JSON_PROGRESS_INTERVAL: Duration = Duration::from_millis(250);
+
+tokio::task_local! {
+    static REQUEST: serde_json::Value;
+}
+
+// Runs `f` on behalf of the `query serve` request `id`, so the progress of its downloads is
+// reported to whoever sent it.
+pub async fn in_request<F: Future>(id: Option<serde_json::Value>, f: F) -> F::Output {
+    match id {
+        Some(id) => REQUEST.scope(id, f).await,
+        None => f.await,
+    }
+}
+
+// The request the current task works for; spawned tasks must be put back into it.
+pub fn request() -> Option<serde_json::Value> {
+    REQUEST.try_with(serde_json::Value::clone).ok()
+}
+
+// A `query serve` request the client may cancel. Awaits are dropped as soon as it is cancelled;
+// unpacking and linking don't await, so they check between packages instead.
+#[derive(Default)]
+pub struct Cancel {
+    cancelled: AtomicBool,
+    notify: tokio::sync::Notify,
+}
+
+impl Cancel {
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+        self.notify.notify_one();
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+
+    pub async fn cancelled(&self) {
+        while !self.is_cancelled() {
+            self.notify.notified().await;
+        }
+    }
+}
+
+tokio::task_local! {
+    static CANCEL: Arc<Cancel>;
+}
+
+pub async fn cancellable<F: Future>(cancel: Arc<Cancel>, f: F) -> F::Output {
+    CANCEL.scope(cancel, f).await
+}
+
+// Fails once the request the current task works for is cancelled; outside `query serve` never.
+pub fn check_cancelled() -> anyhow::Result<()> {
+    if CANCEL.try_with(|cancel| cancel.is_cancelled()).unwrap_or(false) {
+        anyhow::bail!("Cancelled");
+    }
+    Ok(())
+}
+
+#[derive(Serialize)]
+struct JsonEvent<'a> {
+    schema_version: SchemaVersion,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    id: Option<&'a serde_json::Value>,
+    event: &'a str,
+    label: &'a str,
+    total: Option<u64>,
+    done: u64,
+}
+
+impl ProgressObserver for JsonObserver {
+    fn start(&self, label: &str, total: Option<u64>, done: u64) -> Box<dyn Progress> {
+        let progress = JsonProgress {
+            request: request(),
+            label: label.to_string(),
+            total,
+            done: AtomicU64::new(done),
+            last_event: Mutex::new(Instant::now()),
+        };
+        progress.emit("start");
+        Box::new(progress)
+    }
+}
+
+struct JsonProgress {
+    request: Option<serde_json::Value>,
+    label: String,
+    total: Option<u64>,
+    done: AtomicU64,
+    last_event: Mutex<Instant>,
+}
+
+impl JsonProgress {
+    fn emit(&self, event: &str) {
+        let event = JsonEvent {
+            schema_version: SchemaVersion,
+            id: self.request.as_ref(),
+            event,
+            label: &self.label,
+            total: self.total,
+            done: self.done.load(Ordering::Relaxed),
+        };
+        let Ok(line) = serde_json::to_string(&event) else { return };
+        // A request's progress goes to its sender, interleaved with the responses on stdout.
+        if self.request.is_some() {
+            let _ = writeln!(std::io::stdout().lock(), "{}", line);
+        } else {
+            let _ = writeln!(std::io::stderr().lock(), "{}", line);
+        }
+    }
+}
+
+impl Progress for JsonProgress {
+    fn advance(&self, bytes: u64) {
+        self.done.fetch_add(bytes, Ordering::Relaxed);
+        let mut last_event = self.last_event.lock().unwrap_or_else(|e| e.into_inner());
+        if last_event.elapsed() >= JSON_PROGRESS_INTERVAL {
+            *last_event = Instant::now();
+            self.emit("progress");
+        }
+    }
+
+    fn finish(&self) {
+        self.emit("finish");
+    }
+
+    fn abandon(&self) {
+        self.emit("abandon");
+    }
+}
+
+struct TerminalPrompter;
+
+impl Prompter for TerminalPrompter {
+    fn confirm(&self, question: &str, default: bool) -> anyhow::Result<bool> {
+        crate::ui::suspend(|| crate::prompt::ask_yes_no(question, default))
+    }
+}
+
+// Nobody can answer, so every question takes its default.
+struct DefaultPrompter;
+
+impl Prompter for DefaultPrompter {
+    fn confirm(&self, question: &str, default: bool) -> anyhow::Result<bool> {
+        log::info!("{} Answering '{}' (not a terminal)", question, if default { "yes" } else { "no" });
+        Ok(default)
+    }
+}
+
+static OBSERVER: OnceLock<Box<dyn ProgressObserver>> = OnceLock::new();
+static PROMPTER: OnceLock<Box<dyn Prompter>> = OnceLock::new();
+
+pub fn observer() -> &'static dyn ProgressObserver {
+    OBSERVER
+        .get_or_init(|| if crate::ui::is_interactive() { Box::new(TerminalObserver) } else { Box::new(LogObserver) })
+        .as_ref()
+}
+
+// Must run before the first download; later calls have no effect.
+pub fn use_json_progress() {
+    let _ = OBSERVER.set(Box::new(JsonObserver));
+}
+
+pub fn prompter() -> &'static dyn Prompter {
+    PROMPTER
+        .get_or_init(|| if std::io::stdin().is_terminal() { Box::new(TerminalPrompter) } else { Box::new(DefaultPrompter) })
+        .as_ref()
+}
@@ -0,0 +1,324 @@
+use crate::config::{Config, ConflictPolicy, InstallStrategy};
+use crate::Package;
+use rusqlite::{params, Connection, OptionalExtension};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+// Installs, updates, removes, rollbacks, and undos write their package list here before touching a
+// profile, with the revision each package had before, and mark each package once it is linked
+// and recorded. An interrupted run can then be finished, or undone back to the previous
+// revisions; finished ones stay as the profile's history.
+const ACTIONS: [&str; 5] = ["install", "update", "remove", "rollback", "undo"];
+
+pub struct Step {
+    pub name: String,
+    // The revision the transaction installs; None when it removes the package.
+    pub revision: Option<String>,
+    // The revision installed before the transaction, if any.
+    pub previous: Option<String>,
+    // What to download and install, for installs and updates.
+    pub package: Option<Package>,
+    pub committed: bool,
+}
+
+pub struct Entry {
+    pub id: i64,
+    pub action: &'static str,
+    pub profile: String,
+    pub requested: String,
+    pub policy: ConflictPolicy,
+    // pending, committed, rolled back, or undone.
+    pub state: String,
+    pub started_at: i64,
+    pub steps: Vec<Step>,
+}
+
+impl Entry {
+    fn remaining(&self) -> impl Iterator<Item = &Step> {
+        self.steps.iter().filter(|step| !step.committed)
+    }
+}
+
+// `steps` are (name, revision to install or None to remove, package to install) in the order
+// they are applied.
+pub fn begin(
+    conn: &Connection,
+    action: &'static str,
+    profile: &str,
+    requested: &[String],
+    policy: ConflictPolicy,
+    steps: &[(&str, Option<&str>, Option<&Package>)],
+) -> anyhow::Result<i64> {
+    conn.execute(
+        "INSERT INTO journal (action, profile, requested, on_conflict) VALUES (?1, ?2, ?3, ?4)",
+        params![action, profile, requested.join(" "), policy.as_str()],
+    )?;
+    let id = conn.last_insert_rowid();
+    for (name, revision, package) in steps {
+        let previous: Option<String> = conn
+            .query_row("SELECT revision FROM installed_packages WHERE profile = ?1 AND name = ?2", params![profile, name], |row| row.get(0))
+            .optional()?;
+        // An empty revision marks a removal.
+        conn.execute(
+            "INSERT INTO journal_packages (journal_id, name, revision, previous, package) VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![id, name, revision.unwrap_or_default(), previous, serde_json::to_string(package)?],
+        )?;
+    }
+    Ok(id)
+}
+
+pub fn commit(conn: &Connection, id: i64, name: &str) -> anyhow::Result<()> {
+    conn.execute("UPDATE journal_packages SET committed = 1 WHERE journal_id = ?1 AND name = ?2", params![id, name])?;
+    Ok(())
+}
+
+pub fn finish(conn: &Connection, id: i64) -> anyhow::Result<()> {
+    set_state(conn, id, "committed")
+}
+
+pub fn set_state(conn: &Connection, id: i64, state: &str) -> anyhow::Result<()> {
+    conn.execute("UPDATE journal SET state = ?2, finished_at = strftime('%s', 'now') WHERE id = ?1", params![id, state])?;
+    Ok(())
+}
+
+pub fn load(conn: &Connection, id: i64) -> anyhow::Result<Option<Entry>> {
+    let Some((action, profile, requested, policy, state, started_at)) = conn
+        .query_row(
+            "SELECT action, profile, requested, on_conflict, state, started_at FROM journal WHERE id = ?1",
+            params![id],
+            |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, String>(2)?,
+                    row.get::<_, String>(3)?,
+                    row.get::<_, String>(4)?,
+                    row.get::<_, i64>(5)?,
+                ))
+            },
+        )
+        .optional()?
+    else {
+        return Ok(None);
+    };
+    let action = ACTIONS.into_iter().find(|known| *known == action).ok_or_else(|| anyhow::anyhow!("Unknown journal action '{}'", action))?;
+
+    let mut stmt = conn.prepare("SELECT name, revision, previous, package, committed FROM journal_packages WHERE journal_id = ?1 ORDER BY rowid")?;
+    let rows = stmt
+        .query_map(params![id], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?, row.get::<_, Option<String>>(2)?, row.get::<_, String>(3)?, row.get::<_, bool>(4)?))
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut steps = Vec::new();
+    for (name, revision, previous, package, committed) in rows {
+        let revision = Some(revision).filter(|revision| !revision.is_empty() && action != "remove");
+        steps.push(Step { name, revision, previous, package: serde_json::from_str(&package)?, committed });
+    }
+    Ok(Some(Entry { id, action, profile, requested, policy: ConflictPolicy::parse(&policy)?, state, started_at, steps }))
+}
+
+pub fn latest_pending(conn: &Connection) -> anyhow::Result<Option<Entry>> {
+    let id: Option<i64> = conn
+        .query_row("SELECT id FROM journal WHERE state = 'pending' ORDER BY id DESC LIMIT 1", [], |row| row.get(0))
+        .optional()?;
+    match id {
+        Some(id) => load(conn, id),
+        None => Ok(None),
+    }
+}
+
+// Whether a texman process is working on the profile right now, so its pending transaction is
+// running rather than interrupted.
+pub fn is_running(texman_dir: &Path, profile: &str) -> bool {
+    crate::cache::is_locked(&crate::profile_lock(texman_dir, profile))
+}
+
+// A transaction left pending while no texman process holds its profile, described with how to
+// settle it; for the next command to warn about and `texman doctor` to report.
+pub fn interrupted(texman_dir: &Path) -> anyhow::Result<Option<String>> {
+    if !texman_dir.join("db").join("texman.sqlite").exists() {
+        return Ok(None);
+    }
+    let conn = crate::init_db(texman_dir)?;
+    let Some(pending) = latest_pending(&conn)? else {
+        return Ok(None);
+    };
+    if is_running(texman_dir, &pending.profile) {
+        return Ok(None);
+    }
+    Ok(Some(format!(
+        "An interrupted {} of {} in profile '{}' is unfinished ({} of {} packages done); run 'texman resume' to finish it or 'texman resume --rollback' to undo it",
+        pending.action,
+        pending.requested,
+        pending.profile,
+        pending.steps.len() - pending.remaining().count(),
+        pending.steps.len()
+    )))
+}
+
+// The pending transaction, once any process still running it has let go of its profile; by
+// then it is either finished or really interrupted.
+async fn lock_pending(conn: &Connection, texman_dir: &Path) -> anyhow::Result<Option<(Entry, Vec<std::fs::File>)>> {
+    let Some(pending) = latest_pending(conn)? else {
+        return Ok(None);
+    };
+    let locks = crate::lock_profiles(texman_dir, &[&pending.profile]).await?;
+    Ok(latest_pending(conn)?.filter(|latest| latest.id == pending.id).map(|pending| (pending, locks)))
+}
+
+// A profile that packages are put back to given revisions in, by rollbacks and undo. Files
+// are linked under the profile's conflict policy, as the transaction being reverted may have
+// been a removal, which has none.
+pub struct Target<'a> {
+    conn: &'a Connection,
+    texman_dir: &'a Path,
+    config: &'a Config,
+    profile: &'a str,
+    profile_dir: PathBuf,
+    policy: ConflictPolicy,
+    strategy: InstallStrategy,
+}
+
+impl<'a> Target<'a> {
+    pub fn new(conn: &'a Connection, texman_dir: &'a Path, config: &'a Config, profile: &'a str) -> anyhow::Result<Target<'a>> {
+        Ok(Target {
+            conn,
+            texman_dir,
+            config,
+            profile,
+            profile_dir: texman_dir.join("profiles").join(profile),
+            policy: crate::conflict_policy(conn, profile, config, None)?,
+            strategy: crate::install_strategy(conn, profile, config)?,
+        })
+    }
+
+    pub fn installed(&self, name: &str) -> anyhow::Result<Option<String>> {
+        Ok(self
+            .conn
+            .query_row("SELECT revision FROM installed_packages WHERE profile = ?1 AND name = ?2", params![self.profile, name], |row| row.get(0))
+            .optional()?)
+    }
+
+    // Links `revision` of the package into the profile in place of what it has now, or removes
+    // the package when None. `template` is another revision of it, in case this one has to be
+    // unpacked again from cached archives.
+    pub fn set_revision(&self, name: &str, revision: Option<&str>, template: Option<&Package>) -> anyhow::Result<()> {
+        let Some(revision) = revision else {
+            crate::uninstall(self.conn, self.profile, &self.profile_dir, name)?;
+            println!("Removed {}", name);
+            return Ok(());
+        };
+        let store_path = crate::restore_revision(self.conn, self.texman_dir, self.config, name, revision, template)
+            .map_err(|e| anyhow::anyhow!("Cannot restore {}: {}; reinstall it with 'texman install {}'", name, e, name))?;
+        crate::tree::link_package(self.conn, self.profile, &self.profile_dir, name, &store_path, self.policy, self.strategy)?;
+        self.conn.execute(
+            "INSERT OR REPLACE INTO installed_packages (profile, name, revision) VALUES (?1, ?2, ?3)",
+            params![self.profile, name, revision],
+        )?;
+        println!("Restored {} r{}", name, revision);
+        Ok(())
+    }
+
+    // Regenerates what depends on the set of installed packages, after `packages` changed.
+    pub fn refresh(&self, tlpdb: &HashMap<String, Package>, packages: &[Package]) -> anyhow::Result<()> {
+        crate::tree::refresh(self.conn, self.profile, &self.profile_dir, tlpdb)?;
+        crate::formats::after_change(self.conn, self.config, self.profile, &self.profile_dir, tlpdb, packages);
+        crate::fontmaps::after_change(self.config, &self.profile_dir, packages);
+        Ok(())
+    }
+}
+
+// The package a step is about, from the journal or else the TLPDB.
+pub fn template<'a>(step: &'a Step, tlpdb: &'a HashMap<String, Package>) -> Option<&'a Package> {
+    step.package.as_ref().or_else(|| tlpdb.get(&step.name))
+}
+
+// The transaction an undo undid, from its `#<id>`.
+fn undone_id(entry: &Entry) -> Option<i64> {
+    if entry.action != "undo" {
+        return None;
+    }
+    entry.requested.strip_prefix('#')?.parse().ok()
+}
+
+pub async fn resume(tlpdb: &HashMap<String, Package>, config: &Config, destdir: Option<&Path>) -> anyhow::Result<()> {
+    let texman_dir = crate::texman_root(destdir)?;
+    let conn = crate::init_db(&texman_dir)?;
+    let Some((pending, _locks)) = lock_pending(&conn, &texman_dir).await? else {
+        println!("No interrupted transaction to resume.");
+        return Ok(());
+    };
+
+    println!(
+        "Resuming {} of {} in profile '{}': {} packages done, {} remaining",
+        pending.action,
+        pending.requested,
+        pending.profile,
+        pending.steps.len() - pending.remaining().count(),
+        pending.remaining().count()
+    );
+    let transaction = crate::summary::Transaction::begin(pending.action, &pending.profile, &texman_dir)?;
+    // These only relink or unlink what the store has.
+    if matches!(pending.action, "remove" | "rollback" | "undo") {
+        let target = Target::new(&conn, &texman_dir, config, &pending.profile)?;
+        for step in pending.remaining() {
+            target.set_revision(&step.name, step.revision.as_deref(), template(step, tlpdb))?;
+            commit(&conn, pending.id, &step.name)?;
+        }
+        let packages: Vec<Package> = pending.steps.iter().filter_map(|step| template(step, tlpdb).cloned()).collect();
+        target.refresh(tlpdb, &packages)?;
+        finish(&conn, pending.id)?;
+        if let Some(undone) = undone_id(&pending) {
+            set_state(&conn, undone, "undone")?;
+        }
+        return transaction.finish(config, tlpdb);
+    }
+
+    // The journal holds the packages as resolved, so nothing is resolved again; metadata such as
+    // checksums is still taken from the current TLPDB when it lists the same revision.
+    let remaining: Vec<Package> = pending
+        .remaining()
+        .filter_map(|step| step.package.clone())
+        .map(|pkg| match tlpdb.get(&pkg.name) {
+            Some(current) if current.revision == pkg.revision => current.clone(),
+            _ => pkg,
+        })
+        .collect();
+    let failed = crate::install_resolved(&conn, &texman_dir, &pending.profile, &remaining, config, pending.policy, pending.id).await?;
+    let profile_dir = texman_dir.join("profiles").join(&pending.profile);
+    crate::tree::refresh(&conn, &pending.profile, &profile_dir, tlpdb)?;
+    crate::formats::after_change(&conn, config, &pending.profile, &profile_dir, tlpdb, &remaining);
+    crate::fontmaps::after_change(config, &profile_dir, &remaining);
+    // Failed packages stay uncommitted, so resuming again retries just those.
+    if failed.is_empty() {
+        finish(&conn, pending.id)?;
+    }
+    transaction.finish(config, tlpdb)?;
+    crate::report_failures(&failed, "texman resume")
+}
+
+// Puts every package the transaction touched back to the revision it had before, newest change
+// first. Earlier revisions come from the store, which keeps them until `texman clean`.
+pub async fn rollback(tlpdb: &HashMap<String, Package>, config: &Config, destdir: Option<&Path>) -> anyhow::Result<()> {
+    let texman_dir = crate::texman_root(destdir)?;
+    let conn = crate::init_db(&texman_dir)?;
+    let Some((pending, _locks)) = lock_pending(&conn, &texman_dir).await? else {
+        println!("No interrupted transaction to roll back.");
+        return Ok(());
+    };
+
+    println!("Rolling back {} of {} in profile '{}'", pending.action, pending.requested, pending.profile);
+    let target = Target::new(&conn, &texman_dir, config, &pending.profile)?;
+    let transaction = crate::summary::Transaction::begin(pending.action, &pending.profile, &texman_dir)?;
+    // Steps not marked committed may still have been applied just before the interruption, so
+    // every step is checked against what the profile has now.
+    for step in pending.steps.iter().rev() {
+        if target.installed(&step.name)? != step.previous {
+            target.set_revision(&step.name, step.previous.as_deref(), template(step, tlpdb))?;
+        }
+    }
+    let packages: Vec<Package> = pending.steps.iter().filter_map(|step| template(step, tlpdb).cloned()).collect();
+    target.refresh(tlpdb, &packages)?;
+    set_state(&conn, pending.id, "rolled back")?;
+    transaction.finish(config, tlpdb)
+}
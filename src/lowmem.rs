@@ -0,0 +1,111 @@
+use crate::config::Config;
+use crate::{cache, Package};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, BufWriter};
+use std::path::Path;
+use xz2::read::XzDecoder;
+
+// With `low_memory`, the TLPDB goes from the network to disk and from disk into the parser
+// without ever being held as a whole: the download streams to a file, decompression runs file to
+// file, and the parser reads one package block at a time.
+const BUFFER_SIZE: usize = 64 * 1024;
+
+async fn download_tlpdb(config: &Config, client: &reqwest::Client, dest: &Path) -> anyhow::Result<()> {
+    let staged = cache::staging_path(dest);
+    match crate::download_tlpdb_xz(config, client, None).await {
+        Ok(Some((compressed, _))) => {
+            let result = File::open(&compressed).and_then(|file| {
+                let mut reader = XzDecoder::new(BufReader::with_capacity(BUFFER_SIZE, file));
+                let mut writer = BufWriter::with_capacity(BUFFER_SIZE, File::create(&staged)?);
+                std::io::copy(&mut reader, &mut writer)
+            });
+            crate::remove_tlpdb_xz(&compressed);
+            result?;
+        }
+        Ok(None) => anyhow::bail!("The mirror answered an unconditional TLPDB request with 304 Not Modified"),
+        Err(e) => {
+            log::debug!("No compressed TLPDB on the mirror ({}), fetching the plain text", e);
+            let _ = fs::remove_file(&staged);
+            crate::download_to(config, client, "tlpkg/texlive.tlpdb", &staged, "texlive.tlpdb").await?;
+        }
+    }
+    fs::rename(&staged, dest)?;
+    Ok(())
+}
+
+// Like `fetch_tlpdb_text_shared`, but copies files instead of passing the text around.
+pub async fn fetch_tlpdb(config: &Config, client: &reqwest::Client, dest: &Path) -> anyhow::Result<()> {
+    let Some(shared) = cache::shared_dir(config, "tlpdb") else {
+        log::info!("Fetching fresh TLPDB from CTAN mirror to {:?}", dest);
+        return download_tlpdb(config, client, dest).await;
+    };
+    let cached = shared.path.join(cache::tlpdb_name(&config.repository));
+    let _lock = if shared.writable { Some(cache::lock(&cached).await?) } else { None };
+    if cache::is_fresh(&cached, chrono::Duration::hours(24)) {
+        log::info!("Using TLPDB from shared cache {:?}", cached);
+        let staged = cache::staging_path(dest);
+        fs::copy(&cached, &staged)?;
+        fs::rename(&staged, dest)?;
+        return Ok(());
+    }
+
+    log::info!("Fetching fresh TLPDB from CTAN mirror to {:?}", dest);
+    download_tlpdb(config, client, dest).await?;
+    if shared.writable {
+        let staged = cache::staging_path(&cached);
+        fs::copy(dest, &staged)?;
+        cache::publish(&staged, &cached)?;
+        log::info!("Shared TLPDB at {:?}", cached);
+    }
+    Ok(())
+}
+
+pub fn sha256_file(path: &Path) -> anyhow::Result<String> {
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut BufReader::with_capacity(BUFFER_SIZE, File::open(path)?), &mut hasher)?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+// Sequential counterpart of `parse_tlpdb`; blocks are separated by empty lines in both.
+pub fn parse_tlpdb_file(path: &Path) -> anyhow::Result<(HashMap<String, Package>, crate::repo::RepositoryInfo)> {
+    let _timer = crate::timings::start(crate::timings::Phase::Tlpdb);
+    let reader = BufReader::with_capacity(BUFFER_SIZE, File::open(path)?);
+    let mut tlpdb = HashMap::new();
+    let mut repository = None;
+    let mut block = String::new();
+    let mut finish_block = |block: &mut String| {
+        if block.starts_with("name 00texlive.config\n") {
+            repository = Some(crate::repo::parse_config_block(block));
+        } else if !block.starts_with("name 00texlive.")
+            && let Some(pkg) = crate::parse_package_block(block)
+        {
+            tlpdb.insert(pkg.name.clone(), pkg);
+        }
+        block.clear();
+    };
+    for line in reader.lines() {
+        let line = line.map_err(|e| anyhow::anyhow!("Cannot read TLPDB {:?}: {}", path, e))?;
+        if line.is_empty() {
+            finish_block(&mut block);
+        } else {
+            block.push_str(&line);
+            block.push('\n');
+        }
+    }
+    finish_block(&mut block);
+
+    log::info!("Parsed {} packages from TLPDB", tlpdb.len());
+    Ok((tlpdb, repository.unwrap_or_default()))
+}
+
+pub fn save_tlpdb_bin(path: &Path, tlpdb: &HashMap<String, Package>) -> anyhow::Result<()> {
+    let _timer = crate::timings::start(crate::timings::Phase::Tlpdb);
+    let staged = cache::staging_path(path);
+    let mut writer = BufWriter::with_capacity(BUFFER_SIZE, File::create(&staged)?);
+    crate::tlpdb::write_cache(&mut writer, tlpdb)?;
+    writer.into_inner().map_err(|e| anyhow::anyhow!("Failed to write {:?}: {}", staged, e.error()))?;
+    fs::rename(&staged, path)?;
+    Ok(())
+}
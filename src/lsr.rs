@@ -0,0 +1,67 @@
+use std::fmt::Write;
+use std::fs;
+use std::path::Path;
+
+// kpathsea looks files up in a tree's ls-R database instead of searching the disk; a file
+// missing from it is only found by a slow search, if at all. texman writes the databases
+// itself, in the layout mktexlsr produces, so no TeX binaries are needed to keep them current.
+pub const TREES: &[&str] = &["texmf-dist", "texmf-var", "texmf-config"];
+
+const HEADER: &str = "% ls-R -- filename database for kpathsea; do not change this line.\n";
+
+// Version control directories, which mktexlsr leaves out as well.
+const IGNORED: &[&str] = &[".git", ".svn", ".hg", ".bzr", "_darcs", "CVS", "RCS", "SCCS"];
+
+// `./dir:` followed by the names in it and a blank line, for every directory below the tree.
+// Links are followed, since profile files link into the store.
+fn list(tree: &Path, rel_dir: &str, out: &mut String) -> anyhow::Result<()> {
+    let dir = if rel_dir.is_empty() { tree.to_path_buf() } else { tree.join(rel_dir) };
+    let mut names = Vec::new();
+    let mut subdirs = Vec::new();
+    for entry in fs::read_dir(&dir)? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().to_string();
+        if IGNORED.contains(&name.as_str()) || (rel_dir.is_empty() && name == "ls-R") {
+            continue;
+        }
+        if fs::metadata(entry.path()).is_ok_and(|metadata| metadata.is_dir()) {
+            subdirs.push(name.clone());
+        }
+        names.push(name);
+    }
+    names.sort();
+    subdirs.sort();
+    writeln!(out, "./{}:", rel_dir)?;
+    for name in &names {
+        writeln!(out, "{}", name)?;
+    }
+    writeln!(out)?;
+    for subdir in subdirs {
+        let rel_path = if rel_dir.is_empty() { subdir } else { format!("{}/{}", rel_dir, subdir) };
+        list(tree, &rel_path, out)?;
+    }
+    Ok(())
+}
+
+// Rewrites ls-R in each of the profile's trees that exists, after packages were installed,
+// updated, or removed.
+pub fn regenerate(profile_dir: &Path) -> anyhow::Result<()> {
+    for tree in TREES {
+        let tree = profile_dir.join(tree);
+        if !tree.is_dir() {
+            continue;
+        }
+        let mut text = HEADER.to_string();
+        list(&tree, "", &mut text)?;
+        let ls_r = tree.join("ls-R");
+        if fs::read_to_string(&ls_r).is_ok_and(|current| current == text) {
+            continue;
+        }
+        // Written aside and renamed, so TeX running meanwhile never reads half a database.
+        let partial = tree.join("ls-R.texman-tmp");
+        fs::write(&partial, &text)?;
+        fs::rename(&partial, &ls_r)?;
+        log::info!("Updated {}", ls_r.display());
+    }
+    Ok(())
+}
@@ -1,39 +1,198 @@
+mod active;
+mod attest;
+mod autobackup;
+mod bench;
+mod bundle;
+mod cache;
+mod compat;
+mod config;
+mod diff;
+mod direnv;
+mod execute;
+mod explain;
+mod fontmaps;
+mod formats;
+mod expr;
+mod history;
+mod hold;
+mod hooks;
+mod journal;
+mod lowmem;
+mod lsr;
+mod manifest;
+mod mirror;
+mod notice;
+mod orphans;
+mod os;
+mod output;
+mod perms;
+mod plan;
+mod platform;
+mod prompt;
+mod protect;
+mod purge;
+mod query;
+mod repo;
+mod rollback;
+mod run;
+mod schema;
+mod scripts;
+mod serve;
+mod setup;
+mod shellenv;
+mod smoke;
+mod space;
+mod store;
+mod summary;
+mod texmfhome;
+mod timings;
+mod tlpdb;
+mod tlprofile;
+mod tool;
+mod topics;
+mod tree;
+mod ui;
+mod unused;
+mod workspace;
+
 use clap::{Parser, Subcommand};
+use config::{ConflictPolicy, Config, InstallStrategy, PermissionPolicy};
+use output::{OutputFormat, PackageRow, RowOptions};
+use timings::Phase;
+use tlpdb::{parse_package_block, parse_tlpdb};
 use std::collections::HashMap;
 use std::fs::File;
-use std::path::PathBuf;
+use std::path::{Component, Path, PathBuf};
 use chrono::{DateTime, Utc, Duration};
 use std::fs;
 use futures::future::join_all;
 use futures::StreamExt;
 use xz2::read::XzDecoder;
-use tar;
 use rusqlite::{Connection, params, OptionalExtension};
-use indicatif::{ProgressBar, ProgressStyle};
-use std::io::Write;
-use rayon::prelude::*;
+use serde::Serialize;
+use std::fmt::Write as _;
+use std::io::{IsTerminal, Read, Write};
+use sha2::{Digest, Sha256, Sha512};
+use std::sync::Arc;
+use tokio::sync::Semaphore;
 
 #[derive(Parser)]
 #[command(name = "texman", about = "A Rust-based package manager for LaTeX", version = "0.1.0")]
 struct Cli {
     #[command(subcommand)]
     command: Commands,
+    #[arg(long, global = true)]
+    repository: Option<String>,
+    #[arg(long, global = true)]
+    jobs: Option<std::num::NonZeroUsize>,
+    #[arg(long, global = true)]
+    offline: bool,
+    #[arg(long, global = true)]
+    no_pager: bool,
+    #[arg(long, global = true)]
+    json_progress: bool,
+    #[arg(long, global = true)]
+    low_memory: bool,
+    #[arg(long, global = true)]
+    timings: bool,
+    #[arg(long, global = true)]
+    platform: Option<String>,
 }
 
 #[derive(Subcommand)]
 enum Commands {
     Install {
+        #[arg(required_unless_present = "from_plan")]
+        packages: Vec<String>,
+        #[arg(long = "profile")]
+        profiles: Vec<String>,
+        #[arg(long, conflicts_with = "profiles")]
+        all_profiles: bool,
+        #[arg(long, value_enum)]
+        on_conflict: Option<ConflictPolicy>,
+        #[arg(long)]
+        destdir: Option<PathBuf>,
+        #[arg(long)]
+        allow_scripts: bool,
+        #[arg(long)]
+        keep_going: bool,
+        #[arg(long)]
+        explain: bool,
+        #[arg(long)]
+        no_depends_at_all: bool,
+        #[arg(long, conflicts_with_all = ["explain", "from_plan"])]
+        plan: bool,
+        #[arg(long, conflicts_with_all = ["packages", "no_depends_at_all"])]
+        from_plan: Option<PathBuf>,
+        #[arg(long, conflicts_with = "plan")]
+        dry_run: bool,
+    },
+    Resume {
+        #[arg(long)]
+        destdir: Option<PathBuf>,
+        #[arg(long, conflicts_with = "rollback")]
+        keep_going: bool,
+        // Undo the interrupted transaction instead of finishing it.
+        #[arg(long)]
+        rollback: bool,
+    },
+    History {
+        // Show the packages of this transaction.
+        id: Option<i64>,
+        #[arg(long)]
+        profile: Option<String>,
+    },
+    Undo {
+        // The transaction to revert; the latest finished one by default.
+        id: Option<i64>,
+    },
+    Rollback {
         package: String,
-        #[arg(long, default_value = "default")]
-        profile: String,
+        // The revision to go back to, e.g. r71234; the newest one before the installed one by default.
+        #[arg(long)]
+        to: Option<String>,
+    },
+    Update {
+        #[arg(long)]
+        check: bool,
+        #[arg(long, value_enum)]
+        removed: Option<orphans::RemovedAction>,
+        #[arg(long, value_enum)]
+        on_conflict: Option<ConflictPolicy>,
+        #[arg(long)]
+        allow_scripts: bool,
+        #[arg(long)]
+        keep_going: bool,
+        #[arg(long, conflicts_with_all = ["check", "from_plan"])]
+        plan: bool,
+        #[arg(long, conflicts_with_all = ["check", "removed"])]
+        from_plan: Option<PathBuf>,
+        #[arg(long, conflicts_with_all = ["check", "plan"])]
+        dry_run: bool,
+    },
+    List {
+        #[arg(long, value_enum, default_value = "text")]
+        output: OutputFormat,
+        #[command(flatten)]
+        rows: RowOptions,
+        #[arg(long)]
+        long: bool,
     },
-    Update,
-    List,
     Remove {
         package: String,
+        #[arg(long)]
+        purge: bool,
+        #[arg(long)]
+        force: bool,
     },
     Info {
         package: String,
+        #[arg(long, value_enum, default_value = "text")]
+        output: OutputFormat,
+        #[command(flatten)]
+        rows: RowOptions,
+        #[arg(long)]
+        diff_remote: bool,
     },
     Backup {
         #[command(subcommand)]
@@ -41,15 +200,33 @@ enum Commands {
     },
     Restore {
         name: String,
+        #[arg(long, value_enum)]
+        on_conflict: Option<ConflictPolicy>,
+        #[arg(long)]
+        force: bool,
     },
     Search {
-        term: String,
+        #[arg(required_unless_present = "topic")]
+        terms: Vec<String>,
+        // A CTAN topic, e.g. maths-font; see `texman topics`.
+        #[arg(long)]
+        topic: Option<String>,
+        #[arg(long)]
+        exact: bool,
         #[arg(long)]
         description: bool,
         #[arg(long)]
         depends: bool,
         #[arg(long)]
         longdesc: bool,
+        #[arg(long, conflicts_with = "not_installed")]
+        installed: bool,
+        #[arg(long)]
+        not_installed: bool,
+        #[arg(long, value_enum, default_value = "text")]
+        output: OutputFormat,
+        #[command(flatten)]
+        rows: RowOptions,
     },
     Clean {
         #[arg(long)]
@@ -59,14 +236,375 @@ enum Commands {
         #[command(subcommand)]
         action: ProfileAction,
     },
+    SwitchRevision {
+        package: String,
+        revision: String,
+    },
+    Setup,
+    WatchPkg {
+        #[command(subcommand)]
+        action: WatchAction,
+    },
+    Status,
+    Doctor,
+    Changelog {
+        package: String,
+    },
+    Browse {
+        package: String,
+        #[arg(long)]
+        docs: bool,
+    },
+    #[command(args_conflicts_with_subcommands = true)]
+    Query {
+        #[command(subcommand)]
+        action: Option<QueryAction>,
+        expression: Option<String>,
+        #[arg(long, value_enum, default_value = "text")]
+        output: OutputFormat,
+    },
+    Bundle {
+        #[command(subcommand)]
+        action: BundleAction,
+    },
+    Store {
+        #[command(subcommand)]
+        action: StoreAction,
+    },
+    Unused {
+        project: PathBuf,
+        #[arg(long)]
+        profile: Option<String>,
+        #[arg(long, value_enum, default_value = "text")]
+        output: OutputFormat,
+    },
+    Attest {
+        #[arg(long)]
+        output: Option<PathBuf>,
+        #[arg(long)]
+        verify: bool,
+    },
+    Mirror {
+        #[command(subcommand)]
+        action: MirrorAction,
+    },
+    FixPerms {
+        #[arg(long, value_enum)]
+        policy: Option<PermissionPolicy>,
+    },
+    Run {
+        #[arg(long)]
+        profile: Option<String>,
+        tool: String,
+        // Everything after the tool goes to it, flags included.
+        #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+        args: Vec<String>,
+    },
+    Env {
+        #[arg(long, value_enum)]
+        shell: Option<shellenv::Shell>,
+        #[arg(long)]
+        profile: Option<String>,
+    },
+    Direnv {
+        profile: String,
+        #[arg(long, default_value = ".")]
+        dir: PathBuf,
+        #[arg(long)]
+        print: bool,
+        #[arg(long)]
+        force: bool,
+    },
+    Bench {
+        #[arg(long)]
+        tlpdb: Option<PathBuf>,
+        #[arg(long, default_value = "scheme-full")]
+        scheme: String,
+        #[arg(long, default_value_t = 5)]
+        iterations: usize,
+    },
+    Fetch {
+        #[arg(required = true)]
+        packages: Vec<String>,
+        #[arg(long)]
+        dir: Option<PathBuf>,
+        #[arg(long)]
+        keep_going: bool,
+        #[arg(long)]
+        no_depends_at_all: bool,
+    },
+    Repo {
+        #[command(subcommand)]
+        action: RepoAction,
+    },
+    Workspace {
+        #[command(subcommand)]
+        action: WorkspaceAction,
+    },
+    Formats {
+        #[command(subcommand)]
+        action: FormatsAction,
+    },
+    Fontmaps {
+        #[command(subcommand)]
+        action: FontmapsAction,
+    },
+    Topics {
+        // Most packages first instead of by name.
+        #[arg(long)]
+        by_count: bool,
+    },
+    Schema {
+        #[arg(value_enum)]
+        kind: Option<schema::SchemaKind>,
+    },
+    Pin {
+        package: String,
+        #[arg(long)]
+        repo: Option<String>,
+    },
+    Unpin {
+        package: String,
+        // Remove the repository pin instead of the hold in the active profile.
+        #[arg(long)]
+        repo: bool,
+    },
+    ShadowCheck {
+        #[arg(long)]
+        profile: Option<String>,
+    },
+    ImportProfile {
+        path: PathBuf,
+        #[arg(long)]
+        name: Option<String>,
+        #[arg(long)]
+        allow_scripts: bool,
+        #[arg(long)]
+        keep_going: bool,
+    },
+}
+
+impl Commands {
+    // Loading the TLPDB may mean a multi-megabyte download, so commands that only read local
+    // state skip it. A `match` rather than a wildcard, so every new command has to decide.
+    fn needs_tlpdb(&self) -> bool {
+        match self {
+            Commands::Install { .. }
+            | Commands::Resume { .. }
+            | Commands::Undo { .. }
+            | Commands::Rollback { .. }
+            | Commands::Update { .. }
+            | Commands::Info { .. }
+            | Commands::Search { .. }
+            | Commands::Topics { .. }
+            | Commands::Changelog { .. }
+            | Commands::Browse { .. }
+            | Commands::Query { .. }
+            | Commands::Unused { .. }
+            | Commands::ImportProfile { .. }
+            | Commands::Fetch { .. }
+            | Commands::Workspace { .. }
+            | Commands::Doctor
+            | Commands::Status => true,
+            // Sizes and descriptions come from the TLPDB; names and revisions are recorded locally.
+            Commands::List { output, rows, .. } => *output != OutputFormat::Text || rows.is_set(),
+            // Purging needs the package's execute lines to find what TeX tools generated from it.
+            Commands::Remove { purge, .. } => *purge,
+            Commands::WatchPkg { action } => !matches!(action, WatchAction::Remove { .. }),
+            Commands::Mirror { action } => matches!(action, MirrorAction::Create { .. }),
+            Commands::Bundle { action } => matches!(action, BundleAction::Export { .. }),
+            Commands::Backup { .. }
+            | Commands::Restore { .. }
+            | Commands::Clean { .. }
+            | Commands::History { .. }
+            | Commands::Profile { .. }
+            | Commands::SwitchRevision { .. }
+            | Commands::Setup
+            | Commands::Store { .. }
+            | Commands::Formats { .. }
+            | Commands::Fontmaps { .. }
+            | Commands::Attest { .. }
+            | Commands::FixPerms { .. }
+            | Commands::Direnv { .. }
+            | Commands::Env { .. }
+            | Commands::Run { .. }
+            | Commands::Repo { .. }
+            | Commands::Schema { .. }
+            | Commands::Pin { .. }
+            | Commands::Unpin { .. }
+            | Commands::ShadowCheck { .. }
+            | Commands::Bench { .. } => false,
+        }
+    }
+}
+
+#[derive(Subcommand)]
+enum RepoAction {
+    Add { name: String, url: String },
+    Remove { name: String },
+    List,
+    Priority { names: Vec<String> },
+    Set {
+        #[arg(long, required_unless_present = "current")]
+        year: Option<u32>,
+        #[arg(long, conflicts_with = "year")]
+        current: bool,
+        #[arg(long)]
+        profile: Option<String>,
+    },
+}
+
+#[derive(Subcommand)]
+enum WorkspaceAction {
+    Status {
+        #[arg(long, default_value = ".")]
+        dir: PathBuf,
+    },
+    Sync {
+        #[arg(required_unless_present = "all")]
+        members: Vec<String>,
+        #[arg(long, conflicts_with = "members")]
+        all: bool,
+        #[arg(long, default_value = ".")]
+        dir: PathBuf,
+        #[arg(long, value_enum)]
+        on_conflict: Option<ConflictPolicy>,
+        #[arg(long)]
+        allow_scripts: bool,
+        #[arg(long)]
+        keep_going: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum MirrorAction {
+    Set {
+        repository: String,
+        #[arg(long = "fallback")]
+        fallbacks: Vec<String>,
+    },
+    List,
+    Test { urls: Vec<String> },
+    Create {
+        dir: PathBuf,
+        #[arg(long, conflicts_with = "packages")]
+        scheme: Option<String>,
+        #[arg(long, num_args = 1..)]
+        packages: Vec<String>,
+        #[arg(long)]
+        keep_going: bool,
+    },
+    Serve {
+        dir: PathBuf,
+        #[arg(long, default_value_t = 8080)]
+        port: u16,
+        #[arg(long, default_value = "0.0.0.0")]
+        bind: String,
+    },
+}
+
+#[derive(Subcommand)]
+enum FormatsAction {
+    Rebuild {
+        // Only these formats, e.g. pdflatex; all of them when none are given.
+        formats: Vec<String>,
+        #[arg(long)]
+        profile: Option<String>,
+    },
+}
+
+#[derive(Subcommand)]
+enum FontmapsAction {
+    Sync {
+        #[arg(long)]
+        profile: Option<String>,
+    },
+}
+
+#[derive(Subcommand)]
+enum StoreAction {
+    Compress,
+    Dedup,
+}
+
+#[derive(Subcommand)]
+enum BundleAction {
+    Create { out: PathBuf },
+    Install {
+        bundle: PathBuf,
+        #[arg(long)]
+        profile: Option<String>,
+    },
+    Export {
+        #[arg(required = true)]
+        packages: Vec<String>,
+        #[arg(short, long)]
+        output: PathBuf,
+    },
+    Import {
+        bundle: PathBuf,
+        #[arg(long)]
+        profile: Option<String>,
+        #[arg(long, value_enum)]
+        on_conflict: Option<ConflictPolicy>,
+        #[arg(long)]
+        allow_scripts: bool,
+        #[arg(long)]
+        keep_going: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum QueryAction {
+    MissingFile {
+        file: String,
+        #[arg(long)]
+        install: bool,
+        #[arg(long)]
+        profile: Option<String>,
+    },
+    Serve {
+        #[arg(long)]
+        profile: Option<String>,
+    },
 }
 
 #[derive(Subcommand)]
 enum ProfileAction {
-    Create { name: String },
+    Create {
+        name: String,
+        #[arg(long, value_enum)]
+        install_strategy: Option<InstallStrategy>,
+    },
     Switch { name: String },
+    // Print shell code that points the current shell at a profile, or undoes that.
+    Activate {
+        name: String,
+        #[arg(long, value_enum)]
+        shell: Option<shellenv::Shell>,
+    },
+    Deactivate {
+        #[arg(long, value_enum)]
+        shell: Option<shellenv::Shell>,
+    },
+    List,
+    Remove {
+        name: String,
+        #[arg(long)]
+        purge: bool,
+    },
+    SetStrategy {
+        name: String,
+        #[arg(value_enum)]
+        strategy: InstallStrategy,
+    },
+}
+
+#[derive(Subcommand)]
+enum WatchAction {
+    Add { package: String },
+    Remove { package: String },
     List,
-    Remove { name: String },
 }
 
 #[derive(Subcommand)]
@@ -76,45 +614,288 @@ enum BackupAction {
     Remove { name: String },
 }
 
+struct SearchOptions {
+    topic: Option<String>,
+    exact: bool,
+    description: bool,
+    depends: bool,
+    longdesc: bool,
+    installed: Option<bool>,
+}
+
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 struct Package {
     name: String,
+    category: Option<String>,
     revision: String,
-    url: String,
     depends: Vec<String>,
+    // Packages whose `<name>.<platform>` binaries this one needs (`depend <name>.ARCH`).
+    #[serde(default)]
+    arch_depends: Vec<String>,
     runfiles: Vec<String>,
     binfiles: Vec<String>,
+    #[serde(default)]
+    docfiles: Vec<String>,
+    #[serde(default)]
+    srcfiles: Vec<String>,
     description: Option<String>,
     longdesc: Option<String>,
+    // Paths start with RELOC/ instead of texmf-dist/.
+    #[serde(default)]
+    relocated: bool,
+    container_size: Option<u64>,
+    doc_container_size: Option<u64>,
+    src_container_size: Option<u64>,
+    container_checksum: Option<String>,
+    doc_container_checksum: Option<String>,
+    src_container_checksum: Option<String>,
+    executes: Vec<String>,
+    postactions: Vec<String>,
+    run_size: Option<u64>,
+    doc_size: Option<u64>,
+    src_size: Option<u64>,
+    catalogue: Option<String>,
+    catalogue_version: Option<String>,
+    catalogue_date: Option<String>,
+    catalogue_ctan: Option<String>,
+    #[serde(default)]
+    catalogue_license: Option<String>,
+    #[serde(default)]
+    catalogue_topics: Vec<String>,
+    // None for the main repository, else the name of the additional repository it comes from.
+    repository: Option<String>,
 }
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
-    env_logger::init();
+    summary::init_logger();
     let cli = Cli::parse();
+    let _timings = cli.timings.then(timings::Report::start);
+    // `query serve` streams each request's progress to its sender.
+    if cli.json_progress || matches!(cli.command, Commands::Query { action: Some(QueryAction::Serve { .. }), .. }) {
+        hooks::use_json_progress();
+    }
+
+    let texman_dir = dirs::home_dir()
+        .ok_or_else(|| anyhow::anyhow!("Cannot find home directory"))?
+        .join(".texman");
+    let first_run = !Config::path(&texman_dir).exists() && std::io::stdin().is_terminal();
+    let starter_scheme = if first_run || matches!(cli.command, Commands::Setup) {
+        setup::run(&texman_dir)?
+    } else {
+        None
+    };
+
+    let mut config = Config::load(&texman_dir)?;
+    if let Some(repository) = cli.repository {
+        config.repository = repository;
+    } else if let Ok(profile) = active::name(&texman_dir)
+        && let Some(year) = repo::pinned_year(&init_db(&texman_dir)?, &profile)?
+    {
+        // Other mirrors carry the current release, so none of them can stand in.
+        config.repository = repo::historic_url(year);
+        config.mirrors = Vec::new();
+    }
+    if let Some(jobs) = cli.jobs {
+        config.jobs = jobs.get();
+    }
+    if cli.offline {
+        config.offline = true;
+    }
+    if cli.low_memory {
+        config.low_memory = true;
+    }
+    // One download and one unpack at a time, unless --jobs asks for more.
+    if config.low_memory && cli.jobs.is_none() {
+        config.jobs = 1;
+    }
+    repo::resolve_repository(&mut config).await;
+    // `.ARCH` dependencies pull in the binaries of the active profile's platform.
+    let stored_platform = match active::name(&texman_dir) {
+        Ok(profile) if cli.platform.is_none() => profile_setting(&init_db(&texman_dir)?, &profile, "platform")?,
+        _ => None,
+    };
+    platform::select(cli.platform.as_deref().or(stored_platform.as_deref()))?;
+
+    let tlpdb = if !cli.command.needs_tlpdb() && starter_scheme.is_none() {
+        HashMap::new()
+    } else {
+        match fetch_tlpdb(&config).await {
+            Ok(tlpdb) => tlpdb,
+            // Installed packages are recorded locally, so these still work offline before any
+            // TLPDB has been cached, only with less detail.
+            Err(e) if config.offline && matches!(cli.command, Commands::List { .. } | Commands::Remove { .. } | Commands::Info { .. }) => {
+                log::warn!("{}; only locally recorded information is available", e);
+                HashMap::new()
+            }
+            Err(e) => return Err(e),
+        }
+    };
+
+    if let Some(scheme) = starter_scheme {
+        log::info!("Installing starter scheme {} into profile: {}", scheme, config.profile);
+        let failed = install_package(std::slice::from_ref(&scheme), std::slice::from_ref(&config.profile), &tlpdb, &config, None, None, false).await?;
+        report_failures(&failed, &format!("texman install {}", scheme))?;
+    }
+
+    // `update` reports the same thing, and query/bundle output is meant for other programs.
+    // Commands that skipped the TLPDB get no notice, as there is nothing to compare against.
+    let show_notice = !matches!(cli.command, Commands::Update { .. } | Commands::Query { .. } | Commands::Bundle { .. });
 
-    let tlpdb = fetch_tlpdb().await?;
+    // On stderr, so output meant for other programs stays clean; `doctor` reports it itself.
+    if !matches!(cli.command, Commands::Resume { .. } | Commands::Doctor)
+        && let Some(interrupted) = journal::interrupted(&texman_dir)?
+    {
+        eprintln!("{}", interrupted);
+    }
 
     match cli.command {
-        Commands::Install { package, profile } => {
-            log::info!("Installing package: {} into profile: {}", package, profile);
-            install_package(&package, &profile, &tlpdb).await?;
+        Commands::Install { packages, profiles, all_profiles, on_conflict, destdir, allow_scripts, keep_going, explain, no_depends_at_all, plan, from_plan, dry_run } => {
+            config.keep_going |= keep_going;
+            config.no_depends_at_all = no_depends_at_all;
+            if explain {
+                explain::explain(&packages, &tlpdb, &config)?;
+            }
+            let root = texman_root(destdir.as_deref())?;
+            // A plan names every package to install, dependencies included, and its profiles.
+            let from_plan = from_plan.map(|path| plan::load(&path, "install")).transpose()?;
+            let (packages, profiles) = match from_plan {
+                Some(from_plan) => {
+                    config.no_depends_at_all = true;
+                    let planned_profiles = if profiles.is_empty() && !all_profiles { from_plan.profiles.clone() } else { profiles };
+                    (plan::packages(&from_plan, &tlpdb)?, planned_profiles)
+                }
+                None => (packages, profiles),
+            };
+            let profiles = if all_profiles {
+                profile_names(&root)?
+            } else if profiles.is_empty() {
+                vec![config.profile.clone()]
+            } else {
+                profiles
+            };
+            if profiles.is_empty() {
+                anyhow::bail!("No profiles found to install into");
+            }
+            // --platform sticks to the profiles, except for a dry run; otherwise the first one's
+            // platform applies.
+            match &cli.platform {
+                Some(_) if dry_run => {}
+                Some(name) => {
+                    let conn = init_db(&root)?;
+                    for profile in &profiles {
+                        set_profile_setting(&conn, profile, "platform", platform::parse(name)?)?;
+                    }
+                }
+                None => platform::select(profile_setting(&init_db(&root)?, &profiles[0], "platform")?.as_deref())?,
+            }
+            if plan || dry_run {
+                let resolved = resolve_requested(&packages, &tlpdb, &config)?;
+                let resolved: Vec<Package> = resolved.iter().map(|name| tlpdb[name].clone()).collect();
+                let conn = init_db(&root)?;
+                let install_plan = plan::build("install", &profiles, &packages, &resolved, &conn, &config)?;
+                if dry_run {
+                    plan::print_dry_run(&install_plan, &root, &conn, &config)?;
+                } else {
+                    plan::print(&install_plan)?;
+                }
+                return Ok(());
+            }
+            log::info!("Installing packages: {} into profiles: {}", packages.join(", "), profiles.join(", "));
+            let transactions = profiles
+                .iter()
+                .map(|profile| summary::Transaction::begin("install", profile, &root))
+                .collect::<anyhow::Result<Vec<_>>>()?;
+            let failed = install_package(&packages, &profiles, &tlpdb, &config, on_conflict, destdir.as_deref(), allow_scripts).await?;
+            for transaction in transactions {
+                transaction.finish(&config, &tlpdb)?;
+            }
+            if destdir.is_none() {
+                for profile in &profiles {
+                    texmfhome::note(&root, profile)?;
+                }
+            }
+            let names: Vec<&str> = failed.iter().map(|(name, _)| name.as_str()).collect();
+            let profile_flags: Vec<String> = profiles.iter().map(|profile| format!("--profile {}", profile)).collect();
+            report_failures(&failed, &format!("texman install {} {}", names.join(" "), profile_flags.join(" ")))?;
         }
-        Commands::Update => {
+        Commands::Resume { destdir, keep_going, rollback } => {
+            config.keep_going |= keep_going;
+            if rollback {
+                log::info!("Rolling back interrupted transaction");
+                journal::rollback(&tlpdb, &config, destdir.as_deref()).await?;
+            } else {
+                log::info!("Resuming interrupted transaction");
+                journal::resume(&tlpdb, &config, destdir.as_deref()).await?;
+            }
+        }
+        Commands::History { id, profile } => {
+            log::info!("Showing transaction history");
+            history::show(id, profile.as_deref())?;
+        }
+        Commands::Undo { id } => {
+            log::info!("Undoing transaction");
+            autobackup::snapshot("undo", &config)?;
+            history::undo(id, &tlpdb, &config).await?;
+        }
+        Commands::Rollback { package, to } => {
+            log::info!("Rolling back {}", package);
+            autobackup::snapshot("rollback", &config)?;
+            rollback::run(&package, to.as_deref(), &tlpdb, &config).await?;
+        }
+        Commands::Update { check, removed, on_conflict, allow_scripts, keep_going, plan, from_plan, dry_run } => {
+            config.keep_going |= keep_going;
             log::info!("Updating packages in active profile");
-            update_packages(&tlpdb).await?;
+            let only = match from_plan {
+                Some(path) => Some(plan::packages(&plan::load(&path, "update")?, &tlpdb)?),
+                None => None,
+            };
+            if plan {
+                plan::print(&plan_update(&tlpdb, &config)?)?;
+            } else if dry_run {
+                let conn = init_db(&texman_dir)?;
+                for (name, reason) in held_updates(&conn, &active::name(&texman_dir)?, &tlpdb)? {
+                    println!("Not updating {}: {}", name, reason);
+                }
+                plan::print_dry_run(&plan_update(&tlpdb, &config)?, &texman_dir, &conn, &config)?;
+            } else if check {
+                update_packages(&tlpdb, &config, check, removed, on_conflict, allow_scripts, None).await?;
+            } else {
+                autobackup::snapshot("update", &config)?;
+                let profile = active::name(&texman_dir)?;
+                let transaction = summary::Transaction::begin("update", &profile, &texman_dir)?;
+                let failed = update_packages(&tlpdb, &config, check, removed, on_conflict, allow_scripts, only.as_deref()).await?;
+                transaction.finish(&config, &tlpdb)?;
+                texmfhome::note(&texman_dir, &profile)?;
+                report_failures(&failed, "texman update")?;
+            }
         }
-        Commands::List => {
+        Commands::List { output, rows, long } => {
             log::info!("Listing installed packages in active profile");
-            list_packages()?;
+            list_packages(&tlpdb, output, &rows, long)?;
         }
-        Commands::Remove { package } => {
+        Commands::Remove { package, purge, force } => {
             log::info!("Removing package: {}", package);
-            remove_package(&package)?;
+            if protect::is_protected(&package) && !force {
+                anyhow::bail!("{} is part of the TeX infrastructure other packages rely on; pass --force to remove it anyway", package);
+            }
+            autobackup::snapshot("remove", &config)?;
+            let _locks = lock_profiles(&texman_dir, &[&active::name(&texman_dir)?]).await?;
+            let transaction = summary::Transaction::begin("remove", &active::name(&texman_dir)?, &texman_dir)?;
+            remove_package(&package, purge, &tlpdb)?;
+            let _store = cache::lock(&store_lock(&texman_dir)).await?;
+            store::prune_revisions(&init_db(&texman_dir)?, &texman_dir.join("store"), config.keep_revisions)?;
+            store::prune_objects(&texman_dir.join("store"))?;
+            transaction.finish(&config, &tlpdb)?;
         }
-        Commands::Info { package } => {
-            log::info!("Showing info for package: {}", package);
-            info_package(&package, &tlpdb)?;
+        Commands::Info { package, output, rows, diff_remote } => {
+            if diff_remote {
+                log::info!("Comparing installed files of {} with the TLPDB", package);
+                diff::diff_remote(&package, &tlpdb, &config).await?;
+            } else {
+                log::info!("Showing info for package: {}", package);
+                info_package(&package, &tlpdb, &config, output, &rows, cli.no_pager)?;
+            }
         }
         Commands::Backup { action } => match action {
             BackupAction::Create { name } => {
@@ -130,217 +911,1065 @@ async fn main() -> anyhow::Result<()> {
                 remove_backup(&name)?;
             }
         },
-        Commands::Restore { name } => {
+        Commands::Restore { name, on_conflict, force } => {
             log::info!("Restoring active profile from backup '{}'", name);
-            restore_profile(&name)?;
+            autobackup::snapshot("restore", &config)?;
+            restore_profile(&name, &config, on_conflict, force)?;
         }
-        Commands::Search { term, description, depends, longdesc } => {
-            log::info!("Searching for packages matching '{}'", term);
-            search_packages(&term, &tlpdb, description, depends, longdesc)?;
+        Commands::Search { terms, topic, exact, description, depends, longdesc, installed, not_installed, output, rows } => {
+            log::info!("Searching for packages matching '{}'", terms.join(" "));
+            if let Some(topic) = &topic {
+                topics::check(topic, &tlpdb)?;
+            }
+            let options = SearchOptions {
+                topic,
+                exact,
+                description,
+                depends,
+                longdesc,
+                installed: if installed { Some(true) } else if not_installed { Some(false) } else { None },
+            };
+            search_packages(&terms, &tlpdb, &options, output, &rows, cli.no_pager)?;
         }
         Commands::Clean { backups } => {
             log::info!("Cleaning up unused files{}", if backups { " and backups" } else { "" });
-            clean(backups)?;
+            clean(backups, &config)?;
         }
         Commands::Profile { action } => match action {
-            ProfileAction::Create { name } => create_profile(&name)?,
+            ProfileAction::Create { name, install_strategy } => {
+                create_profile(&name)?;
+                if let Some(strategy) = install_strategy {
+                    set_install_strategy(&name, strategy, &config)?;
+                }
+            }
             ProfileAction::Switch { name } => switch_profile(&name)?,
+            ProfileAction::Activate { name, shell } => {
+                let (profile, profile_dir) = existing_profile(Some(name))?;
+                shellenv::activate(shell, &profile, &profile_dir)?;
+            }
+            ProfileAction::Deactivate { shell } => shellenv::deactivate(shell)?,
             ProfileAction::List => {
                 log::info!("Listing all profiles");
                 list_profiles()?;
             }
-            ProfileAction::Remove { name } => {
+            ProfileAction::Remove { name, purge } => {
                 log::info!("Removing profile '{}'", name);
-                remove_profile(&name)?;
+                remove_profile(&name, purge)?;
+            }
+            ProfileAction::SetStrategy { name, strategy } => {
+                log::info!("Setting install strategy of profile '{}' to '{}'", name, strategy.as_str());
+                set_install_strategy(&name, strategy, &config)?;
             }
         },
-    }
-
-    Ok(())
-}
-
-fn init_db(texman_dir: &PathBuf) -> anyhow::Result<Connection> {
-    let db_path = texman_dir.join("db").join("texman.sqlite");
-    let conn = Connection::open(db_path)?;
-    conn.execute(
-        "CREATE TABLE IF NOT EXISTS installed_packages (
-            profile TEXT NOT NULL,
-            name TEXT NOT NULL,
-            revision TEXT NOT NULL,
-            PRIMARY KEY (profile, name)
-        )",
-        [],
-    )?;
-    conn.execute(
-        "CREATE TABLE IF NOT EXISTS backups (
-            backup_name TEXT NOT NULL,
-            profile TEXT NOT NULL,
-            name TEXT NOT NULL,
-            revision TEXT NOT NULL,
-            created_at INTEGER NOT NULL DEFAULT (strftime('%s', 'now')),
-            PRIMARY KEY (backup_name, name)
-        )",
-        [],
-    )?;
-    Ok(conn)
-}
-
-async fn fetch_tlpdb() -> anyhow::Result<HashMap<String, Package>> {
-    let texman_dir = dirs::home_dir()
-        .ok_or_else(|| anyhow::anyhow!("Cannot find home directory"))?
-        .join(".texman");
-    let db_dir = texman_dir.join("db");
-    let tlpdb_path = db_dir.join("tlpdb.txt");
-    let tlpdb_bin_path = db_dir.join("tlpdb.bin");
-
-    std::fs::create_dir_all(&db_dir)?;
-
-    let should_fetch = if tlpdb_path.exists() {
-        let metadata = fs::metadata(&tlpdb_path)?;
-        let modified = metadata.modified()?;
-        let last_modified: DateTime<Utc> = modified.into();
-        let now = Utc::now();
+        Commands::SwitchRevision { package, revision } => {
+            log::info!("Switching {} to revision {}", package, revision);
+            switch_revision(&package, &revision, &config)?;
+        }
+        Commands::Setup => {}
+        Commands::WatchPkg { action } => match action {
+            WatchAction::Add { package } => {
+                log::info!("Watching package '{}'", package);
+                watch_package(&package, &tlpdb)?;
+            }
+            WatchAction::Remove { package } => {
+                log::info!("Unwatching package '{}'", package);
+                unwatch_package(&package)?;
+            }
+            WatchAction::List => {
+                log::info!("Listing watched packages");
+                list_watched(&tlpdb)?;
+            }
+        },
+        Commands::Changelog { package } => {
+            log::info!("Showing changelog for package: {}", package);
+            show_changelog(&package, &tlpdb, &config).await?;
+        }
+        Commands::Browse { package, docs } => {
+            log::info!("Opening {} page for package: {}", if docs { "documentation" } else { "CTAN" }, package);
+            browse_package(&package, &tlpdb, docs)?;
+        }
+        Commands::Query { action, expression, output } => match action {
+            Some(QueryAction::MissingFile { file, install, profile }) => {
+                let profile = profile.unwrap_or_else(|| config.profile.clone());
+                query::query_missing_file(&file, install, &profile, &tlpdb, &config).await?;
+            }
+            Some(QueryAction::Serve { profile }) => {
+                let profile = profile.unwrap_or_else(|| config.profile.clone());
+                query::serve(&profile, tlpdb.clone(), config.clone()).await?;
+            }
+            None => {
+                let expression = expression.ok_or_else(|| anyhow::anyhow!("Give a query expression, e.g. texman query 'depends:pgf AND size>10MB'"))?;
+                log::info!("Querying TLPDB: {}", expression);
+                query_packages(&expression, &tlpdb, output)?;
+            }
+        },
+        Commands::Bundle { action } => match action {
+            BundleAction::Create { out } => {
+                log::info!("Creating bundle {:?} from active profile", out);
+                bundle::create_bundle(&out)?;
+            }
+            BundleAction::Install { bundle, profile } => {
+                let profile = profile.unwrap_or_else(|| config.profile.clone());
+                log::info!("Installing bundle {:?} into profile: {}", bundle, profile);
+                bundle::install_bundle(&bundle, &profile, &config)?;
+            }
+            BundleAction::Export { packages, output } => {
+                log::info!("Exporting {} to {:?}", packages.join(", "), output);
+                bundle::export(&packages, &output, &tlpdb, &config).await?;
+            }
+            BundleAction::Import { bundle, profile, on_conflict, allow_scripts, keep_going } => {
+                config.keep_going |= keep_going;
+                let profile = profile.unwrap_or_else(|| config.profile.clone());
+                log::info!("Importing {:?} into profile: {}", bundle, profile);
+                let failed = bundle::import(&bundle, &profile, &config, on_conflict, allow_scripts).await?;
+                report_failures(&failed, &format!("texman bundle import {} --profile {}", bundle.display(), profile))?;
+            }
+        },
+        Commands::Store { action } => match action {
+            StoreAction::Compress => {
+                log::info!("Compressing store revisions unused by the active profile");
+                store::compress_unused()?;
+            }
+            StoreAction::Dedup => {
+                log::info!("Deduplicating identical files in the store");
+                store::dedup_all()?;
+            }
+        },
+        Commands::Unused { project, profile, output } => {
+            log::info!("Looking for packages unused by {:?}", project);
+            unused::unused_packages(&project, profile.as_deref(), &tlpdb, output)?;
+        }
+        Commands::Attest { output, verify } => {
+            log::info!("{} attestation record", if verify { "Verifying" } else { "Exporting" });
+            attest::attest(output.as_deref(), verify)?;
+        }
+        Commands::Mirror { action } => match action {
+            MirrorAction::Set { repository, fallbacks } => {
+                log::info!("Setting repository to {}", repository);
+                mirror::set(&texman_dir, &repository, &fallbacks)?;
+            }
+            MirrorAction::List => mirror::list(&config),
+            MirrorAction::Test { urls } => {
+                log::info!("Testing mirrors");
+                mirror::test(&config, &urls).await?;
+            }
+            MirrorAction::Create { dir, scheme, packages, keep_going } => {
+                config.keep_going |= keep_going;
+                log::info!("Creating mirror in {:?}", dir);
+                let failed = mirror::create(&dir, scheme.as_deref(), &packages, &tlpdb, &config).await?;
+                report_failures(&failed, &format!("texman mirror create {}", dir.display()))?;
+            }
+            MirrorAction::Serve { dir, port, bind } => {
+                log::info!("Serving mirror {:?}", dir);
+                serve::serve(&dir, &bind, port).await?;
+            }
+        },
+        Commands::Workspace { action } => match action {
+            WorkspaceAction::Status { dir } => {
+                log::info!("Checking workspace projects against their manifests");
+                workspace::print_status(&dir, &tlpdb, &config)?;
+            }
+            WorkspaceAction::Sync { members, all: _, dir, on_conflict, allow_scripts, keep_going } => {
+                config.keep_going |= keep_going;
+                log::info!("Syncing workspace projects");
+                let failed = workspace::sync(&dir, &members, &tlpdb, &config, on_conflict, allow_scripts).await?;
+                report_failures(&failed, "texman workspace sync --all")?;
+            }
+        },
+        Commands::Repo { action } => match action {
+            RepoAction::Add { name, url } => {
+                log::info!("Adding repository '{}'", name);
+                repo::add(&texman_dir, &name, &url)?;
+            }
+            RepoAction::Remove { name } => {
+                log::info!("Removing repository '{}'", name);
+                repo::remove(&texman_dir, &name)?;
+            }
+            RepoAction::List => {
+                let conn = init_db(&texman_dir)?;
+                let pinned = match active::name(&texman_dir) {
+                    Ok(profile) => repo::pinned_year(&conn, &profile)?,
+                    Err(_) => None,
+                };
+                repo::list(&config, pinned, &repo::pins(&conn)?);
+            }
+            RepoAction::Priority { names } => {
+                log::info!("Setting repository priority");
+                repo::set_priority(&texman_dir, &names)?;
+            }
+            RepoAction::Set { year, current: _, profile } => {
+                let profile = match profile {
+                    Some(profile) => profile,
+                    None => active::name(&texman_dir)?,
+                };
+                log::info!("Setting the repository of profile '{}'", profile);
+                repo::set_year(&texman_dir, &profile, year)?;
+            }
+        },
+        Commands::Formats { action } => match action {
+            FormatsAction::Rebuild { formats, profile } => {
+                let (profile, profile_dir) = existing_profile(profile)?;
+                formats::rebuild(&profile_dir, &formats)?;
+                println!("Built {} in profile '{}'", if formats.is_empty() { "all formats".to_string() } else { formats.join(", ") }, profile);
+            }
+        },
+        Commands::Fontmaps { action } => match action {
+            FontmapsAction::Sync { profile } => {
+                let (profile, profile_dir) = existing_profile(profile)?;
+                fontmaps::sync(&profile_dir)?;
+                println!("Rebuilt the font maps of profile '{}'", profile);
+            }
+        },
+        Commands::Topics { by_count } => topics::list(&tlpdb, by_count, cli.no_pager)?,
+        Commands::Schema { kind } => schema::print(kind)?,
+        Commands::Pin { package, repo } => match repo {
+            Some(repo) => {
+                log::info!("Pinning {} to repository '{}'", package, repo);
+                repo::pin(&texman_dir, &package, &repo, &config)?;
+            }
+            None => {
+                log::info!("Pinning {}", package);
+                hold::pin(&texman_dir, &package)?;
+            }
+        },
+        Commands::Unpin { package, repo } => {
+            log::info!("Unpinning {}", package);
+            if repo {
+                repo::unpin(&texman_dir, &package)?;
+            } else {
+                hold::unpin(&texman_dir, &package)?;
+            }
+        }
+        Commands::FixPerms { policy } => {
+            let policy = policy.unwrap_or(config.permissions);
+            log::info!("Fixing permissions in the store and profiles");
+            perms::fix_perms(policy)?;
+        }
+        Commands::Run { profile, tool, args } => {
+            let (profile, profile_dir) = existing_profile(profile)?;
+            run::run(&profile, &profile_dir, &tool, &args)?;
+        }
+        Commands::Env { shell, profile } => {
+            let (profile, profile_dir) = existing_profile(profile)?;
+            shellenv::print(shell, &profile, &profile_dir)?;
+        }
+        Commands::Direnv { profile, dir, print, force } => {
+            log::info!("Generating .envrc for profile '{}'", profile);
+            direnv::generate(&profile, &dir, print, force)?;
+        }
+        Commands::Bench { tlpdb: tlpdb_path, scheme, iterations } => {
+            log::info!("Benchmarking TLPDB parsing and dependency resolution");
+            bench::run(tlpdb_path, &scheme, iterations)?;
+        }
+        Commands::Fetch { packages, dir, keep_going, no_depends_at_all } => {
+            config.keep_going |= keep_going;
+            config.no_depends_at_all = no_depends_at_all;
+            log::info!("Fetching archives for: {}", packages.join(", "));
+            let failed = fetch_packages(&packages, &tlpdb, &config, dir).await?;
+            let names: Vec<&str> = failed.iter().map(|(name, _)| name.as_str()).collect();
+            report_failures(&failed, &format!("texman fetch {}", names.join(" ")))?;
+        }
+        Commands::ImportProfile { path, name, allow_scripts, keep_going } => {
+            config.keep_going |= keep_going;
+            log::info!("Importing installer profile {:?}", path);
+            tlprofile::import(&path, name, &tlpdb, &config, allow_scripts).await?;
+        }
+        Commands::ShadowCheck { profile } => {
+            log::info!("Checking the TEXMFHOME user tree for shadowed files");
+            texmfhome::check(profile.as_deref())?;
+        }
+        Commands::Doctor => {
+            log::info!("Checking infrastructure packages in every profile");
+            protect::doctor(&tlpdb)?;
+        }
+        Commands::Status => {
+            log::info!("Showing status of active profile");
+            show_status(&tlpdb, &config)?;
+        }
+    }
+
+    if show_notice {
+        notice::maybe_print(&config, &tlpdb).await;
+    }
+    Ok(())
+}
+
+fn init_db(texman_dir: &Path) -> anyhow::Result<Connection> {
+    let db_dir = texman_dir.join("db");
+    fs::create_dir_all(&db_dir)?;
+    let db_path = db_dir.join("texman.sqlite");
+    let conn = Connection::open(db_path)?;
+    // Other texman processes, and other requests of `query serve`, write to it too.
+    conn.busy_timeout(std::time::Duration::from_secs(30))?;
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS installed_packages (
+            profile TEXT NOT NULL,
+            name TEXT NOT NULL,
+            revision TEXT NOT NULL,
+            PRIMARY KEY (profile, name)
+        )",
+        [],
+    )?;
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS backups (
+            backup_name TEXT NOT NULL,
+            profile TEXT NOT NULL,
+            name TEXT NOT NULL,
+            revision TEXT NOT NULL,
+            created_at INTEGER NOT NULL DEFAULT (strftime('%s', 'now')),
+            PRIMARY KEY (backup_name, name)
+        )",
+        [],
+    )?;
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS package_origins (
+            profile TEXT NOT NULL,
+            name TEXT NOT NULL,
+            repository TEXT NOT NULL,
+            PRIMARY KEY (profile, name)
+        )",
+        [],
+    )?;
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS kept_orphans (
+            profile TEXT NOT NULL,
+            name TEXT NOT NULL,
+            PRIMARY KEY (profile, name)
+        )",
+        [],
+    )?;
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS repository_pins (
+            name TEXT PRIMARY KEY,
+            repository TEXT NOT NULL
+        )",
+        [],
+    )?;
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS package_holds (
+            profile TEXT NOT NULL,
+            name TEXT NOT NULL,
+            PRIMARY KEY (profile, name)
+        )",
+        [],
+    )?;
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS autobackups (
+            backup_name TEXT PRIMARY KEY,
+            profile TEXT NOT NULL,
+            operation TEXT NOT NULL,
+            created_at INTEGER NOT NULL DEFAULT (strftime('%s', 'now'))
+        )",
+        [],
+    )?;
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS backup_files (
+            backup_name TEXT NOT NULL,
+            path TEXT NOT NULL,
+            target TEXT,
+            sha256 TEXT,
+            size INTEGER,
+            PRIMARY KEY (backup_name, path)
+        )",
+        [],
+    )?;
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS revision_metadata (
+            name TEXT NOT NULL,
+            revision TEXT NOT NULL,
+            catalogue_version TEXT,
+            catalogue_date TEXT,
+            PRIMARY KEY (name, revision)
+        )",
+        [],
+    )?;
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS package_files (
+            name TEXT NOT NULL,
+            revision TEXT NOT NULL,
+            path TEXT NOT NULL,
+            PRIMARY KEY (name, revision, path)
+        )",
+        [],
+    )?;
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS package_executes (
+            name TEXT NOT NULL,
+            revision TEXT NOT NULL,
+            position INTEGER NOT NULL,
+            line TEXT NOT NULL,
+            PRIMARY KEY (name, revision, position)
+        )",
+        [],
+    )?;
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS profile_files (
+            profile TEXT NOT NULL,
+            path TEXT NOT NULL,
+            package TEXT NOT NULL,
+            PRIMARY KEY (profile, path)
+        )",
+        [],
+    )?;
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS profile_settings (
+            profile TEXT NOT NULL,
+            key TEXT NOT NULL,
+            value TEXT NOT NULL,
+            PRIMARY KEY (profile, key)
+        )",
+        [],
+    )?;
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS watchlist (
+            name TEXT PRIMARY KEY,
+            revision TEXT NOT NULL,
+            added_at INTEGER NOT NULL DEFAULT (strftime('%s', 'now'))
+        )",
+        [],
+    )?;
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS container_checksums (
+            name TEXT NOT NULL,
+            revision TEXT NOT NULL,
+            container TEXT NOT NULL,
+            sha512 TEXT NOT NULL,
+            PRIMARY KEY (name, revision, container)
+        )",
+        [],
+    )?;
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS attestations (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            record TEXT NOT NULL,
+            prev_hash TEXT NOT NULL,
+            hash TEXT NOT NULL
+        )",
+        [],
+    )?;
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS journal (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            action TEXT NOT NULL DEFAULT 'install',
+            profile TEXT NOT NULL,
+            requested TEXT NOT NULL,
+            on_conflict TEXT NOT NULL,
+            state TEXT NOT NULL DEFAULT 'pending',
+            started_at INTEGER NOT NULL DEFAULT (strftime('%s', 'now')),
+            finished_at INTEGER
+        )",
+        [],
+    )?;
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS journal_packages (
+            journal_id INTEGER NOT NULL,
+            name TEXT NOT NULL,
+            revision TEXT NOT NULL,
+            previous TEXT,
+            package TEXT NOT NULL,
+            committed INTEGER NOT NULL DEFAULT 0,
+            PRIMARY KEY (journal_id, name)
+        )",
+        [],
+    )?;
+    // The journal once only held installs, and forgot them when they finished.
+    add_column(&conn, "journal", "action", "TEXT NOT NULL DEFAULT 'install'")?;
+    add_column(&conn, "journal", "state", "TEXT NOT NULL DEFAULT 'pending'")?;
+    add_column(&conn, "journal", "finished_at", "INTEGER")?;
+    add_column(&conn, "journal_packages", "previous", "TEXT")?;
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS script_approvals (
+            repository TEXT PRIMARY KEY,
+            approved_at INTEGER NOT NULL DEFAULT (strftime('%s', 'now'))
+        )",
+        [],
+    )?;
+    Ok(conn)
+}
+
+// For columns added to a table after databases were created with it.
+fn add_column(conn: &Connection, table: &str, column: &str, definition: &str) -> anyhow::Result<()> {
+    let exists = conn
+        .prepare(&format!("SELECT 1 FROM pragma_table_info('{}') WHERE name = ?1", table))?
+        .exists(params![column])?;
+    if !exists {
+        conn.execute(&format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, definition), [])?;
+    }
+    Ok(())
+}
+
+async fn fetch_tlpdb(config: &Config) -> anyhow::Result<HashMap<String, Package>> {
+    let mut tlpdb = fetch_main_tlpdb(config).await?;
+    repo::merge_extra(&mut tlpdb, config).await?;
+    Ok(tlpdb)
+}
+
+async fn fetch_main_tlpdb(config: &Config) -> anyhow::Result<HashMap<String, Package>> {
+    let texman_dir = dirs::home_dir()
+        .ok_or_else(|| anyhow::anyhow!("Cannot find home directory"))?
+        .join(".texman");
+    let db_dir = texman_dir.join("db");
+    let tlpdb_path = db_dir.join("tlpdb.txt");
+    let tlpdb_bin_path = db_dir.join("tlpdb.bin");
+    let tlpdb_hash_path = db_dir.join("tlpdb.sha256");
+    let tlpdb_repository_path = db_dir.join("tlpdb.repository");
+    let tlpdb_validators_path = db_dir.join("tlpdb.validators");
+
+    std::fs::create_dir_all(&db_dir)?;
+    // Caches written before the repository was recorded are assumed to match.
+    let cached_repository = fs::read_to_string(&tlpdb_repository_path).ok();
+    let same_repository = cached_repository
+        .as_deref()
+        .is_none_or(|repository| repository.trim() == config.repository.trim_end_matches('/'));
+
+    let should_fetch = if config.offline {
+        if !tlpdb_path.exists() {
+            anyhow::bail!("Offline mode: no cached TLPDB at {:?}", tlpdb_path);
+        }
+        if !same_repository {
+            log::warn!("Offline mode: using the cached TLPDB from {}", cached_repository.unwrap_or_default().trim());
+        }
+        false
+    } else if !same_repository {
+        log::info!("Repository changed to {}, refreshing TLPDB", config.repository);
+        true
+    } else if tlpdb_path.exists() {
+        let metadata = fs::metadata(&tlpdb_path)?;
+        let modified = metadata.modified()?;
+        let last_modified: DateTime<Utc> = modified.into();
+        let now = Utc::now();
         let age = now - last_modified;
         age > Duration::hours(24)
     } else {
         true
     };
 
-    if !should_fetch && tlpdb_bin_path.exists() {
-        let bin_file = File::open(&tlpdb_bin_path)?;
-        let tlpdb: HashMap<String, Package> = bincode::deserialize_from(bin_file)
-            .map_err(|e| anyhow::anyhow!("Failed to deserialize TLPDB: {}", e))?;
+    // Only one process refreshes the TLPDB at a time. Others wait for the lock and then use what
+    // it fetched, rather than downloading again and racing to write the same files.
+    let refresh_started = std::time::SystemTime::now();
+    let _refresh_lock = if should_fetch { Some(cache::lock(&tlpdb_path).await?) } else { None };
+    let should_fetch = if should_fetch && refreshed_since(&tlpdb_path, &tlpdb_repository_path, config, refresh_started) {
+        log::info!("Another texman process refreshed the TLPDB, using it");
+        false
+    } else {
+        should_fetch
+    };
+
+    let client = if should_fetch { Some(mirror::client(config)?) } else { None };
+    // With validators from the last download, the fetch itself asks whether anything changed.
+    let validators = if should_fetch && same_repository && tlpdb_path.exists() && !config.low_memory && cache::shared_dir(config, "tlpdb").is_none() {
+        TlpdbValidators::load(&tlpdb_validators_path, config)
+    } else {
+        None
+    };
+    let should_fetch = if let Some(client) = &client
+        && tlpdb_path.exists()
+        && validators.is_none()
+        && remote_tlpdb_unchanged(config, client, &tlpdb_path).await
+    {
+        log::info!("TLPDB on the mirror is unchanged, keeping {:?}", tlpdb_path);
+        File::options().write(true).open(&tlpdb_path)?.set_modified(std::time::SystemTime::now())?;
+        fs::write(&tlpdb_repository_path, config.repository.trim_end_matches('/'))?;
+        false
+    } else {
+        should_fetch
+    };
+
+    if !should_fetch && repo::is_cached(&texman_dir) && let Some(tlpdb) = load_tlpdb_bin(&tlpdb_bin_path) {
         log::info!("Loaded cached TLPDB from {:?}", tlpdb_bin_path);
         return Ok(tlpdb);
     }
 
-    let tlpdb_text = if should_fetch {
-        log::info!("Fetching fresh TLPDB from CTAN mirror");
-        let text = fetch_tlpdb_text().await?;
-        fs::write(&tlpdb_path, &text)?;
-        log::info!("Cached TLPDB at {:?}", tlpdb_path);
-        text
+    let fetched = if should_fetch && let Some(client) = &client {
+        let result = if config.low_memory {
+            lowmem::fetch_tlpdb(config, client, &tlpdb_path).await.map(|()| Some((None, None)))
+        } else {
+            fetch_tlpdb_text_shared(config, client, validators.as_ref())
+                .await
+                .map(|fetched| fetched.map(|(text, validators)| (Some(text), validators)))
+        };
+        match result {
+            Ok(Some((text, validators))) => {
+                if let Some(text) = &text {
+                    write_replacing(&tlpdb_path, text.as_bytes())?;
+                }
+                fs::write(&tlpdb_repository_path, config.repository.trim_end_matches('/'))?;
+                match validators {
+                    Some(validators) => validators.save(&tlpdb_validators_path)?,
+                    None => {
+                        let _ = fs::remove_file(&tlpdb_validators_path);
+                    }
+                }
+                log::info!("Cached TLPDB at {:?}", tlpdb_path);
+                Some(text)
+            }
+            Ok(None) => {
+                log::info!("TLPDB on the mirror is unchanged (304 Not Modified), keeping {:?}", tlpdb_path);
+                File::options().write(true).open(&tlpdb_path)?.set_modified(std::time::SystemTime::now())?;
+                fs::write(&tlpdb_repository_path, config.repository.trim_end_matches('/'))?;
+                if repo::is_cached(&texman_dir) && let Some(tlpdb) = load_tlpdb_bin(&tlpdb_bin_path) {
+                    log::info!("Loaded cached TLPDB from {:?}", tlpdb_bin_path);
+                    return Ok(tlpdb);
+                }
+                None
+            }
+            // An outdated TLPDB beats none when the network is down.
+            Err(e) if same_repository && tlpdb_path.exists() => {
+                log::warn!("Cannot refresh the TLPDB ({}); using the cached copy. Pass --offline to skip the refresh.", e);
+                None
+            }
+            Err(e) => return Err(e),
+        }
     } else {
-        log::info!("Using cached TLPDB from {:?}", tlpdb_path);
-        fs::read_to_string(&tlpdb_path)?
+        None
+    };
+    // In low-memory mode the text stays on disk; it is hashed and parsed from the file.
+    let tlpdb_text = match fetched {
+        Some(text) => text,
+        None if config.low_memory => None,
+        None => {
+            log::info!("Using cached TLPDB from {:?}", tlpdb_path);
+            Some(fs::read_to_string(&tlpdb_path)?)
+        }
+    };
+
+    let hash = match &tlpdb_text {
+        Some(text) => format!("{:x}", Sha256::digest(text.as_bytes())),
+        None => lowmem::sha256_file(&tlpdb_path)?,
     };
+    let cached_hash = fs::read_to_string(&tlpdb_hash_path).unwrap_or_default();
+    if cached_hash.trim() == hash && repo::is_cached(&texman_dir) && let Some(tlpdb) = load_tlpdb_bin(&tlpdb_bin_path) {
+        log::info!("TLPDB content unchanged, reusing {:?}", tlpdb_bin_path);
+        return Ok(tlpdb);
+    }
 
-    let tlpdb = parse_tlpdb(&tlpdb_text)?;
-    let bin_file = File::create(&tlpdb_bin_path)?;
-    bincode::serialize_into(bin_file, &tlpdb)
-        .map_err(|e| anyhow::anyhow!("Failed to serialize TLPDB: {}", e))?;
+    let spinner = ui::spinner("Parsing the package database");
+    let (tlpdb, repository) = match &tlpdb_text {
+        Some(text) => parse_tlpdb(text)?,
+        None => lowmem::parse_tlpdb_file(&tlpdb_path)?,
+    };
+    drop(tlpdb_text);
+    for warning in repo::warnings(&repository) {
+        log::warn!("{}", warning);
+    }
+    repo::save(&texman_dir, &repository)?;
+    if config.low_memory {
+        lowmem::save_tlpdb_bin(&tlpdb_bin_path, &tlpdb)?;
+    } else {
+        let _timer = timings::start(Phase::Tlpdb);
+        let mut bin = Vec::new();
+        tlpdb::write_cache(&mut bin, &tlpdb)?;
+        write_replacing(&tlpdb_bin_path, &bin)?;
+    }
+    fs::write(&tlpdb_hash_path, &hash)?;
+    drop(spinner);
     log::info!("Saved serialized TLPDB to {:?}", tlpdb_bin_path);
 
     Ok(tlpdb)
 }
 
-async fn fetch_tlpdb_text() -> anyhow::Result<String> {
-    let url = "http://mirror.ctan.org/systems/texlive/tlnet/tlpkg/texlive.tlpdb";
-    let response = reqwest::get(url).await?;
-    let content_length = response.content_length().unwrap_or(0);
-    let pb = ProgressBar::new(content_length);
-    pb.set_style(
-        ProgressStyle::default_bar()
-            .template("[{elapsed_precise}] {bar:40.cyan/blue} {bytes}/{total_bytes} ({bytes_per_sec}, {eta}")?
-            .progress_chars("##-")
-    );
+fn refreshed_since(tlpdb_path: &Path, repository_path: &Path, config: &Config, since: std::time::SystemTime) -> bool {
+    let modified = fs::metadata(tlpdb_path).and_then(|metadata| metadata.modified());
+    let repository = fs::read_to_string(repository_path).unwrap_or_default();
+    modified.is_ok_and(|modified| modified >= since) && repository.trim() == config.repository.trim_end_matches('/')
+}
+
+// Readers that don't take the refresh lock only ever see a complete old or new file.
+fn write_replacing(path: &Path, contents: &[u8]) -> anyhow::Result<()> {
+    let staged = cache::staging_path(path);
+    fs::write(&staged, contents)?;
+    fs::rename(&staged, path)?;
+    Ok(())
+}
+
+fn load_tlpdb_bin(tlpdb_bin_path: &Path) -> Option<HashMap<String, Package>> {
+    let _timer = timings::start(Phase::Tlpdb);
+    let bin_file = File::open(tlpdb_bin_path).ok()?;
+    match tlpdb::read_cache(std::io::BufReader::new(bin_file)) {
+        Ok(Some(tlpdb)) => Some(tlpdb),
+        Ok(None) => {
+            log::info!("Cached TLPDB was written by another texman version, reparsing");
+            None
+        }
+        Err(e) => {
+            log::warn!("Failed to deserialize cached TLPDB, reparsing: {}", e);
+            None
+        }
+    }
+}
+
+// The ETag and Last-Modified the mirror sent with the TLPDB, replayed as If-None-Match and
+// If-Modified-Since on the next refresh so an unchanged TLPDB costs a 304 instead of a download.
+#[derive(Serialize, serde::Deserialize, PartialEq)]
+struct TlpdbValidators {
+    repository: String,
+    etag: Option<String>,
+    last_modified: Option<String>,
+}
+
+impl TlpdbValidators {
+    fn from_response(config: &Config, response: &reqwest::Response) -> Option<TlpdbValidators> {
+        let header = |name| response.headers().get(name).and_then(|value| value.to_str().ok()).map(str::to_string);
+        let etag = header(reqwest::header::ETAG);
+        let last_modified = header(reqwest::header::LAST_MODIFIED);
+        if etag.is_none() && last_modified.is_none() {
+            return None;
+        }
+        Some(TlpdbValidators { repository: config.repository.trim_end_matches('/').to_string(), etag, last_modified })
+    }
+
+    fn load(path: &Path, config: &Config) -> Option<TlpdbValidators> {
+        let validators: TlpdbValidators = serde_json::from_str(&fs::read_to_string(path).ok()?).ok()?;
+        (validators.repository == config.repository.trim_end_matches('/')).then_some(validators)
+    }
+
+    fn save(&self, path: &Path) -> anyhow::Result<()> {
+        fs::write(path, serde_json::to_string(self)?)?;
+        Ok(())
+    }
+
+    fn headers(&self) -> reqwest::header::HeaderMap {
+        let mut headers = reqwest::header::HeaderMap::new();
+        if let Some(etag) = self.etag.as_ref().and_then(|etag| etag.parse().ok()) {
+            headers.insert(reqwest::header::IF_NONE_MATCH, etag);
+        }
+        if let Some(date) = self.last_modified.as_ref().and_then(|date| date.parse().ok()) {
+            headers.insert(reqwest::header::IF_MODIFIED_SINCE, date);
+        }
+        headers
+    }
+}
+
+// None when `validators` show the mirror's TLPDB hasn't changed. A shared cache has its own
+// freshness check, so validators aren't used with one.
+async fn fetch_tlpdb_text_shared(
+    config: &Config,
+    client: &reqwest::Client,
+    validators: Option<&TlpdbValidators>,
+) -> anyhow::Result<Option<(String, Option<TlpdbValidators>)>> {
+    let Some(shared) = cache::shared_dir(config, "tlpdb") else {
+        if validators.is_some() {
+            log::info!("Fetching TLPDB from CTAN mirror if it changed since the last download");
+        } else {
+            log::info!("Fetching fresh TLPDB from CTAN mirror");
+        }
+        return fetch_tlpdb_text(config, client, validators).await;
+    };
+    let cached = shared.path.join(cache::tlpdb_name(&config.repository));
+    let _lock = if shared.writable { Some(cache::lock(&cached).await?) } else { None };
+    if cache::is_fresh(&cached, Duration::hours(24)) {
+        log::info!("Using TLPDB from shared cache {:?}", cached);
+        return Ok(Some((fs::read_to_string(&cached)?, None)));
+    }
+
+    log::info!("Fetching fresh TLPDB from CTAN mirror");
+    let Some((text, _)) = fetch_tlpdb_text(config, client, None).await? else {
+        anyhow::bail!("The mirror answered an unconditional TLPDB request with 304 Not Modified");
+    };
+    if shared.writable {
+        let staged = cache::staging_path(&cached);
+        fs::write(&staged, &text)?;
+        cache::publish(&staged, &cached)?;
+        log::info!("Shared TLPDB at {:?}", cached);
+    }
+    Ok(Some((text, None)))
+}
+
+async fn fetch_tlpdb_text(
+    config: &Config,
+    client: &reqwest::Client,
+    validators: Option<&TlpdbValidators>,
+) -> anyhow::Result<Option<(String, Option<TlpdbValidators>)>> {
+    // The xz-compressed TLPDB is a fraction of the size of the plain text.
+    let (buffer, validators) = match download_tlpdb_xz(config, client, validators).await {
+        Ok(None) => return Ok(None),
+        Ok(Some((path, validators))) => {
+            let compressed = fs::read(&path);
+            remove_tlpdb_xz(&path);
+            let compressed = compressed?;
+            let mut buffer = Vec::new();
+            XzDecoder::new(compressed.as_slice()).read_to_end(&mut buffer)?;
+            log::debug!("Decompressed TLPDB from {} to {} bytes", compressed.len(), buffer.len());
+            (buffer, validators)
+        }
+        Err(e) => {
+            log::debug!("No compressed TLPDB on the mirror ({}), fetching the plain text", e);
+            match download_tlpdb_bytes(config, client, "tlpkg/texlive.tlpdb", validators).await? {
+                Some(downloaded) => downloaded,
+                None => return Ok(None),
+            }
+        }
+    };
+
+    let tlpdb_text = String::from_utf8(buffer)
+        .map_err(|e| anyhow::anyhow!("Invalid UTF-8 in TLPDB: {}", e))?;
+    log::debug!("Fetched TLPDB ({} bytes)", tlpdb_text.len());
+    Ok(Some((tlpdb_text, validators)))
+}
+
+// texlive.tlpdb.xz goes to a file under db/ through `download_to`, so a refresh that breaks off
+// is resumed by the next one. A partial file is only resumed while the mirror still has the TLPDB
+// it was started on: the ETag and Last-Modified it was started under are kept next to it and
+// compared with those of a one-byte probe, which is the conditional request too. None when
+// `validators` show the TLPDB hasn't changed.
+async fn download_tlpdb_xz(
+    config: &Config,
+    client: &reqwest::Client,
+    validators: Option<&TlpdbValidators>,
+) -> anyhow::Result<Option<(PathBuf, Option<TlpdbValidators>)>> {
+    let mut headers = validators.map(TlpdbValidators::headers).unwrap_or_default();
+    headers.insert(reqwest::header::RANGE, "bytes=0-0".parse()?);
+    let (_, probe) = mirror::get_with_headers(config, client, "tlpkg/texlive.tlpdb.xz", &headers).await?;
+    if probe.status() == reqwest::StatusCode::NOT_MODIFIED {
+        return Ok(None);
+    }
+    let current = TlpdbValidators::from_response(config, &probe);
+    drop(probe);
+
+    let db_dir = dirs::home_dir()
+        .ok_or_else(|| anyhow::anyhow!("Cannot find home directory"))?
+        .join(".texman")
+        .join("db");
+    fs::create_dir_all(&db_dir)?;
+    let path = db_dir.join(format!("{}.xz.partial", cache::tlpdb_name(&config.repository)));
+    let started_under = path.with_extension("validators");
+    if current.is_none() || TlpdbValidators::load(&started_under, config) != current {
+        let _ = fs::remove_file(&path);
+    }
+    match &current {
+        Some(current) => current.save(&started_under)?,
+        None => {
+            let _ = fs::remove_file(&started_under);
+        }
+    }
+    download_to(config, client, "tlpkg/texlive.tlpdb.xz", &path, "texlive.tlpdb.xz").await?;
+    Ok(Some((path, current)))
+}
+
+// Once read, or found broken, a downloaded texlive.tlpdb.xz is of no further use.
+fn remove_tlpdb_xz(path: &Path) {
+    let _ = fs::remove_file(path);
+    let _ = fs::remove_file(path.with_extension("validators"));
+}
+
+// Mirrors publish the TLPDB's SHA-512 next to it, so an unchanged database costs one tiny request.
+async fn remote_tlpdb_unchanged(config: &Config, client: &reqwest::Client, cached_path: &Path) -> bool {
+    let _timer = timings::start(Phase::Download);
+    let url = config.repository_url("tlpkg/texlive.tlpdb.sha512");
+    let remote = match mirror::local_path(&url) {
+        Some(local) => fs::read_to_string(local).unwrap_or_default(),
+        None => match mirror::send(config, || client.get(&url)).await {
+            Ok(response) => response.text().await.unwrap_or_default(),
+            Err(e) => {
+                log::debug!("Cannot fetch {}: {}", url, e);
+                return false;
+            }
+        },
+    };
+    let Some(remote_hash) = remote.split_whitespace().next() else { return false };
+    let mut hasher = Sha512::new();
+    let Ok(_) = File::open(cached_path).and_then(|mut file| std::io::copy(&mut file, &mut hasher)) else { return false };
+    format!("{:x}", hasher.finalize()).eq_ignore_ascii_case(remote_hash)
+}
+
+// None when the mirror answers the conditional request with 304 Not Modified.
+async fn download_tlpdb_bytes(
+    config: &Config,
+    client: &reqwest::Client,
+    path: &str,
+    validators: Option<&TlpdbValidators>,
+) -> anyhow::Result<Option<(Vec<u8>, Option<TlpdbValidators>)>> {
+    let _timer = timings::start(Phase::Download);
+    let headers = validators.map(TlpdbValidators::headers).unwrap_or_default();
+    let (_, response) = mirror::get_with_headers(config, client, path, &headers).await?;
+    if response.status() == reqwest::StatusCode::NOT_MODIFIED {
+        return Ok(None);
+    }
+    let validators = TlpdbValidators::from_response(config, &response);
+    let progress = hooks::observer().start(path, response.content_length(), 0);
 
     let mut buffer = Vec::new();
     let mut stream = response.bytes_stream();
     while let Some(chunk) = stream.next().await {
         let chunk = chunk?;
         buffer.extend_from_slice(&chunk);
-        pb.inc(chunk.len() as u64);
+        progress.advance(chunk.len() as u64);
+        summary::record_download(chunk.len() as u64);
     }
-    pb.finish_with_message("Downloaded TLPDB");
+    progress.finish();
+    Ok(Some((buffer, validators)))
+}
 
-    let tlpdb_text = String::from_utf8(buffer)
-        .map_err(|e| anyhow::anyhow!("Invalid UTF-8 in TLPDB: {}", e))?;
-    log::debug!("Fetched TLPDB ({} bytes)", tlpdb_text.len());
-    Ok(tlpdb_text)
+fn record_origin(conn: &Connection, profile: &str, pkg: &Package) -> anyhow::Result<()> {
+    conn.execute(
+        "INSERT OR REPLACE INTO package_origins (profile, name, repository) VALUES (?1, ?2, ?3)",
+        params![profile, pkg.name, pkg.repository.as_deref().unwrap_or(repo::MAIN)],
+    )?;
+    Ok(())
 }
 
-fn parse_tlpdb(tlpdb_text: &str) -> anyhow::Result<HashMap<String, Package>> {
-    let blocks: Vec<&str> = tlpdb_text.split("\n\n").filter(|b| !b.trim().is_empty()).collect();
-    let packages: Vec<Package> = blocks.par_iter().filter_map(|block| {
-        let mut pkg = Package {
-            name: String::new(),
-            revision: "unknown".to_string(),
-            url: String::new(),
-            depends: Vec::new(),
-            runfiles: Vec::new(),
-            binfiles: Vec::new(),
-            description: None,
-            longdesc: None,
-        };
-        let mut in_runfiles = false;
-        let mut in_binfiles = false;
-        let mut in_longdesc = false;
-        let mut longdesc_lines = Vec::new();
-
-        for line in block.lines() {
-            let line = line.trim();
-            if in_longdesc {
-                if line.is_empty() || line.starts_with("name ") {
-                    in_longdesc = false;
-                    pkg.longdesc = Some(longdesc_lines.join("\n"));
-                    longdesc_lines.clear();
-                } else {
-                    longdesc_lines.push(line.to_string());
-                    continue;
-                }
-            }
+// Where each package installed in `profile` came from. Packages installed before origins were
+// recorded have no entry and count as coming from the main repository.
+fn installed_origins(conn: &Connection, profile: &str) -> anyhow::Result<HashMap<String, String>> {
+    let mut stmt = conn.prepare("SELECT name, repository FROM package_origins WHERE profile = ?1")?;
+    let origins = stmt
+        .query_map(params![profile], |row| Ok((row.get(0)?, row.get(1)?)))?
+        .collect::<Result<_, _>>()?;
+    Ok(origins)
+}
 
-            if line.starts_with("name ") {
-                pkg.name = line[5..].to_string();
-                pkg.url = format!("http://mirror.ctan.org/systems/texlive/tlnet/archive/{}.tar.xz", pkg.name);
-            } else if line == "runfiles" {
-                in_runfiles = true;
-                in_binfiles = false;
-            } else if line == "binfiles" {
-                in_runfiles = false;
-                in_binfiles = true;
-            } else if line.starts_with("depends ") {
-                let deps = &line[8..];
-                if !deps.is_empty() {
-                    pkg.depends.extend(deps.split(',').map(|s| s.trim().to_string()));
-                }
-                in_runfiles = false;
-                in_binfiles = false;
-            } else if line.starts_with("revision ") {
-                pkg.revision = line[9..].to_string();
-                in_runfiles = false;
-                in_binfiles = false;
-            } else if line.starts_with("shortdesc ") {
-                pkg.description = Some(line[10..].to_string());
-                in_runfiles = false;
-                in_binfiles = false;
-            } else if line.starts_with("longdesc ") {
-                in_longdesc = true;
-                longdesc_lines.push(line[9..].to_string());
-                in_runfiles = false;
-                in_binfiles = false;
-            } else if in_runfiles && line.starts_with(' ') {
-                pkg.runfiles.push(line.trim_start().to_string());
-            } else if in_binfiles && line.starts_with(' ') {
-                pkg.binfiles.push(line.trim_start().to_string());
-            }
-        }
-
-        if in_longdesc && !longdesc_lines.is_empty() {
-            pkg.longdesc = Some(longdesc_lines.join("\n"));
-        }
-
-        if pkg.name.is_empty() { None } else { Some(pkg) }
-    }).collect();
-
-    let mut tlpdb = HashMap::with_capacity(packages.len());
+fn record_revision_metadata(conn: &Connection, pkg: &Package) -> anyhow::Result<()> {
+    conn.execute(
+        "INSERT OR REPLACE INTO revision_metadata (name, revision, catalogue_version, catalogue_date) VALUES (?1, ?2, ?3, ?4)",
+        params![pkg.name, pkg.revision, pkg.catalogue_version, pkg.catalogue_date],
+    )?;
+    let mut stmt = conn.prepare("INSERT OR IGNORE INTO package_files (name, revision, path) VALUES (?1, ?2, ?3)")?;
+    for path in pkg.runfiles.iter().chain(&pkg.binfiles) {
+        stmt.execute(params![pkg.name, pkg.revision, tree::fold_key(path, false)])?;
+    }
+    execute::record(conn, pkg)
+}
+
+fn profile_setting(conn: &Connection, profile: &str, key: &str) -> anyhow::Result<Option<String>> {
+    Ok(conn
+        .query_row(
+            "SELECT value FROM profile_settings WHERE profile = ?1 AND key = ?2",
+            params![profile, key],
+            |row| row.get(0),
+        )
+        .optional()?)
+}
+
+fn set_profile_setting(conn: &Connection, profile: &str, key: &str, value: &str) -> anyhow::Result<()> {
+    conn.execute(
+        "INSERT OR REPLACE INTO profile_settings (profile, key, value) VALUES (?1, ?2, ?3)",
+        params![profile, key, value],
+    )?;
+    Ok(())
+}
+
+fn conflict_policy(conn: &Connection, profile: &str, config: &Config, flag: Option<ConflictPolicy>) -> anyhow::Result<ConflictPolicy> {
+    if let Some(policy) = flag {
+        set_profile_setting(conn, profile, "on_conflict", policy.as_str())?;
+        log::info!("Using conflict policy '{}' for profile '{}'", policy.as_str(), profile);
+        return Ok(policy);
+    }
+    match profile_setting(conn, profile, "on_conflict")? {
+        Some(value) => ConflictPolicy::parse(&value),
+        None => Ok(config.on_conflict),
+    }
+}
+
+fn install_strategy(conn: &Connection, profile: &str, config: &Config) -> anyhow::Result<InstallStrategy> {
+    match profile_setting(conn, profile, "install_strategy")? {
+        Some(value) => InstallStrategy::parse(&value),
+        None => Ok(config.install_strategy),
+    }
+}
+
+fn check_file_conflicts(
+    conn: &Connection,
+    profile: &str,
+    profile_dir: &Path,
+    packages: &[Package],
+    policy: ConflictPolicy,
+) -> anyhow::Result<()> {
+    let case_insensitive = tree::is_case_insensitive(profile_dir);
+    if case_insensitive {
+        log::warn!("Profile '{}' is on a case-insensitive filesystem; file names differing only in case are treated as conflicts", profile);
+    }
+
+    let mut owners: HashMap<String, &str> = HashMap::new();
+    let mut conflicts = Vec::new();
     for pkg in packages {
-        tlpdb.insert(pkg.name.clone(), pkg);
+        for path in pkg.runfiles.iter().chain(&pkg.binfiles) {
+            if let Some(other) = owners.insert(tree::fold_key(path, case_insensitive), &pkg.name)
+                && other != pkg.name
+            {
+                conflicts.push((path.clone(), other.to_string(), pkg.name.clone()));
+            }
+        }
     }
 
-    log::info!("Parsed {} packages from TLPDB", tlpdb.len());
-    Ok(tlpdb)
+    let mut stmt = conn.prepare(
+        "SELECT f.name, f.path FROM installed_packages i
+         JOIN package_files f ON f.name = i.name AND f.revision = i.revision
+         WHERE i.profile = ?1",
+    )?;
+    let rows = stmt.query_map(params![profile], |row| {
+        Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+    })?;
+    for row in rows {
+        let (owner, path) = row?;
+        if packages.iter().any(|p| p.name == owner) {
+            continue;
+        }
+        if let Some(pkg) = owners.get(&tree::fold_key(&path, case_insensitive)) {
+            conflicts.push((path, owner, pkg.to_string()));
+        }
+    }
+
+    if conflicts.is_empty() {
+        return Ok(());
+    }
+
+    conflicts.sort();
+    if policy != ConflictPolicy::Error {
+        for (path, first, second) in &conflicts {
+            log::warn!("File conflict: {} is provided by both {} and {}; resolving with '{}'", path, first, second, policy.as_str());
+        }
+        return Ok(());
+    }
+    for (path, first, second) in conflicts.iter().take(20) {
+        log::error!("File conflict: {} is provided by both {} and {}", path, first, second);
+    }
+    if conflicts.len() > 20 {
+        log::error!("...and {} more conflicting files", conflicts.len() - 20);
+    }
+    anyhow::bail!("{} file conflicts detected in profile '{}'; nothing was installed", conflicts.len(), profile)
+}
+
+// Collections and schemes hold nothing but their member lists, so installing one means installing
+// everything it pulls in, other collections included.
+fn is_grouping(name: &str) -> bool {
+    name.starts_with("collection-") || name.starts_with("scheme-")
+}
+
+// The packages to install for `requested`, dependencies first; just `requested` itself with
+// --no-depends-at-all.
+fn resolve_requested(requested: &[String], tlpdb: &HashMap<String, Package>, config: &Config) -> anyhow::Result<Vec<String>> {
+    let _timer = timings::start(Phase::Resolve);
+    let mut resolved = Vec::new();
+    if config.no_depends_at_all {
+        for package in requested {
+            if !tlpdb.contains_key(package) {
+                anyhow::bail!("Package '{}' not found in TLPDB", package);
+            }
+            if !resolved.contains(package) {
+                resolved.push(package.clone());
+            }
+        }
+        return Ok(resolved);
+    }
+    let mut visited = Vec::new();
+    for package in requested {
+        resolve_dependencies(package, tlpdb, &mut resolved, &mut visited)?;
+    }
+    Ok(resolved)
+}
+
+// The packages `pkg` depends on, each `<name>.ARCH` replaced by the package with the selected
+// platform's binaries. Without one in the TLPDB, that dependency is dropped, as tlmgr does.
+fn dependencies<'a>(pkg: &'a Package, tlpdb: &'a HashMap<String, Package>) -> Vec<&'a str> {
+    let mut dependencies: Vec<&str> = pkg.depends.iter().map(String::as_str).collect();
+    if let Some(platform) = platform::current() {
+        for base in &pkg.arch_depends {
+            if let Some((name, _)) = tlpdb.get_key_value(&format!("{}.{}", base, platform)) {
+                dependencies.push(name);
+            }
+        }
+    }
+    dependencies
 }
 
 fn resolve_dependencies(
@@ -357,308 +1986,972 @@ fn resolve_dependencies(
 
     visited.push(pkg.name.clone());
 
-    for dep in &pkg.depends {
-        if !resolved.contains(dep) {
-            log::debug!("Resolving dependency: {}", dep);
-            resolve_dependencies(dep, tlpdb, resolved, visited)?;
-            resolved.push(dep.clone());
+    for base in &pkg.arch_depends {
+        match platform::current() {
+            Some(platform) if !tlpdb.contains_key(&format!("{}.{}", base, platform)) => {
+                log::warn!("The repository has no {} binaries of {}; {} is installed without them", platform, base, pkg.name);
+            }
+            Some(_) => {}
+            None => log::warn!("TeX Live has no binaries for this platform (pick one with --platform); {} is installed without those of {}", pkg.name, base),
+        }
+    }
+    for dep in dependencies(pkg, tlpdb) {
+        // A collection or scheme can list members a partial mirror or older TLPDB lacks; the rest
+        // of it is still worth having.
+        if is_grouping(&pkg.name) && !tlpdb.contains_key(dep) {
+            log::warn!("{} lists {}, which is not in the TLPDB; skipping it", pkg.name, dep);
+            continue;
+        }
+        if !resolved.iter().any(|name| name == dep) {
+            log::debug!("Resolving dependency: {}", dep);
+            resolve_dependencies(dep, tlpdb, resolved, visited)?;
+        }
+    }
+
+    if !resolved.contains(&pkg.name) {
+        resolved.push(pkg.name.clone());
+    }
+
+    Ok(())
+}
+
+// The archives to download for `pkg` with their TLPDB checksums: its container, plus the doc and
+// source containers if they are wanted. A platform's binaries are packages of their own
+// (`pdftex.x86_64-linux`) with just a container, which `dependencies` pulls in.
+fn package_archives<'a>(pkg: &'a Package, config: &Config) -> Vec<(String, Option<&'a str>)> {
+    let mut archives = vec![(format!("{}.tar.xz", pkg.name), pkg.container_checksum.as_deref())];
+    if config.docs && pkg.doc_container_size.is_some() {
+        archives.push((format!("{}.doc.tar.xz", pkg.name), pkg.doc_container_checksum.as_deref()));
+    }
+    if config.sources && pkg.src_container_size.is_some() {
+        archives.push((format!("{}.source.tar.xz", pkg.name), pkg.src_container_checksum.as_deref()));
+    }
+    archives
+}
+
+async fn download_package(pkg: &Package, texman_dir: &Path, config: &Config, client: &reqwest::Client) -> anyhow::Result<Vec<PathBuf>> {
+    let repo_config = repo::config_for(config, pkg);
+    let config = repo_config.as_ref().unwrap_or(config);
+    let mut download_paths = Vec::new();
+    for (archive_name, checksum) in &package_archives(pkg, config) {
+        log::info!("Downloading {} r{} ({})", pkg.name, pkg.revision, archive_name);
+        download_paths.push(download_archive(archive_name, &pkg.revision, *checksum, texman_dir, config, client).await?);
+    }
+
+    Ok(download_paths)
+}
+
+async fn download_archive(
+    archive_name: &str,
+    revision: &str,
+    checksum: Option<&str>,
+    texman_dir: &Path,
+    config: &Config,
+    client: &reqwest::Client,
+) -> anyhow::Result<PathBuf> {
+    let url_path = format!("archive/{}", archive_name);
+    if let Some(shared) = cache::shared_dir(config, "archives") {
+        let cached = shared.path.join(cache::archive_name(archive_name, revision));
+        let _lock = if shared.writable { Some(cache::lock(&cached).await?) } else { None };
+        if cached.exists() {
+            if checksum_matches(&cached, checksum)? {
+                log::info!("Using {} from shared cache {:?}", archive_name, shared.path);
+                return Ok(cached);
+            }
+            log::warn!("{:?} in the shared cache does not match the TLPDB checksum", cached);
+            if shared.writable {
+                fs::remove_file(&cached)?;
+            }
+        }
+        if shared.writable {
+            let staged = cache::staging_path(&cached);
+            download_verified(config, client, &url_path, &staged, archive_name, checksum).await?;
+            cache::publish(&staged, &cached)?;
+            return Ok(cached);
+        }
+    }
+
+    // A complete archive left by an interrupted install is reused; partial ones only ever exist as `.part`.
+    let download_path = texman_dir.join(cache::archive_name(archive_name, revision));
+    if download_path.exists() {
+        if checksum_matches(&download_path, checksum)? {
+            log::info!("Reusing downloaded {}", archive_name);
+            return Ok(download_path);
+        }
+        fs::remove_file(&download_path)?;
+    }
+    let mut partial = download_path.as_os_str().to_os_string();
+    partial.push(".part");
+    let partial = PathBuf::from(partial);
+    download_verified(config, client, &url_path, &partial, archive_name, checksum).await?;
+    fs::rename(&partial, &download_path)?;
+    Ok(download_path)
+}
+
+fn checksum_matches(path: &Path, checksum: Option<&str>) -> anyhow::Result<bool> {
+    let Some(expected) = checksum else { return Ok(true) };
+    let mut hasher = Sha512::new();
+    std::io::copy(&mut File::open(path)?, &mut hasher)?;
+    Ok(format!("{:x}", hasher.finalize()).eq_ignore_ascii_case(expected))
+}
+
+// A mismatch usually means the mirror was caught mid-sync, so the archive is fetched again
+// a few times before giving up.
+async fn download_verified(
+    config: &Config,
+    client: &reqwest::Client,
+    url_path: &str,
+    path: &Path,
+    archive_name: &str,
+    checksum: Option<&str>,
+) -> anyhow::Result<()> {
+    // Offline, only archives already in the download cache or the shared cache can be used.
+    if config.offline {
+        anyhow::bail!("Offline mode: {} is not in the download cache", archive_name);
+    }
+    for attempt in 1..=DOWNLOAD_ATTEMPTS {
+        download_to(config, client, url_path, path, archive_name).await?;
+        if checksum_matches(path, checksum)? {
+            return Ok(());
+        }
+        fs::remove_file(path)?;
+        log::warn!("Checksum mismatch for {} (attempt {} of {})", archive_name, attempt, DOWNLOAD_ATTEMPTS);
+    }
+    anyhow::bail!(
+        "{} does not match the TLPDB checksum after {} downloads; the mirror may be out of sync. Try again later or use another mirror with --repository.",
+        archive_name,
+        DOWNLOAD_ATTEMPTS
+    )
+}
+
+// A partial file left at `download_path` by an interrupted download is continued with a Range
+// request, and so is a transfer that breaks off midway, up to `retries` times. The file stays in
+// place when texman gives up, so the next run resumes it.
+async fn download_to(config: &Config, client: &reqwest::Client, url_path: &str, download_path: &Path, archive_name: &str) -> anyhow::Result<()> {
+    let _timer = timings::start(Phase::Download);
+    let mut attempt = 0;
+    'download: loop {
+        let mut offset = fs::metadata(download_path).map(|metadata| metadata.len()).unwrap_or(0);
+        let (_, mut response) = mirror::get_from(config, client, url_path, offset).await?;
+        if response.status() == reqwest::StatusCode::RANGE_NOT_SATISFIABLE {
+            log::info!("Cannot resume {}; downloading it again", archive_name);
+            offset = 0;
+            response = mirror::get(config, client, url_path).await?.1;
+        }
+        let resumed = offset > 0 && response.status() == reqwest::StatusCode::PARTIAL_CONTENT;
+        if resumed {
+            log::info!("Resuming {} after {}", archive_name, output::format_size(offset));
+        }
+        let start = if resumed { offset } else { 0 };
+        let expected = response.content_length().map(|length| start + length);
+        let progress = hooks::observer().start(archive_name, expected, start);
+
+        let mut file = if resumed { fs::OpenOptions::new().append(true).open(download_path)? } else { File::create(download_path)? };
+        let mut stream = response.bytes_stream();
+        while let Some(chunk) = stream.next().await {
+            let chunk = match chunk {
+                Ok(chunk) => chunk,
+                Err(e) if attempt < config.retries => {
+                    attempt += 1;
+                    let delay = mirror::backoff(config, attempt);
+                    progress.abandon();
+                    log::warn!(
+                        "Download of {} interrupted: {}; resuming in {:.1}s (attempt {} of {})",
+                        archive_name,
+                        e,
+                        delay.as_secs_f64(),
+                        attempt,
+                        config.retries
+                    );
+                    tokio::time::sleep(delay).await;
+                    continue 'download;
+                }
+                Err(e) => anyhow::bail!("Download of {} interrupted: {}; run the command again to resume it", archive_name, e),
+            };
+            file.write_all(&chunk)?;
+            progress.advance(chunk.len() as u64);
+            summary::record_download(chunk.len() as u64);
+        }
+        progress.finish();
+
+        let actual = file.metadata()?.len();
+        if let Some(expected) = expected
+            && actual != expected
+        {
+            anyhow::bail!(
+                "Download of {} is incomplete ({} of {} bytes); run the command again to resume it",
+                archive_name,
+                actual,
+                expected
+            );
+        }
+        return Ok(());
+    }
+}
+
+const DOWNLOAD_ATTEMPTS: u32 = 3;
+
+fn store_path(texman_dir: &Path, name: &str, revision: &str) -> PathBuf {
+    texman_dir.join("store").join(format!("{}-r{}", name, revision))
+}
+
+fn stored_revisions(texman_dir: &Path, package: &str) -> anyhow::Result<Vec<String>> {
+    let store_dir = texman_dir.join("store");
+    let prefix = format!("{}-r", package);
+    let mut revisions = Vec::new();
+    if store_dir.exists() {
+        for entry in fs::read_dir(&store_dir)? {
+            let file_name = entry?.file_name();
+            let Some(file_name) = file_name.to_str() else { continue };
+            if let Some(revision) = file_name.strip_prefix(&prefix)
+                && let revision = revision.trim_end_matches(".tar.zst")
+                && !revision.is_empty()
+                && revision.chars().all(|c| c.is_ascii_digit())
+            {
+                revisions.push(revision.to_string());
+            }
         }
     }
+    revisions.sort_by_key(|r| r.parse::<u32>().unwrap_or(0));
+    Ok(revisions)
+}
 
-    if !resolved.contains(&pkg.name) {
-        resolved.push(pkg.name.clone());
+// Moves `source` to `dest`, merging it into what is already there.
+fn move_into(source: &Path, dest: &Path) -> anyhow::Result<()> {
+    if !dest.is_dir() || !source.is_dir() {
+        fs::rename(source, dest)?;
+        return Ok(());
     }
-
+    for entry in fs::read_dir(source)? {
+        let entry = entry?;
+        move_into(&entry.path(), &dest.join(entry.file_name()))?;
+    }
+    fs::remove_dir(source)?;
     Ok(())
 }
 
-async fn download_package(pkg: &Package, texman_dir: &PathBuf) -> anyhow::Result<PathBuf> {
-    let platform = std::env::consts::ARCH;
-    let os = std::env::consts::OS;
-    let platform_suffix = match (platform, os) {
-        ("x86_64", "linux") => "x86_64-linux",
-        ("x86_64", "macos") => "x86_64-darwin",
-        _ => "",
-    };
-
-    let mut archive_name = format!("{}.tar.xz", pkg.name);
-    let mut url = pkg.url.clone();
-
-    for file in &pkg.binfiles {
-        if file.ends_with(&format!("{}.{}.tar.xz", pkg.name, platform_suffix)) {
-            archive_name = format!("{}.{}.tar.xz", pkg.name, platform_suffix);
-            url = format!(
-                "http://mirror.ctan.org/systems/texlive/tlnet/archive/{}",
-                archive_name
-            );
-            break;
+// Relocatable packages hold their files relative to the tree they go into, either at the top of
+// the container or below RELOC/, and their tlpobj says `relocated 1`. Like tlmgr, texman puts
+// them into texmf-dist, where kpathsea looks; only the tlpobj stays in tlpkg/.
+fn relocate(staging: &Path, pkg: &Package) -> anyhow::Result<()> {
+    let texmf_dist = staging.join("texmf-dist");
+    let reloc = staging.join("RELOC");
+    if reloc.is_dir() {
+        move_into(&reloc, &texmf_dist)?;
+    }
+    let tlpobj_relocated = fs::read_dir(staging.join("tlpkg").join("tlpobj"))
+        .into_iter()
+        .flatten()
+        .filter_map(Result::ok)
+        .any(|entry| fs::read_to_string(entry.path()).is_ok_and(|tlpobj| tlpobj.lines().any(|line| line.trim_end() == "relocated 1")));
+    if !pkg.relocated && !tlpobj_relocated {
+        return Ok(());
+    }
+    for entry in fs::read_dir(staging)? {
+        let entry = entry?;
+        if entry.file_name() == "texmf-dist" || entry.file_name() == "tlpkg" {
+            continue;
         }
+        fs::create_dir_all(&texmf_dist)?;
+        move_into(&entry.path(), &texmf_dist.join(entry.file_name()))?;
     }
+    log::info!("Relocated {} into texmf-dist", pkg.name);
+    Ok(())
+}
 
-    if url == pkg.url {
-        for file in &pkg.runfiles {
-            if file.ends_with(&format!("{}.tar.xz", pkg.name)) {
-                archive_name = format!("{}.tar.xz", pkg.name);
-                url = format!(
-                    "http://mirror.ctan.org/systems/texlive/tlnet/archive/{}",
-                    archive_name
-                );
-                break;
-            }
+// Unpacks next to the store path and renames it into place, so an interrupted unpack never
+// leaves a half-filled revision that later looks complete.
+fn unpack_package(pkg: &Package, download_paths: &[PathBuf], store_path: &Path, config: &Config) -> anyhow::Result<()> {
+    let _timer = timings::start(Phase::Unpack);
+    let mut staging = store_path.as_os_str().to_os_string();
+    staging.push(".unpacking");
+    let staging = PathBuf::from(staging);
+    if staging.exists() {
+        fs::remove_dir_all(&staging)?;
+    }
+    std::fs::create_dir_all(&staging)?;
+    for download_path in download_paths {
+        let tar_xz = File::open(download_path)?;
+        let tar = XzDecoder::new(tar_xz);
+        let mut archive = tar::Archive::new(tar);
+        archive.unpack(&staging)
+            .map_err(|e| anyhow::anyhow!("Failed to unpack {}: {}", pkg.name, e))?;
+    }
+    relocate(&staging, pkg)?;
+    perms::normalize(&staging, config.permissions)?;
+    store::dedup(&staging)?;
+    fs::rename(&staging, store_path)?;
+    for download_path in download_paths {
+        // Archives in a shared cache stay for other users.
+        if !config.shared_cache.as_ref().is_some_and(|shared| download_path.starts_with(shared)) {
+            std::fs::remove_file(download_path)?;
         }
     }
+    Ok(())
+}
 
-    let download_path = texman_dir.join(&archive_name);
-    log::info!("Downloading {} r{} from {}", pkg.name, pkg.revision, url);
-    let response = reqwest::get(&url).await
-        .map_err(|e| anyhow::anyhow!("Failed to download {}: {}", url, e))?;
-    let content_length = response.content_length().unwrap_or(0);
-    let pb = ProgressBar::new(content_length);
-    pb.set_style(
-        ProgressStyle::default_bar()
-            .template("[{elapsed_precise}] {bar:40.green/yellow} {bytes}/{total_bytes} ({bytes_per_sec}, {eta}")?
-            .progress_chars("##-")
-    );
-
-    let mut file = File::create(&download_path)?;
-    let mut stream = response.bytes_stream();
-    while let Some(chunk) = stream.next().await {
-        let chunk = chunk?;
-        file.write_all(&chunk)?;
-        pb.inc(chunk.len() as u64);
+// Makes an earlier revision available in the store again for `undo` and rollbacks: expanded if
+// it was compressed, or unpacked again from its archives when the shared cache, or an earlier
+// download, still has them with the checksums recorded at install time. `template` is another
+// revision of the package, for how to unpack it.
+fn restore_revision(conn: &Connection, texman_dir: &Path, config: &Config, name: &str, revision: &str, template: Option<&Package>) -> anyhow::Result<PathBuf> {
+    let store_path = store_path(texman_dir, name, revision);
+    if store::ensure_expanded(&store_path)? {
+        return Ok(store_path);
     }
-    pb.finish_with_message(format!("Downloaded {}", pkg.name));
+    let missing = || anyhow::anyhow!("{} r{} is no longer in the store, and its archives are not cached", name, revision);
+    let mut stmt = conn.prepare("SELECT container, sha512 FROM container_checksums WHERE name = ?1 AND revision = ?2 ORDER BY container")?;
+    let containers = stmt
+        .query_map(params![name, revision], |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)))?
+        .collect::<Result<Vec<_>, _>>()?;
+    let Some(template) = template.filter(|_| !containers.is_empty()) else {
+        return Err(missing());
+    };
+    let shared = cache::shared_dir(config, "archives").map(|shared| shared.path);
+    let mut download_paths = Vec::new();
+    for (container, sha512) in &containers {
+        let file_name = cache::archive_name(container, revision);
+        let cached = shared
+            .as_deref()
+            .into_iter()
+            .chain([texman_dir])
+            .map(|dir| dir.join(&file_name))
+            .find(|path| path.exists() && checksum_matches(path, Some(sha512)).unwrap_or(false));
+        download_paths.push(cached.ok_or_else(missing)?);
+    }
+    log::info!("Unpacking {} r{} again from cached archives", name, revision);
+    let pkg = Package { revision: revision.to_string(), ..template.clone() };
+    unpack_package(&pkg, &download_paths, &store_path, config)?;
+    Ok(store_path)
+}
 
-    Ok(download_path)
+// The named profile, or the active one, and its directory.
+fn existing_profile(profile: Option<String>) -> anyhow::Result<(String, PathBuf)> {
+    let texman_dir = dirs::home_dir()
+        .ok_or_else(|| anyhow::anyhow!("Cannot find home directory"))?
+        .join(".texman");
+    let profile = match profile {
+        Some(profile) => profile,
+        None => active::name(&texman_dir)?,
+    };
+    let profile_dir = texman_dir.join("profiles").join(&profile);
+    if !profile_dir.exists() {
+        anyhow::bail!("Profile '{}' does not exist", profile);
+    }
+    Ok((profile, profile_dir))
 }
 
-async fn install_package(package: &str, profile: &str, tlpdb: &HashMap<String, Package>) -> anyhow::Result<()> {
+// With a DESTDIR everything is written below it, but links and the database only
+// record paths relative to ~/.texman, so the staged tree works once moved into place.
+fn texman_root(destdir: Option<&Path>) -> anyhow::Result<PathBuf> {
     let texman_dir = dirs::home_dir()
         .ok_or_else(|| anyhow::anyhow!("Cannot find home directory"))?
         .join(".texman");
-    let profile_dir = texman_dir.join("profiles").join(profile);
-    std::fs::create_dir_all(&profile_dir)?;
+    Ok(match destdir {
+        // Without the root or drive, so it lands below destdir: /home/a/.texman or C:\Users\a\.texman
+        // become <destdir>/home/a/.texman and <destdir>/Users/a/.texman.
+        Some(destdir) => destdir.join(texman_dir.components().filter(|component| matches!(component, Component::Normal(_))).collect::<PathBuf>()),
+        None => texman_dir,
+    })
+}
 
+// Changes to one profile are serialized, across texman processes and `query serve` requests;
+// changes to different profiles run side by side. Held until the returned files are dropped.
+fn profile_lock(texman_dir: &Path, profile: &str) -> PathBuf {
+    texman_dir.join("locks").join(format!("profile-{}", profile))
+}
+
+// Unpacking into the store and pruning it are serialized the same way, so a `remove` or `clean`
+// never prunes what an install in another profile is unpacking.
+fn store_lock(texman_dir: &Path) -> PathBuf {
+    texman_dir.join("locks").join("store")
+}
+
+async fn lock_profiles(texman_dir: &Path, profiles: &[&str]) -> anyhow::Result<Vec<fs::File>> {
+    fs::create_dir_all(texman_dir.join("locks"))?;
+    // Always in the same order, so two installs into overlapping profiles can't deadlock.
+    let mut profiles = profiles.to_vec();
+    profiles.sort_unstable();
+    profiles.dedup();
+    let mut locks = Vec::new();
+    for profile in profiles {
+        locks.push(cache::lock(&profile_lock(texman_dir, profile)).await?);
+    }
+    Ok(locks)
+}
+
+async fn install_package(
+    requested: &[String],
+    profiles: &[String],
+    tlpdb: &HashMap<String, Package>,
+    config: &Config,
+    on_conflict: Option<ConflictPolicy>,
+    destdir: Option<&Path>,
+    allow_scripts: bool,
+) -> anyhow::Result<Vec<(String, String)>> {
+    let texman_dir = texman_root(destdir)?;
+    if destdir.is_some() {
+        log::info!("Staging install under {:?}", texman_dir);
+    }
     let conn = init_db(&texman_dir)?;
 
-    let mut to_install = Vec::new();
-    let mut visited = Vec::new();
-    resolve_dependencies(package, tlpdb, &mut to_install, &mut visited)?;
+    // One shared resolution, so overlapping dependency closures yield each package once.
+    let to_install = resolve_requested(requested, tlpdb, config)?;
 
     if to_install.is_empty() {
-        log::info!("No packages to install ({} already resolved)", package);
-        return Ok(());
+        log::info!("No packages to install ({} already resolved)", requested.join(", "));
+        return Ok(Vec::new());
     }
     log::info!("Packages to install: {:?}", to_install);
+    let _locks = lock_profiles(&texman_dir, &profiles.iter().map(String::as_str).collect::<Vec<_>>()).await?;
 
     let packages: Vec<Package> = to_install
         .iter()
         .map(|pkg_name| tlpdb.get(pkg_name).unwrap().clone())
         .collect();
 
+    // Every profile is checked before anything is installed, so a conflict in one leaves all untouched.
+    let mut targets = Vec::new();
+    for profile in profiles {
+        let profile_dir = texman_dir.join("profiles").join(profile);
+        std::fs::create_dir_all(&profile_dir)?;
+        let policy = conflict_policy(&conn, profile, config, on_conflict)?;
+        check_file_conflicts(&conn, profile, &profile_dir, &packages, policy)?;
+        targets.push((profile, policy));
+    }
+    scripts::ensure_allowed(&conn, config, &packages, allow_scripts)?;
+
+    // The store is shared, so packages are downloaded once and then linked into each profile.
+    let steps: Vec<(&str, Option<&str>, Option<&Package>)> = packages.iter().map(|pkg| (pkg.name.as_str(), Some(pkg.revision.as_str()), Some(pkg))).collect();
+    let targets = targets
+        .into_iter()
+        .map(|(profile, policy)| Ok((profile, policy, journal::begin(&conn, "install", profile, requested, policy, &steps)?)))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    let profile_names: Vec<&str> = targets.iter().map(|(profile, _, _)| profile.as_str()).collect();
+    let failed = populate_store(&conn, &texman_dir, &packages, config, &profile_names).await?;
+    let packages: Vec<Package> = packages.into_iter().filter(|pkg| !failed.iter().any(|(name, _)| *name == pkg.name)).collect();
+    for (profile, policy, journal_id) in targets {
+        link_resolved(&conn, &texman_dir, profile, &packages, config, policy, journal_id)?;
+        let profile_dir = texman_dir.join("profiles").join(profile);
+        tree::refresh(&conn, profile, &profile_dir, tlpdb)?;
+        formats::after_change(&conn, config, profile, &profile_dir, tlpdb, &packages);
+        fontmaps::after_change(config, &profile_dir, &packages);
+        journal::finish(&conn, journal_id)?;
+    }
+    Ok(failed)
+}
+
+// Lists what --keep-going skipped and fails the command, so scripts still see an error.
+fn report_failures(failed: &[(String, String)], retry: &str) -> anyhow::Result<()> {
+    if failed.is_empty() {
+        return Ok(());
+    }
+    eprintln!("{} packages failed:", failed.len());
+    for (name, error) in failed {
+        eprintln!("  {}: {}", name, error);
+    }
+    anyhow::bail!("{} packages failed; retry them with '{}'", failed.len(), retry)
+}
+
+async fn install_resolved(
+    conn: &Connection,
+    texman_dir: &Path,
+    profile: &str,
+    packages: &[Package],
+    config: &Config,
+    policy: ConflictPolicy,
+    journal_id: i64,
+) -> anyhow::Result<Vec<(String, String)>> {
+    let failed = populate_store(conn, texman_dir, packages, config, &[profile]).await?;
+    let packages: Vec<Package> = packages.iter().filter(|pkg| !failed.iter().any(|(name, _)| *name == pkg.name)).cloned().collect();
+    link_resolved(conn, texman_dir, profile, &packages, config, policy, journal_id)?;
+    Ok(failed)
+}
+
+// Downloads the archives of `packages`, at most `jobs` at a time. With keep_going, packages that
+// fail are returned with their errors instead of stopping the others.
+async fn download_packages<'a>(
+    packages: &'a [Package],
+    texman_dir: &Path,
+    config: &Config,
+) -> anyhow::Result<(Vec<(&'a Package, Vec<PathBuf>)>, Vec<(String, String)>)> {
+    let client = mirror::client(config)?;
+    let semaphore = Arc::new(Semaphore::new(config.jobs.max(1)));
     let download_tasks: Vec<_> = packages
         .iter()
         .map(|pkg| {
             let pkg = pkg.clone();
-            let texman_dir = texman_dir.clone();
-            tokio::spawn(async move { download_package(&pkg, &texman_dir).await })
+            let texman_dir = texman_dir.to_path_buf();
+            let config = config.clone();
+            let client = client.clone();
+            let semaphore = semaphore.clone();
+            tokio::spawn(hooks::in_request(hooks::request(), async move {
+                let _permit = semaphore.acquire_owned().await?;
+                download_package(&pkg, &texman_dir, &config, &client).await
+            }))
         })
         .collect();
 
     let download_results = join_all(download_tasks).await;
-    let download_paths: Vec<PathBuf> = download_results
-        .into_iter()
-        .collect::<Result<Vec<_>, _>>()
-        .map_err(|e| anyhow::anyhow!("Task failed: {}", e))?
-        .into_iter()
-        .collect::<Result<Vec<_>, _>>()
-        .map_err(|e| anyhow::anyhow!("Download failed: {}", e))?;
+    let mut failed = Vec::new();
+    let mut download_paths = Vec::new();
+    for (pkg, result) in packages.iter().zip(download_results) {
+        match result.map_err(|e| anyhow::anyhow!("Task failed: {}", e)).and_then(|paths| paths) {
+            Ok(paths) => download_paths.push((pkg, paths)),
+            Err(e) if config.keep_going => {
+                log::warn!("Failed to download {}: {}", pkg.name, e);
+                failed.push((pkg.name.clone(), e.to_string()));
+            }
+            Err(e) => return Err(anyhow::anyhow!("Download failed: {}", e)),
+        }
+    }
+    Ok((download_paths, failed))
+}
 
-    for (pkg, download_path) in packages.iter().zip(download_paths.iter()) {
-        let store_path = profile_dir.join(format!("{}-r{}", pkg.name, pkg.revision));
-        std::fs::create_dir_all(&store_path)?;
+// Downloads the archives of the requested packages and their dependencies without unpacking
+// them, for a later `--offline` install. With `dir`, they go there in the shared cache layout,
+// so `shared_cache = dir` makes them available, e.g. on another machine.
+async fn fetch_packages(requested: &[String], tlpdb: &HashMap<String, Package>, config: &Config, dir: Option<PathBuf>) -> anyhow::Result<Vec<(String, String)>> {
+    let texman_dir = dirs::home_dir()
+        .ok_or_else(|| anyhow::anyhow!("Cannot find home directory"))?
+        .join(".texman");
+    let resolved = resolve_requested(requested, tlpdb, config)?;
+    let packages: Vec<Package> = resolved.iter().map(|name| tlpdb[name].clone()).collect();
 
-        log::info!("Installing {} r{} to {:?}", pkg.name, pkg.revision, store_path);
-        let tar_xz = File::open(download_path)?;
-        let tar = XzDecoder::new(tar_xz);
-        let mut archive = tar::Archive::new(tar);
-        archive.unpack(&store_path)
-            .map_err(|e| anyhow::anyhow!("Failed to unpack {}: {}", pkg.name, e))?;
+    let mut config = config.clone();
+    if let Some(dir) = dir {
+        config.shared_cache = Some(dir);
+    }
+    let destination = match cache::shared_dir(&config, "archives") {
+        Some(shared) if shared.writable => shared.path,
+        Some(shared) => anyhow::bail!("Cannot write to {:?}", shared.path),
+        None => texman_dir.clone(),
+    };
+    fs::create_dir_all(&destination)?;
+
+    let (downloaded, failed) = download_packages(&packages, &texman_dir, &config).await?;
+    let archives: Vec<&PathBuf> = downloaded.iter().flat_map(|(_, paths)| paths).collect();
+    let size: u64 = archives.iter().filter_map(|path| fs::metadata(path).ok()).map(|metadata| metadata.len()).sum();
+    println!(
+        "Fetched {} archives of {} packages ({}) into {:?}",
+        archives.len(),
+        downloaded.len(),
+        output::format_size(size),
+        destination
+    );
+    Ok(failed)
+}
 
-        std::fs::remove_file(download_path)?;
+// Downloads and unpacks whatever the store lacks; `profiles` are the profiles about to link the packages.
+// With keep_going, packages that fail are returned with their error instead of aborting the rest.
+async fn populate_store(
+    conn: &Connection,
+    texman_dir: &Path,
+    packages: &[Package],
+    config: &Config,
+    profiles: &[&str],
+) -> anyhow::Result<Vec<(String, String)>> {
+    // Profiles installing at the same time would otherwise unpack the same package over each other.
+    let _store = cache::lock(&store_lock(texman_dir)).await?;
+    let mut to_download = Vec::new();
+    for pkg in packages {
+        if !store::ensure_expanded(&store_path(texman_dir, &pkg.name, &pkg.revision))? {
+            to_download.push(pkg.clone());
+        }
+    }
 
-        conn.execute(
-            "INSERT OR REPLACE INTO installed_packages (profile, name, revision) VALUES (?1, ?2, ?3)",
-            params![profile, pkg.name, pkg.revision],
-        )?;
+    let download_dir = match cache::shared_dir(config, "archives") {
+        Some(shared) if shared.writable => shared.path,
+        _ => texman_dir.to_path_buf(),
+    };
+    let mut copy_dirs = Vec::new();
+    for profile in profiles {
+        if install_strategy(conn, profile, config)? == InstallStrategy::Copy {
+            copy_dirs.push(texman_dir.join("profiles").join(profile));
+        }
+    }
+    space::preflight(&download_dir, &texman_dir.join("store"), &copy_dirs, &to_download, config)?;
+
+    let (download_paths, mut failed) = download_packages(&to_download, texman_dir, config).await?;
+    for (pkg, download_path) in download_paths {
+        hooks::check_cancelled()?;
+        let store_path = store_path(texman_dir, &pkg.name, &pkg.revision);
+        log::info!("Unpacking {} r{} to {:?}", pkg.name, pkg.revision, store_path);
+        let result = attest::record_containers(conn, pkg, &download_path).and_then(|_| unpack_package(pkg, &download_path, &store_path, config));
+        match result {
+            Ok(()) => {}
+            Err(e) if config.keep_going => {
+                log::warn!("Failed to unpack {}: {}", pkg.name, e);
+                failed.push((pkg.name.clone(), e.to_string()));
+            }
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(failed)
+}
+
+fn link_resolved(
+    conn: &Connection,
+    texman_dir: &Path,
+    profile: &str,
+    packages: &[Package],
+    config: &Config,
+    policy: ConflictPolicy,
+    journal_id: i64,
+) -> anyhow::Result<()> {
+    let profile_dir = texman_dir.join("profiles").join(profile);
+    std::fs::create_dir_all(&profile_dir)?;
+    let strategy = install_strategy(conn, profile, config)?;
+    for pkg in packages {
+        hooks::check_cancelled()?;
+        tree::link_package(conn, profile, &profile_dir, &pkg.name, &store_path(texman_dir, &pkg.name, &pkg.revision), policy, strategy)?;
+        timings::time(Phase::Database, || -> anyhow::Result<()> {
+            conn.execute(
+                "INSERT OR REPLACE INTO installed_packages (profile, name, revision) VALUES (?1, ?2, ?3)",
+                params![profile, pkg.name, pkg.revision],
+            )?;
+            record_origin(conn, profile, pkg)?;
+            record_revision_metadata(conn, pkg)?;
+            journal::commit(conn, journal_id, &pkg.name)
+        })?;
+        scripts::run_postactions(conn, config, &profile_dir, pkg)?;
         log::info!("Installed {} r{}", pkg.name, pkg.revision);
     }
+    smoke::check(config, &profile_dir, packages);
 
-    let active_path = texman_dir.join("active");
-    if !active_path.exists() {
-        std::os::unix::fs::symlink(&profile_dir, &active_path)?;
+    if !active::is_set(texman_dir) {
+        active::set(texman_dir, profile)?;
         log::info!("Set {} as active profile", profile);
     }
 
     Ok(())
 }
 
-async fn update_packages(tlpdb: &HashMap<String, Package>) -> anyhow::Result<()> {
+async fn update_packages(
+    tlpdb: &HashMap<String, Package>,
+    config: &Config,
+    check: bool,
+    removed: Option<orphans::RemovedAction>,
+    on_conflict: Option<ConflictPolicy>,
+    allow_scripts: bool,
+    only: Option<&[String]>,
+) -> anyhow::Result<Vec<(String, String)>> {
     let texman_dir = dirs::home_dir()
         .ok_or_else(|| anyhow::anyhow!("Cannot find home directory"))?
         .join(".texman");
-    let active_path = texman_dir.join("active");
 
-    if !active_path.exists() {
-        anyhow::bail!("No active profile set. Install a package or switch to a profile first.");
+    let conn = init_db(&texman_dir)?;
+    let active_dir = active::dir(&texman_dir)?;
+    let active_profile = active::name(&texman_dir)?;
+
+    let _locks = lock_profiles(&texman_dir, &[&active_profile]).await?;
+    let updates = timings::time(Phase::Resolve, || find_updates(&conn, &active_profile, tlpdb))?;
+    for (name, reason) in held_updates(&conn, &active_profile, tlpdb)? {
+        println!("Not updating {}: {}", name, reason);
+    }
+    let orphans = orphans::find(&conn, &active_profile, tlpdb, config)?;
+    let mut to_update: Vec<Package> = updates.iter().map(|(pkg, _)| pkg.clone()).collect();
+    // A plan was approved as it is: no removed packages to settle, and nothing it left out.
+    if let Some(only) = only {
+        to_update.retain(|pkg| only.contains(&pkg.name));
+    } else if !check {
+        orphans::print(&orphans, false);
+        // The successor usually took over the orphan's files, so the orphan goes first.
+        for orphan in orphans::settle(&conn, &active_profile, &orphans, removed)? {
+            let successor = orphan.successor.as_deref().unwrap_or_default();
+            remove_package(&orphan.name, false, tlpdb)?;
+            println!("Replacing {} r{} with {}", orphan.name, orphan.revision, successor);
+            let mut resolved = Vec::new();
+            resolve_dependencies(successor, tlpdb, &mut resolved, &mut Vec::new())?;
+            for name in resolved {
+                let installed: Option<String> = conn
+                    .query_row("SELECT revision FROM installed_packages WHERE profile = ?1 AND name = ?2", params![active_profile, name], |row| row.get(0))
+                    .optional()?;
+                if installed.is_none() && !to_update.iter().any(|pkg| pkg.name == name) {
+                    to_update.push(tlpdb[&name].clone());
+                }
+            }
+        }
+    }
+    check_compatibility(&conn, &active_profile, &active_dir, &mut to_update, tlpdb, !check && only.is_none())?;
+
+    if check {
+        let watched = watched_updates(&conn, &active_profile, tlpdb)?;
+        print_watched_updates(&watched);
+        orphans::print(&orphans, true);
+        if orphans.iter().any(|orphan| !orphan.kept) {
+            println!("Run 'texman update --removed migrate' to replace them with their successors, or '--removed keep' to keep them.");
+            println!();
+        }
+        if updates.is_empty() {
+            println!("All packages in profile '{}' are up to date.", active_profile);
+        } else {
+            println!("{} updates available in profile '{}':", updates.len(), active_profile);
+            for (pkg, current_revision) in &updates {
+                println!("  {} r{} -> r{}", pkg.name, current_revision, pkg.revision);
+            }
+        }
+        return Ok(Vec::new());
+    }
+
+    if to_update.is_empty() {
+        log::info!("All packages are up to date");
+        return Ok(Vec::new());
+    }
+    let policy = conflict_policy(&conn, &active_profile, config, on_conflict)?;
+    let strategy = install_strategy(&conn, &active_profile, config)?;
+    check_file_conflicts(&conn, &active_profile, &active_dir, &to_update, policy)?;
+    scripts::ensure_allowed(&conn, config, &to_update, allow_scripts)?;
+
+    // Like tlmgr's --self, infrastructure goes first: the rest may need the newer engines, and
+    // is left alone if they can't be updated.
+    to_update.sort_by_key(|pkg| !protect::is_protected(&pkg.name));
+    let failed = populate_store(&conn, &texman_dir, &to_update, config, &[&active_profile]).await?;
+    let failed_protected: Vec<&str> = failed
+        .iter()
+        .map(|(name, _)| name.as_str())
+        .filter(|name| protect::is_protected(name))
+        .collect();
+    if !failed_protected.is_empty() {
+        anyhow::bail!(
+            "Updating infrastructure packages failed ({}); no other packages were updated",
+            failed_protected.join(", ")
+        );
     }
+    to_update.retain(|pkg| !failed.iter().any(|(name, _)| *name == pkg.name));
+
+    let requested: Vec<String> = to_update.iter().map(|pkg| pkg.name.clone()).collect();
+    let steps: Vec<(&str, Option<&str>, Option<&Package>)> = to_update.iter().map(|pkg| (pkg.name.as_str(), Some(pkg.revision.as_str()), Some(pkg))).collect();
+    let journal_id = journal::begin(&conn, "update", &active_profile, &requested, policy, &steps)?;
+    for pkg in &to_update {
+        tree::link_package(&conn, &active_profile, &active_dir, &pkg.name, &store_path(&texman_dir, &pkg.name, &pkg.revision), policy, strategy)?;
+        timings::time(Phase::Database, || -> anyhow::Result<()> {
+            conn.execute(
+                "INSERT OR REPLACE INTO installed_packages (profile, name, revision) VALUES (?1, ?2, ?3)",
+                params![active_profile, pkg.name, pkg.revision],
+            )?;
+            record_origin(&conn, &active_profile, pkg)?;
+            record_revision_metadata(&conn, pkg)?;
+            journal::commit(&conn, journal_id, &pkg.name)
+        })?;
+        scripts::run_postactions(&conn, config, &active_dir, pkg)?;
+        log::info!("Updated {} r{}", pkg.name, pkg.revision);
+    }
+    tree::refresh(&conn, &active_profile, &active_dir, tlpdb)?;
+    journal::finish(&conn, journal_id)?;
+    {
+        let _store = cache::lock(&store_lock(&texman_dir)).await?;
+        store::prune_revisions(&conn, &texman_dir.join("store"), config.keep_revisions)?;
+        store::prune_objects(&texman_dir.join("store"))?;
+    }
+    smoke::check(config, &active_dir, &to_update);
+    formats::after_change(&conn, config, &active_profile, &active_dir, tlpdb, &to_update);
+    fontmaps::after_change(config, &active_dir, &to_update);
+
+    Ok(failed)
+}
 
+// The updates `update` would install into the active profile, in the order it installs them.
+fn plan_update(tlpdb: &HashMap<String, Package>, config: &Config) -> anyhow::Result<plan::Plan> {
+    let texman_dir = dirs::home_dir()
+        .ok_or_else(|| anyhow::anyhow!("Cannot find home directory"))?
+        .join(".texman");
+    let profile = active::name(&texman_dir)?;
     let conn = init_db(&texman_dir)?;
-    let active_dir = fs::canonicalize(&active_path)?;
-    let active_profile = active_path.read_link()?
-        .file_name()
-        .unwrap()
-        .to_str()
-        .unwrap()
-        .to_string();
-
-    let mut to_update = Vec::new();
-    let mut stmt = conn.prepare("SELECT name, revision FROM installed_packages WHERE profile = ?1")?;
-    let rows = stmt.query_map(params![active_profile], |row| {
+    let mut to_update: Vec<Package> = find_updates(&conn, &profile, tlpdb)?.into_iter().map(|(pkg, _)| pkg).collect();
+    to_update.sort_by_key(|pkg| !protect::is_protected(&pkg.name));
+    plan::build("update", &[profile], &[], &to_update, &conn, config)
+}
+
+fn find_updates(conn: &Connection, profile: &str, tlpdb: &HashMap<String, Package>) -> anyhow::Result<Vec<(Package, String)>> {
+    let mut updates = Vec::new();
+    let mut stmt = conn.prepare("SELECT name, revision FROM installed_packages WHERE profile = ?1 ORDER BY name")?;
+    let rows = stmt.query_map(params![profile], |row| {
         Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
     })?;
 
+    let origins = installed_origins(conn, profile)?;
+    let pins = repo::pins(conn)?;
+    let held = hold::held(conn, profile)?;
     for row in rows {
         let (pkg_name, current_revision) = row?;
+        if held.contains(&pkg_name) {
+            continue;
+        }
         if let Some(latest_pkg) = tlpdb.get(&pkg_name) {
+            let origin = origins.get(&pkg_name).map(String::as_str).unwrap_or(repo::MAIN);
+            if repo::update_blocker(&pkg_name, origin, latest_pkg, pins.get(&pkg_name).map(String::as_str)).is_some() {
+                continue;
+            }
+            // Revisions of different repositories can't be compared; a pin moves the package anyway.
+            let source = latest_pkg.repository.as_deref().unwrap_or(repo::MAIN);
+            if source != origin {
+                log::info!("Moving {} from repository '{}' to '{}' (pinned)", pkg_name, origin, source);
+                updates.push((latest_pkg.clone(), current_revision));
+                continue;
+            }
             let current_rev: u32 = current_revision.parse()
                 .map_err(|e| anyhow::anyhow!("Invalid revision {} for {}: {}", current_revision, pkg_name, e))?;
             let latest_rev: u32 = latest_pkg.revision.parse()
                 .map_err(|e| anyhow::anyhow!("Invalid revision {} for {}: {}", latest_pkg.revision, pkg_name, e))?;
             if latest_rev > current_rev {
                 log::info!("Found update for {}: r{} -> r{}", pkg_name, current_revision, latest_pkg.revision);
-                to_update.push(latest_pkg.clone());
+                updates.push((latest_pkg.clone(), current_revision));
             }
         }
     }
 
-    if to_update.is_empty() {
-        log::info!("All packages are up to date");
-        return Ok(());
-    }
-
-    let download_tasks: Vec<_> = to_update
-        .iter()
-        .map(|pkg| {
-            let pkg = pkg.clone();
-            let texman_dir = texman_dir.clone();
-            tokio::spawn(async move { download_package(&pkg, &texman_dir).await })
-        })
-        .collect();
-
-    let download_results = join_all(download_tasks).await;
-    let download_paths: Vec<PathBuf> = download_results
-        .into_iter()
-        .collect::<Result<Vec<_>, _>>()
-        .map_err(|e| anyhow::anyhow!("Task failed during update: {}", e))?
-        .into_iter()
-        .collect::<Result<Vec<_>, _>>()
-        .map_err(|e| anyhow::anyhow!("Download failed during update: {}", e))?;
-
-    for (pkg, download_path) in to_update.iter().zip(download_paths.iter()) {
-        let store_path = active_dir.join(format!("{}-r{}", pkg.name, pkg.revision));
-        std::fs::create_dir_all(&store_path)?;
-
-        log::info!("Updating {} r{} to {:?}", pkg.name, pkg.revision, store_path);
-        let tar_xz = File::open(download_path)?;
-        let tar = XzDecoder::new(tar_xz);
-        let mut archive = tar::Archive::new(tar);
-        archive.unpack(&store_path)
-            .map_err(|e| anyhow::anyhow!("Failed to unpack {}: {}", pkg.name, e))?;
+    Ok(updates)
+}
 
-        std::fs::remove_file(download_path)?;
+// Installed packages the TLPDB has a different revision of, but that `find_updates` leaves
+// alone because they are pinned or because of where they came from, with the reason.
+fn held_updates(conn: &Connection, profile: &str, tlpdb: &HashMap<String, Package>) -> anyhow::Result<Vec<(String, String)>> {
+    let origins = installed_origins(conn, profile)?;
+    let pins = repo::pins(conn)?;
+    let pinned = hold::held(conn, profile)?;
+    let mut stmt = conn.prepare("SELECT name, revision FROM installed_packages WHERE profile = ?1 ORDER BY name")?;
+    let installed = stmt
+        .query_map(params![profile], |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)))?
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut held = Vec::new();
+    for (name, revision) in installed {
+        let Some(latest) = tlpdb.get(&name).filter(|latest| latest.revision != revision) else { continue };
+        if pinned.contains(&name) {
+            let reason = format!("pinned at r{} (r{} is available); run 'texman unpin {}' to update it", revision, latest.revision, name);
+            held.push((name, reason));
+            continue;
+        }
+        let origin = origins.get(&name).map(String::as_str).unwrap_or(repo::MAIN);
+        if let Some(reason) = repo::update_blocker(&name, origin, latest, pins.get(&name).map(String::as_str)) {
+            held.push((name, reason));
+        }
+    }
+    Ok(held)
+}
 
-        conn.execute(
-            "INSERT OR REPLACE INTO installed_packages (profile, name, revision) VALUES (?1, ?2, ?3)",
-            params![active_profile, pkg.name, pkg.revision],
-        )?;
-        log::info!("Updated {} r{}", pkg.name, pkg.revision);
+fn check_compatibility(
+    conn: &Connection,
+    profile: &str,
+    profile_dir: &Path,
+    to_update: &mut Vec<Package>,
+    tlpdb: &HashMap<String, Package>,
+    interactive: bool,
+) -> anyhow::Result<()> {
+    let mut stmt = conn.prepare(
+        "SELECT i.name, m.catalogue_version FROM installed_packages i
+         LEFT JOIN revision_metadata m ON m.name = i.name AND m.revision = i.revision
+         WHERE i.profile = ?1",
+    )?;
+    let rows = stmt.query_map(params![profile], |row| {
+        Ok((row.get::<_, String>(0)?, row.get::<_, Option<String>>(1)?))
+    })?;
+    let mut installed = HashMap::new();
+    for row in rows {
+        let (name, version) = row?;
+        installed.insert(name, version);
+    }
 
-        let old_path = active_dir.join(format!("{}-r{}", pkg.name, pkg.revision));
-        if old_path.exists() && old_path != store_path {
-            fs::remove_dir_all(&old_path)?;
-            log::info!("Removed old version of {}", pkg.name);
+    for issue in compat::find_incompatibilities(&installed, to_update, tlpdb) {
+        println!(
+            "Warning: updating {} to version {} leaves {} at version {}; {}.",
+            issue.updated, issue.updated_version, issue.partner, issue.partner_version, issue.reason
+        );
+        if !interactive {
+            continue;
+        }
+        if let Some(partner) = &issue.partner_update {
+            let question = format!("Update {} to r{} together with {}?", partner.name, partner.revision, issue.updated);
+            if hooks::prompter().confirm(&question, true)? {
+                to_update.push(partner.clone());
+                continue;
+            }
+        }
+        if hooks::prompter().confirm(&format!("Hold {} at its installed revision?", issue.updated), true)? {
+            to_update.retain(|p| p.name != issue.updated);
+            log::info!("Holding {} to stay compatible with {}", issue.updated, issue.partner);
         }
     }
 
+    for engine in compat::stale_format_engines(profile_dir, to_update) {
+        println!(
+            "Warning: formats in profile '{}' were built by the installed {}; rebuild them after updating it.",
+            profile, engine
+        );
+    }
+
     Ok(())
 }
 
-fn list_packages() -> anyhow::Result<()> {
+fn list_packages(tlpdb: &HashMap<String, Package>, output: OutputFormat, rows_options: &RowOptions, long: bool) -> anyhow::Result<()> {
     let texman_dir = dirs::home_dir()
         .ok_or_else(|| anyhow::anyhow!("Cannot find home directory"))?
         .join(".texman");
-    let active_path = texman_dir.join("active");
-
-    if !active_path.exists() {
-        anyhow::bail!("No active profile set. Install a package or switch to a profile first.");
-    }
 
     let conn = init_db(&texman_dir)?;
-    let active_profile = active_path.read_link()?
-        .file_name()
-        .unwrap()
-        .to_str()
-        .unwrap()
-        .to_string();
+    let active_profile = active::name(&texman_dir)?;
 
     let mut stmt = conn.prepare("SELECT name, revision FROM installed_packages WHERE profile = ?1 ORDER BY name")?;
     let rows = stmt.query_map(params![active_profile], |row| {
         Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
     })?;
 
-    println!("Installed packages in profile '{}':", active_profile);
+    let mut packages = Vec::new();
     for row in rows {
         let (name, revision) = row?;
-        println!("  {} r{}", name, revision);
+        let pkg = tlpdb.get(&name);
+        packages.push(PackageRow {
+            size: pkg.and_then(|p| p.container_size),
+            shortdesc: pkg.and_then(|p| p.description.clone()),
+            name,
+            revision,
+        });
+    }
+
+    if !output::print_rows(&packages, output, rows_options)? {
+        println!("Installed packages in profile '{}':", active_profile);
+        let origins = if long { installed_origins(&conn, &active_profile)? } else { HashMap::new() };
+        for pkg in packages {
+            if long {
+                let origin = origins.get(&pkg.name).map(String::as_str).unwrap_or(repo::MAIN);
+                println!("  {:<30} r{:<8} {}", pkg.name, pkg.revision, origin);
+            } else {
+                println!("  {} r{}", pkg.name, pkg.revision);
+            }
+        }
     }
 
     Ok(())
 }
 
-fn remove_package(package: &str) -> anyhow::Result<()> {
+fn remove_package(package: &str, purge: bool, tlpdb: &HashMap<String, Package>) -> anyhow::Result<()> {
     let texman_dir = dirs::home_dir()
         .ok_or_else(|| anyhow::anyhow!("Cannot find home directory"))?
         .join(".texman");
-    let active_path = texman_dir.join("active");
-
-    if !active_path.exists() {
-        anyhow::bail!("No active profile set. Install a package or switch to a profile first.");
-    }
 
     let conn = init_db(&texman_dir)?;
-    let active_dir = fs::canonicalize(&active_path)?;
-    let active_profile = active_path.read_link()?
-        .file_name()
-        .unwrap()
-        .to_str()
-        .unwrap()
-        .to_string();
+    let active_dir = active::dir(&texman_dir)?;
+    let active_profile = active::name(&texman_dir)?;
 
     let mut stmt = conn.prepare("SELECT revision FROM installed_packages WHERE profile = ?1 AND name = ?2")?;
     let revision: Option<String> = stmt.query_row(params![active_profile, package], |row| row.get(0)).optional()?;
 
     if let Some(revision) = revision {
-        let store_path = active_dir.join(format!("{}-r{}", package, revision));
-        if store_path.exists() {
-            fs::remove_dir_all(&store_path)?;
-            log::info!("Removed files for {} r{}", package, revision);
+        let mut stmt = conn.prepare("SELECT path FROM profile_files WHERE profile = ?1 AND package = ?2")?;
+        let files = stmt
+            .query_map(params![active_profile, package], |row| row.get::<_, String>(0))?
+            .collect::<Result<Vec<_>, _>>()?;
+        let journal_id = journal::begin(&conn, "remove", &active_profile, &[package.to_string()], ConflictPolicy::Error, &[(package, None, None)])?;
+        uninstall(&conn, &active_profile, &active_dir, package)?;
+        journal::commit(&conn, journal_id, package)?;
+        log::info!("Unlinked {} r{}", package, revision);
+        if purge {
+            for artifact in purge::purge_package(&active_dir, tlpdb.get(package), &files)? {
+                println!("Purged {}", artifact);
+            }
         }
-
-        conn.execute(
-            "DELETE FROM installed_packages WHERE profile = ?1 AND name = ?2",
-            params![active_profile, package],
-        )?;
+        tree::refresh(&conn, &active_profile, &active_dir, tlpdb)?;
+        journal::finish(&conn, journal_id)?;
         log::info!("Removed {} from profile '{}'", package, active_profile);
     } else {
         log::warn!("Package {} not found in profile '{}'", package, active_profile);
@@ -667,68 +2960,345 @@ fn remove_package(package: &str) -> anyhow::Result<()> {
     Ok(())
 }
 
-fn info_package(package: &str, tlpdb: &HashMap<String, Package>) -> anyhow::Result<()> {
-    let pkg = tlpdb.get(package).ok_or_else(|| anyhow::anyhow!("Package '{}' not found in TLPDB", package))?;
-    
-    println!("Package: {}", pkg.name);
-    println!("Revision: {}", pkg.revision);
-    println!("Default URL: {}", pkg.url);
-    let deps_str = if pkg.depends.is_empty() { "None".to_string() } else { pkg.depends.join(", ") };
-    println!("Dependencies: {}", deps_str);
+// Unlinks the package's files from the profile and forgets that it was installed there.
+fn uninstall(conn: &Connection, profile: &str, profile_dir: &Path, package: &str) -> anyhow::Result<()> {
+    tree::unlink_package(conn, profile, profile_dir, package)?;
+    conn.execute("DELETE FROM installed_packages WHERE profile = ?1 AND name = ?2", params![profile, package])?;
+    conn.execute("DELETE FROM package_origins WHERE profile = ?1 AND name = ?2", params![profile, package])?;
+    orphans::forget(conn, profile, package)
+}
+
+#[derive(Serialize)]
+struct InfoDocument {
+    schema_version: output::SchemaVersion,
+    name: String,
+    revision: Option<String>,
+    repository: Option<String>,
+    url: Option<String>,
+    depends: Vec<String>,
+    shortdesc: Option<String>,
+    longdesc: Option<String>,
+    version: Option<String>,
+    runfiles: Vec<String>,
+    binfiles: Vec<String>,
+    installed: Option<InstalledDocument>,
+}
+
+#[derive(Serialize)]
+struct InstalledDocument {
+    revision: String,
+    origin: String,
+}
+
+fn info_package(
+    package: &str,
+    tlpdb: &HashMap<String, Package>,
+    config: &Config,
+    output: OutputFormat,
+    rows_options: &RowOptions,
+    no_pager: bool,
+) -> anyhow::Result<()> {
+    let Some(pkg) = tlpdb.get(package) else {
+        return installed_info(package, output, rows_options);
+    };
+    if rows_options.is_set() || matches!(output, OutputFormat::Table | OutputFormat::Csv) {
+        let row = PackageRow {
+            name: pkg.name.clone(),
+            revision: pkg.revision.clone(),
+            size: pkg.container_size,
+            shortdesc: pkg.description.clone(),
+        };
+        output::print_rows(&[row], output, rows_options)?;
+        return Ok(());
+    }
+
+    let repo_config = repo::config_for(config, pkg);
+    let url = repo_config.as_ref().unwrap_or(config).repository_url(&format!("archive/{}.tar.xz", pkg.name));
+    if output == OutputFormat::Json {
+        let document = InfoDocument {
+            schema_version: output::SchemaVersion,
+            name: pkg.name.clone(),
+            revision: Some(pkg.revision.clone()),
+            repository: Some(pkg.repository.clone().unwrap_or_else(|| repo::MAIN.to_string())),
+            url: Some(url),
+            depends: pkg.depends.clone(),
+            shortdesc: pkg.description.clone(),
+            longdesc: pkg.longdesc.clone(),
+            version: pkg.catalogue_version.clone(),
+            runfiles: pkg.runfiles.clone(),
+            binfiles: pkg.binfiles.clone(),
+            installed: installed_origin(package)?.map(|(revision, origin)| InstalledDocument { revision, origin }),
+        };
+        println!("{}", serde_json::to_string(&document)?);
+        return Ok(());
+    }
+
+    let mut out = String::new();
+    writeln!(out, "Package: {}", pkg.name)?;
+    writeln!(out, "Revision: {}", pkg.revision)?;
+    if let Some(repository) = &pkg.repository {
+        writeln!(out, "Repository: {}", repository)?;
+    }
+    writeln!(out, "Default URL: {}", url)?;
+    let depends: Vec<String> = pkg.depends.iter().cloned().chain(pkg.arch_depends.iter().map(|base| format!("{}.ARCH", base))).collect();
+    let deps_str = if depends.is_empty() { "None".to_string() } else { depends.join(", ") };
+    writeln!(out, "Dependencies: {}", deps_str)?;
     if let Some(desc) = &pkg.description {
-        println!("Short Description: {}", desc);
+        out.push_str(&output::wrap_field("", "Short Description", desc));
+    }
+    if let Some(longdesc) = &pkg.longdesc {
+        out.push_str(&output::wrap_field("", "Long Description", longdesc));
+    }
+    if let Some(version) = &pkg.catalogue_version {
+        writeln!(out, "Version: {}", version)?;
+    }
+    writeln!(out, "Runfiles ({}):", pkg.runfiles.len())?;
+    for file in &pkg.runfiles {
+        writeln!(out, "  {}", file)?;
+    }
+    writeln!(out, "Binfiles ({}):", pkg.binfiles.len())?;
+    for file in &pkg.binfiles {
+        writeln!(out, "  {}", file)?;
+    }
+    if let Some((revision, origin)) = installed_origin(package)? {
+        writeln!(out, "Installed: r{} from {}", revision, origin)?;
+    }
+
+    output::page(&out, no_pager)
+}
+
+// The revision of `package` in the active profile and the repository it came from, if it is
+// installed there.
+fn installed_origin(package: &str) -> anyhow::Result<Option<(String, String)>> {
+    let texman_dir = dirs::home_dir()
+        .ok_or_else(|| anyhow::anyhow!("Cannot find home directory"))?
+        .join(".texman");
+    let Ok(profile) = active::name(&texman_dir) else {
+        return Ok(None);
+    };
+    let conn = init_db(&texman_dir)?;
+    let installed = conn
+        .query_row(
+            "SELECT i.revision, o.repository FROM installed_packages i
+             LEFT JOIN package_origins o ON o.profile = i.profile AND o.name = i.name
+             WHERE i.profile = ?1 AND i.name = ?2",
+            params![profile, package],
+            |row| Ok((row.get::<_, String>(0)?, row.get::<_, Option<String>>(1)?)),
+        )
+        .optional()?;
+    Ok(installed.map(|(revision, origin)| (revision, origin.unwrap_or_else(|| repo::MAIN.to_string()))))
+}
+
+// What the database recorded when the package was installed, for packages the TLPDB doesn't
+// describe, e.g. offline without a cached TLPDB.
+fn installed_info(package: &str, output: OutputFormat, rows_options: &RowOptions) -> anyhow::Result<()> {
+    let texman_dir = dirs::home_dir()
+        .ok_or_else(|| anyhow::anyhow!("Cannot find home directory"))?
+        .join(".texman");
+    let conn = init_db(&texman_dir)?;
+    let profile = active::name(&texman_dir)?;
+    let installed: Option<(String, Option<String>)> = conn
+        .query_row(
+            "SELECT i.revision, m.catalogue_version FROM installed_packages i
+             LEFT JOIN revision_metadata m ON m.name = i.name AND m.revision = i.revision
+             WHERE i.profile = ?1 AND i.name = ?2",
+            params![profile, package],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .optional()?;
+    let Some((revision, version)) = installed else {
+        anyhow::bail!("Package '{}' not found in TLPDB", package);
+    };
+    if rows_options.is_set() || matches!(output, OutputFormat::Table | OutputFormat::Csv) {
+        let row = PackageRow { name: package.to_string(), revision, size: None, shortdesc: None };
+        output::print_rows(&[row], output, rows_options)?;
+        return Ok(());
+    }
+
+    let mut stmt = conn.prepare("SELECT path FROM profile_files WHERE profile = ?1 AND package = ?2 ORDER BY path")?;
+    let files = stmt
+        .query_map(params![profile, package], |row| row.get::<_, String>(0))?
+        .collect::<Result<Vec<_>, _>>()?;
+    if output == OutputFormat::Json {
+        let origin = installed_origin(package)?.map(|(_, origin)| origin).unwrap_or_else(|| repo::MAIN.to_string());
+        let document = InfoDocument {
+            schema_version: output::SchemaVersion,
+            name: package.to_string(),
+            revision: None,
+            repository: None,
+            url: None,
+            depends: Vec::new(),
+            shortdesc: None,
+            longdesc: None,
+            version,
+            runfiles: files,
+            binfiles: Vec::new(),
+            installed: Some(InstalledDocument { revision, origin }),
+        };
+        println!("{}", serde_json::to_string(&document)?);
+        return Ok(());
     }
-    if let Some(longdesc) = &pkg.longdesc {
-        println!("Long Description: {}", longdesc);
+    println!("Package: {} (not in the TLPDB; showing what profile '{}' recorded)", package, profile);
+    println!("Installed Revision: {}", revision);
+    if let Some((_, origin)) = installed_origin(package)? {
+        println!("Installed From: {}", origin);
     }
-    println!("Runfiles ({}):", pkg.runfiles.len());
-    for file in &pkg.runfiles {
-        println!("  {}", file);
+    if let Some(version) = version {
+        println!("Version: {}", version);
     }
-    println!("Binfiles ({}):", pkg.binfiles.len());
-    for file in &pkg.binfiles {
+    println!("Files ({}):", files.len());
+    for file in &files {
         println!("  {}", file);
     }
-
     Ok(())
 }
 
-fn search_packages(term: &str, tlpdb: &HashMap<String, Package>, search_desc: bool, search_deps: bool, search_longdesc: bool) -> anyhow::Result<()> {
-    let term_lower = term.to_lowercase();
+fn search_packages(
+    terms: &[String],
+    tlpdb: &HashMap<String, Package>,
+    options: &SearchOptions,
+    output: OutputFormat,
+    rows_options: &RowOptions,
+    no_pager: bool,
+) -> anyhow::Result<()> {
+    let terms_lower: Vec<String> = terms.iter().map(|t| t.to_lowercase()).collect();
+    let query = match (&options.topic, terms.is_empty()) {
+        (Some(topic), true) => format!("in topic '{}'", topic),
+        (Some(topic), false) => format!("matching '{}' in topic '{}'", terms.join(" "), topic),
+        (None, _) => format!("matching '{}'", terms.join(" ")),
+    };
+    let installed = match options.installed {
+        Some(_) => active_installed_packages()?,
+        None => HashMap::new(),
+    };
     let mut matches: Vec<&Package> = tlpdb
         .values()
+        .filter(|pkg| options.installed.is_none_or(|want| installed.contains_key(&pkg.name) == want))
+        .filter(|pkg| options.topic.as_ref().is_none_or(|topic| topics::has_topic(pkg, topic)))
         .filter(|pkg| {
-            let name_match = pkg.name.to_lowercase().contains(&term_lower);
-            let desc_match = search_desc && pkg.description.as_ref().map_or(false, |d| d.to_lowercase().contains(&term_lower));
-            let longdesc_match = search_longdesc && pkg.longdesc.as_ref().map_or(false, |d| d.to_lowercase().contains(&term_lower));
-            let deps_match = search_deps && pkg.depends.iter().any(|d| d.to_lowercase().contains(&term_lower));
-            name_match || desc_match || longdesc_match || deps_match
+            terms_lower.iter().all(|term| {
+                let name_match = name_matches(&pkg.name, term, options.exact);
+                let desc_match = options.description && pkg.description.as_ref().is_some_and(|d| text_matches(d, term, options.exact));
+                let longdesc_match = options.longdesc && pkg.longdesc.as_ref().is_some_and(|d| text_matches(d, term, options.exact));
+                let deps_match = options.depends && pkg.depends.iter().any(|d| name_matches(d, term, options.exact));
+                name_match || desc_match || longdesc_match || deps_match
+            })
         })
         .collect();
     
-    if matches.is_empty() {
-        println!("No packages found matching '{}'", term);
+    if matches.is_empty() && output == OutputFormat::Text && !rows_options.is_set() {
+        println!("No packages found {}", query);
         return Ok(());
     }
 
     matches.sort_by(|a, b| a.name.cmp(&b.name));
-    println!("Found {} packages matching '{}':", matches.len(), term);
+    let rows: Vec<PackageRow> = matches
+        .iter()
+        .map(|pkg| PackageRow {
+            name: pkg.name.clone(),
+            revision: pkg.revision.clone(),
+            size: pkg.container_size,
+            shortdesc: pkg.description.clone(),
+        })
+        .collect();
+    if output::print_rows(&rows, output, rows_options)? {
+        return Ok(());
+    }
+
+    let mut out = String::new();
+    writeln!(out, "Found {} packages {}:", matches.len(), query)?;
     for pkg in matches {
-        println!("  {} r{}", pkg.name, pkg.revision);
-        if search_desc && pkg.description.is_some() {
-            println!("    Short Description: {}", pkg.description.as_ref().unwrap());
+        match installed.get(&pkg.name) {
+            Some(revision) if *revision != pkg.revision => writeln!(out, "  {} r{} (installed: r{})", pkg.name, pkg.revision, revision)?,
+            Some(_) => writeln!(out, "  {} r{} (installed)", pkg.name, pkg.revision)?,
+            None => writeln!(out, "  {} r{}", pkg.name, pkg.revision)?,
+        }
+        if let Some(desc) = pkg.description.as_ref().filter(|_| options.description) {
+            out.push_str(&output::wrap_field("    ", "Short Description", desc));
         }
-        if search_longdesc && pkg.longdesc.is_some() {
-            println!("    Long Description: {}", pkg.longdesc.as_ref().unwrap());
+        if let Some(longdesc) = pkg.longdesc.as_ref().filter(|_| options.longdesc) {
+            out.push_str(&output::wrap_field("    ", "Long Description", longdesc));
         }
-        if search_deps && !pkg.depends.is_empty() {
-            println!("    Depends: {}", pkg.depends.join(", "));
+        if options.depends && !pkg.depends.is_empty() {
+            writeln!(out, "    Depends: {}", pkg.depends.join(", "))?;
+        }
+    }
+
+    output::page(&out, no_pager)
+}
+
+fn query_packages(expression: &str, tlpdb: &HashMap<String, Package>, output: OutputFormat) -> anyhow::Result<()> {
+    let expr = expr::parse(expression)?;
+    let installed = if expr.uses_installed() { active_installed_packages()? } else { HashMap::new() };
+    let mut matches: Vec<&Package> = tlpdb.values().filter(|pkg| expr.matches(pkg, &installed)).collect();
+    matches.sort_by(|a, b| a.name.cmp(&b.name));
+
+    if output != OutputFormat::Text {
+        let rows: Vec<PackageRow> = matches
+            .iter()
+            .map(|pkg| PackageRow {
+                name: pkg.name.clone(),
+                revision: pkg.revision.clone(),
+                size: pkg.container_size,
+                shortdesc: pkg.description.clone(),
+            })
+            .collect();
+        match output {
+            OutputFormat::Table => output::print_table(&rows),
+            OutputFormat::Json => output::print_json(&rows)?,
+            _ => output::print_csv(&rows),
         }
+        return Ok(());
     }
 
+    if matches.is_empty() {
+        println!("No packages match '{}'", expression);
+        return Ok(());
+    }
+    println!("{} packages match '{}':", matches.len(), expression);
+    for pkg in matches {
+        match &pkg.description {
+            Some(desc) => println!("  {} r{}: {}", pkg.name, pkg.revision, desc),
+            None => println!("  {} r{}", pkg.name, pkg.revision),
+        }
+    }
     Ok(())
 }
 
+fn name_matches(name: &str, term: &str, exact: bool) -> bool {
+    let name = name.to_lowercase();
+    if exact { name == term } else { name.contains(term) }
+}
+
+fn text_matches(text: &str, term: &str, exact: bool) -> bool {
+    let text = text.to_lowercase();
+    if exact {
+        text.split(|c: char| !c.is_alphanumeric()).any(|word| word == term)
+    } else {
+        text.contains(term)
+    }
+}
+
+fn active_installed_packages() -> anyhow::Result<HashMap<String, String>> {
+    let texman_dir = dirs::home_dir()
+        .ok_or_else(|| anyhow::anyhow!("Cannot find home directory"))?
+        .join(".texman");
+
+    let conn = init_db(&texman_dir)?;
+    let active_profile = active::name(&texman_dir)?;
+
+    let mut stmt = conn.prepare("SELECT name, revision FROM installed_packages WHERE profile = ?1")?;
+    let rows = stmt.query_map(params![active_profile], |row| {
+        Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+    })?;
+    let mut installed = HashMap::new();
+    for row in rows {
+        let (name, revision) = row?;
+        installed.insert(name, revision);
+    }
+    Ok(installed)
+}
+
 fn create_profile(name: &str) -> anyhow::Result<()> {
     let texman_dir = dirs::home_dir()
         .ok_or_else(|| anyhow::anyhow!("Cannot find home directory"))?
@@ -744,26 +3314,39 @@ fn switch_profile(name: &str) -> anyhow::Result<()> {
         .ok_or_else(|| anyhow::anyhow!("Cannot find home directory"))?
         .join(".texman");
     let profile_path = texman_dir.join("profiles").join(name);
-    let active_path = texman_dir.join("active");
 
     if !profile_path.exists() {
         anyhow::bail!("Profile '{}' does not exist. Use 'profile create {}' to create it.", name, name);
     }
 
-    if active_path.exists() {
-        std::fs::remove_file(&active_path)?;
-    }
-    std::os::unix::fs::symlink(&profile_path, &active_path)?;
+    let conn = init_db(&texman_dir)?;
+    store::expand_profile(&conn, &texman_dir, name)?;
+
+    active::set(&texman_dir, name)?;
     log::info!("Switched to profile: {}", name);
     Ok(())
 }
 
+fn profile_names(texman_dir: &Path) -> anyhow::Result<Vec<String>> {
+    let profiles_dir = texman_dir.join("profiles");
+    let mut profiles = Vec::new();
+    if profiles_dir.exists() {
+        for entry in fs::read_dir(&profiles_dir)? {
+            let entry = entry?;
+            if entry.file_type()?.is_dir() {
+                profiles.push(entry.file_name().to_string_lossy().to_string());
+            }
+        }
+    }
+    profiles.sort();
+    Ok(profiles)
+}
+
 fn list_profiles() -> anyhow::Result<()> {
     let texman_dir = dirs::home_dir()
         .ok_or_else(|| anyhow::anyhow!("Cannot find home directory"))?
         .join(".texman");
     let profiles_dir = texman_dir.join("profiles");
-    let active_path = texman_dir.join("active");
 
     if !profiles_dir.exists() {
         println!("No profiles found.");
@@ -782,16 +3365,7 @@ fn list_profiles() -> anyhow::Result<()> {
         return Ok(());
     }
 
-    let active_profile = if active_path.exists() {
-        active_path.read_link()?
-            .file_name()
-            .unwrap()
-            .to_str()
-            .unwrap()
-            .to_string()
-    } else {
-        String::new()
-    };
+    let active_profile = active::get(&texman_dir)?.unwrap_or_default();
 
     println!("Available profiles:");
     for profile in profiles {
@@ -802,34 +3376,75 @@ fn list_profiles() -> anyhow::Result<()> {
     Ok(())
 }
 
-fn remove_profile(name: &str) -> anyhow::Result<()> {
+fn remove_profile(name: &str, purge: bool) -> anyhow::Result<()> {
     let texman_dir = dirs::home_dir()
         .ok_or_else(|| anyhow::anyhow!("Cannot find home directory"))?
         .join(".texman");
     let profile_path = texman_dir.join("profiles").join(name);
-    let active_path = texman_dir.join("active");
 
     if !profile_path.exists() {
         anyhow::bail!("Profile '{}' does not exist.", name);
     }
 
-    if active_path.exists() && active_path.read_link()?.file_name().unwrap().to_str().unwrap() == name {
+    if active::get(&texman_dir)?.as_deref() == Some(name) {
         anyhow::bail!("Cannot remove active profile '{}'. Switch to another profile first.", name);
     }
 
     fs::remove_dir_all(&profile_path)?;
     let conn = init_db(&texman_dir)?;
+    let mut stmt = conn.prepare("SELECT name, revision FROM installed_packages WHERE profile = ?1")?;
+    let revisions = stmt
+        .query_map(params![name], |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)))?
+        .collect::<Result<Vec<_>, _>>()?;
     conn.execute(
         "DELETE FROM installed_packages WHERE profile = ?1",
         params![name],
     )?;
+    conn.execute("DELETE FROM profile_files WHERE profile = ?1", params![name])?;
+    conn.execute("DELETE FROM profile_settings WHERE profile = ?1", params![name])?;
+    // A profile created later under the same name starts without these.
+    conn.execute("DELETE FROM package_holds WHERE profile = ?1", params![name])?;
+    conn.execute("DELETE FROM package_origins WHERE profile = ?1", params![name])?;
+    conn.execute("DELETE FROM kept_orphans WHERE profile = ?1", params![name])?;
     log::info!("Removed profile '{}'", name);
+    if purge {
+        for artifact in purge::purge_profile(&conn, &texman_dir, name, &revisions)? {
+            println!("Purged {}", artifact);
+        }
+    }
+
+    Ok(())
+}
+
+fn set_install_strategy(name: &str, strategy: InstallStrategy, config: &Config) -> anyhow::Result<()> {
+    let texman_dir = dirs::home_dir()
+        .ok_or_else(|| anyhow::anyhow!("Cannot find home directory"))?
+        .join(".texman");
+    let profile_dir = texman_dir.join("profiles").join(name);
+    if !profile_dir.exists() {
+        anyhow::bail!("Profile '{}' does not exist.", name);
+    }
 
+    let conn = init_db(&texman_dir)?;
+    set_profile_setting(&conn, name, "install_strategy", strategy.as_str())?;
+    store::expand_profile(&conn, &texman_dir, name)?;
+    let policy = conflict_policy(&conn, name, config, None)?;
+    let mut stmt = conn.prepare("SELECT name, revision FROM installed_packages WHERE profile = ?1")?;
+    let packages = stmt
+        .query_map(params![name], |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)))?
+        .collect::<Result<Vec<_>, _>>()?;
+    for (pkg_name, revision) in &packages {
+        tree::link_package(&conn, name, &profile_dir, pkg_name, &store_path(&texman_dir, pkg_name, revision), policy, strategy)?;
+    }
+    log::info!("Profile '{}' now uses the '{}' install strategy ({} packages relinked)", name, strategy.as_str(), packages.len());
     Ok(())
 }
 
-fn copy_recursively(source: &PathBuf, destination: &PathBuf) -> anyhow::Result<()> {
-    if source.is_dir() {
+fn copy_recursively(source: &Path, destination: &Path) -> anyhow::Result<()> {
+    let metadata = fs::symlink_metadata(source)?;
+    if metadata.file_type().is_symlink() {
+        crate::os::symlink(&fs::read_link(source)?, destination)?;
+    } else if metadata.is_dir() {
         fs::create_dir_all(destination)?;
         for entry in fs::read_dir(source)? {
             let entry = entry?;
@@ -847,19 +3462,9 @@ fn backup_profile(name: &str) -> anyhow::Result<()> {
     let texman_dir = dirs::home_dir()
         .ok_or_else(|| anyhow::anyhow!("Cannot find home directory"))?
         .join(".texman");
-    let active_path = texman_dir.join("active");
-
-    if !active_path.exists() {
-        anyhow::bail!("No active profile set. Install a package or switch to a profile first.");
-    }
 
-    let active_dir = fs::canonicalize(&active_path)?;
-    let active_profile = active_path.read_link()?
-        .file_name()
-        .unwrap()
-        .to_str()
-        .unwrap()
-        .to_string();
+    let active_dir = active::dir(&texman_dir)?;
+    let active_profile = active::name(&texman_dir)?;
     let backup_dir = texman_dir.join("backups").join(name);
     std::fs::create_dir_all(&backup_dir)?;
 
@@ -882,66 +3487,102 @@ fn backup_profile(name: &str) -> anyhow::Result<()> {
             params![name, active_profile, pkg_name, revision],
         )?;
     }
+    manifest::record(&conn, name, &backup_dir)?;
 
     log::info!("Created backup '{}' for profile '{}'", name, active_profile);
     Ok(())
 }
 
-fn restore_profile(name: &str) -> anyhow::Result<()> {
+fn restore_profile(name: &str, config: &Config, on_conflict: Option<ConflictPolicy>, force: bool) -> anyhow::Result<()> {
     let texman_dir = dirs::home_dir()
         .ok_or_else(|| anyhow::anyhow!("Cannot find home directory"))?
         .join(".texman");
-    let active_path = texman_dir.join("active");
     let backup_dir = texman_dir.join("backups").join(name);
-
-    if !active_path.exists() {
-        anyhow::bail!("No active profile set. Install a package or switch to a profile first.");
-    }
     if !backup_dir.exists() {
         anyhow::bail!("Backup '{}' does not exist.", name);
     }
 
-    let active_dir = fs::canonicalize(&active_path)?;
-    let active_profile = active_path.read_link()?
-        .file_name()
-        .unwrap()
-        .to_str()
-        .unwrap()
-        .to_string();
+    let active_dir = active::dir(&texman_dir)?;
+    let active_profile = active::name(&texman_dir)?;
+
+    let conn = init_db(&texman_dir)?;
+    let policy = conflict_policy(&conn, &active_profile, config, on_conflict)?;
+    let strategy = install_strategy(&conn, &active_profile, config)?;
+    let mut stmt = conn.prepare("SELECT name, revision FROM backups WHERE backup_name = ?1")?;
+    let packages = stmt
+        .query_map(params![name], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    for (pkg_name, revision) in &packages {
+        if !store::ensure_expanded(&store_path(&texman_dir, pkg_name, revision))? {
+            anyhow::bail!("Backup '{}' needs {} r{}, which is no longer in the store", name, pkg_name, revision);
+        }
+    }
+
+    // Nothing is touched until the backup checks out against what was recorded when it was made.
+    let mut problems = manifest::verify(&conn, name, &backup_dir)?.unwrap_or_else(|| {
+        log::warn!("Backup '{}' was made before texman recorded checksums; its files cannot be verified", name);
+        Vec::new()
+    });
+    let backup_profiles: Vec<String> = conn
+        .prepare("SELECT DISTINCT profile FROM backups WHERE backup_name = ?1")?
+        .query_map(params![name], |row| row.get(0))?
+        .collect::<Result<_, _>>()?;
+    if let Some(profile) = backup_profiles.iter().find(|profile| **profile != active_profile) {
+        problems.insert(0, format!("the backup was made from profile '{}', but '{}' is active", profile, active_profile));
+    }
+    if !problems.is_empty() {
+        eprintln!("Backup '{}' does not match what was recorded when it was made:", name);
+        for problem in problems.iter().take(20) {
+            eprintln!("  {}", problem);
+        }
+        if problems.len() > 20 {
+            eprintln!("  ...and {} more", problems.len() - 20);
+        }
+        if !force {
+            anyhow::bail!("{} problems found; nothing was restored. Pass --force to restore it anyway.", problems.len());
+        }
+        eprintln!("Restoring anyway (--force).");
+    }
 
     for entry in fs::read_dir(&active_dir)? {
         let entry = entry?;
-        if entry.path().is_dir() {
+        if entry.file_type()?.is_dir() {
             fs::remove_dir_all(entry.path())?;
         } else {
             fs::remove_file(entry.path())?;
         }
     }
-
-    for entry in fs::read_dir(&backup_dir)? {
-        let entry = entry?;
-        let src_path = entry.path();
-        let dest_path = active_dir.join(entry.file_name());
-        copy_recursively(&src_path, &dest_path)?;
-    }
-
-    let conn = init_db(&texman_dir)?;
+    conn.execute("DELETE FROM profile_files WHERE profile = ?1", params![active_profile])?;
     conn.execute(
         "DELETE FROM installed_packages WHERE profile = ?1",
         params![active_profile],
     )?;
-    let mut stmt = conn.prepare("SELECT name, revision FROM backups WHERE backup_name = ?1")?;
-    let rows = stmt.query_map(params![name], |row| {
-        Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
-    })?;
-    for row in rows {
-        let (pkg_name, revision) = row?;
+
+    for (pkg_name, revision) in &packages {
+        tree::link_package(&conn, &active_profile, &active_dir, pkg_name, &store_path(&texman_dir, pkg_name, revision), policy, strategy)?;
         conn.execute(
             "INSERT INTO installed_packages (profile, name, revision) VALUES (?1, ?2, ?3)",
             params![active_profile, pkg_name, revision],
         )?;
     }
 
+    let store_dir = fs::canonicalize(texman_dir.join("store")).unwrap_or_else(|_| texman_dir.join("store"));
+    for rel_path in tree::walk_files(&backup_dir)? {
+        let src_path = backup_dir.join(&rel_path);
+        let dest_path = active_dir.join(&rel_path);
+        let managed = src_path.is_symlink() && fs::canonicalize(&src_path).is_ok_and(|target| target.starts_with(&store_dir));
+        if !managed && dest_path.symlink_metadata().is_err() {
+            if let Some(parent) = dest_path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            copy_recursively(&src_path, &dest_path)?;
+        }
+    }
+    // The backup's own ls-R may predate files linked since.
+    tree::refresh(&conn, &active_profile, &active_dir, &HashMap::new())?;
+
     log::info!("Restored profile '{}' from backup '{}'", active_profile, name);
     Ok(())
 }
@@ -952,12 +3593,17 @@ fn list_backups() -> anyhow::Result<()> {
         .join(".texman");
     let conn = init_db(&texman_dir)?;
 
-    let mut stmt = conn.prepare("SELECT backup_name, MIN(created_at), COUNT(name) FROM backups GROUP BY backup_name ORDER BY backup_name")?;
+    let mut stmt = conn.prepare(
+        "SELECT b.backup_name, MIN(b.created_at), COUNT(b.name), a.operation FROM backups b
+         LEFT JOIN autobackups a ON a.backup_name = b.backup_name
+         GROUP BY b.backup_name ORDER BY b.backup_name",
+    )?;
     let backups = stmt.query_map([], |row| {
         let name: String = row.get(0)?;
         let timestamp: i64 = row.get(1)?;
         let pkg_count: i64 = row.get(2)?;
-        Ok((name, timestamp, pkg_count))
+        let operation: Option<String> = row.get(3)?;
+        Ok((name, timestamp, pkg_count, operation))
     })?;
 
     let mut backup_list = Vec::new();
@@ -971,12 +3617,13 @@ fn list_backups() -> anyhow::Result<()> {
     }
 
     println!("Available backups:");
-    for (name, timestamp, pkg_count) in backup_list {
+    for (name, timestamp, pkg_count, operation) in backup_list {
         let dt = DateTime::<Utc>::from_timestamp(timestamp, 0)
             .unwrap()
             .format("%Y-%m-%d %H:%M:%S UTC")
             .to_string();
-        println!("  {} (created: {}, packages: {})", name, dt, pkg_count);
+        let automatic = operation.map(|operation| format!(", automatic before {}", operation)).unwrap_or_default();
+        println!("  {} (created: {}, packages: {}{})", name, dt, pkg_count, automatic);
     }
 
     Ok(())
@@ -995,12 +3642,14 @@ fn remove_backup(name: &str) -> anyhow::Result<()> {
     fs::remove_dir_all(&backup_dir)?;
     let conn = init_db(&texman_dir)?;
     conn.execute("DELETE FROM backups WHERE backup_name = ?1", params![name])?;
+    conn.execute("DELETE FROM backup_files WHERE backup_name = ?1", params![name])?;
+    conn.execute("DELETE FROM autobackups WHERE backup_name = ?1", params![name])?;
     log::info!("Removed backup '{}'", name);
 
     Ok(())
 }
 
-fn clean(remove_backups: bool) -> anyhow::Result<()> {
+fn clean(remove_backups: bool, config: &Config) -> anyhow::Result<()> {
     let texman_dir = dirs::home_dir()
         .ok_or_else(|| anyhow::anyhow!("Cannot find home directory"))?
         .join(".texman");
@@ -1024,11 +3673,344 @@ fn clean(remove_backups: bool) -> anyhow::Result<()> {
             fs::create_dir_all(&backups_dir)?;
             let conn = init_db(&texman_dir)?;
             conn.execute("DELETE FROM backups", [])?;
+            conn.execute("DELETE FROM backup_files", [])?;
+            conn.execute("DELETE FROM autobackups", [])?;
             log::info!("Removed all backups");
         } else {
             log::info!("No backups to remove");
         }
     }
 
+    // After the backups, whose revisions are then unused, and before pruning objects, which
+    // the removed revisions and staging directories may still link to.
+    let _store = cache::lock_blocking(&store_lock(&texman_dir))?;
+    let staging = store::remove_staging(&texman_dir.join("store"))?;
+    log::info!("Removed {} interrupted unpacks", staging);
+
+    let revisions = store::prune_revisions(&init_db(&texman_dir)?, &texman_dir.join("store"), config.keep_revisions)?;
+    log::info!("Removed {} old store revisions", revisions);
+
+    let (objects, size) = store::prune_objects(&texman_dir.join("store"))?;
+    log::info!("Removed {} unused store objects ({})", objects, output::format_size(size));
+
+    Ok(())
+}
+
+fn switch_revision(package: &str, revision: &str, config: &Config) -> anyhow::Result<()> {
+    let texman_dir = dirs::home_dir()
+        .ok_or_else(|| anyhow::anyhow!("Cannot find home directory"))?
+        .join(".texman");
+
+    let conn = init_db(&texman_dir)?;
+    let active_dir = active::dir(&texman_dir)?;
+    let active_profile = active::name(&texman_dir)?;
+    let revision = revision.trim_start_matches('r');
+
+    let current: Option<String> = conn
+        .query_row(
+            "SELECT revision FROM installed_packages WHERE profile = ?1 AND name = ?2",
+            params![active_profile, package],
+            |row| row.get(0),
+        )
+        .optional()?;
+    let Some(current) = current else {
+        anyhow::bail!("Package '{}' is not installed in profile '{}'", package, active_profile);
+    };
+
+    if current == revision {
+        log::info!("{} is already at r{} in profile '{}'", package, revision, active_profile);
+        return Ok(());
+    }
+
+    let target = store_path(&texman_dir, package, revision);
+    if !store::ensure_expanded(&target)? {
+        let available = stored_revisions(&texman_dir, package)?
+            .iter()
+            .map(|r| format!("r{}", r))
+            .collect::<Vec<_>>()
+            .join(", ");
+        anyhow::bail!("Revision r{} of '{}' is not in the store. Available revisions: {}", revision, package, available);
+    }
+
+    let policy = conflict_policy(&conn, &active_profile, config, None)?;
+    let strategy = install_strategy(&conn, &active_profile, config)?;
+    tree::link_package(&conn, &active_profile, &active_dir, package, &target, policy, strategy)?;
+    conn.execute(
+        "UPDATE installed_packages SET revision = ?1 WHERE profile = ?2 AND name = ?3",
+        params![revision, active_profile, package],
+    )?;
+    tree::refresh(&conn, &active_profile, &active_dir, &HashMap::new())?;
+    log::info!("Switched {} from r{} to r{} in profile '{}'", package, current, revision, active_profile);
+
+    Ok(())
+}
+
+fn watch_package(package: &str, tlpdb: &HashMap<String, Package>) -> anyhow::Result<()> {
+    let texman_dir = dirs::home_dir()
+        .ok_or_else(|| anyhow::anyhow!("Cannot find home directory"))?
+        .join(".texman");
+    let pkg = tlpdb.get(package).ok_or_else(|| anyhow::anyhow!("Package '{}' not found in TLPDB", package))?;
+
+    let conn = init_db(&texman_dir)?;
+    conn.execute(
+        "INSERT OR REPLACE INTO watchlist (name, revision) VALUES (?1, ?2)",
+        params![pkg.name, pkg.revision],
+    )?;
+    log::info!("Watching {} (currently r{})", pkg.name, pkg.revision);
+    Ok(())
+}
+
+fn unwatch_package(package: &str) -> anyhow::Result<()> {
+    let texman_dir = dirs::home_dir()
+        .ok_or_else(|| anyhow::anyhow!("Cannot find home directory"))?
+        .join(".texman");
+    let conn = init_db(&texman_dir)?;
+    let removed = conn.execute("DELETE FROM watchlist WHERE name = ?1", params![package])?;
+    if removed == 0 {
+        log::warn!("Package {} is not on the watchlist", package);
+    } else {
+        log::info!("Stopped watching {}", package);
+    }
+    Ok(())
+}
+
+fn list_watched(tlpdb: &HashMap<String, Package>) -> anyhow::Result<()> {
+    let texman_dir = dirs::home_dir()
+        .ok_or_else(|| anyhow::anyhow!("Cannot find home directory"))?
+        .join(".texman");
+    let conn = init_db(&texman_dir)?;
+
+    let mut stmt = conn.prepare("SELECT name, revision FROM watchlist ORDER BY name")?;
+    let rows = stmt.query_map([], |row| {
+        Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+    })?;
+    let mut watched = Vec::new();
+    for row in rows {
+        watched.push(row?);
+    }
+
+    if watched.is_empty() {
+        println!("No watched packages.");
+        return Ok(());
+    }
+
+    println!("Watched packages:");
+    for (name, revision) in watched {
+        match tlpdb.get(&name) {
+            Some(pkg) => println!("  {} (watched at r{}, latest r{})", name, revision, pkg.revision),
+            None => println!("  {} (watched at r{}, no longer in TLPDB)", name, revision),
+        }
+    }
+    Ok(())
+}
+
+fn watched_updates(conn: &Connection, profile: &str, tlpdb: &HashMap<String, Package>) -> anyhow::Result<Vec<(String, String, String)>> {
+    let mut stmt = conn.prepare(
+        "SELECT w.name, COALESCE(i.revision, w.revision) FROM watchlist w
+         LEFT JOIN installed_packages i ON i.name = w.name AND i.profile = ?1
+         ORDER BY w.name",
+    )?;
+    let rows = stmt.query_map(params![profile], |row| {
+        Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+    })?;
+
+    let mut updates = Vec::new();
+    for row in rows {
+        let (name, baseline) = row?;
+        if let Some(pkg) = tlpdb.get(&name) {
+            let baseline_rev: u32 = baseline.parse().unwrap_or(0);
+            let latest_rev: u32 = pkg.revision.parse().unwrap_or(0);
+            if latest_rev > baseline_rev {
+                updates.push((name, baseline, pkg.revision.clone()));
+            }
+        }
+    }
+    Ok(updates)
+}
+
+fn print_watched_updates(watched: &[(String, String, String)]) {
+    if watched.is_empty() {
+        return;
+    }
+    println!("*** Watched packages with new revisions ***");
+    for (name, baseline, latest) in watched {
+        println!("  {} r{} -> r{}", name, baseline, latest);
+    }
+    println!();
+}
+
+fn show_status(tlpdb: &HashMap<String, Package>, config: &Config) -> anyhow::Result<()> {
+    let texman_dir = dirs::home_dir()
+        .ok_or_else(|| anyhow::anyhow!("Cannot find home directory"))?
+        .join(".texman");
+    let conn = init_db(&texman_dir)?;
+
+    let Some(active_profile) = active::get(&texman_dir)? else {
+        println!("No active profile set.");
+        return Ok(());
+    };
+    let installed: i64 = conn.query_row(
+        "SELECT COUNT(*) FROM installed_packages WHERE profile = ?1",
+        params![active_profile],
+        |row| row.get(0),
+    )?;
+    let updates = find_updates(&conn, &active_profile, tlpdb)?;
+    let watched = watched_updates(&conn, &active_profile, tlpdb)?;
+
+    print_watched_updates(&watched);
+    println!("Active profile: {}", active_profile);
+    println!("Installed packages: {}", installed);
+    println!("Available updates: {}", updates.len());
+    let removed = orphans::find(&conn, &active_profile, tlpdb, config)?;
+    if !removed.is_empty() {
+        println!("Removed upstream: {} (see 'texman update --check')", removed.len());
+    }
+    println!("TLPDB packages: {}", tlpdb.len());
+    match platform::current() {
+        Some(platform) if platform::is_foreign() => println!("Platform: {} (binaries can't run on this machine)", platform),
+        Some(platform) => println!("Platform: {}", platform),
+        None => println!("Platform: none; TeX Live has no binaries for this machine (see --platform)"),
+    }
+    if let Some(tree) = texmfhome::user_tree() {
+        let shadowed = texmfhome::shadows(&conn, &active_profile, &tree)?.len();
+        if shadowed > 0 {
+            println!("User tree: {} ({} shadowed files; see 'texman shadow-check')", tree.display(), shadowed);
+        } else {
+            println!("User tree: {}", tree.display());
+        }
+    }
+
+    if let Some(repository) = repo::load(&texman_dir) {
+        println!("Repository: {}", config.repository);
+        if let Some(release) = repository.release {
+            let label = if repo::is_pretest(&config.repository) {
+                " (pretest)"
+            } else if repository.frozen {
+                " (frozen)"
+            } else {
+                ""
+            };
+            println!("TeX Live release: {}{}", release, label);
+        }
+        if let Some(revision) = repository.revision {
+            println!("Repository revision: {}", revision);
+        }
+        if let Some(minrelease) = repository.minrelease {
+            println!("Minimum supported release: {}", minrelease);
+        }
+        if let Some(format) = &repository.container_format {
+            println!("Container format: {}", format);
+        }
+        for warning in repo::warnings(&repository) {
+            println!("Warning: {}", warning);
+        }
+    }
+    Ok(())
+}
+
+async fn show_changelog(package: &str, tlpdb: &HashMap<String, Package>, config: &Config) -> anyhow::Result<()> {
+    let texman_dir = dirs::home_dir()
+        .ok_or_else(|| anyhow::anyhow!("Cannot find home directory"))?
+        .join(".texman");
+    let pkg = tlpdb.get(package).ok_or_else(|| anyhow::anyhow!("Package '{}' not found in TLPDB", package))?;
+    let conn = init_db(&texman_dir)?;
+
+    let installed_revision = if active::is_set(&texman_dir) {
+        active_installed_packages()?.remove(&pkg.name)
+    } else {
+        None
+    };
+    let describe = |version: Option<&String>, date: Option<&String>| match (version, date) {
+        (Some(v), Some(d)) => format!("version {}, {}", v, d),
+        (Some(v), None) => format!("version {}", v),
+        (None, Some(d)) => d.clone(),
+        (None, None) => "no catalogue version".to_string(),
+    };
+
+    println!("Package: {}", pkg.name);
+    match &installed_revision {
+        Some(revision) => {
+            let metadata: Option<(Option<String>, Option<String>)> = conn
+                .query_row(
+                    "SELECT catalogue_version, catalogue_date FROM revision_metadata WHERE name = ?1 AND revision = ?2",
+                    params![pkg.name, revision],
+                    |row| Ok((row.get(0)?, row.get(1)?)),
+                )
+                .optional()?;
+            let (version, date) = metadata.unwrap_or((None, None));
+            println!("Installed: r{} ({})", revision, describe(version.as_ref(), date.as_ref()));
+        }
+        None => println!("Installed: no"),
+    }
+    println!("Available: r{} ({})", pkg.revision, describe(pkg.catalogue_version.as_ref(), pkg.catalogue_date.as_ref()));
+    if installed_revision.as_deref() == Some(pkg.revision.as_str()) {
+        println!("The installed revision is current.");
+    }
+
+    if config.offline {
+        log::info!("Offline mode: skipping CTAN announcement lookup");
+        return Ok(());
+    }
+
+    let ctan_id = pkg.catalogue.as_deref().unwrap_or(&pkg.name);
+    let ann_url = format!("https://ctan.org/ctan-ann/pkg/{}", ctan_id);
+    let client = mirror::client_builder(config)?.build()?;
+    let page = mirror::send(config, || client.get(&ann_url)).await;
+    let announcements = match page {
+        Ok(response) => parse_announcements(&response.text().await?),
+        Err(e) => {
+            log::warn!("Failed to fetch CTAN announcements for {}: {}", ctan_id, e);
+            Vec::new()
+        }
+    };
+
+    println!();
+    if announcements.is_empty() {
+        println!("No CTAN announcements found. See {}", ann_url);
+    } else {
+        println!("CTAN announcements ({}):", ann_url);
+        for (title, link) in announcements {
+            println!("  {}", title);
+            println!("    https://ctan.org{}", link);
+        }
+    }
+
+    Ok(())
+}
+
+fn parse_announcements(html: &str) -> Vec<(String, String)> {
+    let mut announcements = Vec::new();
+    let mut rest = html;
+    while let Some(start) = rest.find("href=\"/ctan-ann/id/") {
+        rest = &rest[start + 6..];
+        let Some(end) = rest.find('"') else { break };
+        let link = rest[..end].to_string();
+        let Some(text_start) = rest.find('>') else { break };
+        let Some(text_end) = rest[text_start..].find("</a>") else { break };
+        let title = rest[text_start + 1..text_start + text_end].trim().to_string();
+        if !title.is_empty() && !announcements.iter().any(|(_, l)| *l == link) {
+            announcements.push((title, link));
+        }
+        rest = &rest[text_start + text_end..];
+    }
+    announcements
+}
+
+fn browse_package(package: &str, tlpdb: &HashMap<String, Package>, docs: bool) -> anyhow::Result<()> {
+    let pkg = tlpdb.get(package).ok_or_else(|| anyhow::anyhow!("Package '{}' not found in TLPDB", package))?;
+    let url = if docs {
+        format!("https://texdoc.org/pkg/{}", pkg.name)
+    } else if let Some(ctan) = &pkg.catalogue_ctan {
+        format!("https://ctan.org/tex-archive{}", ctan)
+    } else {
+        format!("https://ctan.org/pkg/{}", pkg.catalogue.as_deref().unwrap_or(&pkg.name))
+    };
+
+    println!("{}", url);
+    match os::open_url(&url) {
+        Ok(status) if status.success() => log::info!("Opened {} in the default browser", url),
+        Ok(status) => log::warn!("Opening {} in the browser exited with {}", url, status),
+        Err(e) => log::warn!("Failed to open {} in the browser: {}", url, e),
+    }
     Ok(())
 }
@@ -0,0 +1,93 @@
+use rusqlite::{params, Connection};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::path::Path;
+
+// What a backup held when it was made. Links record their target, and, when it resolves, the
+// checksum of the store file behind it, which pins the package revision the link stood for.
+struct Entry {
+    target: Option<String>,
+    sha256: Option<String>,
+    size: Option<u64>,
+}
+
+fn sha256(path: &Path) -> anyhow::Result<String> {
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut File::open(path)?, &mut hasher)?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+fn entry(path: &Path) -> anyhow::Result<Entry> {
+    let metadata = fs::symlink_metadata(path)?;
+    if metadata.file_type().is_symlink() {
+        let target = fs::read_link(path)?.to_string_lossy().to_string();
+        let resolved = fs::metadata(path).ok().filter(|metadata| metadata.is_file());
+        let sha256 = match resolved {
+            Some(_) => Some(sha256(path)?),
+            None => None,
+        };
+        Ok(Entry { target: Some(target), sha256, size: resolved.map(|metadata| metadata.len()) })
+    } else {
+        Ok(Entry { target: None, sha256: Some(sha256(path)?), size: Some(metadata.len()) })
+    }
+}
+
+pub fn record(conn: &Connection, backup_name: &str, backup_dir: &Path) -> anyhow::Result<()> {
+    conn.execute("DELETE FROM backup_files WHERE backup_name = ?1", params![backup_name])?;
+    for rel_path in crate::tree::walk_files(backup_dir)? {
+        let entry = entry(&backup_dir.join(&rel_path))?;
+        conn.execute(
+            "INSERT INTO backup_files (backup_name, path, target, sha256, size) VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![backup_name, rel_path.to_string_lossy(), entry.target, entry.sha256, entry.size.map(|size| size as i64)],
+        )?;
+    }
+    Ok(())
+}
+
+// Compares `backup_dir` with what was recorded when the backup was made: files that were
+// changed, removed, or added since, and links whose store files no longer hold the content they
+// had, e.g. because a store directory was damaged. Returns None for backups made before
+// checksums were recorded.
+pub fn verify(conn: &Connection, backup_name: &str, backup_dir: &Path) -> anyhow::Result<Option<Vec<String>>> {
+    let mut stmt = conn.prepare("SELECT path, target, sha256, size FROM backup_files WHERE backup_name = ?1")?;
+    let recorded: HashMap<String, Entry> = stmt
+        .query_map(params![backup_name], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                Entry { target: row.get(1)?, sha256: row.get(2)?, size: row.get::<_, Option<i64>>(3)?.map(|size| size as u64) },
+            ))
+        })?
+        .collect::<Result<_, _>>()?;
+    if recorded.is_empty() {
+        return Ok(None);
+    }
+
+    let mut problems = Vec::new();
+    let present = crate::tree::walk_files(backup_dir)?;
+    for rel_path in &present {
+        let key = rel_path.to_string_lossy().to_string();
+        if !recorded.contains_key(&key) {
+            problems.push(format!("{} was added to the backup", key));
+        }
+    }
+    let mut paths: Vec<&String> = recorded.keys().collect();
+    paths.sort();
+    for path in paths {
+        let expected = &recorded[path];
+        let file = backup_dir.join(path);
+        if file.symlink_metadata().is_err() {
+            problems.push(format!("{} is missing from the backup", path));
+            continue;
+        }
+        let actual = entry(&file)?;
+        if actual.target != expected.target {
+            problems.push(format!("{} was replaced since the backup was made", path));
+        } else if expected.target.is_some() && actual.sha256 != expected.sha256 {
+            problems.push(format!("{} links to a store file whose content changed", path));
+        } else if expected.target.is_none() && (actual.size != expected.size || actual.sha256 != expected.sha256) {
+            problems.push(format!("{} was modified since the backup was made", path));
+        }
+    }
+    Ok(Some(problems))
+}
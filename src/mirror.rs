@@ -0,0 +1,494 @@
+use crate::config::{Config, DEFAULT_REPOSITORY};
+use crate::Package;
+use futures::future::join_all;
+use reqwest::header::HeaderMap;
+use sha2::{Digest, Sha512};
+use std::collections::hash_map::RandomState;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::hash::{BuildHasher, Hasher};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+use tokio::sync::Semaphore;
+use xz2::write::XzEncoder;
+
+pub const KNOWN_MIRRORS: &[(&str, &str)] = &[
+    ("Automatic (mirror.ctan.org redirector)", DEFAULT_REPOSITORY),
+    ("Germany (ftp.fau.de)", "https://ftp.fau.de/ctan/systems/texlive/tlnet"),
+    ("United States (mirrors.mit.edu)", "https://mirrors.mit.edu/CTAN/systems/texlive/tlnet"),
+    ("China (mirrors.tuna.tsinghua.edu.cn)", "https://mirrors.tuna.tsinghua.edu.cn/CTAN/systems/texlive/tlnet"),
+];
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+// Between two chunks of a response, not for the whole download.
+const READ_TIMEOUT: Duration = Duration::from_secs(30);
+const TEST_TIMEOUT: Duration = Duration::from_secs(10);
+const MAX_REDIRECTS: usize = 10;
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+const POOL_IDLE_TIMEOUT: Duration = Duration::from_secs(90);
+const TCP_KEEPALIVE: Duration = Duration::from_secs(60);
+const LOCAL_CHUNK_SIZE: usize = 64 * 1024;
+
+// Every HTTP client trusts the configured CA bundle on top of the system roots, and refuses
+// redirects from HTTPS to plain HTTP, which proxies that only allow HTTPS would block anyway.
+// Without a `proxy` setting, reqwest follows http_proxy, https_proxy, all_proxy, and no_proxy.
+pub fn client_builder(config: &Config) -> anyhow::Result<reqwest::ClientBuilder> {
+    let mut builder = reqwest::Client::builder()
+        .user_agent(concat!("texman/", env!("CARGO_PKG_VERSION")))
+        .redirect(reqwest::redirect::Policy::custom(|attempt| {
+            let downgrade = attempt.previous().last().is_some_and(|url| url.scheme() == "https") && attempt.url().scheme() == "http";
+            if downgrade {
+                let error = format!("refusing redirect from HTTPS to {}", attempt.url());
+                attempt.error(error)
+            } else if attempt.previous().len() >= MAX_REDIRECTS {
+                attempt.error("too many redirects")
+            } else {
+                attempt.follow()
+            }
+        }));
+    if let Some(proxy) = &config.proxy {
+        let scheme = proxy.split_once("://").map(|(scheme, _)| scheme).unwrap_or_default();
+        if !matches!(scheme, "http" | "https" | "socks5" | "socks5h") {
+            anyhow::bail!("Invalid proxy '{}': expected an http://, https://, socks5://, or socks5h:// URL", proxy);
+        }
+        let proxy = reqwest::Proxy::all(proxy)
+            .map_err(|e| anyhow::anyhow!("Invalid proxy '{}': {}", proxy, e))?
+            .no_proxy(reqwest::NoProxy::from_env());
+        builder = builder.proxy(proxy);
+    }
+    if let Some(ca_bundle) = &config.ca_bundle {
+        let pem = fs::read(ca_bundle).map_err(|e| anyhow::anyhow!("Cannot read CA bundle {:?}: {}", ca_bundle, e))?;
+        let certificates = reqwest::Certificate::from_pem_bundle(&pem)
+            .map_err(|e| anyhow::anyhow!("Invalid CA bundle {:?}: {}", ca_bundle, e))?;
+        if certificates.is_empty() {
+            anyhow::bail!("No certificates found in CA bundle {:?}", ca_bundle);
+        }
+        for certificate in certificates {
+            builder = builder.add_root_certificate(certificate);
+        }
+    }
+    Ok(builder)
+}
+
+// One client is built per command and shared by all its downloads, so connections to the mirror
+// are kept alive and reused (over HTTP/2 where the mirror offers it) instead of paying for a new
+// TCP and TLS handshake per package.
+pub fn client(config: &Config) -> anyhow::Result<reqwest::Client> {
+    Ok(client_builder(config)?
+        .connect_timeout(CONNECT_TIMEOUT)
+        .read_timeout(READ_TIMEOUT)
+        .pool_idle_timeout(POOL_IDLE_TIMEOUT)
+        .tcp_keepalive(TCP_KEEPALIVE)
+        .http2_adaptive_window(true)
+        .build()?)
+}
+
+// Worth another try: the request timed out, the connection dropped, or the server said it is
+// overloaded or broken for now.
+fn is_transient(error: &reqwest::Error) -> bool {
+    error.is_timeout()
+        || error.is_connect()
+        || error.is_request()
+        || error.status().is_some_and(|status| status == reqwest::StatusCode::TOO_MANY_REQUESTS || status.is_server_error())
+}
+
+// A mirror that lacks the file or still fails after retries is skipped; any other error (e.g. a
+// 403) is reported as is, since the next mirror would likely fail the same way.
+fn should_fail_over(error: &reqwest::Error) -> bool {
+    is_transient(error) || error.status() == Some(reqwest::StatusCode::NOT_FOUND)
+}
+
+// `retry_delay_ms` doubled for each further attempt; with jitter, a random 50-100% of that, so
+// parallel downloads don't all hit the mirror again at the same moment.
+pub fn backoff(config: &Config, attempt: u32) -> Duration {
+    let delay = Duration::from_millis(config.retry_delay_ms)
+        .saturating_mul(1 << attempt.saturating_sub(1).min(16))
+        .min(MAX_BACKOFF);
+    if !config.retry_jitter {
+        return delay;
+    }
+    let random = RandomState::new().build_hasher().finish();
+    delay.mul_f64(0.5 + (random % 1000) as f64 / 2000.0)
+}
+
+fn retry_after(response: &reqwest::Response) -> Option<Duration> {
+    let seconds: u64 = response.headers().get(reqwest::header::RETRY_AFTER)?.to_str().ok()?.trim().parse().ok()?;
+    Some(Duration::from_secs(seconds).min(MAX_BACKOFF))
+}
+
+// Sends the request built by `request`, retrying transient failures up to `retries` times and
+// honoring a server's Retry-After. 416 Range Not Satisfiable is passed through for the caller.
+pub async fn send(config: &Config, request: impl Fn() -> reqwest::RequestBuilder) -> reqwest::Result<reqwest::Response> {
+    let mut attempt = 0;
+    loop {
+        let result = request().send().await;
+        let server_delay = result.as_ref().ok().and_then(retry_after);
+        let result = result.and_then(|response| {
+            if response.status() == reqwest::StatusCode::RANGE_NOT_SATISFIABLE {
+                Ok(response)
+            } else {
+                response.error_for_status()
+            }
+        });
+        match result {
+            Err(e) if attempt < config.retries && is_transient(&e) => {
+                attempt += 1;
+                let delay = server_delay.unwrap_or_else(|| backoff(config, attempt));
+                log::warn!("{}; retrying in {:.1}s (attempt {} of {})", e, delay.as_secs_f64(), attempt, config.retries);
+                tokio::time::sleep(delay).await;
+            }
+            result => return result,
+        }
+    }
+}
+
+// Fetches `path` from the configured repository, falling back to each of `mirrors` in turn.
+// Returns the URL that answered together with the response.
+pub async fn get(config: &Config, client: &reqwest::Client, path: &str) -> anyhow::Result<(String, reqwest::Response)> {
+    get_from(config, client, path, 0).await
+}
+
+// Like `get`, but asks for the bytes from `offset` on. The caller must check for 206 Partial
+// Content, since servers may ignore the range and send everything; 416 Range Not Satisfiable is
+// passed through too.
+pub async fn get_from(config: &Config, client: &reqwest::Client, path: &str, offset: u64) -> anyhow::Result<(String, reqwest::Response)> {
+    let mut headers = HeaderMap::new();
+    if offset > 0 {
+        log::debug!("Fetching {} from byte {}", path, offset);
+        headers.insert(reqwest::header::RANGE, format!("bytes={}-", offset).parse()?);
+    }
+    get_with_headers(config, client, path, &headers).await
+}
+
+// Like `get`, with extra request headers, e.g. for a conditional request; 304 Not Modified is
+// returned like any other success.
+pub async fn get_with_headers(config: &Config, client: &reqwest::Client, path: &str, headers: &HeaderMap) -> anyhow::Result<(String, reqwest::Response)> {
+    let urls = config.repository_urls(path);
+    let mut last_error = None;
+    for (i, url) in urls.iter().enumerate() {
+        log::debug!("Fetching {}", url);
+        let (result, fail_over) = match local_path(url) {
+            Some(local) => {
+                let result = local_response(&local, headers).await;
+                let missing = result.as_ref().is_err_and(|e| e.kind() == std::io::ErrorKind::NotFound);
+                (result.map_err(|e| e.to_string()), missing)
+            }
+            None => {
+                let result = send(config, || client.get(url).headers(headers.clone())).await;
+                let fail_over = result.as_ref().is_err_and(should_fail_over);
+                (result.map_err(|e| e.to_string()), fail_over)
+            }
+        };
+        match result {
+            Ok(response) => {
+                if i > 0 {
+                    log::warn!("Fetched {} from fallback mirror {}", path, url);
+                }
+                return Ok((url.clone(), response));
+            }
+            Err(e) if fail_over && i + 1 < urls.len() => {
+                log::warn!("Mirror failed for {}: {}; trying the next mirror", url, e);
+                last_error = Some(e);
+            }
+            Err(e) => return Err(anyhow::anyhow!("Failed to download {}: {}", url, e)),
+        }
+    }
+    Err(anyhow::anyhow!("Failed to download {} from any mirror: {}", path, last_error.unwrap_or_default()))
+}
+
+// A repository or mirror can be a local tlnet tree, such as an rsync mirror or an NFS share,
+// given as a file:// URL.
+pub fn local_path(url: &str) -> Option<PathBuf> {
+    url.strip_prefix("file://").map(PathBuf::from)
+}
+
+// Turns a plain directory into a file:// URL with an absolute path, so the setting doesn't
+// depend on where texman runs. Anything else is returned as given.
+pub fn normalize_url(url: &str) -> String {
+    let url = url.trim_end_matches('/');
+    if url.contains("://") || matches!(url, "ctan" | "pretest") {
+        return url.to_string();
+    }
+    match fs::canonicalize(url) {
+        Ok(path) if path.is_dir() => format!("file://{}", path.display()),
+        _ => url.to_string(),
+    }
+}
+
+fn http_date(time: SystemTime) -> String {
+    chrono::DateTime::<chrono::Utc>::from(time).format("%a, %d %b %Y %H:%M:%S GMT").to_string()
+}
+
+fn file_body(file: tokio::fs::File) -> reqwest::Body {
+    let chunks = futures::stream::try_unfold(file, |mut file| async move {
+        let mut buffer = vec![0; LOCAL_CHUNK_SIZE];
+        let read = file.read(&mut buffer).await?;
+        buffer.truncate(read);
+        Ok::<_, std::io::Error>((read > 0).then_some((buffer, file)))
+    });
+    reqwest::Body::wrap_stream(chunks)
+}
+
+// Answers a request for a file of a local tree the way a web server would, so callers don't
+// need to tell the two apart: ranges are honored, and an unchanged file gets 304 Not Modified
+// for a matching If-Modified-Since. A missing file is a NotFound error.
+async fn local_response(path: &Path, headers: &HeaderMap) -> std::io::Result<reqwest::Response> {
+    let mut file = tokio::fs::File::open(path).await?;
+    let metadata = file.metadata().await?;
+    if !metadata.is_file() {
+        return Err(std::io::Error::new(std::io::ErrorKind::NotFound, format!("{:?} is not a file", path)));
+    }
+    let length = metadata.len();
+    let modified = metadata.modified().ok().map(http_date);
+    let header = |name| headers.get(name).and_then(|value: &reqwest::header::HeaderValue| value.to_str().ok());
+    let mut response = http::Response::builder().header(reqwest::header::ACCEPT_RANGES, "bytes");
+    if let Some(modified) = &modified {
+        response = response.header(reqwest::header::LAST_MODIFIED, modified);
+    }
+    let start = header(reqwest::header::RANGE)
+        .and_then(|range| range.strip_prefix("bytes="))
+        .and_then(|range| range.strip_suffix('-'))
+        .and_then(|start| start.parse::<u64>().ok());
+
+    let response = if modified.is_some() && header(reqwest::header::IF_MODIFIED_SINCE) == modified.as_deref() {
+        response.status(reqwest::StatusCode::NOT_MODIFIED).body(reqwest::Body::from(Vec::new()))
+    } else {
+        match start {
+            Some(start) if start >= length => response
+                .status(reqwest::StatusCode::RANGE_NOT_SATISFIABLE)
+                .header(reqwest::header::CONTENT_RANGE, format!("bytes */{}", length))
+                .body(reqwest::Body::from(Vec::new())),
+            Some(start) => {
+                file.seek(std::io::SeekFrom::Start(start)).await?;
+                response
+                    .status(reqwest::StatusCode::PARTIAL_CONTENT)
+                    .header(reqwest::header::CONTENT_RANGE, format!("bytes {}-{}/{}", start, length - 1, length))
+                    .body(file_body(file))
+            }
+            None => response.status(reqwest::StatusCode::OK).body(file_body(file)),
+        }
+    };
+    Ok(response.map_err(std::io::Error::other)?.into())
+}
+
+pub fn set(texman_dir: &Path, repository: &str, fallbacks: &[String]) -> anyhow::Result<()> {
+    let mut config = Config::load_file(texman_dir)?;
+    config.repository = normalize_url(repository);
+    config.mirrors = fallbacks.iter().map(|mirror| normalize_url(mirror)).collect();
+    config.save(texman_dir)?;
+    println!("Repository set to {}", config.repository);
+    if !config.mirrors.is_empty() {
+        println!("Fallback mirrors: {}", config.mirrors.join(", "));
+    }
+    Ok(())
+}
+
+pub fn list(config: &Config) {
+    println!("Repository: {}", config.repository);
+    if config.mirrors.is_empty() {
+        println!("Fallback mirrors: none");
+    } else {
+        println!("Fallback mirrors:");
+        for mirror in &config.mirrors {
+            println!("  {}", mirror);
+        }
+    }
+    println!("Known mirrors:");
+    for (label, url) in KNOWN_MIRRORS {
+        println!("  {} [{}]", label, url);
+    }
+}
+
+// Times a request for the TLPDB checksum, the smallest file every mirror carries.
+pub async fn test(config: &Config, urls: &[String]) -> anyhow::Result<()> {
+    let mut candidates: Vec<String> = if urls.is_empty() {
+        std::iter::once(config.repository.clone())
+            .chain(config.mirrors.iter().cloned())
+            .chain(KNOWN_MIRRORS.iter().map(|(_, url)| url.to_string()))
+            .collect()
+    } else {
+        urls.to_vec()
+    };
+    let mut seen = std::collections::HashSet::new();
+    candidates.retain(|url| seen.insert(url.trim_end_matches('/').to_string()));
+
+    let client = client_builder(config)?.timeout(TEST_TIMEOUT).build()?;
+    let mut results = Vec::new();
+    for mirror in &candidates {
+        let url = format!("{}/tlpkg/texlive.tlpdb.sha512", mirror.trim_end_matches('/'));
+        let started = Instant::now();
+        let result = match local_path(&url) {
+            Some(local) => fs::metadata(local).map(|_| ()).map_err(|e| e.to_string()),
+            None => client.get(&url).send().await.and_then(|response| response.error_for_status()).map(|_| ()).map_err(|e| e.to_string()),
+        };
+        results.push((mirror, result.map(|_| started.elapsed())));
+    }
+    results.sort_by_key(|(_, result)| result.as_ref().map(|elapsed| *elapsed).unwrap_or(Duration::MAX));
+    for (mirror, result) in results {
+        match result {
+            Ok(elapsed) => println!("  {:>6} ms  {}", elapsed.as_millis(), mirror),
+            Err(e) => println!("  {:>9}  {} ({})", "failed", mirror, e),
+        }
+    }
+    Ok(())
+}
+
+// The archives a mirror has to carry for `pkg`: its container and, where the TLPDB lists them,
+// the doc and source containers, so clients with any `docs`/`sources` setting can use it.
+fn containers(pkg: &Package) -> Vec<(String, Option<String>)> {
+    let mut containers = vec![(format!("{}.tar.xz", pkg.name), pkg.container_checksum.clone())];
+    if pkg.doc_container_size.is_some() {
+        containers.push((format!("{}.doc.tar.xz", pkg.name), pkg.doc_container_checksum.clone()));
+    }
+    if pkg.src_container_size.is_some() {
+        containers.push((format!("{}.source.tar.xz", pkg.name), pkg.src_container_checksum.clone()));
+    }
+    containers
+}
+
+// Keeps the repository's `00texlive.*` blocks and those of the mirrored packages, so clients of a
+// partial mirror are told a package doesn't exist instead of getting a 404 for its archive.
+pub fn filter_tlpdb(text: &str, keep: &HashSet<&str>) -> String {
+    let mut filtered = String::new();
+    for block in text.split("\n\n").filter(|block| !block.trim().is_empty()) {
+        let name = block.trim_start().lines().next().and_then(|line| line.strip_prefix("name ")).unwrap_or("");
+        if name.starts_with("00texlive.") || keep.contains(name) {
+            filtered.push_str(block.trim_matches('\n'));
+            filtered.push_str("\n\n");
+        }
+    }
+    filtered
+}
+
+// Writes the TLPDB the way tlnet publishes it: plain, xz-compressed, and with its SHA-512.
+fn write_tlpdb(dir: &Path, text: &str) -> anyhow::Result<()> {
+    let tlpkg = dir.join("tlpkg");
+    fs::create_dir_all(&tlpkg)?;
+    let mut encoder = XzEncoder::new(Vec::new(), 9);
+    encoder.write_all(text.as_bytes())?;
+    let compressed = encoder.finish()?;
+    for (name, contents) in [("texlive.tlpdb", text.as_bytes()), ("texlive.tlpdb.xz", compressed.as_slice())] {
+        let path = tlpkg.join(name);
+        let staged = crate::cache::staging_path(&path);
+        fs::write(&staged, contents)?;
+        fs::rename(&staged, &path)?;
+    }
+    fs::write(tlpkg.join("texlive.tlpdb.sha512"), format!("{:x}  texlive.tlpdb\n", Sha512::digest(text.as_bytes())))?;
+    Ok(())
+}
+
+// Builds or refreshes a tlnet-style tree in `dir` (tlpkg/texlive.tlpdb*, archive/*.tar.xz) that
+// texman clients can use as their repository. Without a selection every package is mirrored.
+// Archives already there with the right checksum are kept, and archives the TLPDB no longer
+// lists are removed, so rerunning it updates the mirror.
+pub async fn create(
+    dir: &Path,
+    scheme: Option<&str>,
+    packages: &[String],
+    tlpdb: &HashMap<String, Package>,
+    config: &Config,
+) -> anyhow::Result<Vec<(String, String)>> {
+    let texman_dir = dirs::home_dir()
+        .ok_or_else(|| anyhow::anyhow!("Cannot find home directory"))?
+        .join(".texman");
+    if config.offline {
+        anyhow::bail!("Offline mode: cannot download archives for a mirror");
+    }
+    let tlpdb_text = fs::read_to_string(texman_dir.join("db").join("tlpdb.txt"))?;
+
+    let roots: Vec<String> = match scheme {
+        Some(scheme) => vec![scheme.to_string()],
+        None => packages.to_vec(),
+    };
+    let mut selected = Vec::new();
+    if roots.is_empty() {
+        selected.extend(tlpdb.keys().cloned());
+        selected.sort();
+    } else {
+        let mut visited = Vec::new();
+        for root in &roots {
+            crate::resolve_dependencies(root, tlpdb, &mut selected, &mut visited)?;
+        }
+    }
+
+    let archive_dir = dir.join("archive");
+    fs::create_dir_all(&archive_dir)?;
+    let wanted: Vec<(String, Option<String>)> = selected.iter().flat_map(|name| containers(&tlpdb[name])).collect();
+    let mut missing = Vec::new();
+    for (archive, checksum) in &wanted {
+        let path = archive_dir.join(archive);
+        if !path.exists() || !crate::checksum_matches(&path, checksum.as_deref())? {
+            missing.push((archive.clone(), checksum.clone()));
+        }
+    }
+    println!(
+        "Mirroring {} packages into {:?}: {} archives, {} to download",
+        selected.len(),
+        dir,
+        wanted.len(),
+        missing.len()
+    );
+
+    let client = client(config)?;
+    let semaphore = Arc::new(Semaphore::new(config.jobs.max(1)));
+    let tasks: Vec<_> = missing
+        .iter()
+        .map(|(archive, checksum)| {
+            let (archive, checksum) = (archive.clone(), checksum.clone());
+            let path = archive_dir.join(&archive);
+            let (config, client, semaphore) = (config.clone(), client.clone(), semaphore.clone());
+            tokio::spawn(async move {
+                let _permit = semaphore.acquire_owned().await?;
+                let partial = path.with_extension("xz.part");
+                let url_path = format!("archive/{}", archive);
+                crate::download_verified(&config, &client, &url_path, &partial, &archive, checksum.as_deref()).await?;
+                fs::rename(&partial, &path)?;
+                anyhow::Ok(())
+            })
+        })
+        .collect();
+    let mut failed = Vec::new();
+    for ((archive, _), result) in missing.iter().zip(join_all(tasks).await) {
+        match result.map_err(|e| anyhow::anyhow!("Task failed: {}", e)).and_then(|result| result) {
+            Ok(()) => {}
+            Err(e) if config.keep_going => {
+                log::warn!("Failed to download {}: {}", archive, e);
+                failed.push((archive.clone(), e.to_string()));
+            }
+            Err(e) => return Err(anyhow::anyhow!("Download failed: {}", e)),
+        }
+    }
+
+    let wanted_names: HashSet<&str> = wanted.iter().map(|(archive, _)| archive.as_str()).collect();
+    let mut removed = 0;
+    for entry in fs::read_dir(&archive_dir)? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().to_string();
+        if name.ends_with(".tar.xz") && !wanted_names.contains(name.as_str()) {
+            fs::remove_file(entry.path())?;
+            removed += 1;
+        }
+    }
+
+    // The TLPDB goes in last, so clients never see packages whose archives aren't there yet.
+    let keep: HashSet<&str> = selected.iter().map(String::as_str).collect();
+    let text = if roots.is_empty() { tlpdb_text } else { filter_tlpdb(&tlpdb_text, &keep) };
+    write_tlpdb(dir, &text)?;
+
+    let size: u64 = wanted_names
+        .iter()
+        .filter_map(|name| fs::metadata(archive_dir.join(name)).ok())
+        .map(|metadata| metadata.len())
+        .sum();
+    println!(
+        "Mirror at {:?} is ready: {} packages, {}{}. Serve the directory over HTTP and point clients at it with 'texman mirror set <url>'.",
+        dir,
+        selected.len(),
+        crate::output::format_size(size),
+        if removed > 0 { format!(", {} stale archives removed", removed) } else { String::new() }
+    );
+    Ok(failed)
+}
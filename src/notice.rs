@@ -0,0 +1,82 @@
+use crate::config::Config;
+use crate::Package;
+use chrono::{DateTime, Duration, Utc};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+const CHECK_INTERVAL_HOURS: i64 = 24;
+const RELEASES_URL: &str = "https://api.github.com/repos/amirhosseinghanipour/texman/releases/latest";
+
+// Printed to stderr so JSON output on stdout stays parseable.
+pub async fn maybe_print(config: &Config, tlpdb: &HashMap<String, Package>) {
+    if !config.update_notice || tlpdb.is_empty() {
+        return;
+    }
+    if let Err(e) = check(config, tlpdb).await {
+        log::debug!("Update notice check failed: {}", e);
+    }
+}
+
+async fn check(config: &Config, tlpdb: &HashMap<String, Package>) -> anyhow::Result<()> {
+    let texman_dir = dirs::home_dir()
+        .ok_or_else(|| anyhow::anyhow!("Cannot find home directory"))?
+        .join(".texman");
+    let stamp_path = texman_dir.join("db").join("update_notice.stamp");
+    if !is_due(&stamp_path) {
+        return Ok(());
+    }
+    fs::write(&stamp_path, Utc::now().to_rfc3339())?;
+
+    let mut notices = Vec::new();
+    if let Ok(profile) = crate::active::name(&texman_dir) {
+        let conn = crate::init_db(&texman_dir)?;
+        let updates = crate::find_updates(&conn, &profile, tlpdb)?;
+        if !updates.is_empty() {
+            notices.push(format!(
+                "{} package updates available in profile '{}'; run 'texman update' to install them.",
+                updates.len(),
+                profile
+            ));
+        }
+    }
+    if !config.offline
+        && let Some(latest) = latest_release(config).await
+        && is_newer(&latest, env!("CARGO_PKG_VERSION"))
+    {
+        notices.push(format!("texman {} is available (you have {}).", latest, env!("CARGO_PKG_VERSION")));
+    }
+
+    if !notices.is_empty() {
+        eprintln!();
+        for notice in notices {
+            eprintln!("notice: {}", notice);
+        }
+    }
+    Ok(())
+}
+
+fn is_due(stamp_path: &Path) -> bool {
+    let Some(last_check) = fs::read_to_string(stamp_path)
+        .ok()
+        .and_then(|text| DateTime::parse_from_rfc3339(text.trim()).ok())
+    else {
+        return true;
+    };
+    Utc::now() - last_check.with_timezone(&Utc) > Duration::hours(CHECK_INTERVAL_HOURS)
+}
+
+async fn latest_release(config: &Config) -> Option<String> {
+    let client = crate::mirror::client_builder(config)
+        .ok()?
+        .timeout(std::time::Duration::from_secs(3))
+        .build()
+        .ok()?;
+    let release: serde_json::Value = client.get(RELEASES_URL).send().await.ok()?.error_for_status().ok()?.json().await.ok()?;
+    release["tag_name"].as_str().map(|tag| tag.trim_start_matches('v').to_string())
+}
+
+fn is_newer(latest: &str, current: &str) -> bool {
+    let parse = |version: &str| -> Vec<u64> { version.split('.').map(|part| part.parse().unwrap_or(0)).collect() };
+    parse(latest) > parse(current)
+}
@@ -0,0 +1,125 @@
+use crate::config::Config;
+use crate::Package;
+use clap::ValueEnum;
+use rusqlite::{params, Connection};
+use std::collections::{HashMap, HashSet};
+
+// Packages TeX Live retired by folding their files into another package. `renames` in the config
+// file adds to these or overrides them.
+const KNOWN_RENAMES: &[(&str, &str)] = &[
+    ("pdftex-def", "graphics-def"),
+    ("xetex-def", "graphics-def"),
+    ("dvipdfmx-def", "graphics-def"),
+    ("ifxetex", "iftex"),
+    ("ifluatex", "iftex"),
+];
+
+// What `update` does with installed packages the TLPDB no longer lists.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum RemovedAction {
+    Migrate,
+    Keep,
+}
+
+pub struct Orphan {
+    pub name: String,
+    pub revision: String,
+    pub successor: Option<String>,
+    pub kept: bool,
+    // Why it is gone when there is no successor.
+    pub reason: String,
+}
+
+fn successor(name: &str, tlpdb: &HashMap<String, Package>, config: &Config) -> Option<String> {
+    let renamed = config
+        .renames
+        .get(name)
+        .map(String::as_str)
+        .or_else(|| KNOWN_RENAMES.iter().find(|(old, _)| *old == name).map(|(_, new)| *new))?;
+    tlpdb.contains_key(renamed).then(|| renamed.to_string())
+}
+
+// Installed packages of `profile` that the TLPDB no longer lists, because they were renamed or
+// retired. Packages installed from a bundle were never in it, so they don't count.
+pub fn find(conn: &Connection, profile: &str, tlpdb: &HashMap<String, Package>, config: &Config) -> anyhow::Result<Vec<Orphan>> {
+    // Offline without a cached TLPDB, every package would look removed.
+    if tlpdb.is_empty() {
+        return Ok(Vec::new());
+    }
+    let origins = crate::installed_origins(conn, profile)?;
+    let mut stmt = conn.prepare("SELECT name FROM kept_orphans WHERE profile = ?1")?;
+    let kept = stmt.query_map(params![profile], |row| row.get::<_, String>(0))?.collect::<Result<HashSet<_>, _>>()?;
+    let mut stmt = conn.prepare("SELECT name, revision FROM installed_packages WHERE profile = ?1 ORDER BY name")?;
+    let installed = stmt
+        .query_map(params![profile], |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)))?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(installed
+        .into_iter()
+        .filter(|(name, _)| !tlpdb.contains_key(name) && origins.get(name).map(String::as_str) != Some(crate::repo::BUNDLE))
+        .map(|(name, revision)| {
+            let origin = origins.get(&name).map(String::as_str).unwrap_or(crate::repo::MAIN);
+            let reason = if origin == crate::repo::MAIN {
+                "retired".to_string()
+            } else if config.repositories.contains_key(origin) {
+                format!("no longer in repository '{}'", origin)
+            } else {
+                format!("repository '{}' is no longer configured", origin)
+            };
+            Orphan { successor: successor(&name, tlpdb, config), kept: kept.contains(&name), reason, name, revision }
+        })
+        .collect())
+}
+
+pub fn forget(conn: &Connection, profile: &str, name: &str) -> anyhow::Result<()> {
+    conn.execute("DELETE FROM kept_orphans WHERE profile = ?1 AND name = ?2", params![profile, name])?;
+    Ok(())
+}
+
+// Kept orphans are only listed with `include_kept`, e.g. for `update --check`.
+pub fn print(orphans: &[Orphan], include_kept: bool) {
+    let shown: Vec<&Orphan> = orphans.iter().filter(|orphan| include_kept || !orphan.kept).collect();
+    if shown.is_empty() {
+        return;
+    }
+    println!("{} installed packages are no longer in the repository:", shown.len());
+    for orphan in shown {
+        match (&orphan.successor, orphan.kept) {
+            (_, true) => println!("  {} r{} (kept)", orphan.name, orphan.revision),
+            (Some(successor), false) => println!("  {} r{} (replaced by {})", orphan.name, orphan.revision, successor),
+            (None, false) => println!("  {} r{} ({})", orphan.name, orphan.revision, orphan.reason),
+        }
+    }
+    println!();
+}
+
+// Settles each orphan that wasn't kept before: with `action` when given, else by asking. Kept
+// orphans are recorded, so they are left alone from then on until removed. Returns those to
+// replace with their successors.
+pub fn settle<'a>(conn: &Connection, profile: &str, orphans: &'a [Orphan], action: Option<RemovedAction>) -> anyhow::Result<Vec<&'a Orphan>> {
+    let mut migrate = Vec::new();
+    for orphan in orphans.iter().filter(|orphan| !orphan.kept) {
+        let replace = match (&orphan.successor, action) {
+            (Some(_), Some(action)) => action == RemovedAction::Migrate,
+            (Some(successor), None) => crate::hooks::prompter().confirm(
+                &format!("{} was removed upstream in favor of {}. Replace it with {}?", orphan.name, successor, successor),
+                false,
+            )?,
+            (None, _) => false,
+        };
+        if replace {
+            migrate.push(orphan);
+            continue;
+        }
+        let keep = match action {
+            Some(action) => action == RemovedAction::Keep,
+            None => crate::hooks::prompter().confirm(&format!("{} r{} was removed upstream. Keep it installed and stop asking?", orphan.name, orphan.revision), false)?,
+        };
+        if keep {
+            conn.execute("INSERT OR IGNORE INTO kept_orphans (profile, name) VALUES (?1, ?2)", params![profile, orphan.name])?;
+            println!("Keeping {} r{}; it gets no further updates", orphan.name, orphan.revision);
+        } else {
+            println!("{} r{} was removed upstream; it stays installed without updates ('texman remove {}' removes it)", orphan.name, orphan.revision, orphan.name);
+        }
+    }
+    Ok(migrate)
+}
@@ -0,0 +1,154 @@
+use std::io;
+use std::path::Path;
+
+// What texman needs from the operating system beyond std, for Unix and Windows. Elsewhere the
+// code only deals with paths and files.
+
+#[cfg(unix)]
+pub fn symlink(target: &Path, link: &Path) -> io::Result<()> {
+    std::os::unix::fs::symlink(target, link)
+}
+
+// Windows has separate links for files and directories; a relative target is taken from the
+// link's directory, as on Unix.
+#[cfg(windows)]
+pub fn symlink(target: &Path, link: &Path) -> io::Result<()> {
+    let resolved = link.parent().unwrap_or(Path::new(".")).join(target);
+    if resolved.is_dir() {
+        std::os::windows::fs::symlink_dir(target, link)
+    } else {
+        std::os::windows::fs::symlink_file(target, link)
+    }
+}
+
+// Permission bits, which Windows doesn't have; there every file reads as 0o644 (0o444 when
+// read-only) and setting a mode does nothing.
+#[cfg(unix)]
+pub fn mode(metadata: &std::fs::Metadata) -> u32 {
+    use std::os::unix::fs::PermissionsExt;
+    metadata.permissions().mode() & 0o7777
+}
+
+#[cfg(windows)]
+pub fn mode(metadata: &std::fs::Metadata) -> u32 {
+    if metadata.permissions().readonly() { 0o444 } else { 0o644 }
+}
+
+#[cfg(unix)]
+pub fn set_mode(path: &Path, mode: u32) -> io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode))
+}
+
+#[cfg(windows)]
+pub fn set_mode(_path: &Path, _mode: u32) -> io::Result<()> {
+    Ok(())
+}
+
+pub fn create_dir_all_with_mode(path: &Path, mode: u32) -> io::Result<()> {
+    let mut builder = std::fs::DirBuilder::new();
+    builder.recursive(true);
+    #[cfg(unix)]
+    std::os::unix::fs::DirBuilderExt::mode(&mut builder, mode);
+    #[cfg(windows)]
+    let _ = mode;
+    builder.create(path)
+}
+
+// Identifies the file behind a hardlink, and how many links it has. Windows has both, but std
+// only exposes them on Unix, so there texman can't tell hardlinked files apart.
+#[cfg(unix)]
+pub fn file_id(metadata: &std::fs::Metadata) -> Option<(u64, u64)> {
+    use std::os::unix::fs::MetadataExt;
+    Some((metadata.dev(), metadata.ino()))
+}
+
+#[cfg(windows)]
+pub fn file_id(_metadata: &std::fs::Metadata) -> Option<(u64, u64)> {
+    None
+}
+
+#[cfg(unix)]
+pub fn link_count(metadata: &std::fs::Metadata) -> Option<u64> {
+    use std::os::unix::fs::MetadataExt;
+    Some(metadata.nlink())
+}
+
+#[cfg(windows)]
+pub fn link_count(_metadata: &std::fs::Metadata) -> Option<u64> {
+    None
+}
+
+// The filesystem `path` is on, as an id that is equal for paths on the same filesystem, and the
+// bytes available on it. None where texman can't tell.
+#[cfg(unix)]
+pub fn free_space(path: &Path) -> anyhow::Result<Option<(u64, u64)>> {
+    use std::os::unix::ffi::OsStrExt;
+    use std::os::unix::fs::MetadataExt;
+    let c_path = std::ffi::CString::new(path.as_os_str().as_bytes())?;
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    // SAFETY: c_path is a valid NUL-terminated string and stat is a writable statvfs.
+    if unsafe { libc::statvfs(c_path.as_ptr(), &mut stat) } != 0 {
+        anyhow::bail!("Cannot check free space on {:?}: {}", path, io::Error::last_os_error());
+    }
+    Ok(Some((std::fs::metadata(path)?.dev(), stat.f_bavail as u64 * stat.f_frsize as u64)))
+}
+
+#[cfg(windows)]
+pub fn free_space(_path: &Path) -> anyhow::Result<Option<(u64, u64)>> {
+    Ok(None)
+}
+
+// Rows and columns of the terminal on stdout, if it is one.
+#[cfg(unix)]
+pub fn terminal_size() -> Option<(usize, usize)> {
+    let mut size: libc::winsize = unsafe { std::mem::zeroed() };
+    // SAFETY: TIOCGWINSZ only writes the winsize struct passed by pointer.
+    let ok = unsafe { libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut size) } == 0 && size.ws_col > 0;
+    ok.then_some((size.ws_row as usize, size.ws_col as usize))
+}
+
+#[cfg(windows)]
+pub fn terminal_size() -> Option<(usize, usize)> {
+    None
+}
+
+// Whether starting a program failed because it is a binary for another platform.
+pub fn is_foreign_binary(error: &io::Error) -> bool {
+    #[cfg(unix)]
+    return error.raw_os_error() == Some(libc::ENOEXEC);
+    // ERROR_BAD_EXE_FORMAT
+    #[cfg(windows)]
+    return error.raw_os_error() == Some(193);
+}
+
+// Runs `command` in place of texman, so its exit status and signals are the caller's. Only
+// returns if it could not be started. Windows can't replace a process, so there texman waits
+// for it and exits with its code.
+#[cfg(unix)]
+pub fn exec(command: &mut std::process::Command) -> io::Error {
+    std::os::unix::process::CommandExt::exec(command)
+}
+
+#[cfg(windows)]
+pub fn exec(command: &mut std::process::Command) -> io::Error {
+    match command.status() {
+        Ok(status) => std::process::exit(status.code().unwrap_or(1)),
+        Err(e) => e,
+    }
+}
+
+// Opens `url` in the default browser: with `start` on Windows, a cmd builtin whose first quoted
+// argument is the window title, `open` on macOS, and `xdg-open` elsewhere.
+pub fn open_url(url: &str) -> io::Result<std::process::ExitStatus> {
+    let mut command = if cfg!(windows) {
+        let mut command = std::process::Command::new("cmd");
+        command.args(["/C", "start", ""]);
+        command
+    } else if cfg!(target_os = "macos") {
+        std::process::Command::new("open")
+    } else {
+        std::process::Command::new("xdg-open")
+    };
+    command.arg(url).status()
+}
@@ -0,0 +1,337 @@
+use clap::{Args, ValueEnum};
+use serde::{Serialize, Serializer};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    Text,
+    Table,
+    Csv,
+    Json,
+}
+
+// Every JSON document and event texman prints starts with this version; it goes up whenever a
+// field is removed, renamed, or changes meaning, so tools can refuse output they don't know.
+// Adding a field doesn't change it. `texman schema` prints the schemas.
+pub const SCHEMA_VERSION: u32 = 1;
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SchemaVersion;
+
+impl Serialize for SchemaVersion {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u32(SCHEMA_VERSION)
+    }
+}
+
+pub struct PackageRow {
+    pub name: String,
+    pub revision: String,
+    pub size: Option<u64>,
+    pub shortdesc: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Field {
+    Name,
+    Revision,
+    Size,
+    Shortdesc,
+}
+
+const ALL_FIELDS: [Field; 4] = [Field::Name, Field::Revision, Field::Size, Field::Shortdesc];
+
+impl Field {
+    fn header(&self) -> &'static str {
+        match self {
+            Field::Name => "NAME",
+            Field::Revision => "REVISION",
+            Field::Size => "SIZE",
+            Field::Shortdesc => "DESCRIPTION",
+        }
+    }
+
+    fn csv_header(&self) -> &'static str {
+        match self {
+            Field::Name => "name",
+            Field::Revision => "revision",
+            Field::Size => "size",
+            Field::Shortdesc => "shortdesc",
+        }
+    }
+
+    fn json_value(&self, row: &PackageRow) -> serde_json::Value {
+        match self {
+            Field::Size => row.size.into(),
+            Field::Shortdesc => row.shortdesc.clone().into(),
+            _ => self.value(row).into(),
+        }
+    }
+
+    // Sizes are plain byte counts, so scripts can compute with them.
+    fn value(&self, row: &PackageRow) -> String {
+        match self {
+            Field::Name => row.name.clone(),
+            Field::Revision => row.revision.clone(),
+            Field::Size => row.size.map(|s| s.to_string()).unwrap_or_default(),
+            Field::Shortdesc => row.shortdesc.clone().unwrap_or_default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Args)]
+pub struct RowOptions {
+    #[arg(long, value_enum, value_delimiter = ',')]
+    pub fields: Vec<Field>,
+    #[arg(long)]
+    pub format: Option<String>,
+}
+
+impl RowOptions {
+    pub fn is_set(&self) -> bool {
+        !self.fields.is_empty() || self.format.is_some()
+    }
+}
+
+enum Segment {
+    Text(String),
+    Field(Field),
+}
+
+// `{field}` is replaced by the field's value, `{{` and `}}` stand for literal braces, and `\t`
+// and `\n` for a tab and a newline.
+fn parse_template(template: &str) -> anyhow::Result<Vec<Segment>> {
+    let mut segments = Vec::new();
+    let mut text = String::new();
+    let mut chars = template.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '{' if chars.peek() == Some(&'{') => {
+                chars.next();
+                text.push('{');
+            }
+            '}' if chars.peek() == Some(&'}') => {
+                chars.next();
+                text.push('}');
+            }
+            '{' => {
+                let name: String = chars.by_ref().take_while(|c| *c != '}').collect();
+                let field = <Field as ValueEnum>::from_str(&name, true).map_err(|_| {
+                    anyhow::anyhow!("Unknown field '{{{}}}' in --format: expected name, revision, size or shortdesc", name)
+                })?;
+                segments.push(Segment::Text(std::mem::take(&mut text)));
+                segments.push(Segment::Field(field));
+            }
+            '\\' if chars.peek() == Some(&'t') => {
+                chars.next();
+                text.push('\t');
+            }
+            '\\' if chars.peek() == Some(&'n') => {
+                chars.next();
+                text.push('\n');
+            }
+            _ => text.push(c),
+        }
+    }
+    segments.push(Segment::Text(text));
+    Ok(segments)
+}
+
+// Prints rows as a table, CSV, JSON, a `--format` template, or the `--fields` of a text listing.
+// Returns false when the caller should print its own text output instead.
+pub fn print_rows(rows: &[PackageRow], output: OutputFormat, options: &RowOptions) -> anyhow::Result<bool> {
+    if let Some(template) = &options.format {
+        let segments = parse_template(template)?;
+        for row in rows {
+            let line: String = segments
+                .iter()
+                .map(|segment| match segment {
+                    Segment::Text(text) => text.clone(),
+                    Segment::Field(field) => field.value(row),
+                })
+                .collect();
+            println!("{}", line);
+        }
+        return Ok(true);
+    }
+    let fields = if options.fields.is_empty() { ALL_FIELDS.as_slice() } else { options.fields.as_slice() };
+    match output {
+        OutputFormat::Table => print_table_fields(rows, fields),
+        OutputFormat::Csv => print_csv_fields(rows, fields),
+        OutputFormat::Json => print_json_fields(rows, fields)?,
+        OutputFormat::Text if !options.fields.is_empty() => {
+            for row in rows {
+                println!("{}", fields.iter().map(|field| field.value(row)).collect::<Vec<_>>().join("\t"));
+            }
+        }
+        OutputFormat::Text => return Ok(false),
+    }
+    Ok(true)
+}
+
+pub fn print_table(rows: &[PackageRow]) {
+    print_table_fields(rows, &ALL_FIELDS);
+}
+
+fn print_table_fields(rows: &[PackageRow], fields: &[Field]) {
+    let cells: Vec<Vec<String>> = rows
+        .iter()
+        .map(|row| {
+            fields
+                .iter()
+                .map(|field| match field {
+                    Field::Size => row.size.map(format_size).unwrap_or_default(),
+                    _ => field.value(row),
+                })
+                .collect()
+        })
+        .collect();
+    let widths: Vec<usize> = fields
+        .iter()
+        .enumerate()
+        .map(|(i, field)| cells.iter().map(|row| row[i].len()).chain([field.header().len()]).max().unwrap_or(0))
+        .collect();
+    let headers: Vec<String> = fields.iter().map(|field| field.header().to_string()).collect();
+
+    for line in std::iter::once(&headers).chain(cells.iter()) {
+        let columns: Vec<String> = line
+            .iter()
+            .zip(fields)
+            .zip(&widths)
+            .enumerate()
+            .map(|(i, ((cell, field), width))| match field {
+                Field::Size => format!("{:>width$}", cell),
+                // The last column isn't padded, so lines carry no trailing spaces.
+                _ if i + 1 == fields.len() => cell.clone(),
+                _ => format!("{:<width$}", cell),
+            })
+            .collect();
+        println!("{}", columns.join("  "));
+    }
+}
+
+pub fn print_csv(rows: &[PackageRow]) {
+    print_csv_fields(rows, &ALL_FIELDS);
+}
+
+fn print_csv_fields(rows: &[PackageRow], fields: &[Field]) {
+    println!("{}", fields.iter().map(|field| field.csv_header()).collect::<Vec<_>>().join(","));
+    for row in rows {
+        println!("{}", fields.iter().map(|field| csv_field(&field.value(row))).collect::<Vec<_>>().join(","));
+    }
+}
+
+pub fn print_json(rows: &[PackageRow]) -> anyhow::Result<()> {
+    print_json_fields(rows, &ALL_FIELDS)
+}
+
+#[derive(Serialize)]
+struct PackageList {
+    schema_version: SchemaVersion,
+    packages: Vec<serde_json::Map<String, serde_json::Value>>,
+}
+
+fn print_json_fields(rows: &[PackageRow], fields: &[Field]) -> anyhow::Result<()> {
+    let packages = rows
+        .iter()
+        .map(|row| fields.iter().map(|field| (field.csv_header().to_string(), field.json_value(row))).collect())
+        .collect();
+    println!("{}", serde_json::to_string(&PackageList { schema_version: SchemaVersion, packages })?);
+    Ok(())
+}
+
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["B", "KiB", "MiB", "GiB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 { format!("{} B", bytes) } else { format!("{:.1} {}", size, UNITS[unit]) }
+}
+
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+// Rows and columns of the terminal on stdout; COLUMNS overrides the width, as in most tools.
+fn terminal_size() -> Option<(usize, usize)> {
+    let size = crate::os::terminal_size();
+    let columns = std::env::var("COLUMNS").ok().and_then(|columns| columns.parse().ok());
+    match (size, columns) {
+        (_, Some(columns)) => Some((size.map_or(0, |(rows, _)| rows), columns)),
+        (size, None) => size,
+    }
+}
+
+// Renders `label: text` with the TLPDB's hard line breaks reflowed: lines join into paragraphs,
+// and empty lines separate them. On a terminal the paragraphs are wrapped to its width, with
+// continuation lines indented under the text; otherwise each paragraph stays on one line.
+pub fn wrap_field(indent: &str, label: &str, text: &str) -> String {
+    let paragraphs: Vec<String> = text
+        .split("\n\n")
+        .map(|paragraph| paragraph.split_whitespace().collect::<Vec<_>>().join(" "))
+        .filter(|paragraph| !paragraph.is_empty())
+        .collect();
+    let first = format!("{}{}: ", indent, label);
+    let Some((_, columns)) = terminal_size() else {
+        return format!("{}{}\n", first, paragraphs.join(&format!("\n{}  ", indent)));
+    };
+    let hanging = " ".repeat(indent.len() + 2);
+    let width = columns.max(hanging.len() + 20);
+    let mut out = String::new();
+    let mut line = first;
+    for (i, paragraph) in paragraphs.iter().enumerate() {
+        if i > 0 {
+            out.push_str(line.trim_end());
+            out.push('\n');
+            line = hanging.clone();
+        }
+        let mut empty = true;
+        for word in paragraph.split(' ') {
+            if !empty && line.len() + 1 + word.len() > width {
+                out.push_str(&line);
+                out.push('\n');
+                line = hanging.clone();
+                empty = true;
+            }
+            if !empty {
+                line.push(' ');
+            }
+            line.push_str(word);
+            empty = false;
+        }
+    }
+    out.push_str(line.trim_end());
+    out.push('\n');
+    out
+}
+
+// Prints `text`, through $PAGER (default `less -FRX`) when it won't fit on the terminal. Pipes
+// and --no-pager get it directly.
+pub fn page(text: &str, no_pager: bool) -> anyhow::Result<()> {
+    use std::io::{IsTerminal, Write};
+    use std::process::{Command, Stdio};
+    let fits = terminal_size().is_none_or(|(rows, _)| rows == 0 || text.lines().count() < rows);
+    if no_pager || fits || !std::io::stdout().is_terminal() {
+        print!("{}", text);
+        return Ok(());
+    }
+    let pager = std::env::var("PAGER").ok().filter(|pager| !pager.trim().is_empty()).unwrap_or_else(|| "less -FRX".to_string());
+    let child = Command::new("sh").arg("-c").arg(&pager).stdin(Stdio::piped()).spawn();
+    let Ok(mut child) = child else {
+        log::warn!("Cannot run pager '{}'; printing directly", pager);
+        print!("{}", text);
+        return Ok(());
+    };
+    if let Some(mut stdin) = child.stdin.take() {
+        // The user may quit the pager before reading everything.
+        let _ = stdin.write_all(text.as_bytes());
+    }
+    child.wait()?;
+    Ok(())
+}
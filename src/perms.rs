@@ -0,0 +1,98 @@
+use crate::config::PermissionPolicy;
+use crate::os;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// The process umask, read from /proc so it needn't be changed to be read; other threads may be
+// creating files meanwhile.
+fn umask() -> u32 {
+    fs::read_to_string("/proc/self/status")
+        .ok()
+        .and_then(|status| {
+            status
+                .lines()
+                .find_map(|line| line.strip_prefix("Umask:"))
+                .and_then(|mask| u32::from_str_radix(mask.trim(), 8).ok())
+        })
+        .unwrap_or(0o022)
+}
+
+fn target_mode(policy: PermissionPolicy, is_dir: bool, executable: bool, umask: u32) -> Option<u32> {
+    let full = if is_dir || executable { 0o777 } else { 0o666 };
+    match policy {
+        PermissionPolicy::Normalize => Some(full & !0o022),
+        PermissionPolicy::Umask => Some(full & !umask),
+        PermissionPolicy::Preserve => None,
+    }
+}
+
+// Sets every directory and file below `root` to 0755/0644, or what the umask allows, keeping
+// files executable when the archive marked them so. Symlinks are left alone. Returns the number
+// of entries changed.
+pub fn normalize(root: &Path, policy: PermissionPolicy) -> anyhow::Result<usize> {
+    // Windows has no permission bits to normalize.
+    if policy == PermissionPolicy::Preserve || cfg!(windows) {
+        return Ok(0);
+    }
+    let umask = umask();
+    let mut changed = 0;
+    let mut pending = vec![root.to_path_buf()];
+    while let Some(path) = pending.pop() {
+        let metadata = fs::symlink_metadata(&path)?;
+        if metadata.file_type().is_symlink() {
+            continue;
+        }
+        let mode = os::mode(&metadata);
+        let is_dir = metadata.is_dir();
+        if let Some(target) = target_mode(policy, is_dir, mode & 0o111 != 0, umask)
+            && target != mode
+        {
+            os::set_mode(&path, target)?;
+            changed += 1;
+        }
+        if is_dir {
+            for entry in fs::read_dir(&path)? {
+                pending.push(entry?.path());
+            }
+        }
+    }
+    Ok(changed)
+}
+
+// Repairs the store and every profile, for trees unpacked before permissions were normalized.
+pub fn fix_perms(policy: PermissionPolicy) -> anyhow::Result<()> {
+    let texman_dir = dirs::home_dir()
+        .ok_or_else(|| anyhow::anyhow!("Cannot find home directory"))?
+        .join(".texman");
+    if policy == PermissionPolicy::Preserve {
+        println!("The permissions policy is 'preserve'; nothing to fix. Pass --policy normalize to override it.");
+        return Ok(());
+    }
+
+    let mut roots: Vec<PathBuf> = Vec::new();
+    for dir in ["store", "profiles"] {
+        let Ok(entries) = fs::read_dir(texman_dir.join(dir)) else { continue };
+        for entry in entries {
+            let path = entry?.path();
+            // Unpacks in progress belong to another texman process; compressed revisions are archives.
+            let transient = path
+                .extension()
+                .is_some_and(|extension| extension == "unpacking" || extension == "expanding");
+            if path.is_dir() && !path.is_symlink() && !transient {
+                roots.push(path);
+            }
+        }
+    }
+    roots.sort();
+
+    let mut total = 0;
+    for root in &roots {
+        let changed = normalize(root, policy)?;
+        if changed > 0 {
+            log::info!("Fixed permissions of {} entries in {:?}", changed, root);
+        }
+        total += changed;
+    }
+    println!("Fixed permissions of {} files and directories in {} trees.", total, roots.len());
+    Ok(())
+}
@@ -0,0 +1,172 @@
+use crate::config::{Config, InstallStrategy};
+use crate::output::{format_size, SCHEMA_VERSION};
+use crate::space::{download_size, unpacked_size};
+use crate::Package;
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::io::Read;
+use std::path::Path;
+
+// What `install --plan` and `update --plan` print instead of doing anything. Packages come in
+// the order they would be installed, each after its dependencies. Another tool may drop packages
+// from it before handing it back with `--from-plan`, which installs exactly what is left.
+#[derive(Serialize, Deserialize)]
+pub struct Plan {
+    pub schema_version: u32,
+    pub operation: String,
+    pub profiles: Vec<String>,
+    pub requested: Vec<String>,
+    pub packages: Vec<PlannedPackage>,
+    pub download_size: u64,
+    pub installed_size: u64,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct PlannedPackage {
+    pub name: String,
+    pub revision: String,
+    pub repository: String,
+    // The profiles that already have the package, with their revision.
+    pub installed: BTreeMap<String, String>,
+    pub archives: Vec<PlannedArchive>,
+    pub download_size: u64,
+    pub installed_size: u64,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct PlannedArchive {
+    pub url: String,
+    pub checksum: Option<String>,
+}
+
+pub fn build(operation: &str, profiles: &[String], requested: &[String], packages: &[Package], conn: &Connection, config: &Config) -> anyhow::Result<Plan> {
+    let mut planned = Vec::new();
+    for pkg in packages {
+        let mut installed = BTreeMap::new();
+        for profile in profiles {
+            let revision: Option<String> = conn
+                .query_row("SELECT revision FROM installed_packages WHERE profile = ?1 AND name = ?2", params![profile, pkg.name], |row| row.get(0))
+                .optional()?;
+            if let Some(revision) = revision {
+                installed.insert(profile.clone(), revision);
+            }
+        }
+        let repo_config = crate::repo::config_for(config, pkg);
+        let repo_config = repo_config.as_ref().unwrap_or(config);
+        let archives = crate::package_archives(pkg, config)
+            .into_iter()
+            .map(|(archive, checksum)| PlannedArchive {
+                url: repo_config.repository_url(&format!("archive/{}", archive)),
+                checksum: checksum.map(str::to_string),
+            })
+            .collect();
+        planned.push(PlannedPackage {
+            name: pkg.name.clone(),
+            revision: pkg.revision.clone(),
+            repository: pkg.repository.clone().unwrap_or_else(|| crate::repo::MAIN.to_string()),
+            installed,
+            archives,
+            download_size: download_size(pkg, config),
+            installed_size: unpacked_size(pkg, config),
+        });
+    }
+    Ok(Plan {
+        schema_version: SCHEMA_VERSION,
+        operation: operation.to_string(),
+        profiles: profiles.to_vec(),
+        requested: requested.to_vec(),
+        download_size: planned.iter().map(|pkg| pkg.download_size).sum(),
+        installed_size: planned.iter().map(|pkg| pkg.installed_size).sum(),
+        packages: planned,
+    })
+}
+
+pub fn print(plan: &Plan) -> anyhow::Result<()> {
+    println!("{}", serde_json::to_string(plan)?);
+    Ok(())
+}
+
+// What `--dry-run` prints: the plan for a person to review, with only what isn't in the store
+// yet counted as download. The disk delta is that much unpacked in the store, plus a copy in each
+// profile using the copy strategy; revisions an update replaces stay in the store for `rollback`.
+pub fn print_dry_run(plan: &Plan, texman_dir: &Path, conn: &Connection, config: &Config) -> anyhow::Result<()> {
+    println!("Dry run of {} in profile{} '{}'; nothing is downloaded or changed.", plan.operation, if plan.profiles.len() == 1 { "" } else { "s" }, plan.profiles.join("', '"));
+    let mut download = 0;
+    let mut disk = 0;
+    let mut unchanged = 0;
+    let mut changes = 0;
+    for pkg in &plan.packages {
+        let changed: Vec<&String> = plan.profiles.iter().filter(|profile| pkg.installed.get(*profile) != Some(&pkg.revision)).collect();
+        if changed.is_empty() {
+            unchanged += 1;
+            continue;
+        }
+        changes += 1;
+        let mut previous: Vec<&str> = changed.iter().filter_map(|profile| pkg.installed.get(*profile)).map(|revision| revision.as_str()).collect();
+        previous.dedup();
+        let change = if previous.is_empty() {
+            format!("+{} r{}", pkg.name, pkg.revision)
+        } else {
+            format!("~{} r{} -> r{}", pkg.name, previous.join("/r"), pkg.revision)
+        };
+        let store_path = crate::store_path(texman_dir, &pkg.name, &pkg.revision);
+        let size = if store_path.exists() || crate::store::compressed_path(&store_path).exists() {
+            "in the store".to_string()
+        } else {
+            download += pkg.download_size;
+            disk += pkg.installed_size;
+            format!("{} download, {} unpacked", format_size(pkg.download_size), format_size(pkg.installed_size))
+        };
+        for profile in &changed {
+            if crate::install_strategy(conn, profile, config)? == InstallStrategy::Copy {
+                disk += pkg.installed_size;
+            }
+        }
+        println!("  {:<40} {}", change, size);
+    }
+    if changes == 0 {
+        println!("  no changes");
+    }
+    if unchanged > 0 {
+        println!("  ({} already installed at the planned revision)", unchanged);
+    }
+    println!("  Download: {}", format_size(download));
+    println!("  Disk:     +{}", format_size(disk));
+    Ok(())
+}
+
+// Reads a plan from `path`, or from stdin for "-".
+pub fn load(path: &Path, operation: &str) -> anyhow::Result<Plan> {
+    let mut text = String::new();
+    if path == Path::new("-") {
+        std::io::stdin().read_to_string(&mut text)?;
+    } else {
+        text = std::fs::read_to_string(path).map_err(|e| anyhow::anyhow!("Cannot read plan {:?}: {}", path, e))?;
+    }
+    let plan: Plan = serde_json::from_str(&text).map_err(|e| anyhow::anyhow!("Invalid plan {:?}: {}", path, e))?;
+    if plan.schema_version != SCHEMA_VERSION {
+        anyhow::bail!("Plan {:?} has schema version {}, but this texman writes version {}", path, plan.schema_version, SCHEMA_VERSION);
+    }
+    if plan.operation != operation {
+        anyhow::bail!("Plan {:?} is for '{}', not '{}'", path, plan.operation, operation);
+    }
+    Ok(plan)
+}
+
+// The planned packages, as long as the TLPDB still has the planned revisions; a plan made
+// against an older TLPDB would otherwise install something nobody approved.
+pub fn packages(plan: &Plan, tlpdb: &HashMap<String, Package>) -> anyhow::Result<Vec<String>> {
+    let mut stale = Vec::new();
+    for planned in &plan.packages {
+        match tlpdb.get(&planned.name) {
+            Some(pkg) if pkg.revision == planned.revision => {}
+            Some(pkg) => stale.push(format!("{} r{} is now r{}", planned.name, planned.revision, pkg.revision)),
+            None => stale.push(format!("{} is no longer in the TLPDB", planned.name)),
+        }
+    }
+    if !stale.is_empty() {
+        anyhow::bail!("The plan is out of date ({}); make a new one with --plan", stale.join(", "));
+    }
+    Ok(plan.packages.iter().map(|pkg| pkg.name.clone()).collect())
+}
@@ -0,0 +1,71 @@
+use std::sync::Mutex;
+
+// TeX Live's platform names: the suffixes of the `<package>.<platform>` packages holding each
+// platform's binaries. `universal-darwin` covers both Intel and Apple silicon Macs.
+pub const PLATFORMS: &[&str] = &[
+    "aarch64-linux",
+    "amd64-freebsd",
+    "amd64-netbsd",
+    "armhf-linux",
+    "i386-freebsd",
+    "i386-linux",
+    "i386-netbsd",
+    "i386-solaris",
+    "universal-darwin",
+    "windows",
+    "x86_64-cygwin",
+    "x86_64-darwinlegacy",
+    "x86_64-linux",
+    "x86_64-linuxmusl",
+    "x86_64-solaris",
+];
+
+// The platform of the profile a command works on, when it isn't the host's.
+static SELECTED: Mutex<Option<&'static str>> = Mutex::new(None);
+
+// The platform texman runs on, if TeX Live builds binaries for it.
+pub fn host() -> Option<&'static str> {
+    let musl = cfg!(target_env = "musl");
+    match (std::env::consts::ARCH, std::env::consts::OS) {
+        ("x86_64", "linux") if musl => Some("x86_64-linuxmusl"),
+        ("x86_64", "linux") => Some("x86_64-linux"),
+        ("aarch64", "linux") => Some("aarch64-linux"),
+        ("x86", "linux") => Some("i386-linux"),
+        ("arm", "linux") => Some("armhf-linux"),
+        ("x86_64" | "aarch64", "macos") => Some("universal-darwin"),
+        ("x86_64", "freebsd") => Some("amd64-freebsd"),
+        ("x86", "freebsd") => Some("i386-freebsd"),
+        ("x86_64", "netbsd") => Some("amd64-netbsd"),
+        ("x86", "netbsd") => Some("i386-netbsd"),
+        ("x86_64", "solaris" | "illumos") => Some("x86_64-solaris"),
+        ("x86", "solaris") => Some("i386-solaris"),
+        ("x86_64" | "aarch64", "windows") => Some("windows"),
+        _ => None,
+    }
+}
+
+pub fn parse(name: &str) -> anyhow::Result<&'static str> {
+    // Releases before 2023 called the Windows binaries win32 and win64.
+    let name = if matches!(name, "win32" | "win64") { "windows" } else { name };
+    PLATFORMS
+        .iter()
+        .find(|platform| **platform == name)
+        .copied()
+        .ok_or_else(|| anyhow::anyhow!("Unknown platform '{}'; TeX Live has binaries for {}", name, PLATFORMS.join(", ")))
+}
+
+// Makes `.ARCH` dependencies resolve to `platform`'s binaries; None goes back to the host's.
+pub fn select(platform: Option<&str>) -> anyhow::Result<()> {
+    *SELECTED.lock().unwrap() = platform.map(parse).transpose()?;
+    Ok(())
+}
+
+pub fn current() -> Option<&'static str> {
+    SELECTED.lock().unwrap().or_else(host)
+}
+
+// Binaries of another platform can be installed, e.g. into a profile shared over the network,
+// but not run here.
+pub fn is_foreign() -> bool {
+    current() != host()
+}
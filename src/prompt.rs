@@ -0,0 +1,37 @@
+use std::io::{self, Write};
+
+fn read_answer(question: &str, hint: &str) -> anyhow::Result<Option<String>> {
+    print!("{} [{}]: ", question, hint);
+    io::stdout().flush()?;
+    let mut answer = String::new();
+    io::stdin().read_line(&mut answer)?;
+    let answer = answer.trim();
+    Ok(if answer.is_empty() { None } else { Some(answer.to_string()) })
+}
+
+pub fn ask(question: &str, default: &str) -> anyhow::Result<String> {
+    Ok(read_answer(question, default)?.unwrap_or_else(|| default.to_string()))
+}
+
+pub fn ask_yes_no(question: &str, default: bool) -> anyhow::Result<bool> {
+    loop {
+        let Some(answer) = read_answer(question, if default { "Y/n" } else { "y/N" })? else {
+            return Ok(default);
+        };
+        match answer.to_lowercase().as_str() {
+            "y" | "yes" => return Ok(true),
+            "n" | "no" => return Ok(false),
+            _ => println!("Please answer 'y' or 'n'."),
+        }
+    }
+}
+
+pub fn choose_index(count: usize, default: usize) -> anyhow::Result<usize> {
+    loop {
+        let answer = ask("Choice", &(default + 1).to_string())?;
+        match answer.parse::<usize>() {
+            Ok(n) if (1..=count).contains(&n) => return Ok(n - 1),
+            _ => println!("Please enter a number between 1 and {}.", count),
+        }
+    }
+}
@@ -0,0 +1,111 @@
+use crate::Package;
+use rusqlite::params;
+use std::collections::{HashMap, HashSet};
+
+// The infrastructure every TeX setup relies on: TeX Live's own scripts, the kpathsea library,
+// the engines, and the files formats are built from. Platform variants (`name.ARCH`) count too.
+const PROTECTED: &[&str] = &[
+    "texlive.infra",
+    "texlive-scripts",
+    "kpathsea",
+    "hyphen-base",
+    "tex-ini-files",
+    "tex",
+    "etex",
+    "pdftex",
+    "luatex",
+    "luahbtex",
+    "xetex",
+    "latex-bin",
+];
+
+pub fn is_protected(name: &str) -> bool {
+    PROTECTED
+        .iter()
+        .any(|base| name == *base || name.strip_prefix(base).is_some_and(|rest| rest.starts_with('.')))
+}
+
+// Protected packages that something installed depends on, directly or not, but that are missing.
+fn missing(installed: &HashSet<String>, tlpdb: &HashMap<String, Package>) -> Vec<String> {
+    let mut needed = HashSet::new();
+    let mut pending: Vec<&str> = installed.iter().map(String::as_str).collect();
+    let mut seen = HashSet::new();
+    while let Some(name) = pending.pop() {
+        if !seen.insert(name) {
+            continue;
+        }
+        let Some(pkg) = tlpdb.get(name) else { continue };
+        for dep in crate::dependencies(pkg, tlpdb) {
+            if is_protected(dep) {
+                needed.insert(dep.to_string());
+            }
+            pending.push(dep);
+        }
+    }
+    let mut missing: Vec<String> = needed.into_iter().filter(|name| !installed.contains(name)).collect();
+    missing.sort();
+    missing
+}
+
+// Checks every profile: protected packages that installed packages need must be installed, and
+// every file recorded for an installed protected package must still resolve in the profile.
+pub fn doctor(tlpdb: &HashMap<String, Package>) -> anyhow::Result<()> {
+    let texman_dir = dirs::home_dir()
+        .ok_or_else(|| anyhow::anyhow!("Cannot find home directory"))?
+        .join(".texman");
+    let conn = crate::init_db(&texman_dir)?;
+    let mut problems = 0;
+    if let Some(interrupted) = crate::journal::interrupted(&texman_dir)? {
+        println!("{}", interrupted);
+        problems += 1;
+    }
+    for profile in crate::profile_names(&texman_dir)? {
+        let profile_dir = texman_dir.join("profiles").join(&profile);
+        let mut stmt = conn.prepare("SELECT name, revision FROM installed_packages WHERE profile = ?1 ORDER BY name")?;
+        let installed: Vec<(String, String)> = stmt
+            .query_map(params![profile], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<Result<_, _>>()?;
+        let names: HashSet<String> = installed.iter().map(|(name, _)| name.clone()).collect();
+
+        let mut issues = Vec::new();
+        for name in missing(&names, tlpdb) {
+            issues.push(format!("{} is required but not installed; run 'texman install {} --profile {}'", name, name, profile));
+        }
+        for (name, revision) in installed.iter().filter(|(name, _)| is_protected(name)) {
+            let mut stmt = conn.prepare("SELECT path FROM profile_files WHERE profile = ?1 AND package = ?2")?;
+            let files = stmt
+                .query_map(params![profile, name], |row| row.get::<_, String>(0))?
+                .collect::<Result<Vec<_>, _>>()?;
+            // `exists` follows symlinks, so links into a pruned or compressed store count as broken.
+            let broken = files.iter().filter(|path| !profile_dir.join(path).exists()).count();
+            if files.is_empty() {
+                issues.push(format!("{} r{} has no files recorded", name, revision));
+            } else if broken > 0 {
+                issues.push(format!(
+                    "{} r{} is damaged: {} of {} files are missing or dangling; reinstall it with 'texman install {} --profile {}'",
+                    name,
+                    revision,
+                    broken,
+                    files.len(),
+                    name,
+                    profile
+                ));
+            }
+        }
+
+        let protected = installed.iter().filter(|(name, _)| is_protected(name)).count();
+        if issues.is_empty() {
+            println!("Profile '{}': ok ({} infrastructure packages checked)", profile, protected);
+        } else {
+            println!("Profile '{}':", profile);
+            for issue in &issues {
+                println!("  {}", issue);
+            }
+            problems += issues.len();
+        }
+    }
+    if problems > 0 {
+        anyhow::bail!("{} problems found", problems);
+    }
+    Ok(())
+}
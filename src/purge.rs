@@ -0,0 +1,153 @@
+use crate::lsr::TREES;
+use crate::{execute, Package};
+use rusqlite::{params, Connection};
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+
+fn remove_path(path: &Path, removed: &mut Vec<String>) -> anyhow::Result<()> {
+    if path.is_symlink() || path.is_file() {
+        fs::remove_file(path)?;
+    } else if path.is_dir() {
+        fs::remove_dir_all(path)?;
+    } else {
+        return Ok(());
+    }
+    removed.push(path.display().to_string());
+    Ok(())
+}
+
+// Drops the given files from an ls-R database, which lists each directory as `./dir:`
+// followed by the names in it.
+fn prune_ls_r(tree: &Path, files: &HashSet<String>) -> anyhow::Result<usize> {
+    let ls_r = tree.join("ls-R");
+    if !ls_r.is_file() || ls_r.is_symlink() {
+        return Ok(0);
+    }
+    let text = fs::read_to_string(&ls_r)?;
+    let mut dir = String::new();
+    let mut pruned = 0;
+    let mut kept = Vec::new();
+    for line in text.lines() {
+        if let Some(header) = line.strip_suffix(':')
+            && (header.starts_with("./") || header.starts_with('/'))
+        {
+            dir = header.trim_start_matches("./").trim_end_matches('/').to_string();
+        } else if !line.is_empty() && !line.starts_with('%') {
+            let path = if dir.is_empty() || dir == "." { line.to_string() } else { format!("{}/{}", dir, line) };
+            if files.contains(&path) {
+                pruned += 1;
+                continue;
+            }
+        }
+        kept.push(line);
+    }
+    if pruned > 0 {
+        fs::write(&ls_r, format!("{}\n", kept.join("\n")))?;
+    }
+    Ok(pruned)
+}
+
+fn prune_updmap_cfg(path: &Path, maps: &[String]) -> anyhow::Result<usize> {
+    if !path.is_file() || path.is_symlink() {
+        return Ok(0);
+    }
+    let text = fs::read_to_string(path)?;
+    let mut pruned = 0;
+    let kept: Vec<&str> = text
+        .lines()
+        .filter(|line| {
+            let mut fields = line.split_whitespace();
+            let is_map = matches!(fields.next(), Some("Map" | "MixedMap" | "KanjiMap"));
+            let listed = is_map && fields.next().is_some_and(|map| maps.iter().any(|m| m == map));
+            if listed {
+                pruned += 1;
+            }
+            !listed
+        })
+        .collect();
+    if pruned > 0 {
+        fs::write(path, format!("{}\n", kept.join("\n")))?;
+    }
+    Ok(pruned)
+}
+
+// Removes what TeX tools generated from a package's files: its formats, font map entries and
+// the merged maps built from them, font caches, and its entries in ls-R. `files` are the
+// profile-relative paths the package owned.
+pub fn purge_package(profile_dir: &Path, pkg: Option<&Package>, files: &[String]) -> anyhow::Result<Vec<String>> {
+    let mut removed = Vec::new();
+    let mut stale: HashSet<String> = files.iter().cloned().collect();
+
+    if let Some(pkg) = pkg {
+        for format in execute::formats(&pkg.executes) {
+            for extension in ["fmt", "log"] {
+                let rel_path = format!("texmf-var/web2c/{}/{}.{}", format.engine, format.name, extension);
+                remove_path(&profile_dir.join(&rel_path), &mut removed)?;
+                stale.insert(rel_path);
+            }
+        }
+
+        let maps: Vec<String> = execute::maps(&pkg.executes).into_iter().map(|map| map.file).collect();
+        if !maps.is_empty() {
+            for cfg in ["texmf-var/web2c/updmap.cfg", "texmf-config/web2c/updmap.cfg"] {
+                let pruned = prune_updmap_cfg(&profile_dir.join(cfg), &maps)?;
+                if pruned > 0 {
+                    removed.push(format!("{} map entries in {}", pruned, profile_dir.join(cfg).display()));
+                }
+            }
+            // The merged maps still contain the package's fonts until updmap runs again.
+            remove_path(&profile_dir.join("texmf-var/fonts/map"), &mut removed)?;
+        }
+    }
+
+    if files.iter().any(|file| file.starts_with("texmf-dist/fonts/")) {
+        for cache in ["texmf-var/fonts/pk", "texmf-var/fonts/tfm", "texmf-var/luatex-cache"] {
+            remove_path(&profile_dir.join(cache), &mut removed)?;
+        }
+    }
+
+    for tree in TREES {
+        let prefix = format!("{}/", tree);
+        let in_tree: HashSet<String> = stale
+            .iter()
+            .filter_map(|file| file.strip_prefix(&prefix).map(str::to_string))
+            .collect();
+        let pruned = prune_ls_r(&profile_dir.join(tree), &in_tree)?;
+        if pruned > 0 {
+            removed.push(format!("{} entries in {}", pruned, profile_dir.join(tree).join("ls-R").display()));
+        }
+    }
+    Ok(removed)
+}
+
+// Everything generated inside a profile goes with its tree; what is left is texman's own state:
+// an unfinished install journal and store revisions that no other profile or backup uses.
+pub fn purge_profile(conn: &Connection, texman_dir: &Path, profile: &str, revisions: &[(String, String)]) -> anyhow::Result<Vec<String>> {
+    let mut removed = Vec::new();
+    conn.execute(
+        "DELETE FROM journal_packages WHERE journal_id IN (SELECT id FROM journal WHERE profile = ?1)",
+        params![profile],
+    )?;
+    conn.execute("DELETE FROM journal WHERE profile = ?1", params![profile])?;
+
+    let _store = crate::cache::lock_blocking(&crate::store_lock(texman_dir))?;
+    for (name, revision) in revisions {
+        let in_use: i64 = conn.query_row(
+            "SELECT (SELECT COUNT(*) FROM installed_packages WHERE name = ?1 AND revision = ?2)
+                  + (SELECT COUNT(*) FROM backups WHERE name = ?1 AND revision = ?2)",
+            params![name, revision],
+            |row| row.get(0),
+        )?;
+        if in_use == 0 {
+            let store_path = crate::store_path(texman_dir, name, revision);
+            remove_path(&store_path, &mut removed)?;
+            remove_path(&crate::store::compressed_path(&store_path), &mut removed)?;
+        }
+    }
+    let (objects, size) = crate::store::prune_objects(&texman_dir.join("store"))?;
+    if objects > 0 {
+        removed.push(format!("{} unused store objects ({})", objects, crate::output::format_size(size)));
+    }
+    Ok(removed)
+}
@@ -0,0 +1,331 @@
+use crate::config::Config;
+use crate::hooks::{self, Cancel};
+use crate::output::SchemaVersion;
+use crate::Package;
+use rusqlite::params;
+use futures::stream::{FuturesUnordered, StreamExt};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io::{BufRead, Write};
+use std::sync::Arc;
+
+#[derive(Serialize)]
+struct Provider {
+    package: String,
+    revision: String,
+    path: String,
+    installed: bool,
+}
+
+#[derive(Serialize, Default)]
+struct Response {
+    schema_version: SchemaVersion,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    id: Option<serde_json::Value>,
+    file: String,
+    providers: Vec<Provider>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    installed: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Request {
+    id: Option<serde_json::Value>,
+    method: String,
+    file: Option<String>,
+    #[serde(default)]
+    install: bool,
+    // The profile to look in and install into; the one `serve` was started for when not given.
+    profile: Option<String>,
+    // For `cancel`: the id of the request to give up on.
+    request: Option<serde_json::Value>,
+}
+
+// Maps bare file names to the packages shipping them, so repeated lookups in
+// `serve` mode don't rescan every file list in the TLPDB.
+struct FileIndex {
+    // Package name and path of every file, by bare file name.
+    by_name: HashMap<String, Vec<(String, String)>>,
+}
+
+impl FileIndex {
+    fn new(tlpdb: &HashMap<String, Package>) -> FileIndex {
+        let mut by_name: HashMap<String, Vec<(String, String)>> = HashMap::new();
+        for pkg in tlpdb.values() {
+            for path in pkg.runfiles.iter().chain(&pkg.binfiles) {
+                let name = path.rsplit('/').next().unwrap_or(path);
+                by_name.entry(name.to_string()).or_default().push((pkg.name.clone(), path.clone()));
+            }
+        }
+        for providers in by_name.values_mut() {
+            providers.sort();
+        }
+        FileIndex { by_name }
+    }
+
+    // A bare name matches anywhere in the tree; a name with directories must match the end of the path.
+    fn providers(&self, file: &str) -> Vec<(&str, &str)> {
+        let name = file.rsplit('/').next().unwrap_or(file);
+        self.by_name
+            .get(name)
+            .map(|providers| {
+                providers
+                    .iter()
+                    .filter(|(_, path)| !file.contains('/') || path.ends_with(&format!("/{}", file.trim_start_matches('/'))))
+                    .map(|(package, path)| (package.as_str(), path.as_str()))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+}
+
+fn installed_packages(profile: &str) -> anyhow::Result<HashMap<String, String>> {
+    let texman_dir = dirs::home_dir()
+        .ok_or_else(|| anyhow::anyhow!("Cannot find home directory"))?
+        .join(".texman");
+    let conn = crate::init_db(&texman_dir)?;
+    let mut stmt = conn.prepare("SELECT name, revision FROM installed_packages WHERE profile = ?1")?;
+    let rows = stmt
+        .query_map(params![profile], |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)))?
+        .collect::<Result<HashMap<_, _>, _>>()?;
+    Ok(rows)
+}
+
+async fn missing_file(
+    index: &FileIndex,
+    file: &str,
+    install: bool,
+    profile: &str,
+    tlpdb: &HashMap<String, Package>,
+    config: &Config,
+) -> anyhow::Result<Response> {
+    let installed = installed_packages(profile)?;
+    let mut response = Response {
+        file: file.to_string(),
+        providers: index
+            .providers(file)
+            .into_iter()
+            .map(|(package, path)| Provider {
+                package: package.to_string(),
+                revision: tlpdb.get(package).map(|pkg| pkg.revision.clone()).unwrap_or_default(),
+                path: path.to_string(),
+                installed: installed.contains_key(package),
+            })
+            .collect(),
+        ..Default::default()
+    };
+
+    if !install || response.providers.iter().any(|p| p.installed) {
+        return Ok(response);
+    }
+    let mut candidates: Vec<&str> = response.providers.iter().map(|p| p.package.as_str()).collect();
+    candidates.dedup();
+    match candidates.as_slice() {
+        [] => anyhow::bail!("No package provides '{}'", file),
+        [package] => {
+            let package = package.to_string();
+            let failed = crate::install_package(std::slice::from_ref(&package), &[profile.to_string()], tlpdb, config, None, None, false).await?;
+            crate::report_failures(&failed, &format!("texman install {} --profile {}", package, profile))?;
+            for provider in response.providers.iter_mut() {
+                provider.installed = true;
+            }
+            response.installed = Some(package);
+        }
+        _ => anyhow::bail!("'{}' is provided by several packages ({}); install one of them explicitly", file, candidates.join(", ")),
+    }
+    Ok(response)
+}
+
+pub async fn query_missing_file(
+    file: &str,
+    install: bool,
+    profile: &str,
+    tlpdb: &HashMap<String, Package>,
+    config: &Config,
+) -> anyhow::Result<()> {
+    let index = FileIndex::new(tlpdb);
+    let response = missing_file(&index, file, install, profile, tlpdb, config).await;
+    let failed = response.is_err();
+    let response = response.unwrap_or_else(|e| Response { file: file.to_string(), error: Some(e.to_string()), ..Default::default() });
+    println!("{}", serde_json::to_string(&response)?);
+    if failed {
+        anyhow::bail!("Query for '{}' failed", file);
+    }
+    Ok(())
+}
+
+fn write_response(response: &Response) -> anyhow::Result<()> {
+    let mut stdout = std::io::stdout().lock();
+    writeln!(stdout, "{}", serde_json::to_string(response)?)?;
+    stdout.flush()?;
+    Ok(())
+}
+
+// Line-delimited JSON over stdin/stdout: one request object per line, one response object per
+// line. Requests run concurrently and are answered as they finish, so responses carry the id of
+// their request; installs into the same profile still wait for each other. While a request
+// downloads, progress events with its id come before its response.
+pub async fn serve(profile: &str, tlpdb: HashMap<String, Package>, config: Config) -> anyhow::Result<()> {
+    // Shared with every request's thread.
+    let index = Arc::new(FileIndex::new(&tlpdb));
+    let tlpdb = Arc::new(tlpdb);
+    let config = Arc::new(config);
+    log::info!("Serving queries for profile '{}' on stdin", profile);
+    // Reading stdin blocks, so it gets a thread of its own and requests keep arriving, e.g. a
+    // cancel for one that is still installing.
+    let (lines_tx, lines) = tokio::sync::mpsc::unbounded_channel();
+    std::thread::spawn(move || {
+        for line in std::io::stdin().lock().lines() {
+            let Ok(line) = line else { break };
+            if lines_tx.send(line).is_err() {
+                break;
+            }
+        }
+    });
+
+    let runtime = tokio::runtime::Handle::current();
+    let handler = move |request: &Request, file: &str, profile: &str, cancel: &Arc<Cancel>| {
+        let lookup = missing_file(&index, file, request.install, profile, &tlpdb, &config);
+        runtime.block_on(hooks::in_request(
+            request.id.clone(),
+            hooks::cancellable(cancel.clone(), async {
+                tokio::select! {
+                    response = lookup => response,
+                    _ = cancel.cancelled() => anyhow::bail!("Cancelled"),
+                }
+            }),
+        ))
+    };
+    dispatch(lines, profile.to_string(), handler, write_response).await
+}
+
+// Answers the requests arriving on `lines`, running `handler` for each lookup. An install unpacks
+// and links without awaiting, so every request gets a blocking thread of its own: the others,
+// and cancels, go on meanwhile.
+async fn dispatch<H>(
+    mut lines: tokio::sync::mpsc::UnboundedReceiver<String>,
+    profile: String,
+    handler: H,
+    mut write: impl FnMut(&Response) -> anyhow::Result<()>,
+) -> anyhow::Result<()>
+where
+    H: Fn(&Request, &str, &str, &Arc<Cancel>) -> anyhow::Result<Response> + Send + Sync + 'static,
+{
+    let handler = Arc::new(handler);
+    let mut running = FuturesUnordered::new();
+    // The requests in progress that have an id, by the id's JSON text.
+    let mut in_progress: HashMap<String, Arc<Cancel>> = HashMap::new();
+    let mut reading = true;
+    loop {
+        tokio::select! {
+            line = lines.recv(), if reading => {
+                let Some(line) = line else {
+                    reading = false;
+                    continue;
+                };
+                if line.trim().is_empty() {
+                    continue;
+                }
+                let request = match serde_json::from_str::<Request>(&line) {
+                    Ok(request) => request,
+                    Err(e) => {
+                        write(&Response { error: Some(format!("Invalid request: {}", e)), ..Default::default() })?;
+                        continue;
+                    }
+                };
+                let key = request.id.as_ref().map(serde_json::Value::to_string);
+                let error = match (request.method.as_str(), &request.file) {
+                    ("cancel", _) => match request.request.as_ref().and_then(|id| in_progress.remove(&id.to_string())) {
+                        Some(cancel) => {
+                            cancel.cancel();
+                            None
+                        }
+                        None => Some("cancel requires the 'request' id of a request in progress".to_string()),
+                    },
+                    ("missing-file", Some(_)) if key.as_ref().is_some_and(|key| in_progress.contains_key(key)) => {
+                        Some(format!("Request {} is already in progress", key.unwrap_or_default()))
+                    }
+                    ("missing-file", Some(file)) => {
+                        let cancel = Arc::new(Cancel::default());
+                        if let Some(key) = &key {
+                            in_progress.insert(key.clone(), cancel.clone());
+                        }
+                        let file = file.clone();
+                        let profile = request.profile.clone().unwrap_or_else(|| profile.clone());
+                        let handler = handler.clone();
+                        running.push(tokio::task::spawn_blocking(move || {
+                            let mut response = handler(&request, &file, &profile, &cancel)
+                                .unwrap_or_else(|e| Response { file, error: Some(e.to_string()), ..Default::default() });
+                            response.id = request.id;
+                            (key, response)
+                        }));
+                        continue;
+                    }
+                    ("missing-file", None) => Some("missing-file requires a 'file' field".to_string()),
+                    (method, _) => Some(format!("Unknown method '{}'", method)),
+                };
+                write(&Response { id: request.id, file: request.file.unwrap_or_default(), error, ..Default::default() })?;
+            }
+            Some(done) = running.next() => {
+                let (key, response) = done?;
+                if let Some(key) = key {
+                    in_progress.remove(&key);
+                }
+                write(&response)?;
+            }
+            else => break,
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    async fn next(responses: &mut tokio::sync::mpsc::UnboundedReceiver<serde_json::Value>) -> serde_json::Value {
+        tokio::time::timeout(Duration::from_secs(5), responses.recv()).await.expect("no response in time").expect("server stopped")
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn serves_profiles_at_once_and_cancels_an_install() {
+        let (lines_tx, lines) = tokio::sync::mpsc::unbounded_channel();
+        let (responses_tx, mut responses) = tokio::sync::mpsc::unbounded_channel();
+        // Installing into "slow" keeps unpacking, blocking its thread and checking for a cancel
+        // between packages the way populate_store does.
+        let handler = |_: &Request, file: &str, profile: &str, cancel: &Arc<Cancel>| {
+            if profile == "slow" {
+                while !cancel.is_cancelled() {
+                    std::thread::sleep(Duration::from_millis(10));
+                }
+                anyhow::bail!("Cancelled");
+            }
+            Ok(Response { file: file.to_string(), installed: Some(profile.to_string()), ..Default::default() })
+        };
+        let write = move |response: &Response| {
+            responses_tx.send(serde_json::to_value(response)?)?;
+            Ok(())
+        };
+        let server = tokio::spawn(dispatch(lines, "slow".to_string(), handler, write));
+
+        lines_tx.send(r#"{"id": 1, "method": "missing-file", "file": "a.sty", "install": true}"#.to_string()).unwrap();
+        lines_tx.send(r#"{"id": 2, "method": "missing-file", "file": "b.sty", "install": true, "profile": "fast"}"#.to_string()).unwrap();
+        let fast = next(&mut responses).await;
+        assert_eq!(fast["id"], 2);
+        assert_eq!(fast["installed"], "fast");
+
+        lines_tx.send(r#"{"id": 3, "method": "cancel", "request": 1}"#.to_string()).unwrap();
+        let mut answers = [next(&mut responses).await, next(&mut responses).await];
+        answers.sort_by_key(|response| response["id"].as_i64());
+        assert_eq!(answers[0]["id"], 1);
+        assert_eq!(answers[0]["error"], "Cancelled");
+        assert_eq!(answers[1]["id"], 3);
+        assert!(answers[1].get("error").is_none());
+
+        drop(lines_tx);
+        server.await.unwrap().unwrap();
+    }
+}
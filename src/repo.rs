@@ -0,0 +1,405 @@
+use crate::config::{Config, DEFAULT_REPOSITORY};
+use crate::Package;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// The newest TeX Live release whose repository layout texman understands; a repository
+// whose minrelease is above it may use containers or TLPDB fields texman can't handle.
+pub const SUPPORTED_RELEASE: u32 = 2025;
+
+// Pretest mirrors carry the upcoming release before it is frozen and published on CTAN.
+pub const PRETEST_MIRRORS: &[&str] = &[
+    "https://ftp.math.utah.edu/pub/tlpretest",
+    "https://ftp.tu-chemnitz.de/pub/tug/historic/tlpretest",
+    "https://ftp.cstug.cz/pub/tex/local/tlpretest",
+];
+
+// Settings from the `00texlive.config` block, which lists them as `depend key/value` lines.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct RepositoryInfo {
+    pub release: Option<u32>,
+    pub minrelease: Option<u32>,
+    pub frozen: bool,
+    pub revision: Option<u64>,
+    pub container_format: Option<String>,
+    pub container_split_doc_files: bool,
+    pub container_split_src_files: bool,
+}
+
+pub fn parse_config_block(block: &str) -> RepositoryInfo {
+    let mut info = RepositoryInfo::default();
+    for line in block.lines() {
+        let Some((key, value)) = line.trim().strip_prefix("depend ").and_then(|setting| setting.split_once('/')) else {
+            continue;
+        };
+        match key {
+            "release" => info.release = value.parse().ok(),
+            "minrelease" => info.minrelease = value.parse().ok(),
+            "frozen" => info.frozen = value == "1",
+            "revision" => info.revision = value.parse().ok(),
+            "container_format" => info.container_format = Some(value.to_string()),
+            "container_split_doc_files" => info.container_split_doc_files = value == "1",
+            "container_split_src_files" => info.container_split_src_files = value == "1",
+            _ => log::debug!("Ignoring repository setting {}/{}", key, value),
+        }
+    }
+    info
+}
+
+fn path(texman_dir: &Path) -> PathBuf {
+    texman_dir.join("db").join("repository.json")
+}
+
+pub fn save(texman_dir: &Path, info: &RepositoryInfo) -> anyhow::Result<()> {
+    fs::write(path(texman_dir), serde_json::to_string_pretty(info)?)?;
+    Ok(())
+}
+
+pub fn load(texman_dir: &Path) -> Option<RepositoryInfo> {
+    serde_json::from_str(&fs::read_to_string(path(texman_dir)).ok()?).ok()
+}
+
+pub fn is_cached(texman_dir: &Path) -> bool {
+    path(texman_dir).exists()
+}
+
+pub fn warnings(info: &RepositoryInfo) -> Vec<String> {
+    let mut warnings = Vec::new();
+    if let Some(minrelease) = info.minrelease
+        && minrelease > SUPPORTED_RELEASE
+    {
+        warnings.push(format!(
+            "The repository requires TeX Live {} tools or newer, but texman {} supports up to {}; upgrade texman",
+            minrelease,
+            env!("CARGO_PKG_VERSION"),
+            SUPPORTED_RELEASE
+        ));
+    }
+    if info.frozen {
+        warnings.push(format!(
+            "The repository is frozen{}: it receives no further updates",
+            info.release.map(|release| format!(" (TeX Live {} is final)", release)).unwrap_or_default()
+        ));
+    }
+    warnings
+}
+
+pub fn is_pretest(repository: &str) -> bool {
+    PRETEST_MIRRORS.iter().any(|mirror| repository.trim_end_matches('/') == *mirror)
+}
+
+// Expands the `ctan` and `pretest` shorthands. For pretest the first mirror that serves a
+// TLPDB checksum is used; offline, the pretest mirror the cached TLPDB came from is kept.
+pub async fn resolve_repository(config: &mut Config) {
+    match config.repository.as_str() {
+        "ctan" => config.repository = DEFAULT_REPOSITORY.to_string(),
+        "pretest" => config.repository = pick_pretest_mirror(config).await,
+        repository => config.repository = crate::mirror::normalize_url(repository),
+    }
+    config.mirrors = config.mirrors.iter().map(|mirror| crate::mirror::normalize_url(mirror)).collect();
+    if config.repository.starts_with("http://") {
+        log::warn!("Repository {} uses plain HTTP, which is unencrypted and often blocked by proxies; use an https:// mirror", config.repository);
+    }
+}
+
+async fn pick_pretest_mirror(config: &Config) -> String {
+    let cached = dirs::home_dir()
+        .and_then(|home| fs::read_to_string(home.join(".texman").join("db").join("tlpdb.repository")).ok())
+        .map(|repository| repository.trim().to_string())
+        .filter(|repository| is_pretest(repository));
+    if config.offline {
+        return cached.unwrap_or_else(|| PRETEST_MIRRORS[0].to_string());
+    }
+    let Ok(client) = crate::mirror::client_builder(config).and_then(|builder| Ok(builder.timeout(std::time::Duration::from_secs(5)).build()?)) else {
+        return PRETEST_MIRRORS[0].to_string();
+    };
+    let candidates = cached.iter().map(String::as_str).chain(PRETEST_MIRRORS.iter().copied());
+    for mirror in candidates {
+        let url = format!("{}/tlpkg/texlive.tlpdb.sha512", mirror);
+        match client.head(&url).send().await.and_then(|response| response.error_for_status()) {
+            Ok(_) => {
+                log::info!("Using pretest mirror {}", mirror);
+                return mirror.to_string();
+            }
+            Err(e) => log::info!("Pretest mirror {} unavailable: {}", mirror, e),
+        }
+    }
+    log::warn!("No pretest mirror responded; trying {}", PRETEST_MIRRORS[0]);
+    PRETEST_MIRRORS[0].to_string()
+}
+
+// Final snapshots of past TeX Live releases, one `<year>/tlnet-final` repository per release.
+// 2008 is the first release installed through a TLPDB.
+pub const HISTORIC_ARCHIVE: &str = "https://ftp.tug.org/historic/systems/texlive";
+const FIRST_HISTORIC_YEAR: u32 = 2008;
+const YEAR_SETTING: &str = "repository_year";
+
+pub fn historic_url(year: u32) -> String {
+    format!("{}/{}/tlnet-final", HISTORIC_ARCHIVE, year)
+}
+
+// The release `profile` is pinned to with `texman repo set --year`, if any.
+pub fn pinned_year(conn: &rusqlite::Connection, profile: &str) -> anyhow::Result<Option<u32>> {
+    Ok(crate::profile_setting(conn, profile, YEAR_SETTING)?.and_then(|year| year.parse().ok()))
+}
+
+// Pins `profile` to the final snapshot of TeX Live `year`, or back to the configured repository
+// with None. The pin applies whenever the profile is active, unless --repository is passed.
+pub fn set_year(texman_dir: &Path, profile: &str, year: Option<u32>) -> anyhow::Result<()> {
+    if !texman_dir.join("profiles").join(profile).is_dir() {
+        anyhow::bail!("Profile '{}' does not exist", profile);
+    }
+    let conn = crate::init_db(texman_dir)?;
+    let Some(year) = year else {
+        conn.execute(
+            "DELETE FROM profile_settings WHERE profile = ?1 AND key = ?2",
+            rusqlite::params![profile, YEAR_SETTING],
+        )?;
+        println!("Profile '{}' follows the configured repository again", profile);
+        return Ok(());
+    };
+    let current_year = chrono::Datelike::year(&chrono::Utc::now()) as u32;
+    if year < FIRST_HISTORIC_YEAR || year >= current_year {
+        anyhow::bail!(
+            "No historic snapshot of TeX Live {}: the archive has {} to {}",
+            year,
+            FIRST_HISTORIC_YEAR,
+            current_year - 1
+        );
+    }
+    crate::set_profile_setting(&conn, profile, YEAR_SETTING, &year.to_string())?;
+    println!("Pinned profile '{}' to TeX Live {} ({})", profile, year, historic_url(year));
+    println!("Installed packages keep their revisions; 'texman install <package>' replaces one with its {} revision.", year);
+    Ok(())
+}
+
+// The label packages from `repository` carry; additional repositories go by the name they were
+// added under.
+pub const MAIN: &str = "main";
+// The origin recorded for packages that came out of a profile bundle rather than a repository.
+pub const BUNDLE: &str = "bundle";
+
+fn extra_cache(texman_dir: &Path, name: &str) -> (PathBuf, PathBuf) {
+    let dir = texman_dir.join("db").join("repos");
+    (dir.join(format!("{}.tlpdb", name)), dir.join(format!("{}.url", name)))
+}
+
+pub fn add(texman_dir: &Path, name: &str, url: &str) -> anyhow::Result<()> {
+    if name == MAIN || name == BUNDLE || name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+        anyhow::bail!("Invalid repository name '{}': use letters, digits, '-' and '_', and not '{}' or '{}'", name, MAIN, BUNDLE);
+    }
+    let mut config = Config::load_file(texman_dir)?;
+    let url = crate::mirror::normalize_url(url);
+    if let Some(previous) = config.repositories.insert(name.to_string(), url.clone()) {
+        println!("Repository '{}' changed from {} to {}", name, previous, url);
+    } else {
+        println!("Added repository '{}' ({})", name, url);
+    }
+    config.save(texman_dir)?;
+    Ok(())
+}
+
+pub fn remove(texman_dir: &Path, name: &str) -> anyhow::Result<()> {
+    let mut config = Config::load_file(texman_dir)?;
+    if config.repositories.remove(name).is_none() {
+        anyhow::bail!("No repository named '{}'; see 'texman repo list'", name);
+    }
+    config.save(texman_dir)?;
+    let (tlpdb_path, url_path) = extra_cache(texman_dir, name);
+    let _ = fs::remove_file(tlpdb_path);
+    let _ = fs::remove_file(url_path);
+    println!("Removed repository '{}'; packages installed from it stay installed", name);
+    Ok(())
+}
+
+pub fn list(config: &Config, pinned: Option<u32>, pins: &HashMap<String, String>) {
+    match pinned {
+        Some(year) => println!("  {:<16} {} (pinned to TeX Live {})", MAIN, config.repository, year),
+        None => println!("  {:<16} {}", MAIN, config.repository),
+    }
+    for (name, url) in &config.repositories {
+        println!("  {:<16} {}", name, url);
+    }
+    if !config.repository_priority.is_empty() {
+        println!("Priority: {}", config.repository_priority.join(" > "));
+    }
+    if !pins.is_empty() {
+        let mut pins: Vec<_> = pins.iter().collect();
+        pins.sort();
+        println!("Pinned packages:");
+        for (package, repository) in pins {
+            println!("  {:<30} {}", package, repository);
+        }
+    }
+}
+
+// The cached TLPDB of an additional repository, refreshed once a day like the main one. A
+// repository that can't be reached doesn't stop the command: its cached copy is used, or its
+// packages are left out.
+async fn extra_tlpdb_text(texman_dir: &Path, name: &str, url: &str, config: &Config) -> anyhow::Result<Option<String>> {
+    let (tlpdb_path, url_path) = extra_cache(texman_dir, name);
+    let same_url = fs::read_to_string(&url_path).is_ok_and(|cached| cached.trim() == url);
+    let cached = if same_url { fs::read_to_string(&tlpdb_path).ok() } else { None };
+    let fresh = same_url && crate::cache::is_fresh(&tlpdb_path, chrono::Duration::hours(24));
+    if config.offline || (fresh && cached.is_some()) {
+        if cached.is_none() {
+            log::warn!("Offline mode: no cached TLPDB for repository '{}'; its packages are unavailable", name);
+        }
+        return Ok(cached);
+    }
+
+    let mut repo_config = config.clone();
+    repo_config.repository = crate::mirror::normalize_url(url);
+    repo_config.mirrors = Vec::new();
+    let client = crate::mirror::client(&repo_config)?;
+    log::info!("Fetching TLPDB of repository '{}' from {}", name, url);
+    let fetched = crate::fetch_tlpdb_text(&repo_config, &client, None)
+        .await
+        .and_then(|fetched| fetched.ok_or_else(|| anyhow::anyhow!("unexpected 304 Not Modified")));
+    match fetched {
+        Ok((text, _)) => {
+            fs::create_dir_all(tlpdb_path.parent().unwrap_or(texman_dir))?;
+            fs::write(&tlpdb_path, &text)?;
+            fs::write(&url_path, url)?;
+            Ok(Some(text))
+        }
+        Err(e) => {
+            log::warn!("Cannot fetch the TLPDB of repository '{}' ({}){}", name, e, if cached.is_some() { "; using the cached copy" } else { "" });
+            Ok(cached)
+        }
+    }
+}
+
+// Where a repository ranks when several provide a package: the order of `repository_priority`,
+// then the main repository, then the others by name.
+fn rank(config: &Config, repository: &str) -> (usize, bool, String) {
+    let position = config.repository_priority.iter().position(|name| name == repository).unwrap_or(usize::MAX);
+    (position, repository != MAIN, repository.to_string())
+}
+
+pub fn pins(conn: &rusqlite::Connection) -> anyhow::Result<HashMap<String, String>> {
+    let mut stmt = conn.prepare("SELECT name, repository FROM repository_pins")?;
+    let pins = stmt
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+        .collect::<Result<_, _>>()?;
+    Ok(pins)
+}
+
+// Adds the packages of every additional repository to `tlpdb`, tagged with the repository's
+// name. A package several repositories have is taken from the one it is pinned to with
+// `texman pin`, or else from the highest-ranked one.
+pub async fn merge_extra(tlpdb: &mut HashMap<String, Package>, config: &Config) -> anyhow::Result<()> {
+    let texman_dir = dirs::home_dir()
+        .ok_or_else(|| anyhow::anyhow!("Cannot find home directory"))?
+        .join(".texman");
+    let pins = pins(&crate::init_db(&texman_dir)?)?;
+    for (name, url) in &config.repositories {
+        let Some(text) = extra_tlpdb_text(&texman_dir, name, url, config).await? else { continue };
+        let (packages, _) = crate::parse_tlpdb(&text)?;
+        let mut added = 0;
+        for (pkg_name, mut pkg) in packages {
+            if let Some(current) = tlpdb.get(&pkg_name) {
+                let current_repository = current.repository.as_deref().unwrap_or(MAIN);
+                let preferred = match pins.get(&pkg_name) {
+                    Some(pinned) if pinned == name => true,
+                    Some(pinned) if pinned == current_repository => false,
+                    _ => rank(config, name) < rank(config, current_repository),
+                };
+                let used = if preferred { name.as_str() } else { current_repository };
+                log::debug!("{} is in both '{}' and '{}'; using '{}'", pkg_name, current_repository, name, used);
+                if !preferred {
+                    continue;
+                }
+            }
+            pkg.repository = Some(name.clone());
+            tlpdb.insert(pkg_name, pkg);
+            added += 1;
+        }
+        log::info!("Added {} packages from repository '{}'", added, name);
+    }
+    for (pkg_name, pinned) in &pins {
+        if let Some(pkg) = tlpdb.get(pkg_name)
+            && let source = pkg.repository.as_deref().unwrap_or(MAIN)
+            && source != pinned
+        {
+            log::warn!("{} is pinned to repository '{}', which doesn't provide it; using '{}'", pkg_name, pinned, source);
+        }
+    }
+    Ok(())
+}
+
+pub fn pin(texman_dir: &Path, package: &str, repository: &str, config: &Config) -> anyhow::Result<()> {
+    if repository != MAIN && !config.repositories.contains_key(repository) {
+        anyhow::bail!("No repository named '{}'; see 'texman repo list'", repository);
+    }
+    let conn = crate::init_db(texman_dir)?;
+    conn.execute(
+        "INSERT OR REPLACE INTO repository_pins (name, repository) VALUES (?1, ?2)",
+        rusqlite::params![package, repository],
+    )?;
+    println!("Pinned {} to repository '{}'; 'texman update' moves an installed copy to it", package, repository);
+    Ok(())
+}
+
+pub fn unpin(texman_dir: &Path, package: &str) -> anyhow::Result<()> {
+    let conn = crate::init_db(texman_dir)?;
+    if conn.execute("DELETE FROM repository_pins WHERE name = ?1", rusqlite::params![package])? == 0 {
+        anyhow::bail!("{} is not pinned to a repository", package);
+    }
+    println!("Unpinned {}; it comes from the highest-priority repository that has it", package);
+    Ok(())
+}
+
+// Sets the order in which repositories win when several provide a package; without names, prints
+// the order in effect.
+pub fn set_priority(texman_dir: &Path, names: &[String]) -> anyhow::Result<()> {
+    let mut config = Config::load_file(texman_dir)?;
+    if names.is_empty() {
+        let mut order: Vec<&str> = std::iter::once(MAIN).chain(config.repositories.keys().map(String::as_str)).collect();
+        order.sort_by_key(|name| rank(&config, name));
+        println!("{}", order.join(" > "));
+        return Ok(());
+    }
+    for name in names {
+        if name != MAIN && !config.repositories.contains_key(name) {
+            anyhow::bail!("No repository named '{}'; see 'texman repo list'", name);
+        }
+    }
+    config.repository_priority = names.to_vec();
+    config.save(texman_dir)?;
+    println!("Repository priority: {}", names.join(" > "));
+    Ok(())
+}
+
+// Why a package installed from `origin` is not updated to `latest`: packages follow the
+// repository they came from, so a package that another repository now provides, e.g. because its
+// own repository was removed, stays put unless it is pinned there. Bundle installs follow no
+// repository.
+pub fn update_blocker(name: &str, origin: &str, latest: &Package, pinned: Option<&str>) -> Option<String> {
+    let source = latest.repository.as_deref().unwrap_or(MAIN);
+    // A pin is an explicit choice of source, so it moves the package over.
+    if pinned == Some(source) {
+        None
+    } else if origin == BUNDLE {
+        Some(format!("installed from a bundle; run 'texman install {}' to follow repository '{}' instead", name, source))
+    } else if origin != source {
+        Some(format!(
+            "installed from repository '{}', but it now comes from '{}'; run 'texman install {}' or 'texman pin {} --repo {}' to switch",
+            origin, source, name, name, source
+        ))
+    } else {
+        None
+    }
+}
+
+// Downloads of a package from an additional repository go to that repository, without the
+// main repository's fallback mirrors.
+pub fn config_for(config: &Config, pkg: &Package) -> Option<Config> {
+    let url = config.repositories.get(pkg.repository.as_deref()?)?;
+    let mut repo_config = config.clone();
+    repo_config.repository = crate::mirror::normalize_url(url);
+    repo_config.mirrors = Vec::new();
+    Some(repo_config)
+}
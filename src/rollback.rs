@@ -0,0 +1,61 @@
+use crate::config::Config;
+use crate::journal::{self, Target};
+use crate::Package;
+use std::collections::HashMap;
+
+// `texman rollback <pkg>` puts one package of the active profile back to an earlier revision
+// kept in the store, e.g. after an update broke it, without restoring a whole backup. It is a
+// journaled transaction like an update, so `texman undo` takes it back.
+
+pub async fn run(package: &str, to: Option<&str>, tlpdb: &HashMap<String, Package>, config: &Config) -> anyhow::Result<()> {
+    let texman_dir = dirs::home_dir()
+        .ok_or_else(|| anyhow::anyhow!("Cannot find home directory"))?
+        .join(".texman");
+    let conn = crate::init_db(&texman_dir)?;
+    let profile = crate::active::name(&texman_dir)?;
+    let _locks = crate::lock_profiles(&texman_dir, &[&profile]).await?;
+
+    let target = Target::new(&conn, &texman_dir, config, &profile)?;
+    let Some(current) = target.installed(package)? else {
+        anyhow::bail!("Package '{}' is not installed in profile '{}'", package, profile);
+    };
+    let stored = crate::stored_revisions(&texman_dir, package)?;
+    let available = || stored.iter().filter(|revision| **revision != current).map(|revision| format!("r{}", revision)).collect::<Vec<_>>().join(", ");
+    let revision = match to {
+        Some(to) => to.trim_start_matches('r').to_string(),
+        // The newest revision before the installed one.
+        None => {
+            let current_number: u32 = current.parse().unwrap_or(u32::MAX);
+            stored
+                .iter()
+                .rev()
+                .find(|revision| revision.parse::<u32>().is_ok_and(|number| number < current_number))
+                .cloned()
+                .ok_or_else(|| anyhow::anyhow!("No revision of {} before r{} is kept in the store", package, current))?
+        }
+    };
+    if revision == current {
+        println!("{} is already at r{} in profile '{}'", package, revision, profile);
+        return Ok(());
+    }
+    crate::restore_revision(&conn, &texman_dir, config, package, &revision, tlpdb.get(package)).map_err(|e| {
+        let available = available();
+        if available.is_empty() {
+            e
+        } else {
+            anyhow::anyhow!("{}. Revisions in the store: {}", e, available)
+        }
+    })?;
+
+    let transaction = crate::summary::Transaction::begin("rollback", &profile, &texman_dir)?;
+    let journal_id = journal::begin(&conn, "rollback", &profile, &[package.to_string()], crate::conflict_policy(&conn, &profile, config, None)?, &[(package, Some(&revision), None)])?;
+    target.set_revision(package, Some(&revision), tlpdb.get(package))?;
+    journal::commit(&conn, journal_id, package)?;
+    target.refresh(tlpdb, tlpdb.get(package).map(std::slice::from_ref).unwrap_or_default())?;
+    journal::finish(&conn, journal_id)?;
+    println!(
+        "Rolled back {} from r{} to r{}; the next 'texman update' updates it again unless you 'texman pin {}'",
+        package, current, revision, package
+    );
+    transaction.finish(config, tlpdb)
+}
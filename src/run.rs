@@ -0,0 +1,24 @@
+use crate::shellenv;
+use std::path::Path;
+use std::process::Command;
+
+// `texman run pdflatex paper.tex`: runs a program of the profile with the profile's
+// environment, as `texman env` would set it up, without touching the shell.
+pub fn run(profile: &str, profile_dir: &Path, tool: &str, args: &[String]) -> anyhow::Result<()> {
+    let bin_dir = shellenv::bin_dir(profile_dir)
+        .ok_or_else(|| anyhow::anyhow!("TeX Live has no binaries for this platform"))?;
+    let program = bin_dir.join(tool).with_extension(std::env::consts::EXE_EXTENSION);
+    // A dangling link is a script whose package is missing.
+    if program.symlink_metadata().is_err() {
+        anyhow::bail!("'{}' is not installed in profile '{}'; find the package that has it with 'texman search {}'", tool, profile, tool);
+    }
+    let inherited = std::env::var_os("PATH").unwrap_or_default();
+    let path = std::env::join_paths(std::iter::once(bin_dir).chain(std::env::split_paths(&inherited))).unwrap_or(inherited);
+    let mut command = Command::new(&program);
+    command.args(args).env("PATH", path).envs(shellenv::vars(profile, profile_dir));
+    let error = crate::os::exec(&mut command);
+    if !program.exists() {
+        anyhow::bail!("'{}' in profile '{}' links to {:?}, which is missing; reinstall the package it comes from", tool, profile, std::fs::read_link(&program).unwrap_or_default());
+    }
+    anyhow::bail!("Cannot run {:?}: {}", program, error)
+}
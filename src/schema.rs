@@ -0,0 +1,219 @@
+use crate::output::SCHEMA_VERSION;
+use clap::ValueEnum;
+use serde_json::{json, Value};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum SchemaKind {
+    List,
+    Search,
+    Info,
+    Progress,
+    Query,
+    Plan,
+}
+
+const ALL_KINDS: [SchemaKind; 6] =
+    [SchemaKind::List, SchemaKind::Search, SchemaKind::Info, SchemaKind::Progress, SchemaKind::Query, SchemaKind::Plan];
+
+impl SchemaKind {
+    fn name(&self) -> &'static str {
+        match self {
+            SchemaKind::List => "list",
+            SchemaKind::Search => "search",
+            SchemaKind::Info => "info",
+            SchemaKind::Progress => "progress",
+            SchemaKind::Query => "query",
+            SchemaKind::Plan => "plan",
+        }
+    }
+}
+
+fn schema_version() -> Value {
+    json!({ "type": "integer", "const": SCHEMA_VERSION })
+}
+
+fn nullable(kind: &str) -> Value {
+    json!({ "type": [kind, "null"] })
+}
+
+fn strings() -> Value {
+    json!({ "type": "array", "items": { "type": "string" } })
+}
+
+// `list`, `search`, `query <expression>` and `unused` all print package rows; `--fields` leaves
+// out the fields that weren't asked for.
+fn packages(title: &str) -> Value {
+    json!({
+        "title": title,
+        "type": "object",
+        "required": ["schema_version", "packages"],
+        "properties": {
+            "schema_version": schema_version(),
+            "packages": {
+                "type": "array",
+                "items": {
+                    "type": "object",
+                    "properties": {
+                        "name": { "type": "string" },
+                        "revision": { "type": "string" },
+                        "size": { "type": ["integer", "null"], "description": "Download size in bytes" },
+                        "shortdesc": nullable("string"),
+                    },
+                },
+            },
+        },
+    })
+}
+
+fn info() -> Value {
+    json!({
+        "title": "texman info --output json",
+        "type": "object",
+        "required": ["schema_version", "name", "depends", "runfiles", "binfiles", "installed"],
+        "properties": {
+            "schema_version": schema_version(),
+            "name": { "type": "string" },
+            "revision": { "type": ["string", "null"], "description": "Null when the TLPDB doesn't describe the package" },
+            "repository": { "type": ["string", "null"], "description": "'main' or the name of an additional repository" },
+            "url": nullable("string"),
+            "depends": strings(),
+            "shortdesc": nullable("string"),
+            "longdesc": nullable("string"),
+            "version": nullable("string"),
+            "runfiles": {
+                "type": "array",
+                "items": { "type": "string" },
+                "description": "For a package the TLPDB doesn't describe, the files the active profile recorded for it",
+            },
+            "binfiles": strings(),
+            "installed": {
+                "description": "The package in the active profile, or null when it isn't installed there",
+                "type": ["object", "null"],
+                "required": ["revision", "origin"],
+                "properties": {
+                    "revision": { "type": "string" },
+                    "origin": { "type": "string", "description": "'main', 'bundle', or the name of an additional repository" },
+                },
+            },
+        },
+    })
+}
+
+// One event per line on stderr with `--json-progress`; `progress` events come at most a few
+// times a second per download.
+fn progress() -> Value {
+    json!({
+        "title": "texman --json-progress events",
+        "type": "object",
+        "required": ["schema_version", "event", "label", "total", "done"],
+        "properties": {
+            "schema_version": schema_version(),
+            "id": { "description": "The 'query serve' request the download is for; such events go to stdout" },
+            "event": { "enum": ["start", "progress", "finish", "abandon"] },
+            "label": { "type": "string" },
+            "total": { "type": ["integer", "null"], "description": "Expected bytes, when the server says" },
+            "done": { "type": "integer", "description": "Bytes transferred so far, including any resumed part" },
+        },
+    })
+}
+
+fn query() -> Value {
+    json!({
+        "title": "texman query missing-file and query serve responses",
+        "type": "object",
+        "required": ["schema_version", "file", "providers"],
+        "properties": {
+            "schema_version": schema_version(),
+            "id": { "description": "The request's id, echoed back by 'query serve'" },
+            "file": { "type": "string" },
+            "providers": {
+                "type": "array",
+                "items": {
+                    "type": "object",
+                    "required": ["package", "revision", "path", "installed"],
+                    "properties": {
+                        "package": { "type": "string" },
+                        "revision": { "type": "string" },
+                        "path": { "type": "string" },
+                        "installed": { "type": "boolean" },
+                    },
+                },
+            },
+            "installed": { "type": "string", "description": "The package that was installed for the file" },
+            "error": { "type": "string" },
+        },
+    })
+}
+
+// `--from-plan` reads the same document back; packages may be removed from it, but not changed.
+fn plan() -> Value {
+    json!({
+        "title": "texman install --plan and update --plan",
+        "type": "object",
+        "required": ["schema_version", "operation", "profiles", "requested", "packages", "download_size", "installed_size"],
+        "properties": {
+            "schema_version": schema_version(),
+            "operation": { "enum": ["install", "update"] },
+            "profiles": strings(),
+            "requested": strings(),
+            "packages": {
+                "type": "array",
+                "description": "In installation order, dependencies first",
+                "items": {
+                    "type": "object",
+                    "required": ["name", "revision", "repository", "installed", "archives", "download_size", "installed_size"],
+                    "properties": {
+                        "name": { "type": "string" },
+                        "revision": { "type": "string" },
+                        "repository": { "type": "string", "description": "'main' or the name of an additional repository" },
+                        "installed": {
+                            "type": "object",
+                            "description": "The revision in each planned profile that already has the package",
+                            "additionalProperties": { "type": "string" },
+                        },
+                        "archives": {
+                            "type": "array",
+                            "items": {
+                                "type": "object",
+                                "required": ["url", "checksum"],
+                                "properties": {
+                                    "url": { "type": "string" },
+                                    "checksum": { "type": ["string", "null"], "description": "SHA-512 from the TLPDB" },
+                                },
+                            },
+                        },
+                        "download_size": { "type": "integer" },
+                        "installed_size": { "type": "integer" },
+                    },
+                },
+            },
+            "download_size": { "type": "integer", "description": "Bytes to download for all packages" },
+            "installed_size": { "type": "integer", "description": "Bytes unpacked for all packages" },
+        },
+    })
+}
+
+fn schema(kind: SchemaKind) -> Value {
+    let mut schema = match kind {
+        SchemaKind::List => packages("texman list --output json"),
+        SchemaKind::Search => packages("texman search --output json"),
+        SchemaKind::Info => info(),
+        SchemaKind::Progress => progress(),
+        SchemaKind::Query => query(),
+        SchemaKind::Plan => plan(),
+    };
+    schema["$schema"] = json!("https://json-schema.org/draft/2020-12/schema");
+    schema
+}
+
+pub fn print(kind: Option<SchemaKind>) -> anyhow::Result<()> {
+    let document = match kind {
+        Some(kind) => schema(kind),
+        None => json!({
+            "schema_version": SCHEMA_VERSION,
+            "schemas": ALL_KINDS.iter().map(|kind| (kind.name().to_string(), schema(*kind))).collect::<serde_json::Map<_, _>>(),
+        }),
+    };
+    println!("{}", serde_json::to_string_pretty(&document)?);
+    Ok(())
+}
@@ -0,0 +1,275 @@
+use crate::config::Config;
+use crate::Package;
+use rusqlite::{params, Connection, OptionalExtension};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::Write;
+#[cfg(unix)]
+use std::os::unix::process::CommandExt;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::Instant;
+
+// Limits applied inside the sandbox: CPU seconds, address space, and the largest file written.
+const CPU_SECONDS: u64 = 120;
+const MEMORY_BYTES: u64 = 1 << 30;
+const FILE_BYTES: u64 = 256 << 20;
+
+// What perl needs from the system, bound read-only; the rest of the filesystem, $HOME included,
+// isn't there at all. Missing ones are left out, and symlinks (/bin -> usr/bin) are recreated.
+const SYSTEM_PATHS: &[&str] = &[
+    "/usr",
+    "/bin",
+    "/sbin",
+    "/lib",
+    "/lib32",
+    "/lib64",
+    "/etc/alternatives",
+    "/etc/ld.so.cache",
+    "/etc/ld.so.conf",
+    "/etc/ld.so.conf.d",
+    "/etc/localtime",
+    "/etc/perl",
+];
+
+// The only trees of the profile a script may write to; the rest is read-only.
+const WRITABLE_TREES: &[&str] = &["texmf-var", "texmf-config"];
+
+// `postaction script file=tlpkg/tlpostcode/foo.pl [filew32=...]`; the other postaction kinds
+// (shortcut, filetype, fileassoc) only apply on Windows.
+fn script_files(pkg: &Package) -> Vec<String> {
+    pkg.postactions
+        .iter()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            if fields.next() != Some("script") {
+                return None;
+            }
+            fields.find_map(|field| field.strip_prefix("file=")).map(str::to_string)
+        })
+        .collect()
+}
+
+fn is_allowed(conn: &Connection, repository: &str) -> anyhow::Result<bool> {
+    Ok(conn
+        .query_row("SELECT 1 FROM script_approvals WHERE repository = ?1", params![repository], |_| Ok(()))
+        .optional()?
+        .is_some())
+}
+
+// Approvals are per repository, so a package from an additional repository needs its own.
+fn repository_of(config: &Config, pkg: &Package) -> String {
+    crate::repo::config_for(config, pkg).map_or_else(|| config.repository.clone(), |repo_config| repo_config.repository)
+}
+
+// A repository's scripts only run once the user has said so with --allow-scripts; the
+// approval is remembered. Checked before anything is downloaded.
+pub fn ensure_allowed(conn: &Connection, config: &Config, packages: &[Package], allow_scripts: bool) -> anyhow::Result<()> {
+    let mut with_scripts: BTreeMap<String, Vec<&str>> = BTreeMap::new();
+    for pkg in packages.iter().filter(|pkg| !script_files(pkg).is_empty()) {
+        with_scripts.entry(repository_of(config, pkg)).or_default().push(pkg.name.as_str());
+    }
+    let mut unapproved = Vec::new();
+    for (repository, names) in with_scripts {
+        if !is_allowed(conn, &repository)? {
+            unapproved.push((repository, names));
+        }
+    }
+    if unapproved.is_empty() {
+        return Ok(());
+    }
+    if !allow_scripts {
+        let listed: Vec<String> = unapproved
+            .iter()
+            .map(|(repository, names)| format!("Packages from {} run install scripts: {}.", repository, names.join(", ")))
+            .collect();
+        anyhow::bail!(
+            "{} Rerun with --allow-scripts to allow scripts from {}; they run sandboxed without network access.",
+            listed.join(" "),
+            if unapproved.len() == 1 { "this repository" } else { "these repositories" }
+        );
+    }
+    for (repository, _) in unapproved {
+        conn.execute("INSERT OR IGNORE INTO script_approvals (repository) VALUES (?1)", params![repository])?;
+        log::info!("Allowed install scripts from {}", repository);
+    }
+    Ok(())
+}
+
+fn find_in_path(program: &str) -> Option<PathBuf> {
+    std::env::var_os("PATH")
+        .iter()
+        .flat_map(std::env::split_paths)
+        .map(|dir| dir.join(program))
+        .find(|path| path.is_file())
+}
+
+#[cfg(unix)]
+fn set_limits() -> std::io::Result<()> {
+    for (resource, limit) in [
+        (libc::RLIMIT_CPU, CPU_SECONDS),
+        (libc::RLIMIT_AS, MEMORY_BYTES),
+        (libc::RLIMIT_FSIZE, FILE_BYTES),
+    ] {
+        let rlimit = libc::rlimit { rlim_cur: limit as libc::rlim_t, rlim_max: limit as libc::rlim_t };
+        // SAFETY: setrlimit only reads the rlimit struct passed by reference.
+        if unsafe { libc::setrlimit(resource, &rlimit) } != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+fn audit(entry: &str) -> anyhow::Result<()> {
+    let texman_dir = dirs::home_dir()
+        .ok_or_else(|| anyhow::anyhow!("Cannot find home directory"))?
+        .join(".texman");
+    let mut log = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(texman_dir.join("scripts.log"))?;
+    writeln!(log, "{}\t{}", chrono::Utc::now().to_rfc3339(), entry)?;
+    Ok(())
+}
+
+// Under the hardlink strategy a profile's files are the store's objects, so a script writing to
+// one would change every profile sharing it. Files in the writable trees get copies of their own.
+fn unshare_links(dir: &Path) -> anyhow::Result<()> {
+    for rel_path in crate::tree::walk_files(dir)? {
+        let path = dir.join(&rel_path);
+        let metadata = fs::symlink_metadata(&path)?;
+        if !metadata.is_file() || crate::os::link_count(&metadata).is_none_or(|links| links <= 1) {
+            continue;
+        }
+        let copy = path.with_file_name(format!(".{}.unshared", rel_path.file_name().unwrap_or_default().to_string_lossy()));
+        fs::copy(&path, &copy)?;
+        fs::rename(&copy, &path)?;
+    }
+    Ok(())
+}
+
+fn sandbox_binds(command: &mut Command, profile_dir: &Path, store_dir: &Path) -> anyhow::Result<()> {
+    for path in SYSTEM_PATHS {
+        match fs::symlink_metadata(path) {
+            Ok(metadata) if metadata.file_type().is_symlink() => {
+                command.arg("--symlink").arg(fs::read_link(path)?).arg(path);
+            }
+            Ok(_) => {
+                command.args(["--ro-bind", path, path]);
+            }
+            Err(_) => {}
+        }
+    }
+    // Symlinked profiles point into the store.
+    if store_dir.is_dir() {
+        command.arg("--ro-bind").arg(store_dir).arg(store_dir);
+    }
+    command.arg("--ro-bind").arg(profile_dir).arg(profile_dir);
+    for tree in WRITABLE_TREES {
+        let dir = profile_dir.join(tree);
+        fs::create_dir_all(&dir)?;
+        unshare_links(&dir)?;
+        command.arg("--bind").arg(&dir).arg(&dir);
+    }
+    Ok(())
+}
+
+// Scripts run under bubblewrap: they see only the system directories perl needs, the store and
+// the profile read-only, the profile's texmf-var and texmf-config trees writable, and a private
+// /tmp for a home. There is no network, and CPU, memory, and file size are capped. Without
+// bubblewrap scripts are skipped rather than run unconfined.
+pub fn run_postactions(conn: &Connection, config: &Config, profile_dir: &Path, pkg: &Package) -> anyhow::Result<()> {
+    let scripts = script_files(pkg);
+    if scripts.is_empty() {
+        return Ok(());
+    }
+    let repository = repository_of(config, pkg);
+    if !is_allowed(conn, &repository)? {
+        log::warn!("Skipped install scripts of {}: scripts from {} are not allowed", pkg.name, repository);
+        return Ok(());
+    }
+    let Some(bwrap) = find_in_path("bwrap") else {
+        log::warn!("Skipped install scripts of {}: install bubblewrap (bwrap) to run them sandboxed", pkg.name);
+        audit(&format!("{}\t{} r{}\tskipped\tno sandbox available", repository, pkg.name, pkg.revision))?;
+        return Ok(());
+    };
+    // Next to the profiles, as the profile's symlinks name it, so before resolving symlinks.
+    let store_dir = profile_dir.parent().and_then(Path::parent).map(|root| root.join("store")).unwrap_or_default();
+    let profile_dir = fs::canonicalize(profile_dir)?;
+
+    for script in scripts {
+        let script_path = profile_dir.join(&script);
+        if !script_path.exists() {
+            log::warn!("Install script {} of {} is missing from the profile", script, pkg.name);
+            continue;
+        }
+        log::info!("Running install script {} of {} in a sandbox", script, pkg.name);
+        let started = Instant::now();
+        let mut command = Command::new(&bwrap);
+        // The private /tmp first, so it doesn't hide a profile under /tmp.
+        command.args(["--dev", "/dev", "--proc", "/proc", "--tmpfs", "/tmp"]);
+        sandbox_binds(&mut command, &profile_dir, &store_dir)?;
+        command
+            .args(["--unshare-all", "--die-with-parent", "--new-session", "--chdir"])
+            .arg(&profile_dir)
+            .args(["--", "perl"])
+            .arg(&script_path)
+            .arg("install")
+            .arg(&profile_dir)
+            .env_clear()
+            .env("PATH", "/usr/local/bin:/usr/bin:/bin")
+            .env("HOME", "/tmp")
+            .env("TEXMFROOT", &profile_dir);
+        // bubblewrap only exists on Linux, so elsewhere scripts never get here.
+        #[cfg(unix)]
+        // SAFETY: set_limits only calls setrlimit, which is async-signal-safe.
+        unsafe {
+            command.pre_exec(set_limits);
+        }
+        let status = command.status()?;
+        audit(&format!(
+            "{}\t{} r{}\t{}\t{}\t{:.1}s",
+            repository,
+            pkg.name,
+            pkg.revision,
+            script,
+            status,
+            started.elapsed().as_secs_f64()
+        ))?;
+        if !status.success() {
+            log::warn!("Install script {} of {} failed: {}", script, pkg.name, status);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn with_script(name: &str, repository: Option<&str>) -> Package {
+        let block = format!("name {}\nrevision 1\npostaction script file=tlpkg/tlpostcode/{}.pl\n", name, name);
+        let mut pkg = crate::tlpdb::parse_package_block(&block).unwrap();
+        pkg.repository = repository.map(str::to_string);
+        pkg
+    }
+
+    #[test]
+    fn approving_the_main_repository_does_not_approve_another() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute("CREATE TABLE script_approvals (repository TEXT PRIMARY KEY)", []).unwrap();
+        let mut config = Config { repository: "https://main.example/tlnet".to_string(), ..Default::default() };
+        config.repositories.insert("contrib".to_string(), "https://contrib.example/tlnet".to_string());
+        let main = with_script("alpha", None);
+        let contrib = with_script("beta", Some("contrib"));
+
+        ensure_allowed(&conn, &config, std::slice::from_ref(&main), true).unwrap();
+        ensure_allowed(&conn, &config, std::slice::from_ref(&main), false).unwrap();
+        let error = ensure_allowed(&conn, &config, &[main, contrib.clone()], false).unwrap_err().to_string();
+        assert!(error.contains("https://contrib.example/tlnet run install scripts: beta"), "{}", error);
+        assert!(!error.contains("alpha"), "{}", error);
+
+        ensure_allowed(&conn, &config, std::slice::from_ref(&contrib), true).unwrap();
+        assert!(is_allowed(&conn, "https://contrib.example/tlnet").unwrap());
+    }
+}
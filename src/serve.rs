@@ -0,0 +1,152 @@
+use std::net::SocketAddr;
+use std::path::{Component, Path, PathBuf};
+use tokio::fs::File;
+use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+const MAX_REQUEST_HEAD: usize = 16 * 1024;
+
+struct Request {
+    method: String,
+    path: String,
+    range: Option<(u64, Option<u64>)>,
+}
+
+// Reads the request line and headers; the mirror has no use for a body.
+async fn read_request(stream: &mut TcpStream) -> anyhow::Result<Request> {
+    let mut head = Vec::new();
+    let mut buffer = [0; 1024];
+    while !head.windows(4).any(|window| window == b"\r\n\r\n") {
+        if head.len() > MAX_REQUEST_HEAD {
+            anyhow::bail!("Request head too large");
+        }
+        let read = stream.read(&mut buffer).await?;
+        if read == 0 {
+            anyhow::bail!("Connection closed before the request was complete");
+        }
+        head.extend_from_slice(&buffer[..read]);
+    }
+    let head = String::from_utf8_lossy(&head);
+    let mut lines = head.lines();
+    let mut request_line = lines.next().unwrap_or("").split_whitespace();
+    let method = request_line.next().unwrap_or("").to_string();
+    let target = request_line.next().unwrap_or("/");
+    let path = target.split(['?', '#']).next().unwrap_or("/").to_string();
+    let mut range = None;
+    for line in lines {
+        if let Some((name, value)) = line.split_once(':')
+            && name.trim().eq_ignore_ascii_case("range")
+            && let Some(spec) = value.trim().strip_prefix("bytes=")
+            && let Some((start, end)) = spec.split_once('-')
+            && let Ok(start) = start.trim().parse()
+        {
+            range = Some((start, end.trim().parse().ok().filter(|end| *end >= start)));
+        }
+    }
+    Ok(Request { method, path, range })
+}
+
+// Only plain relative components are allowed, so requests can't leave the mirror directory.
+fn resolve(root: &Path, path: &str) -> Option<PathBuf> {
+    let relative = Path::new(path.trim_start_matches('/'));
+    if relative.components().any(|component| !matches!(component, Component::Normal(_))) {
+        return None;
+    }
+    let file = root.join(relative);
+    file.is_file().then_some(file)
+}
+
+fn content_type(path: &Path) -> &'static str {
+    match path.extension().and_then(|extension| extension.to_str()) {
+        Some("tlpdb") | Some("sha512") => "text/plain; charset=utf-8",
+        Some("xz") => "application/x-xz",
+        _ => "application/octet-stream",
+    }
+}
+
+async fn respond_status(stream: &mut TcpStream, status: &str, extra: &str) -> anyhow::Result<()> {
+    let body = format!("{}\n", status);
+    let head = format!(
+        "HTTP/1.1 {}\r\nContent-Type: text/plain\r\nContent-Length: {}\r\n{}Connection: close\r\n\r\n",
+        status,
+        body.len(),
+        extra
+    );
+    stream.write_all(head.as_bytes()).await?;
+    stream.write_all(body.as_bytes()).await?;
+    Ok(())
+}
+
+async fn handle(mut stream: TcpStream, peer: SocketAddr, root: &Path) -> anyhow::Result<()> {
+    let request = read_request(&mut stream).await?;
+    if request.method != "GET" && request.method != "HEAD" {
+        log::info!("{} {} {} 405", peer, request.method, request.path);
+        return respond_status(&mut stream, "405 Method Not Allowed", "Allow: GET, HEAD\r\n").await;
+    }
+    let Some(path) = resolve(root, &request.path) else {
+        log::info!("{} {} {} 404", peer, request.method, request.path);
+        return respond_status(&mut stream, "404 Not Found", "").await;
+    };
+
+    let mut file = File::open(&path).await?;
+    let length = file.metadata().await?.len();
+    // Clients resume interrupted archive downloads with `Range: bytes=N-`.
+    let (status, start, end) = match request.range {
+        Some((start, _)) if start >= length => {
+            log::info!("{} {} {} 416", peer, request.method, request.path);
+            let extra = format!("Content-Range: bytes */{}\r\n", length);
+            return respond_status(&mut stream, "416 Range Not Satisfiable", &extra).await;
+        }
+        Some((start, end)) => ("206 Partial Content", start, end.unwrap_or(length - 1).min(length - 1)),
+        None => ("200 OK", 0, length.saturating_sub(1)),
+    };
+    let body_length = if length == 0 { 0 } else { end + 1 - start };
+    let mut head = format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nAccept-Ranges: bytes\r\n",
+        status,
+        content_type(&path),
+        body_length
+    );
+    if request.range.is_some() {
+        head.push_str(&format!("Content-Range: bytes {}-{}/{}\r\n", start, end, length));
+    }
+    head.push_str("Connection: close\r\n\r\n");
+    stream.write_all(head.as_bytes()).await?;
+    if request.method == "GET" {
+        file.seek(std::io::SeekFrom::Start(start)).await?;
+        tokio::io::copy(&mut file.take(body_length), &mut stream).await?;
+    }
+    stream.shutdown().await?;
+    log::info!("{} {} {} {}", peer, request.method, request.path, &status[..3]);
+    Ok(())
+}
+
+// Serves a directory made by `texman mirror create` until interrupted. Every connection answers
+// one request, which is all texman and other tlnet clients need.
+pub async fn serve(dir: &Path, bind: &str, port: u16) -> anyhow::Result<()> {
+    if !dir.join("tlpkg").join("texlive.tlpdb").is_file() {
+        anyhow::bail!("{:?} is not a mirror; create one with 'texman mirror create {}'", dir, dir.display());
+    }
+    let root = std::fs::canonicalize(dir)?;
+    let listener = TcpListener::bind((bind, port))
+        .await
+        .map_err(|e| anyhow::anyhow!("Cannot listen on {}:{}: {}", bind, port, e))?;
+    let address = listener.local_addr()?;
+    println!("Serving {:?} on http://{}", root, address);
+    println!("Point clients at it with 'texman mirror set http://<this machine>:{}'. Press Ctrl-C to stop.", address.port());
+
+    loop {
+        let (stream, peer) = tokio::select! {
+            accepted = listener.accept() => accepted?,
+            _ = tokio::signal::ctrl_c() => break,
+        };
+        let root = root.clone();
+        tokio::spawn(async move {
+            if let Err(e) = handle(stream, peer, &root).await {
+                log::warn!("Request from {} failed: {}", peer, e);
+            }
+        });
+    }
+    println!("Stopped serving {:?}", root);
+    Ok(())
+}
@@ -0,0 +1,90 @@
+use crate::config::Config;
+use crate::mirror::KNOWN_MIRRORS;
+use crate::prompt::{ask, ask_yes_no, choose_index};
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+
+const STARTER_SCHEMES: &[(&str, &str)] = &[
+    ("None, I'll install packages myself", ""),
+    ("scheme-minimal: plain TeX only", "scheme-minimal"),
+    ("scheme-basic: plain TeX and LaTeX", "scheme-basic"),
+    ("scheme-small: basic plus common packages", "scheme-small"),
+];
+
+pub fn run(texman_dir: &Path) -> anyhow::Result<Option<String>> {
+    println!("Welcome to texman! A few questions to set up your TeX environment.");
+    let mut config = Config::load_file(texman_dir)?;
+
+    println!();
+    config.repository = choose_mirror(&config.repository)?;
+    config.docs = ask_yes_no("Install package documentation?", config.docs)?;
+    config.sources = ask_yes_no("Install package sources?", config.sources)?;
+    config.profile = ask("Default profile name", &config.profile)?;
+
+    println!();
+    println!("Starter scheme:");
+    for (i, (label, _)) in STARTER_SCHEMES.iter().enumerate() {
+        println!("  {}) {}", i + 1, label);
+    }
+    let scheme = STARTER_SCHEMES[choose_index(STARTER_SCHEMES.len(), 1)?].1;
+
+    crate::create_profile(&config.profile)?;
+    if !crate::active::is_set(texman_dir) {
+        crate::switch_profile(&config.profile)?;
+    }
+
+    if ask_yes_no("Add texman-managed binaries to your shell's PATH?", true)? {
+        configure_path(texman_dir)?;
+    }
+
+    config.save(texman_dir)?;
+    println!("Saved configuration to {:?}", Config::path(texman_dir));
+
+    Ok(if scheme.is_empty() { None } else { Some(scheme.to_string()) })
+}
+
+fn choose_mirror(current: &str) -> anyhow::Result<String> {
+    println!("Package mirror:");
+    for (i, (label, url)) in KNOWN_MIRRORS.iter().enumerate() {
+        println!("  {}) {} [{}]", i + 1, label, url);
+    }
+    println!("  {}) Custom URL", KNOWN_MIRRORS.len() + 1);
+    let default = KNOWN_MIRRORS.iter().position(|(_, url)| *url == current).unwrap_or(0);
+    let choice = choose_index(KNOWN_MIRRORS.len() + 1, default)?;
+    if choice < KNOWN_MIRRORS.len() {
+        Ok(KNOWN_MIRRORS[choice].1.to_string())
+    } else {
+        ask("Repository URL", current)
+    }
+}
+
+fn configure_path(texman_dir: &Path) -> anyhow::Result<()> {
+    // There is no shell profile to edit, and no `active` link to put on PATH.
+    if cfg!(windows) {
+        let bin_dir = crate::active::dir(texman_dir)?.join("bin").join("windows");
+        println!("Add {:?} to your PATH, e.g. with: setx PATH \"%PATH%;{}\"", bin_dir, bin_dir.display());
+        return Ok(());
+    }
+    let env_path = texman_dir.join("env.sh");
+    let script = format!(
+        "# Generated by texman setup\nfor dir in \"{}\"/active/bin/*/; do\n    [ -d \"$dir\" ] && PATH=\"${{dir%/}}:$PATH\"\ndone\nexport PATH\n",
+        texman_dir.display()
+    );
+    fs::write(&env_path, script)?;
+
+    let home = dirs::home_dir().ok_or_else(|| anyhow::anyhow!("Cannot find home directory"))?;
+    let shell = std::env::var("SHELL").unwrap_or_default();
+    let rc_path = home.join(if shell.ends_with("zsh") { ".zshrc" } else { ".bashrc" });
+    let source_line = format!(". \"{}\"", env_path.display());
+    let existing = fs::read_to_string(&rc_path).unwrap_or_default();
+    if existing.contains(&source_line) {
+        println!("{:?} already sources {:?}", rc_path, env_path);
+        return Ok(());
+    }
+
+    let mut rc = fs::OpenOptions::new().create(true).append(true).open(&rc_path)?;
+    writeln!(rc, "\n# texman\n{}", source_line)?;
+    println!("Added texman to PATH in {:?}; restart your shell to pick it up.", rc_path);
+    Ok(())
+}
@@ -0,0 +1,161 @@
+use clap::ValueEnum;
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+
+// `texman env` prints what a shell needs to run TeX from a profile, for
+// `eval "$(texman env)"` in a shell's startup file or a CI step. The variables are the ones
+// `texman direnv` writes, with the profile's path resolved now: the engines find the profile's
+// trees instead of their compiled-in defaults. TEXMFHOME is left alone, so the user's own tree
+// still comes first.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Shell {
+    Bash,
+    Zsh,
+    Fish,
+    Powershell,
+}
+
+impl Shell {
+    // From $SHELL, which Windows doesn't set.
+    fn detect() -> Shell {
+        let shell = std::env::var("SHELL").unwrap_or_default();
+        if shell.ends_with("fish") {
+            Shell::Fish
+        } else if shell.ends_with("zsh") {
+            Shell::Zsh
+        } else if shell.is_empty() && cfg!(windows) {
+            Shell::Powershell
+        } else {
+            Shell::Bash
+        }
+    }
+
+    fn quote(self, value: &str) -> String {
+        match self {
+            Shell::Bash | Shell::Zsh => format!("'{}'", value.replace('\'', "'\\''")),
+            Shell::Fish => format!("'{}'", value.replace('\\', "\\\\").replace('\'', "\\'")),
+            Shell::Powershell => format!("'{}'", value.replace('\'', "''")),
+        }
+    }
+
+    fn export(self, out: &mut String, name: &str, value: &str) -> std::fmt::Result {
+        let value = self.quote(value);
+        match self {
+            Shell::Bash | Shell::Zsh => writeln!(out, "export {}={}", name, value),
+            Shell::Fish => writeln!(out, "set -gx {} {}", name, value),
+            Shell::Powershell => writeln!(out, "$env:{} = {}", name, value),
+        }
+    }
+
+    // Prepends `dir` to PATH unless it is there already, so evaluating the output twice, e.g.
+    // in a nested shell, doesn't grow PATH.
+    fn prepend_path(self, out: &mut String, dir: &str) -> std::fmt::Result {
+        let dir = self.quote(dir);
+        match self {
+            Shell::Bash | Shell::Zsh => writeln!(out, "case \":$PATH:\" in *:{dir}:*) ;; *) export PATH={dir}\":$PATH\" ;; esac", dir = dir),
+            Shell::Fish => writeln!(out, "contains -- {dir} $PATH; or set -gx PATH {dir} $PATH", dir = dir),
+            Shell::Powershell => writeln!(
+                out,
+                "if (-not (($env:PATH -split [IO.Path]::PathSeparator) -contains {dir})) {{ $env:PATH = {dir} + [IO.Path]::PathSeparator + $env:PATH }}",
+                dir = dir
+            ),
+        }
+    }
+
+    fn unset(self, out: &mut String, name: &str) -> std::fmt::Result {
+        match self {
+            Shell::Bash | Shell::Zsh => writeln!(out, "unset {}", name),
+            Shell::Fish => writeln!(out, "set -e {}", name),
+            Shell::Powershell => writeln!(out, "Remove-Item Env:{} -ErrorAction SilentlyContinue", name),
+        }
+    }
+
+    // Takes the directory the last activation put on PATH off it again.
+    fn remove_active_bin(self, out: &mut String) -> std::fmt::Result {
+        match self {
+            Shell::Bash | Shell::Zsh => writeln!(
+                out,
+                "if [ -n \"${{{var}:-}}\" ]; then PATH=\":$PATH:\"; PATH=\"${{PATH//:\"${var}\":/:}}\"; PATH=\"${{PATH#:}}\"; export PATH=\"${{PATH%:}}\"; fi",
+                var = ACTIVE_BIN
+            ),
+            Shell::Fish => writeln!(out, "if set -q {var}; and set -l i (contains -i -- ${var} $PATH); set -e PATH[$i]; end", var = ACTIVE_BIN),
+            Shell::Powershell => writeln!(
+                out,
+                "if ($env:{var}) {{ $env:PATH = (($env:PATH -split [IO.Path]::PathSeparator) | Where-Object {{ $_ -ne $env:{var} }}) -join [IO.Path]::PathSeparator }}",
+                var = ACTIVE_BIN
+            ),
+        }
+    }
+}
+
+// Where `profile activate` records the directory it put on PATH, for the next activation or
+// `profile deactivate` to remove.
+const ACTIVE_BIN: &str = "TEXMAN_ACTIVE_BIN";
+
+// The profile's binaries, as TeX Live lays them out.
+pub fn bin_dir(profile_dir: &Path) -> Option<PathBuf> {
+    crate::platform::current().map(|platform| profile_dir.join("bin").join(platform))
+}
+
+const VARS: [&str; 7] = ["TEXMAN_PROFILE", "TEXMFROOT", "TEXMFDIST", "TEXMFVAR", "TEXMFCONFIG", "TEXMFSYSVAR", "TEXMFSYSCONFIG"];
+
+pub fn vars(profile: &str, profile_dir: &Path) -> Vec<(&'static str, String)> {
+    let dir = |rel_path: &str| profile_dir.join(rel_path).to_string_lossy().to_string();
+    let values = [
+        profile.to_string(),
+        profile_dir.to_string_lossy().to_string(),
+        dir("texmf-dist"),
+        dir("texmf-var"),
+        dir("texmf-config"),
+        dir("texmf-var"),
+        dir("texmf-config"),
+    ];
+    VARS.into_iter().zip(values).collect()
+}
+
+fn script(shell: Shell, profile: &str, profile_dir: &Path) -> anyhow::Result<String> {
+    let mut out = String::new();
+    if let Some(bin_dir) = bin_dir(profile_dir) {
+        shell.prepend_path(&mut out, &bin_dir.to_string_lossy())?;
+    }
+    for (name, value) in vars(profile, profile_dir) {
+        shell.export(&mut out, name, &value)?;
+    }
+    Ok(out)
+}
+
+fn deactivate_script(shell: Shell) -> anyhow::Result<String> {
+    let mut out = String::new();
+    shell.remove_active_bin(&mut out)?;
+    for name in VARS.into_iter().chain([ACTIVE_BIN]) {
+        shell.unset(&mut out, name)?;
+    }
+    Ok(out)
+}
+
+// Unlike `texman env`, activating first undoes an earlier activation, so switching profiles in
+// a shell swaps the trees instead of stacking their bin directories on PATH.
+fn activate_script(shell: Shell, profile: &str, profile_dir: &Path) -> anyhow::Result<String> {
+    let mut out = deactivate_script(shell)?;
+    out.push_str(&script(shell, profile, profile_dir)?);
+    if let Some(bin_dir) = bin_dir(profile_dir) {
+        shell.export(&mut out, ACTIVE_BIN, &bin_dir.to_string_lossy())?;
+    }
+    Ok(out)
+}
+
+pub fn activate(shell: Option<Shell>, profile: &str, profile_dir: &Path) -> anyhow::Result<()> {
+    print!("{}", activate_script(shell.unwrap_or_else(Shell::detect), profile, profile_dir)?);
+    Ok(())
+}
+
+pub fn deactivate(shell: Option<Shell>) -> anyhow::Result<()> {
+    print!("{}", deactivate_script(shell.unwrap_or_else(Shell::detect))?);
+    Ok(())
+}
+
+pub fn print(shell: Option<Shell>, profile: &str, profile_dir: &Path) -> anyhow::Result<()> {
+    print!("{}", script(shell.unwrap_or_else(Shell::detect), profile, profile_dir)?);
+    Ok(())
+}
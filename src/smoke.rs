@@ -0,0 +1,101 @@
+use crate::config::Config;
+use crate::Package;
+use std::io::Read;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+
+// Programs that answer `--version` without a format or input files, so running them shows that
+// the binary starts: its shared libraries load and it was built for this platform. Other
+// binaries of a package are only run when they are named after it.
+const KNOWN: &[&str] = &[
+    "tex", "etex", "pdftex", "xetex", "luatex", "luahbtex", "euptex", "kpsewhich", "bibtex", "bibtexu", "biber",
+    "dvipdfmx", "dvips", "mpost", "makeindex", "xindy", "dvisvgm",
+];
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+fn programs(pkg: &Package) -> Vec<&str> {
+    // Only the `<name>.<platform>` packages carry binaries.
+    let Some((base, _)) = pkg.name.rsplit_once('.') else { return Vec::new() };
+    pkg.binfiles
+        .iter()
+        .filter(|file| {
+            let program = Path::new(file).file_name().and_then(|name| name.to_str()).unwrap_or_default();
+            let program = program.strip_suffix(".exe").unwrap_or(program);
+            program == base || KNOWN.contains(&program)
+        })
+        .map(String::as_str)
+        .collect()
+}
+
+// Runs `program --version` the way the profile's users would, with its bin directory on PATH and
+// its trees in the TEXMF variables. Returns why it failed, if it did.
+fn run(profile_dir: &Path, program: &Path) -> Option<String> {
+    let inherited = std::env::var_os("PATH").unwrap_or_default();
+    let path = std::env::join_paths(program.parent().into_iter().map(Path::to_path_buf).chain(std::env::split_paths(&inherited)))
+        .unwrap_or(inherited);
+    let mut child = match Command::new(program)
+        .arg("--version")
+        .env("PATH", path)
+        .env("TEXMFROOT", profile_dir)
+        .env("TEXMFDIST", profile_dir.join("texmf-dist"))
+        .env("TEXMFVAR", profile_dir.join("texmf-var"))
+        .env("TEXMFCONFIG", profile_dir.join("texmf-config"))
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+    {
+        Ok(child) => child,
+        // A binary for another platform, or one whose interpreter or loader is missing (ENOENT).
+        Err(e) if crate::os::is_foreign_binary(&e) => return Some("not built for this platform".to_string()),
+        Err(e) => return Some(format!("cannot run it: {}", e)),
+    };
+    let started = Instant::now();
+    let status = loop {
+        match child.try_wait() {
+            Ok(Some(status)) => break status,
+            Ok(None) if started.elapsed() < TIMEOUT => std::thread::sleep(Duration::from_millis(20)),
+            Ok(None) => {
+                let _ = child.kill();
+                let _ = child.wait();
+                return Some(format!("no answer after {}s", TIMEOUT.as_secs()));
+            }
+            Err(e) => return Some(e.to_string()),
+        }
+    };
+    if status.success() {
+        return None;
+    }
+    let mut stderr = String::new();
+    if let Some(mut pipe) = child.stderr.take() {
+        let _ = pipe.read_to_string(&mut stderr);
+    }
+    // e.g. "error while loading shared libraries: libfreetype.so.6: cannot open shared object file"
+    match stderr.lines().map(str::trim).find(|line| !line.is_empty()) {
+        Some(line) => Some(format!("{} ({})", status, line)),
+        None => Some(status.to_string()),
+    }
+}
+
+// Smoke-tests the binaries `packages` just linked into the profile and warns about each that
+// doesn't start, so a broken binary shows up now instead of at the next compile.
+pub fn check(config: &Config, profile_dir: &Path, packages: &[Package]) {
+    if !config.smoke_tests || crate::platform::is_foreign() {
+        return;
+    }
+    for pkg in packages {
+        for file in programs(pkg) {
+            let program = profile_dir.join(file);
+            if !program.exists() {
+                continue;
+            }
+            log::info!("Smoke-testing {}", file);
+            if let Some(reason) = run(profile_dir, &program) {
+                let name = program.file_name().unwrap_or_default().to_string_lossy();
+                log::warn!("{} from {} r{} is broken: '{} --version' failed: {}", file, pkg.name, pkg.revision, name, reason);
+            }
+        }
+    }
+}
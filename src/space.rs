@@ -0,0 +1,76 @@
+use crate::config::Config;
+use crate::output::format_size;
+use crate::Package;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+// Headroom on top of the TLPDB estimates, which round file sizes and ignore filesystem overhead.
+const MARGIN_PERCENT: u64 = 10;
+
+struct Filesystem {
+    available: u64,
+    required: u64,
+    paths: Vec<PathBuf>,
+}
+
+// The path itself may not exist yet; its nearest existing ancestor is on the same filesystem.
+fn existing_ancestor(path: &Path) -> &Path {
+    path.ancestors().find(|p| p.exists()).unwrap_or(path)
+}
+
+pub fn download_size(pkg: &Package, config: &Config) -> u64 {
+    pkg.container_size.unwrap_or(0)
+        + if config.docs { pkg.doc_container_size.unwrap_or(0) } else { 0 }
+        + if config.sources { pkg.src_container_size.unwrap_or(0) } else { 0 }
+}
+
+pub fn unpacked_size(pkg: &Package, config: &Config) -> u64 {
+    pkg.run_size.unwrap_or(0)
+        + if config.docs { pkg.doc_size.unwrap_or(0) } else { 0 }
+        + if config.sources { pkg.src_size.unwrap_or(0) } else { 0 }
+}
+
+// Archives are downloaded to `download_dir`, unpacked into the store, and then removed, so both
+// must fit at once. Profiles using the copy strategy need another unpacked copy each.
+pub fn preflight(download_dir: &Path, store_dir: &Path, copy_dirs: &[PathBuf], to_download: &[Package], config: &Config) -> anyhow::Result<()> {
+    let download: u64 = to_download.iter().map(|pkg| download_size(pkg, config)).sum();
+    let unpacked: u64 = to_download.iter().map(|pkg| unpacked_size(pkg, config)).sum();
+    let mut needs = vec![(download_dir, download), (store_dir, unpacked)];
+    needs.extend(copy_dirs.iter().map(|dir| (dir.as_path(), unpacked)));
+
+    let mut filesystems: HashMap<u64, Filesystem> = HashMap::new();
+    for (path, bytes) in needs {
+        if bytes == 0 {
+            continue;
+        }
+        let Some((id, available)) = crate::os::free_space(existing_ancestor(path))? else {
+            log::info!("Cannot check free space for {:?} on this platform", path);
+            continue;
+        };
+        let filesystem = filesystems.entry(id).or_insert_with(|| Filesystem {
+            available,
+            required: 0,
+            paths: Vec::new(),
+        });
+        filesystem.required += bytes + bytes * MARGIN_PERCENT / 100;
+        filesystem.paths.push(path.to_path_buf());
+    }
+
+    for filesystem in filesystems.values() {
+        log::info!(
+            "Disk space for {:?}: {} needed, {} available",
+            filesystem.paths,
+            format_size(filesystem.required),
+            format_size(filesystem.available)
+        );
+        if filesystem.required > filesystem.available {
+            anyhow::bail!(
+                "Not enough disk space for {:?}: about {} needed but only {} available. Free up space or set a shared_cache on another disk.",
+                filesystem.paths,
+                format_size(filesystem.required),
+                format_size(filesystem.available)
+            );
+        }
+    }
+    Ok(())
+}
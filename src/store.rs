@@ -0,0 +1,310 @@
+use crate::output::format_size;
+use rusqlite::params;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+
+const COMPRESSION_LEVEL: i32 = 19;
+
+// Identical files are kept once, whichever revisions ship them: every file of an unpacked
+// revision is a hardlink to `store/objects/<hash prefix>/<sha256>-<mode>`. A new revision then
+// takes space only for the files that changed, and packages shipping the same file share it.
+// An object whose only link is its own is unused, and `texman clean` removes it.
+pub const OBJECTS: &str = "objects";
+
+fn object_path(objects_dir: &Path, hash: &str, mode: u32) -> PathBuf {
+    objects_dir.join(&hash[..2]).join(format!("{}-{:o}", &hash[2..], mode))
+}
+
+// Links the files below `dir`, a revision being added to the store, to their objects, and
+// returns the bytes that are now stored once instead of twice.
+pub fn dedup(dir: &Path) -> anyhow::Result<u64> {
+    let Some(objects_dir) = dir.parent().map(|store_dir| store_dir.join(OBJECTS)) else { return Ok(0) };
+    // Without link counts unused objects can't be told apart, so there every revision keeps its own files.
+    if crate::os::link_count(&fs::metadata(dir)?).is_none() {
+        return Ok(0);
+    }
+    let mut saved = 0;
+    for rel_path in crate::tree::walk_files(dir)? {
+        let path = dir.join(&rel_path);
+        let metadata = fs::symlink_metadata(&path)?;
+        if !metadata.is_file() {
+            continue;
+        }
+        let object = object_path(&objects_dir, &crate::lowmem::sha256_file(&path)?, crate::os::mode(&metadata));
+        if let Some(parent) = object.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        match fs::hard_link(&path, &object) {
+            Ok(()) => continue,
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {}
+            Err(e) => {
+                log::info!("Not deduplicating {:?}: cannot hardlink into {:?} ({})", dir, objects_dir, e);
+                return Ok(saved);
+            }
+        }
+        let object_metadata = fs::metadata(&object)?;
+        if crate::os::file_id(&object_metadata) == crate::os::file_id(&metadata) {
+            continue;
+        }
+        // Linked aside and renamed over the file, so the revision never lacks it.
+        let mut linking = path.as_os_str().to_os_string();
+        linking.push(".texman-dedup");
+        let linking = PathBuf::from(linking);
+        fs::hard_link(&object, &linking)?;
+        fs::rename(&linking, &path)?;
+        saved += metadata.len();
+    }
+    Ok(saved)
+}
+
+// Removes objects no revision links to any more, e.g. after `clean` or `profile remove --purge`
+// deleted the revisions. Returns how many there were and their size.
+pub fn prune_objects(store_dir: &Path) -> anyhow::Result<(usize, u64)> {
+    let objects_dir = store_dir.join(OBJECTS);
+    if !objects_dir.is_dir() {
+        return Ok((0, 0));
+    }
+    let mut removed = 0;
+    let mut size = 0;
+    for rel_path in crate::tree::walk_files(&objects_dir)? {
+        let path = objects_dir.join(rel_path);
+        let metadata = fs::metadata(&path)?;
+        if crate::os::link_count(&metadata) == Some(1) {
+            fs::remove_file(&path)?;
+            removed += 1;
+            size += metadata.len();
+        }
+    }
+    Ok((removed, size))
+}
+
+// Revisions are unpacked or expanded into `<revision>.unpacking` or `<revision>.expanding` and
+// renamed into place when complete.
+fn is_staging(file_name: &str) -> bool {
+    file_name.ends_with(".unpacking") || file_name.ends_with(".expanding")
+}
+
+// Removes the staging directories an interrupted unpack or expansion left behind. Returns how
+// many there were.
+pub fn remove_staging(store_dir: &Path) -> anyhow::Result<usize> {
+    if !store_dir.is_dir() {
+        return Ok(0);
+    }
+    let mut removed = 0;
+    for entry in fs::read_dir(store_dir)? {
+        let entry = entry?;
+        if entry.file_type()?.is_dir() && is_staging(&entry.file_name().to_string_lossy()) {
+            fs::remove_dir_all(entry.path())?;
+            removed += 1;
+        }
+    }
+    Ok(removed)
+}
+
+// Removes the revisions of each package beyond the `keep` newest that nothing uses; their
+// objects go with the next `prune_objects`. Revisions installed in a profile, recorded in a backup, or needed
+// to finish or roll back an interrupted transaction always stay. Returns how many revisions
+// were removed.
+pub fn prune_revisions(conn: &rusqlite::Connection, store_dir: &Path, keep: usize) -> anyhow::Result<usize> {
+    if !store_dir.is_dir() {
+        return Ok(0);
+    }
+    let mut in_use = HashSet::new();
+    for query in [
+        "SELECT name, revision FROM installed_packages",
+        "SELECT name, revision FROM backups",
+        "SELECT p.name, p.revision FROM journal_packages p JOIN journal j ON j.id = p.journal_id WHERE j.state = 'pending'",
+        "SELECT p.name, p.previous FROM journal_packages p JOIN journal j ON j.id = p.journal_id WHERE j.state = 'pending' AND p.previous IS NOT NULL",
+    ] {
+        let mut stmt = conn.prepare(query)?;
+        for row in stmt.query_map([], |row| Ok(format!("{}-r{}", row.get::<_, String>(0)?, row.get::<_, String>(1)?)))? {
+            in_use.insert(row?);
+        }
+    }
+
+    // `<name>-r<revision>` directories and their compressed `.tar.zst` form, by package and
+    // revision. Objects and staging directories don't end in a revision number.
+    let mut unused: HashMap<String, BTreeMap<u32, Vec<PathBuf>>> = HashMap::new();
+    for entry in fs::read_dir(store_dir)? {
+        let entry = entry?;
+        let file_name = entry.file_name().to_string_lossy().to_string();
+        let revision_name = file_name.strip_suffix(".tar.zst").unwrap_or(&file_name);
+        let Some((name, revision)) = revision_name.rsplit_once("-r") else { continue };
+        let Ok(number) = revision.parse::<u32>() else { continue };
+        if !in_use.contains(revision_name) {
+            unused.entry(name.to_string()).or_default().entry(number).or_default().push(entry.path());
+        }
+    }
+
+    let mut removed = 0;
+    for (name, revisions) in unused {
+        for (revision, paths) in revisions.into_iter().rev().skip(keep) {
+            for path in paths {
+                if path.is_dir() {
+                    fs::remove_dir_all(&path)?;
+                } else {
+                    fs::remove_file(&path)?;
+                }
+            }
+            log::info!("Removed {} r{} from the store", name, revision);
+            removed += 1;
+        }
+    }
+    Ok(removed)
+}
+
+pub fn compressed_path(store_path: &Path) -> PathBuf {
+    let mut path = store_path.as_os_str().to_os_string();
+    path.push(".tar.zst");
+    PathBuf::from(path)
+}
+
+// Returns whether the revision is available unpacked, re-expanding it first if it was compressed.
+pub fn ensure_expanded(store_path: &Path) -> anyhow::Result<bool> {
+    if store_path.exists() {
+        return Ok(true);
+    }
+    let archive = compressed_path(store_path);
+    if !archive.exists() {
+        return Ok(false);
+    }
+
+    log::info!("Expanding {:?}", archive);
+    let mut staging = store_path.as_os_str().to_os_string();
+    staging.push(".expanding");
+    let staging = PathBuf::from(staging);
+    if staging.exists() {
+        fs::remove_dir_all(&staging)?;
+    }
+    let decoder = zstd::Decoder::new(File::open(&archive)?)?;
+    tar::Archive::new(decoder)
+        .unpack(&staging)
+        .map_err(|e| anyhow::anyhow!("Failed to expand {:?}: {}", archive, e))?;
+    dedup(&staging)?;
+    fs::rename(&staging, store_path)?;
+    fs::remove_file(&archive)?;
+    Ok(true)
+}
+
+fn compress(store_path: &Path) -> anyhow::Result<u64> {
+    let archive = compressed_path(store_path);
+    let mut staging = archive.as_os_str().to_os_string();
+    staging.push(".part");
+    let staging = PathBuf::from(staging);
+
+    let encoder = zstd::Encoder::new(File::create(&staging)?, COMPRESSION_LEVEL)?;
+    let mut builder = tar::Builder::new(encoder);
+    builder.follow_symlinks(false);
+    builder.append_dir_all(".", store_path)?;
+    builder.into_inner()?.finish()?.sync_all()?;
+
+    fs::rename(&staging, &archive)?;
+    fs::remove_dir_all(store_path)?;
+    Ok(fs::metadata(&archive)?.len())
+}
+
+// Files hardlinked to the same object count once.
+pub fn dir_size(dir: &Path) -> anyhow::Result<u64> {
+    let mut size = 0;
+    let mut seen = HashSet::new();
+    for rel_path in crate::tree::walk_files(dir)? {
+        let metadata = fs::symlink_metadata(dir.join(rel_path))?;
+        if metadata.is_file()
+            && let Some(id) = crate::os::file_id(&metadata)
+            && !seen.insert(id)
+        {
+            continue;
+        }
+        size += metadata.len();
+    }
+    Ok(size)
+}
+
+// Compresses every unpacked revision the active profile doesn't use: revisions kept only for
+// inactive profiles, backups, or rollback. They are expanded again on demand.
+pub fn compress_unused() -> anyhow::Result<()> {
+    let texman_dir = dirs::home_dir()
+        .ok_or_else(|| anyhow::anyhow!("Cannot find home directory"))?
+        .join(".texman");
+    let store_dir = texman_dir.join("store");
+
+    let conn = crate::init_db(&texman_dir)?;
+    let mut in_use = HashSet::new();
+    if let Some(active_profile) = crate::active::get(&texman_dir)? {
+        let mut stmt = conn.prepare("SELECT name, revision FROM installed_packages WHERE profile = ?1")?;
+        let rows = stmt.query_map(params![active_profile], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+        })?;
+        for row in rows {
+            let (name, revision) = row?;
+            in_use.insert(format!("{}-r{}", name, revision));
+        }
+    }
+
+    let mut compressed = 0;
+    let mut before = 0;
+    let mut after = 0;
+    if store_dir.exists() {
+        for entry in fs::read_dir(&store_dir)? {
+            let entry = entry?;
+            let file_name = entry.file_name().to_string_lossy().to_string();
+            if !entry.file_type()?.is_dir() || in_use.contains(&file_name) || file_name == OBJECTS || is_staging(&file_name) {
+                continue;
+            }
+            let size = dir_size(&entry.path())?;
+            let compressed_size = compress(&entry.path())?;
+            log::info!("Compressed {} ({} -> {})", file_name, format_size(size), format_size(compressed_size));
+            compressed += 1;
+            before += size;
+            after += compressed_size;
+        }
+    }
+
+    if compressed == 0 {
+        println!("No unused store revisions to compress.");
+    } else {
+        println!(
+            "Compressed {} store revisions from {} to {}",
+            compressed, format_size(before), format_size(after)
+        );
+    }
+    Ok(())
+}
+
+// `texman store dedup`: links the revisions unpacked before the object store existed, or by a
+// texman that couldn't hardlink, to their objects.
+pub fn dedup_all() -> anyhow::Result<()> {
+    let texman_dir = dirs::home_dir()
+        .ok_or_else(|| anyhow::anyhow!("Cannot find home directory"))?
+        .join(".texman");
+    let store_dir = texman_dir.join("store");
+    let mut revisions = 0;
+    let mut saved = 0;
+    if store_dir.exists() {
+        for entry in fs::read_dir(&store_dir)? {
+            let entry = entry?;
+            let file_name = entry.file_name().to_string_lossy().to_string();
+            if !entry.file_type()?.is_dir() || file_name == OBJECTS || is_staging(&file_name) {
+                continue;
+            }
+            saved += dedup(&entry.path())?;
+            revisions += 1;
+        }
+    }
+    println!("Deduplicated {} store revisions, freeing {}", revisions, format_size(saved));
+    Ok(())
+}
+
+pub fn expand_profile(conn: &rusqlite::Connection, texman_dir: &Path, profile: &str) -> anyhow::Result<()> {
+    let mut stmt = conn.prepare("SELECT name, revision FROM installed_packages WHERE profile = ?1")?;
+    let rows = stmt
+        .query_map(params![profile], |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)))?
+        .collect::<Result<Vec<_>, _>>()?;
+    for (name, revision) in rows {
+        if !ensure_expanded(&crate::store_path(texman_dir, &name, &revision))? {
+            log::warn!("{} r{} used by profile '{}' is missing from the store", name, revision, profile);
+        }
+    }
+    Ok(())
+}
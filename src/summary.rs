@@ -0,0 +1,171 @@
+use crate::config::Config;
+use crate::Package;
+use crate::output::format_size;
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use rusqlite::params;
+use std::collections::HashMap;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Instant;
+
+static DOWNLOADED_BYTES: AtomicU64 = AtomicU64::new(0);
+static WARNINGS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+// Wraps env_logger so warnings reach the summary even when RUST_LOG hides them.
+struct SummaryLogger {
+    inner: env_logger::Logger,
+}
+
+impl Log for SummaryLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= Level::Warn || self.inner.enabled(metadata)
+    }
+
+    fn log(&self, record: &Record) {
+        if record.level() == Level::Warn && record.target().starts_with("texman") {
+            WARNINGS.lock().unwrap().push(record.args().to_string());
+        }
+        if self.inner.enabled(record.metadata()) {
+            crate::ui::suspend(|| self.inner.log(record));
+        }
+    }
+
+    fn flush(&self) {
+        self.inner.flush();
+    }
+}
+
+pub fn init_logger() {
+    let inner = env_logger::Builder::from_default_env().build();
+    let level = inner.filter().max(LevelFilter::Warn);
+    log::set_boxed_logger(Box::new(SummaryLogger { inner })).expect("logger already initialized");
+    log::set_max_level(level);
+}
+
+pub fn record_download(bytes: u64) {
+    DOWNLOADED_BYTES.fetch_add(bytes, Ordering::Relaxed);
+}
+
+pub struct Transaction {
+    action: &'static str,
+    profile: String,
+    texman_dir: PathBuf,
+    started: Instant,
+    installed_before: HashMap<String, String>,
+    disk_before: u64,
+}
+
+impl Transaction {
+    pub fn begin(action: &'static str, profile: &str, texman_dir: &Path) -> anyhow::Result<Transaction> {
+        DOWNLOADED_BYTES.store(0, Ordering::Relaxed);
+        WARNINGS.lock().unwrap().clear();
+        Ok(Transaction {
+            action,
+            profile: profile.to_string(),
+            texman_dir: texman_dir.to_path_buf(),
+            started: Instant::now(),
+            installed_before: installed_packages(texman_dir, profile)?,
+            disk_before: disk_usage(texman_dir, profile),
+        })
+    }
+
+    pub fn finish(self, config: &Config, tlpdb: &HashMap<String, Package>) -> anyhow::Result<()> {
+        let elapsed = self.started.elapsed().as_secs_f64();
+        let downloaded = DOWNLOADED_BYTES.load(Ordering::Relaxed);
+        let warnings = std::mem::take(&mut *WARNINGS.lock().unwrap());
+        let installed_after = installed_packages(&self.texman_dir, &self.profile)?;
+        let disk_delta = disk_usage(&self.texman_dir, &self.profile) as i64 - self.disk_before as i64;
+
+        let mut changes = Vec::new();
+        for (name, revision) in &installed_after {
+            match self.installed_before.get(name) {
+                None => changes.push(format!("+{} r{}", name, revision)),
+                Some(old) if old != revision => changes.push(format!("~{} r{} -> r{}", name, old, revision)),
+                Some(_) => {}
+            }
+        }
+        for (name, revision) in &self.installed_before {
+            if !installed_after.contains_key(name) {
+                changes.push(format!("-{} r{}", name, revision));
+            }
+        }
+        changes.sort_by(|a, b| a[1..].cmp(&b[1..]));
+
+        let speed = if elapsed > 0.0 { (downloaded as f64 / elapsed) as u64 } else { 0 };
+        let delta = if disk_delta < 0 {
+            format!("-{}", format_size(disk_delta.unsigned_abs()))
+        } else {
+            format!("+{}", format_size(disk_delta as u64))
+        };
+
+        println!();
+        println!("Summary ({} in profile '{}'):", self.action, self.profile);
+        if changes.is_empty() {
+            println!("  Packages:   no changes");
+        } else {
+            println!("  Packages:   {} changed: {}", changes.len(), changes.join(", "));
+        }
+        println!("  Downloaded: {} ({}/s)", format_size(downloaded), format_size(speed));
+        println!("  Time:       {:.1}s", elapsed);
+        println!("  Disk:       {}", delta);
+        if !warnings.is_empty() {
+            println!("  Warnings:   {}", warnings.len());
+            for warning in &warnings {
+                println!("    - {}", warning);
+            }
+        }
+
+        let conn = crate::init_db(&self.texman_dir)?;
+        let (tlpdb_sha256, tlpdb_revision) = crate::attest::tlpdb_identity(tlpdb);
+        crate::attest::append(&conn, &crate::attest::Record {
+            action: self.action.to_string(),
+            profile: self.profile.clone(),
+            created_at: chrono::Utc::now().to_rfc3339(),
+            repository: config.repository.clone(),
+            tlpdb_sha256,
+            tlpdb_revision,
+            changes: changes.clone(),
+            packages: crate::attest::packages(&conn, &installed_after)?,
+        })?;
+
+        if config.history_log {
+            let mut log = fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(self.texman_dir.join("history.log"))?;
+            writeln!(
+                log,
+                "{}\t{}\t{}\t{}\tdownloaded={}\tseconds={:.1}\tdisk_delta={}\twarnings={}",
+                chrono::Utc::now().to_rfc3339(),
+                self.action,
+                self.profile,
+                if changes.is_empty() { "-".to_string() } else { changes.join(",") },
+                downloaded,
+                elapsed,
+                disk_delta,
+                warnings.len()
+            )?;
+        }
+        Ok(())
+    }
+}
+
+fn installed_packages(texman_dir: &Path, profile: &str) -> anyhow::Result<HashMap<String, String>> {
+    let conn = crate::init_db(texman_dir)?;
+    let mut stmt = conn.prepare("SELECT name, revision FROM installed_packages WHERE profile = ?1")?;
+    let rows = stmt
+        .query_map(params![profile], |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)))?
+        .collect::<Result<HashMap<_, _>, _>>()?;
+    Ok(rows)
+}
+
+fn disk_usage(texman_dir: &Path, profile: &str) -> u64 {
+    [texman_dir.join("store"), texman_dir.join("profiles").join(profile)]
+        .iter()
+        .filter(|dir| dir.exists())
+        .map(|dir| crate::store::dir_size(dir).unwrap_or(0))
+        .sum()
+}
@@ -0,0 +1,118 @@
+use rusqlite::{params, Connection};
+use std::collections::HashMap;
+use std::path::{Component, Path, PathBuf};
+
+// kpathsea searches TEXMFHOME before the profile's texmf-dist, so a file left in the user tree
+// wins over the one texman installed, which is a common answer to "why is my old version used".
+pub struct Shadow {
+    pub user_file: PathBuf,
+    pub path: String,
+    pub package: String,
+}
+
+// $TEXMFHOME when set, else ~/texmf; None when there is no such directory.
+pub fn user_tree() -> Option<PathBuf> {
+    let home = dirs::home_dir()?;
+    let tree = match std::env::var("TEXMFHOME") {
+        Ok(value) if value == "~" => home.clone(),
+        Ok(value) if !value.is_empty() => match value.strip_prefix("~/") {
+            Some(rest) => home.join(rest),
+            None => PathBuf::from(value),
+        },
+        _ => home.join("texmf"),
+    };
+    tree.is_dir().then_some(tree)
+}
+
+// Files are looked up by name within a top-level TDS directory (tex, fonts, bibtex, ...), so a
+// copy anywhere below the same one shadows the installed file. Documentation and sources are
+// never searched.
+fn key(rel_path: &Path) -> Option<(String, String)> {
+    let Some(Component::Normal(top)) = rel_path.components().next() else { return None };
+    let top = top.to_str()?;
+    let name = rel_path.file_name()?.to_str()?;
+    if matches!(top, "doc" | "source") || name == "ls-R" || rel_path.parent() == Some(Path::new("")) {
+        return None;
+    }
+    Some((top.to_string(), name.to_string()))
+}
+
+pub fn shadows(conn: &Connection, profile: &str, tree: &Path) -> anyhow::Result<Vec<Shadow>> {
+    let mut stmt = conn.prepare("SELECT path, package FROM profile_files WHERE profile = ?1")?;
+    let mut installed: HashMap<(String, String), Vec<(String, String)>> = HashMap::new();
+    for row in stmt.query_map(params![profile], |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)))? {
+        let (path, package) = row?;
+        if let Some(rel_path) = path.strip_prefix("texmf-dist/")
+            && let Some(key) = key(Path::new(rel_path))
+        {
+            installed.entry(key).or_default().push((path, package));
+        }
+    }
+
+    let mut shadows = Vec::new();
+    for rel_path in crate::tree::walk_files(tree)? {
+        if let Some(key) = key(&rel_path)
+            && let Some(files) = installed.get(&key)
+        {
+            for (path, package) in files {
+                shadows.push(Shadow { user_file: tree.join(&rel_path), path: path.clone(), package: package.clone() });
+            }
+        }
+    }
+    shadows.sort_by(|a, b| (&a.package, &a.path, &a.user_file).cmp(&(&b.package, &b.path, &b.user_file)));
+    Ok(shadows)
+}
+
+// `texman shadow-check`: lists the files of `profile` (the active one by default) that the user
+// tree shadows, grouped by package.
+pub fn check(profile: Option<&str>) -> anyhow::Result<()> {
+    let texman_dir = dirs::home_dir()
+        .ok_or_else(|| anyhow::anyhow!("Cannot find home directory"))?
+        .join(".texman");
+    let profile = match profile {
+        Some(profile) => profile.to_string(),
+        None => crate::active::name(&texman_dir)?,
+    };
+    if !texman_dir.join("profiles").join(&profile).exists() {
+        anyhow::bail!("Profile '{}' does not exist", profile);
+    }
+    let Some(tree) = user_tree() else {
+        println!("No TEXMFHOME user tree found (set TEXMFHOME or create ~/texmf); nothing can shadow profile '{}'.", profile);
+        return Ok(());
+    };
+
+    let conn = crate::init_db(&texman_dir)?;
+    let shadows = shadows(&conn, &profile, &tree)?;
+    if shadows.is_empty() {
+        println!("No files in {:?} shadow packages installed in profile '{}'.", tree, profile);
+        return Ok(());
+    }
+    println!("{} files in {:?} shadow files installed in profile '{}'; TeX uses the user tree's copies:", shadows.len(), tree, profile);
+    let mut current = None;
+    for shadow in &shadows {
+        if current != Some(&shadow.package) {
+            println!("  {}:", shadow.package);
+            current = Some(&shadow.package);
+        }
+        println!("    {} shadows {}", shadow.user_file.display(), shadow.path);
+    }
+    println!("Remove or rename the user tree's copies to use the installed versions.");
+    Ok(())
+}
+
+// A one-line hint after install and update, as a shadowed file makes the change look like it
+// didn't happen.
+pub fn note(texman_dir: &Path, profile: &str) -> anyhow::Result<()> {
+    let Some(tree) = user_tree() else { return Ok(()) };
+    let shadows = shadows(&crate::init_db(texman_dir)?, profile, &tree)?;
+    if !shadows.is_empty() {
+        println!(
+            "Note: {} files in {:?} shadow files installed in profile '{}'; see 'texman shadow-check --profile {}'",
+            shadows.len(),
+            tree,
+            profile,
+            profile
+        );
+    }
+    Ok(())
+}
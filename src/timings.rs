@@ -0,0 +1,97 @@
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+// With `--timings`, the time spent in each phase is added up while the command runs and reported
+// on stderr when it ends. Downloads and unpacks of several jobs overlap, so their totals count
+// every job and can exceed the wall-clock time.
+#[derive(Clone, Copy)]
+pub enum Phase {
+    Tlpdb,
+    Resolve,
+    Download,
+    Unpack,
+    Link,
+    Database,
+}
+
+const PHASES: [Phase; 6] = [Phase::Tlpdb, Phase::Resolve, Phase::Download, Phase::Unpack, Phase::Link, Phase::Database];
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static NANOS: [AtomicU64; 6] = [const { AtomicU64::new(0) }; 6];
+static COUNTS: [AtomicU64; 6] = [const { AtomicU64::new(0) }; 6];
+
+impl Phase {
+    fn label(self) -> &'static str {
+        match self {
+            Phase::Tlpdb => "TLPDB parse and cache",
+            Phase::Resolve => "Dependency resolution",
+            Phase::Download => "Downloads",
+            Phase::Unpack => "Unpacking",
+            Phase::Link => "Linking into profiles",
+            Phase::Database => "Database writes",
+        }
+    }
+
+    fn unit(self) -> &'static str {
+        match self {
+            Phase::Tlpdb => "load",
+            Phase::Resolve => "resolution",
+            Phase::Download => "request",
+            Phase::Unpack | Phase::Link | Phase::Database => "package",
+        }
+    }
+}
+
+// Adds the time until it is dropped to `phase`; does nothing without `--timings`.
+pub struct Timer {
+    phase: Phase,
+    started: Option<Instant>,
+}
+
+impl Drop for Timer {
+    fn drop(&mut self) {
+        if let Some(started) = self.started {
+            let index = self.phase as usize;
+            NANOS[index].fetch_add(started.elapsed().as_nanos() as u64, Ordering::Relaxed);
+            COUNTS[index].fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+pub fn start(phase: Phase) -> Timer {
+    Timer { phase, started: ENABLED.load(Ordering::Relaxed).then(Instant::now) }
+}
+
+pub fn time<T>(phase: Phase, f: impl FnOnce() -> T) -> T {
+    let _timer = start(phase);
+    f()
+}
+
+// Prints the report when dropped, so a command that fails still shows where its time went.
+pub struct Report {
+    started: Instant,
+}
+
+impl Report {
+    pub fn start() -> Report {
+        ENABLED.store(true, Ordering::Relaxed);
+        Report { started: Instant::now() }
+    }
+}
+
+impl Drop for Report {
+    fn drop(&mut self) {
+        eprintln!();
+        eprintln!("Timings ({:.2}s in total):", self.started.elapsed().as_secs_f64());
+        for phase in PHASES {
+            let count = COUNTS[phase as usize].load(Ordering::Relaxed);
+            let spent = Duration::from_nanos(NANOS[phase as usize].load(Ordering::Relaxed));
+            if count == 0 {
+                eprintln!("  {:<22} {:>9}", phase.label(), "-");
+            } else {
+                let unit = if count == 1 { phase.unit().to_string() } else { format!("{}s", phase.unit()) };
+                eprintln!("  {:<22} {:>8.2}s  ({} {})", phase.label(), spent.as_secs_f64(), count, unit);
+            }
+        }
+    }
+}
@@ -0,0 +1,419 @@
+use crate::{repo, timings, Package};
+use rayon::prelude::*;
+use std::collections::HashMap;
+use std::io::{Read, Write};
+
+// Written ahead of the packages in tlpdb.bin. bincode data doesn't describe itself, so a cache
+// written before `Package` changed would be misread; bump the low bits with every change.
+const CACHE_FORMAT: u64 = 0x746d_6462_0000_0003;
+
+// The file lists of a block: a `runfiles`, `binfiles`, `docfiles` or `srcfiles` line, followed by
+// one line per file that starts with a space.
+#[derive(Clone, Copy)]
+enum FileList {
+    Run,
+    Bin,
+    Doc,
+    Src,
+}
+
+// `size=` counts 4 KiB units.
+fn files_size(fields: &str) -> u64 {
+    fields
+        .split_whitespace()
+        .find_map(|field| field.strip_prefix("size="))
+        .and_then(|size| size.parse::<u64>().ok())
+        .unwrap_or(0)
+        * 4096
+}
+
+// Doc files carry `details="..."` and `language="..."` after the path.
+fn file_path(line: &str) -> &str {
+    let line = line.trim();
+    [" details=\"", " language=\""]
+        .iter()
+        .filter_map(|attribute| line.find(attribute))
+        .min()
+        .map_or(line, |end| line[..end].trim_end())
+}
+
+fn empty_package() -> Package {
+    Package {
+        name: String::new(),
+        category: None,
+        revision: "unknown".to_string(),
+        depends: Vec::new(),
+        arch_depends: Vec::new(),
+        runfiles: Vec::new(),
+        binfiles: Vec::new(),
+        docfiles: Vec::new(),
+        srcfiles: Vec::new(),
+        description: None,
+        longdesc: None,
+        relocated: false,
+        container_size: None,
+        doc_container_size: None,
+        src_container_size: None,
+        container_checksum: None,
+        doc_container_checksum: None,
+        src_container_checksum: None,
+        executes: Vec::new(),
+        postactions: Vec::new(),
+        run_size: None,
+        doc_size: None,
+        src_size: None,
+        catalogue: None,
+        catalogue_version: None,
+        catalogue_date: None,
+        catalogue_ctan: None,
+        catalogue_license: None,
+        catalogue_topics: Vec::new(),
+        repository: None,
+    }
+}
+
+// Parses one package block in TeX Live's tlpobj format: a `key value` line per field, repeated
+// for `depend`, `execute`, `postaction` and `longdesc`, plus the file lists. Keys this parser
+// doesn't know are skipped, so TLPDBs from newer TeX Live releases still load.
+pub fn parse_package_block(block: &str) -> Option<Package> {
+    let mut pkg = empty_package();
+    let mut list = None;
+    let mut longdesc_lines = Vec::new();
+
+    for line in block.lines() {
+        if let Some(file) = line.strip_prefix(' ') {
+            let files = match list {
+                Some(FileList::Run) => &mut pkg.runfiles,
+                Some(FileList::Bin) => &mut pkg.binfiles,
+                Some(FileList::Doc) => &mut pkg.docfiles,
+                Some(FileList::Src) => &mut pkg.srcfiles,
+                None => continue,
+            };
+            let path = file_path(file);
+            if !path.is_empty() {
+                files.push(path.to_string());
+            }
+            continue;
+        }
+
+        list = None;
+        let line = line.trim_end();
+        let (key, value) = line.split_once(' ').unwrap_or((line, ""));
+        let value = value.trim();
+        match key {
+            "name" => pkg.name = value.to_string(),
+            "category" => pkg.category = Some(value.to_string()),
+            "revision" => pkg.revision = value.to_string(),
+            "shortdesc" => pkg.description = Some(value.to_string()),
+            "longdesc" => longdesc_lines.push(value.to_string()),
+            "relocated" => pkg.relocated = value == "1",
+            // Dependencies on `<name>.ARCH` stand for the package's binaries on each platform.
+            "depend" => match value.strip_suffix(".ARCH") {
+                Some(base) => pkg.arch_depends.push(base.to_string()),
+                None if !value.is_empty() => pkg.depends.push(value.to_string()),
+                None => {}
+            },
+            // Comma-separated, as hand-written TLPDBs sometimes have it.
+            "depends" => pkg.depends.extend(value.split(',').map(str::trim).filter(|dep| !dep.is_empty()).map(str::to_string)),
+            "execute" => pkg.executes.push(value.to_string()),
+            "postaction" => pkg.postactions.push(value.to_string()),
+            "runfiles" => {
+                list = Some(FileList::Run);
+                pkg.run_size = Some(pkg.run_size.unwrap_or(0) + files_size(value));
+            }
+            // A package can have a binfiles list per platform.
+            "binfiles" => {
+                list = Some(FileList::Bin);
+                pkg.run_size = Some(pkg.run_size.unwrap_or(0) + files_size(value));
+            }
+            "docfiles" => {
+                list = Some(FileList::Doc);
+                pkg.doc_size = Some(files_size(value));
+            }
+            "srcfiles" => {
+                list = Some(FileList::Src);
+                pkg.src_size = Some(files_size(value));
+            }
+            "containersize" => pkg.container_size = value.parse().ok(),
+            "doccontainersize" => pkg.doc_container_size = value.parse().ok(),
+            "srccontainersize" => pkg.src_container_size = value.parse().ok(),
+            "containerchecksum" => pkg.container_checksum = Some(value.to_string()),
+            "doccontainerchecksum" => pkg.doc_container_checksum = Some(value.to_string()),
+            "srccontainerchecksum" => pkg.src_container_checksum = Some(value.to_string()),
+            "catalogue" => pkg.catalogue = Some(value.to_string()),
+            "catalogue-version" => pkg.catalogue_version = Some(value.to_string()),
+            "catalogue-date" => pkg.catalogue_date = Some(value.to_string()),
+            "catalogue-ctan" => pkg.catalogue_ctan = Some(value.to_string()),
+            "catalogue-license" => pkg.catalogue_license = Some(value.to_string()),
+            "catalogue-topics" => pkg.catalogue_topics = value.split_whitespace().map(str::to_string).collect(),
+            _ => {}
+        }
+    }
+
+    if !longdesc_lines.is_empty() {
+        pkg.longdesc = Some(longdesc_lines.join("\n"));
+    }
+    // RELOC/ stands for the tree relocatable packages install into, which is texmf-dist.
+    if pkg.relocated {
+        for file in pkg.runfiles.iter_mut().chain(&mut pkg.docfiles).chain(&mut pkg.srcfiles) {
+            if let Some(rest) = file.strip_prefix("RELOC/") {
+                *file = format!("texmf-dist/{}", rest);
+            }
+        }
+    }
+
+    if pkg.name.is_empty() { None } else { Some(pkg) }
+}
+
+// The value of a block's `name` line, which comes first.
+pub fn block_name(block: &str) -> Option<&str> {
+    block.lines().next()?.trim_end().strip_prefix("name ")
+}
+
+// Blocks are separated by blank lines; lines holding only whitespace or a CR count as blank.
+pub fn blocks(text: &str) -> Vec<&str> {
+    let mut blocks = Vec::new();
+    let mut start = None;
+    let mut offset = 0;
+    for line in text.split_inclusive('\n') {
+        if line.trim().is_empty() {
+            if let Some(start) = start.take() {
+                blocks.push(&text[start..offset]);
+            }
+        } else if start.is_none() {
+            start = Some(offset);
+        }
+        offset += line.len();
+    }
+    if let Some(start) = start {
+        blocks.push(&text[start..]);
+    }
+    blocks
+}
+
+pub fn parse_tlpdb(tlpdb_text: &str) -> anyhow::Result<(HashMap<String, Package>, repo::RepositoryInfo)> {
+    let _timer = timings::start(timings::Phase::Tlpdb);
+    let (special, blocks): (Vec<&str>, Vec<&str>) = blocks(tlpdb_text)
+        .into_iter()
+        .partition(|block| block_name(block).is_some_and(|name| name.starts_with("00texlive.")));
+    let repository = special
+        .iter()
+        .find(|block| block_name(block) == Some("00texlive.config"))
+        .map(|block| repo::parse_config_block(block))
+        .unwrap_or_default();
+    let packages: Vec<Package> = blocks.par_iter().filter_map(|block| parse_package_block(block)).collect();
+
+    let mut tlpdb = HashMap::with_capacity(packages.len());
+    for pkg in packages {
+        tlpdb.insert(pkg.name.clone(), pkg);
+    }
+
+    log::info!("Parsed {} packages from TLPDB", tlpdb.len());
+    Ok((tlpdb, repository))
+}
+
+pub fn write_cache(mut writer: impl Write, tlpdb: &HashMap<String, Package>) -> anyhow::Result<()> {
+    bincode::serialize_into(&mut writer, &CACHE_FORMAT).map_err(|e| anyhow::anyhow!("Failed to serialize TLPDB: {}", e))?;
+    bincode::serialize_into(&mut writer, tlpdb).map_err(|e| anyhow::anyhow!("Failed to serialize TLPDB: {}", e))?;
+    Ok(())
+}
+
+// None for a cache written by a texman with a different `Package`.
+pub fn read_cache(mut reader: impl Read) -> anyhow::Result<Option<HashMap<String, Package>>> {
+    let format: u64 = bincode::deserialize_from(&mut reader).map_err(|e| anyhow::anyhow!("Failed to deserialize TLPDB: {}", e))?;
+    if format != CACHE_FORMAT {
+        return Ok(None);
+    }
+    let tlpdb = bincode::deserialize_from(reader).map_err(|e| anyhow::anyhow!("Failed to deserialize TLPDB: {}", e))?;
+    Ok(Some(tlpdb))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Excerpts in the shape of tlnet's texlive.tlpdb, checksums shortened.
+    const CONFIG: &str = "\
+name 00texlive.config
+category Config
+depend container_format/xz
+depend minrelease/2016
+depend release/2025
+depend revision/75000
+";
+
+    const KPATHSEA: &str = "\
+name kpathsea
+category TLCore
+revision 73496
+shortdesc Path searching library for TeX-related files
+longdesc Kpathsea is a library and utility programs which provide path
+longdesc searching facilities for TeX file types.
+depend kpathsea.ARCH
+execute addMap dummy.map
+postaction shortcut type=menu name=\"kpsewhich\" cmd=TEXDIR/bin/kpsewhich
+containersize 43112
+containerchecksum 5d0e1c4a
+doccontainersize 1287516
+doccontainerchecksum 8c2f07aa
+docfiles size=367
+ texmf-dist/doc/info/kpathsea.info
+ texmf-dist/doc/kpathsea/kpathsea.pdf details=\"Manual\" language=\"en\"
+runfiles size=33
+ texmf-dist/web2c/texmf.cnf
+ texmf-dist/web2c/fmtutil.cnf
+catalogue-contact-home https://tug.org/kpathsea
+catalogue-license lgpl2.1
+catalogue-topics tex-syst lib
+";
+
+    const KPATHSEA_BIN: &str = "\
+name kpathsea.x86_64-linux
+category TLCore
+revision 73477
+shortdesc x86_64-linux files of kpathsea
+containersize 53148
+containerchecksum 9ab3f1e2
+binfiles arch=x86_64-linux size=34
+ bin/x86_64-linux/kpseaccess
+ bin/x86_64-linux/kpsewhich
+";
+
+    const AMSMATH: &str = "\
+name amsmath
+category Package
+revision 72779
+shortdesc AMS mathematical facilities for LaTeX
+relocated 1
+depend tools
+containersize 39840
+containerchecksum 0e3f4c9b
+srccontainersize 145256
+srccontainerchecksum 61bb0c1f
+srcfiles size=172
+ RELOC/source/latex/amsmath/amsmath.dtx
+ RELOC/source/latex/amsmath/amsmath.ins
+runfiles size=55
+ RELOC/tex/latex/amsmath/amsmath.sty
+catalogue-ctan /macros/latex/required/amsmath
+catalogue-date 2024-11-05 19:37:54 +0100
+catalogue-license lppl1.3c
+catalogue-version 2.17t
+";
+
+    #[test]
+    fn parses_fields_and_file_lists() {
+        let pkg = parse_package_block(KPATHSEA).unwrap();
+        assert_eq!(pkg.name, "kpathsea");
+        assert_eq!(pkg.category.as_deref(), Some("TLCore"));
+        assert_eq!(pkg.revision, "73496");
+        assert_eq!(pkg.description.as_deref(), Some("Path searching library for TeX-related files"));
+        assert_eq!(
+            pkg.longdesc.as_deref(),
+            Some("Kpathsea is a library and utility programs which provide path\nsearching facilities for TeX file types.")
+        );
+        assert_eq!(pkg.executes, ["addMap dummy.map"]);
+        assert_eq!(pkg.postactions, ["shortcut type=menu name=\"kpsewhich\" cmd=TEXDIR/bin/kpsewhich"]);
+        assert_eq!(pkg.container_size, Some(43112));
+        assert_eq!(pkg.container_checksum.as_deref(), Some("5d0e1c4a"));
+        assert_eq!(pkg.doc_container_size, Some(1287516));
+        assert_eq!(pkg.doc_container_checksum.as_deref(), Some("8c2f07aa"));
+        assert_eq!(pkg.runfiles, ["texmf-dist/web2c/texmf.cnf", "texmf-dist/web2c/fmtutil.cnf"]);
+        assert_eq!(pkg.run_size, Some(33 * 4096));
+        assert_eq!(pkg.doc_size, Some(367 * 4096));
+        assert_eq!(pkg.catalogue_license.as_deref(), Some("lgpl2.1"));
+        assert_eq!(pkg.catalogue_topics, ["tex-syst", "lib"]);
+        assert!(!pkg.relocated);
+    }
+
+    #[test]
+    fn strips_doc_file_attributes() {
+        let pkg = parse_package_block(KPATHSEA).unwrap();
+        assert_eq!(pkg.docfiles, ["texmf-dist/doc/info/kpathsea.info", "texmf-dist/doc/kpathsea/kpathsea.pdf"]);
+    }
+
+    #[test]
+    fn separates_arch_dependencies() {
+        let kpathsea = parse_package_block(KPATHSEA).unwrap();
+        assert!(kpathsea.depends.is_empty());
+        assert_eq!(kpathsea.arch_depends, ["kpathsea"]);
+        let amsmath = parse_package_block(AMSMATH).unwrap();
+        assert_eq!(amsmath.depends, ["tools"]);
+        assert!(amsmath.arch_depends.is_empty());
+    }
+
+    #[test]
+    fn parses_platform_binfiles() {
+        let pkg = parse_package_block(KPATHSEA_BIN).unwrap();
+        assert_eq!(pkg.binfiles, ["bin/x86_64-linux/kpseaccess", "bin/x86_64-linux/kpsewhich"]);
+        assert_eq!(pkg.run_size, Some(34 * 4096));
+        assert!(pkg.runfiles.is_empty());
+    }
+
+    #[test]
+    fn parses_relocated_sources_and_catalogue() {
+        let pkg = parse_package_block(AMSMATH).unwrap();
+        assert!(pkg.relocated);
+        assert_eq!(pkg.srcfiles, ["texmf-dist/source/latex/amsmath/amsmath.dtx", "texmf-dist/source/latex/amsmath/amsmath.ins"]);
+        assert_eq!(pkg.src_size, Some(172 * 4096));
+        assert_eq!(pkg.src_container_size, Some(145256));
+        assert_eq!(pkg.src_container_checksum.as_deref(), Some("61bb0c1f"));
+        assert_eq!(pkg.runfiles, ["texmf-dist/tex/latex/amsmath/amsmath.sty"]);
+        assert_eq!(pkg.catalogue_ctan.as_deref(), Some("/macros/latex/required/amsmath"));
+        assert_eq!(pkg.catalogue_date.as_deref(), Some("2024-11-05 19:37:54 +0100"));
+        assert_eq!(pkg.catalogue_version.as_deref(), Some("2.17t"));
+    }
+
+    #[test]
+    fn accepts_comma_separated_depends() {
+        let pkg = parse_package_block("name scheme-test\nrevision 10\ndepends alpha, beta,\n").unwrap();
+        assert_eq!(pkg.depends, ["alpha", "beta"]);
+    }
+
+    #[test]
+    fn skips_unknown_keys_and_nameless_blocks() {
+        let pkg = parse_package_block("name future\nrevision 1\nnewkey some value\n somefile\nrevision 2\n").unwrap();
+        assert_eq!(pkg.revision, "2");
+        assert!(pkg.runfiles.is_empty());
+        assert!(parse_package_block("category Package\nrevision 1\n").is_none());
+    }
+
+    #[test]
+    fn parses_a_whole_tlpdb() {
+        let text = [CONFIG, KPATHSEA, KPATHSEA_BIN, AMSMATH].join("\n");
+        let (tlpdb, repository) = parse_tlpdb(&text).unwrap();
+        assert_eq!(tlpdb.len(), 3);
+        assert!(tlpdb.contains_key("kpathsea.x86_64-linux"));
+        assert!(!tlpdb.contains_key("00texlive.config"));
+        assert_eq!(repository.release, Some(2025));
+        assert_eq!(repository.minrelease, Some(2016));
+        assert_eq!(repository.container_format.as_deref(), Some("xz"));
+    }
+
+    #[test]
+    fn handles_crlf_and_whitespace_separators() {
+        let text = [CONFIG, KPATHSEA, AMSMATH].join(" \n").replace('\n', "\r\n");
+        let (tlpdb, repository) = parse_tlpdb(&text).unwrap();
+        assert_eq!(tlpdb.len(), 2);
+        assert_eq!(tlpdb["amsmath"].runfiles, ["texmf-dist/tex/latex/amsmath/amsmath.sty"]);
+        assert_eq!(tlpdb["kpathsea"].catalogue_topics, ["tex-syst", "lib"]);
+        assert_eq!(repository.release, Some(2025));
+    }
+
+    #[test]
+    fn round_trips_the_cache() {
+        let (tlpdb, _) = parse_tlpdb(&[KPATHSEA, AMSMATH].join("\n")).unwrap();
+        let mut bin = Vec::new();
+        write_cache(&mut bin, &tlpdb).unwrap();
+        let cached = read_cache(bin.as_slice()).unwrap().unwrap();
+        assert_eq!(cached.len(), 2);
+        assert_eq!(cached["amsmath"].srcfiles, tlpdb["amsmath"].srcfiles);
+    }
+
+    #[test]
+    fn rejects_a_cache_in_an_older_format() {
+        let (tlpdb, _) = parse_tlpdb(AMSMATH).unwrap();
+        let bin = bincode::serialize(&tlpdb).unwrap();
+        assert!(read_cache(bin.as_slice()).unwrap().is_none());
+    }
+}
@@ -0,0 +1,132 @@
+use crate::config::Config;
+use crate::Package;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+// What texman takes from a `texlive.profile` written by install-tl: the scheme, the collections
+// ticked on top of or instead of it, and whether docs and sources are installed. Paths
+// (TEXDIR etc.), binaries, and instopt_* settings have no texman counterpart.
+struct InstallerProfile {
+    scheme: Option<String>,
+    collections: Vec<String>,
+    docs: Option<bool>,
+    sources: Option<bool>,
+}
+
+fn parse_flag(key: &str, value: &str) -> anyhow::Result<bool> {
+    match value {
+        "1" => Ok(true),
+        "0" => Ok(false),
+        _ => anyhow::bail!("Invalid value '{}' for {} (expected 0 or 1)", value, key),
+    }
+}
+
+fn parse(text: &str) -> anyhow::Result<InstallerProfile> {
+    let mut profile = InstallerProfile { scheme: None, collections: Vec::new(), docs: None, sources: None };
+    let mut ignored = Vec::new();
+    for line in text.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (key, value) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        let value = value.trim();
+        match key {
+            "selected_scheme" => profile.scheme = Some(value.to_string()),
+            // `option_doc` and `option_src` are what installers before 2017 wrote.
+            "tlpdbopt_install_docfiles" | "option_doc" => profile.docs = Some(parse_flag(key, value)?),
+            "tlpdbopt_install_srcfiles" | "option_src" => profile.sources = Some(parse_flag(key, value)?),
+            _ if key.starts_with("collection-") => {
+                if parse_flag(key, value)? {
+                    profile.collections.push(key.to_string());
+                }
+            }
+            _ => ignored.push(key),
+        }
+    }
+    if !ignored.is_empty() {
+        log::debug!("Ignoring installer settings without a texman equivalent: {}", ignored.join(", "));
+    }
+    Ok(profile)
+}
+
+// Like install-tl: the selected collections, plus whatever the scheme pulls in besides
+// collections. A collection the profile leaves unticked stays out even if the scheme lists it.
+fn packages(profile: &InstallerProfile, tlpdb: &HashMap<String, Package>) -> anyhow::Result<Vec<String>> {
+    let mut packages = Vec::new();
+    if let Some(scheme) = profile.scheme.as_deref().filter(|scheme| *scheme != "scheme-custom") {
+        let pkg = tlpdb.get(scheme).ok_or_else(|| anyhow::anyhow!("Scheme '{}' not found in TLPDB", scheme))?;
+        if profile.collections.is_empty() {
+            // A hand-written profile that only names the scheme.
+            packages.push(pkg.name.clone());
+        } else {
+            packages.extend(pkg.depends.iter().filter(|dep| !dep.starts_with("collection-")).cloned());
+        }
+    }
+    for collection in &profile.collections {
+        if tlpdb.contains_key(collection) {
+            packages.push(collection.clone());
+        } else {
+            log::warn!("Skipping {}: not in the TLPDB of this repository", collection);
+        }
+    }
+    if packages.is_empty() {
+        anyhow::bail!("The installer profile selects no scheme or collections");
+    }
+    Ok(packages)
+}
+
+pub async fn import(
+    path: &Path,
+    name: Option<String>,
+    tlpdb: &HashMap<String, Package>,
+    config: &Config,
+    allow_scripts: bool,
+) -> anyhow::Result<()> {
+    let texman_dir = dirs::home_dir()
+        .ok_or_else(|| anyhow::anyhow!("Cannot find home directory"))?
+        .join(".texman");
+    let text = fs::read_to_string(path).map_err(|e| anyhow::anyhow!("Cannot read {:?}: {}", path, e))?;
+    let profile = parse(&text).map_err(|e| anyhow::anyhow!("Invalid installer profile {:?}: {}", path, e))?;
+    let name = match name {
+        Some(name) => name,
+        None => path
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .ok_or_else(|| anyhow::anyhow!("Cannot derive a profile name from {:?}; pass --name", path))?
+            .to_string(),
+    };
+    if texman_dir.join("profiles").join(&name).exists() {
+        anyhow::bail!("Profile '{}' already exists; pass --name to import under another name", name);
+    }
+    let packages = packages(&profile, tlpdb)?;
+
+    let mut config = config.clone();
+    config.docs = profile.docs.unwrap_or(config.docs);
+    config.sources = profile.sources.unwrap_or(config.sources);
+
+    crate::create_profile(&name)?;
+    let conn = crate::init_db(&texman_dir)?;
+    crate::set_profile_setting(&conn, &name, "imported_from", &fs::canonicalize(path)?.to_string_lossy())?;
+    if let Some(scheme) = &profile.scheme {
+        crate::set_profile_setting(&conn, &name, "scheme", scheme)?;
+    }
+    println!(
+        "Importing {:?} as profile '{}': {} (docs {}, sources {})",
+        path,
+        name,
+        packages.join(", "),
+        if config.docs { "on" } else { "off" },
+        if config.sources { "on" } else { "off" }
+    );
+
+    let profiles = [name.clone()];
+    let transaction = crate::summary::Transaction::begin("install", &name, &texman_dir)?;
+    let failed = crate::install_package(&packages, &profiles, tlpdb, &config, None, None, allow_scripts).await?;
+    transaction.finish(&config, tlpdb)?;
+    let names: Vec<&str> = failed.iter().map(|(name, _)| name.as_str()).collect();
+    crate::report_failures(&failed, &format!("texman install {} --profile {}", names.join(" "), name))?;
+    println!("Run 'texman profile switch {}' to use it.", name);
+    Ok(())
+}
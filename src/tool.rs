@@ -0,0 +1,47 @@
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output, Stdio};
+
+// TeX Live's maintenance scripts (fmtutil, updmap) run against a profile: its own trees stand in
+// for the system ones, and its bin directory comes first on PATH, so they find the profile's
+// engines and configuration rather than a system TeX Live's.
+
+// Runs the first of `programs` that is installed, the profile's own copy before one on PATH,
+// with its extra arguments and then `args`. None when none of them is.
+pub fn run(profile_dir: &Path, programs: &[(&str, &[&str])], args: &[String]) -> anyhow::Result<Option<Output>> {
+    let inherited = std::env::var_os("PATH").unwrap_or_default();
+    let path = std::env::join_paths(crate::shellenv::bin_dir(profile_dir).into_iter().chain(std::env::split_paths(&inherited))).unwrap_or(inherited);
+    for (program, extra) in programs {
+        let local = crate::shellenv::bin_dir(profile_dir)
+            .map(|dir| dir.join(program).with_extension(std::env::consts::EXE_EXTENSION))
+            .filter(|program| program.exists());
+        let result = Command::new(local.unwrap_or_else(|| PathBuf::from(program)))
+            .args(*extra)
+            .args(args)
+            .env("PATH", &path)
+            .env("TEXMFROOT", profile_dir)
+            .env("TEXMFDIST", profile_dir.join("texmf-dist"))
+            .env("TEXMFSYSVAR", profile_dir.join("texmf-var"))
+            .env("TEXMFSYSCONFIG", profile_dir.join("texmf-config"))
+            .env("TEXMFVAR", profile_dir.join("texmf-var"))
+            .env("TEXMFCONFIG", profile_dir.join("texmf-config"))
+            .stdin(Stdio::null())
+            .output();
+        match result {
+            Ok(output) => return Ok(Some(output)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+            Err(e) => anyhow::bail!("Cannot run {}: {}", program, e),
+        }
+    }
+    Ok(None)
+}
+
+// Why a run failed: its exit status and the last line it printed to stderr, where fmtutil and
+// updmap put their summary.
+pub fn failure(output: &Output) -> String {
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    match stderr.lines().rev().map(str::trim).find(|line| !line.is_empty()) {
+        Some(line) => format!("{}: {}", output.status, line),
+        None => output.status.to_string(),
+    }
+}
@@ -0,0 +1,56 @@
+use crate::Package;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write;
+
+// CTAN files every package under topics such as `maths-font` or `bibtex-sty`; the TLPDB carries
+// them as `catalogue-topics`. Browsing them finds packages whose names say nothing about what
+// they do.
+
+pub fn has_topic(pkg: &Package, topic: &str) -> bool {
+    pkg.catalogue_topics.iter().any(|t| t.eq_ignore_ascii_case(topic))
+}
+
+fn counts(tlpdb: &HashMap<String, Package>) -> BTreeMap<&str, usize> {
+    let mut counts = BTreeMap::new();
+    for pkg in tlpdb.values() {
+        for topic in &pkg.catalogue_topics {
+            *counts.entry(topic.as_str()).or_insert(0) += 1;
+        }
+    }
+    counts
+}
+
+// `search --topic` with a topic no package has: the topics it is part of, if any, so a
+// near miss such as `font` leads on to `maths-font`.
+pub fn check(topic: &str, tlpdb: &HashMap<String, Package>) -> anyhow::Result<()> {
+    let counts = counts(tlpdb);
+    if counts.keys().any(|t| t.eq_ignore_ascii_case(topic)) {
+        return Ok(());
+    }
+    let needle = topic.to_lowercase();
+    let similar: Vec<&str> = counts.keys().filter(|t| t.to_lowercase().contains(&needle)).copied().collect();
+    if similar.is_empty() {
+        anyhow::bail!("No package has the topic '{}'; 'texman topics' lists them", topic);
+    }
+    anyhow::bail!("No package has the topic '{}'; did you mean {}?", topic, similar.join(", "))
+}
+
+// `texman topics`: every topic with the number of packages under it, by name or, with
+// `by_count`, most packages first.
+pub fn list(tlpdb: &HashMap<String, Package>, by_count: bool, no_pager: bool) -> anyhow::Result<()> {
+    let mut counts: Vec<(&str, usize)> = counts(tlpdb).into_iter().collect();
+    if counts.is_empty() {
+        println!("The TLPDB has no catalogue topics.");
+        return Ok(());
+    }
+    if by_count {
+        counts.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+    }
+    let width = counts.iter().map(|(topic, _)| topic.len()).max().unwrap_or(0);
+    let mut out = String::new();
+    for (topic, count) in &counts {
+        writeln!(out, "  {:<width$}  {}", topic, count, width = width)?;
+    }
+    writeln!(out, "{} topics; browse one with 'texman search --topic <topic>'.", counts.len())?;
+    crate::output::page(&out, no_pager)
+}
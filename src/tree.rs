@@ -0,0 +1,305 @@
+use crate::config::{ConflictPolicy, InstallStrategy};
+use crate::Package;
+use rusqlite::{params, Connection, OptionalExtension};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use unicode_normalization::UnicodeNormalization;
+
+pub fn walk_files(root: &Path) -> anyhow::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    let mut pending = vec![PathBuf::new()];
+    while let Some(rel_dir) = pending.pop() {
+        for entry in fs::read_dir(root.join(&rel_dir))? {
+            let entry = entry?;
+            let rel_path = rel_dir.join(entry.file_name());
+            if entry.file_type()?.is_dir() {
+                pending.push(rel_path);
+            } else {
+                files.push(rel_path);
+            }
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+// Paths are recorded in NFC so that manifests written by TeX Live match file names
+// read back from filesystems that store them decomposed (NFD), such as HFS+.
+pub fn path_key(rel_path: &Path) -> anyhow::Result<String> {
+    let path = rel_path.to_str().ok_or_else(|| anyhow::anyhow!("Non UTF-8 path in package: {:?}", rel_path))?;
+    // Keys use '/' like the TLPDB, also where walking a tree yields '\' (Windows).
+    if cfg!(windows) {
+        return Ok(path.replace('\\', "/").nfc().collect());
+    }
+    Ok(path.nfc().collect())
+}
+
+pub fn fold_key(path: &str, case_insensitive: bool) -> String {
+    let path: String = path.nfc().collect();
+    if case_insensitive { path.to_lowercase() } else { path }
+}
+
+pub fn is_case_insensitive(dir: &Path) -> bool {
+    let probe = dir.join(".texman-case-probe");
+    if fs::write(&probe, b"").is_err() {
+        return false;
+    }
+    let insensitive = dir.join(".TEXMAN-CASE-PROBE").exists();
+    let _ = fs::remove_file(&probe);
+    insensitive
+}
+
+fn owner_of(conn: &Connection, profile: &str, rel_path: &str, case_insensitive: bool) -> anyhow::Result<Option<String>> {
+    let sql = if case_insensitive {
+        "SELECT package FROM profile_files WHERE profile = ?1 AND path = ?2 COLLATE NOCASE"
+    } else {
+        "SELECT package FROM profile_files WHERE profile = ?1 AND path = ?2"
+    };
+    Ok(conn.query_row(sql, params![profile, rel_path], |row| row.get(0)).optional()?)
+}
+
+// The profile's tree is a TDS tree of links into the store, which kpathsea only searches
+// through what is generated from it: updmap.cfg and fmtutil.cnf from the packages' execute
+// lines, and the ls-R databases. Brings those up to date after packages were linked or unlinked.
+pub fn refresh(conn: &Connection, profile: &str, profile_dir: &Path, tlpdb: &HashMap<String, Package>) -> anyhow::Result<()> {
+    crate::execute::regenerate(conn, profile, profile_dir, tlpdb)?;
+    crate::lsr::regenerate(profile_dir)
+}
+
+pub fn link_package(
+    conn: &Connection,
+    profile: &str,
+    profile_dir: &Path,
+    name: &str,
+    store_path: &Path,
+    policy: ConflictPolicy,
+    strategy: InstallStrategy,
+) -> anyhow::Result<()> {
+    let _timer = crate::timings::start(crate::timings::Phase::Link);
+    let tx = conn.unchecked_transaction()?;
+    let mut undo = Undo::default();
+    // On an error the transaction is dropped, so the database is rolled back along with the files.
+    let result = (|| -> anyhow::Result<()> {
+        // The previous revision's files are only deleted once the new ones are all in place.
+        let mut stmt = tx.prepare("SELECT path FROM profile_files WHERE profile = ?1 AND package = ?2")?;
+        let old_paths = stmt
+            .query_map(params![profile, name], |row| row.get::<_, String>(0))?
+            .collect::<Result<Vec<_>, _>>()?;
+        for path in &old_paths {
+            let dest = profile_dir.join(path);
+            if dest.symlink_metadata().is_ok() {
+                undo.set_aside(&dest)?;
+            }
+        }
+        tx.execute("DELETE FROM profile_files WHERE profile = ?1 AND package = ?2", params![profile, name])?;
+
+        let files = walk_files(store_path)?;
+        let case_insensitive = is_case_insensitive(profile_dir);
+        if case_insensitive {
+            let mut seen: HashMap<String, &PathBuf> = HashMap::new();
+            for rel_path in &files {
+                if let Some(other) = seen.insert(fold_key(&path_key(rel_path)?, true), rel_path) {
+                    log::warn!(
+                        "{} ships {} and {}, which differ only in case; only one of them can exist on this case-insensitive filesystem",
+                        name, other.display(), rel_path.display()
+                    );
+                }
+            }
+        }
+
+        let mut conflicts = Vec::new();
+        for rel_path in &files {
+            if profile_dir.join(rel_path).symlink_metadata().is_ok() {
+                let owner = owner_of(&tx, profile, &path_key(rel_path)?, case_insensitive)?;
+                conflicts.push((rel_path.clone(), owner.unwrap_or_else(|| "an unmanaged file".to_string())));
+            }
+        }
+
+        if policy == ConflictPolicy::Error && !conflicts.is_empty() {
+            for (rel_path, owner) in conflicts.iter().take(20) {
+                log::error!("File conflict: {} from {} is already provided by {}", rel_path.display(), name, owner);
+            }
+            if conflicts.len() > 20 {
+                log::error!("...and {} more conflicting files", conflicts.len() - 20);
+            }
+            anyhow::bail!(
+                "{} files of {} conflict with existing files in profile '{}'; use --on-conflict skip|overwrite|backup to resolve them",
+                conflicts.len(), name, profile
+            );
+        }
+
+        let conflicting: HashSet<&PathBuf> = conflicts.iter().map(|(p, _)| p).collect();
+        for (rel_path, owner) in &conflicts {
+            let dest = profile_dir.join(rel_path);
+            match policy {
+                ConflictPolicy::Skip => {
+                    log::warn!("Skipped {} from {}: kept the version from {}", rel_path.display(), name, owner);
+                }
+                ConflictPolicy::Overwrite => {
+                    undo.set_aside(&dest)?;
+                    if case_insensitive {
+                        tx.execute(
+                            "DELETE FROM profile_files WHERE profile = ?1 AND path = ?2 COLLATE NOCASE",
+                            params![profile, path_key(rel_path)?],
+                        )?;
+                    }
+                    log::warn!("Overwrote {} from {} with the version from {}", rel_path.display(), owner, name);
+                }
+                ConflictPolicy::Backup => {
+                    let mut backup_name = dest.file_name().unwrap_or_default().to_os_string();
+                    backup_name.push(".texman-backup");
+                    let backup_path = dest.with_file_name(backup_name);
+                    fs::rename(&dest, &backup_path)?;
+                    undo.moved.push((dest.clone(), backup_path.clone(), true));
+                    log::warn!("Moved {} from {} to {:?} before installing the version from {}", rel_path.display(), owner, backup_path, name);
+                }
+                ConflictPolicy::Error => unreachable!(),
+            }
+        }
+
+        for rel_path in &files {
+            if policy == ConflictPolicy::Skip && conflicting.contains(rel_path) {
+                continue;
+            }
+            let dest = profile_dir.join(rel_path);
+            if let Some(parent) = dest.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            place_file(&store_path.join(rel_path), &dest, strategy)?;
+            undo.created.push(dest);
+            tx.execute(
+                "INSERT OR REPLACE INTO profile_files (profile, path, package) VALUES (?1, ?2, ?3)",
+                params![profile, path_key(rel_path)?, name],
+            )?;
+        }
+
+        Ok(())
+    })();
+
+    match result {
+        Ok(()) => {
+            tx.commit()?;
+            undo.finish(profile_dir);
+            Ok(())
+        }
+        Err(e) => {
+            undo.rollback(profile_dir);
+            Err(e)
+        }
+    }
+}
+
+// What linking a package has changed in the profile so far: the links it created, and files it
+// moved out of the way, with whether they stay moved (backups) or go once linking succeeded.
+// Undone when linking fails halfway, so the profile is left as it was instead of with part of
+// a package that the database doesn't know about.
+#[derive(Default)]
+struct Undo {
+    created: Vec<PathBuf>,
+    moved: Vec<(PathBuf, PathBuf, bool)>,
+}
+
+impl Undo {
+    fn set_aside(&mut self, path: &Path) -> anyhow::Result<()> {
+        let mut aside = path.as_os_str().to_os_string();
+        aside.push(".texman-old");
+        let aside = PathBuf::from(aside);
+        if aside.symlink_metadata().is_ok() {
+            fs::remove_file(&aside)?;
+        }
+        fs::rename(path, &aside)?;
+        self.moved.push((path.to_path_buf(), aside, false));
+        Ok(())
+    }
+
+    fn finish(self, profile_dir: &Path) {
+        for (_, aside, keep) in self.moved {
+            if !keep && fs::remove_file(&aside).is_ok() {
+                prune_empty_dirs(aside.parent(), profile_dir);
+            }
+        }
+    }
+
+    fn rollback(self, profile_dir: &Path) {
+        for path in self.created.iter().rev() {
+            if fs::remove_file(path).is_ok() {
+                prune_empty_dirs(path.parent(), profile_dir);
+            }
+        }
+        for (path, aside, _) in self.moved.iter().rev() {
+            if let Err(e) = fs::rename(aside, path) {
+                log::warn!("Cannot move {:?} back to {:?}: {}", aside, path, e);
+            }
+        }
+    }
+}
+
+// Hardlinks share the inode with the store, so a tool editing a file in place also
+// changes it for every other profile; copies avoid that at the cost of disk space.
+fn place_file(source: &Path, dest: &Path, strategy: InstallStrategy) -> anyhow::Result<()> {
+    // Links a package ships, e.g. TeX Live's bin/<platform>/latexmk -> ../../texmf-dist/scripts/latexmk/latexmk.pl,
+    // point into the tree the package goes into, so they are recreated as they are. Linked to
+    // the store instead, they would resolve inside the binary package's store directory, where
+    // the script isn't.
+    if source.symlink_metadata()?.file_type().is_symlink() {
+        crate::os::symlink(&fs::read_link(source)?, dest)?;
+        return Ok(());
+    }
+    match strategy {
+        InstallStrategy::Symlink => crate::os::symlink(&relative_to(source, dest.parent().unwrap_or(dest)), dest)?,
+        InstallStrategy::Hardlink => {
+            if let Err(e) = fs::hard_link(source, dest) {
+                log::warn!("Cannot hardlink {:?} ({}); copying it instead", dest, e);
+                fs::copy(source, dest)?;
+            }
+        }
+        InstallStrategy::Copy => {
+            fs::copy(source, dest)?;
+        }
+    }
+    Ok(())
+}
+
+// Links are relative so a tree keeps working when ~/.texman is staged under a DESTDIR
+// or moved as a whole.
+fn relative_to(target: &Path, from_dir: &Path) -> PathBuf {
+    let target: Vec<_> = target.components().collect();
+    let from: Vec<_> = from_dir.components().collect();
+    let common = target.iter().zip(&from).take_while(|(a, b)| a == b).count();
+    let mut relative = PathBuf::new();
+    for _ in common..from.len() {
+        relative.push("..");
+    }
+    relative.extend(&target[common..]);
+    relative
+}
+
+pub fn unlink_package(conn: &Connection, profile: &str, profile_dir: &Path, name: &str) -> anyhow::Result<()> {
+    let mut stmt = conn.prepare("SELECT path FROM profile_files WHERE profile = ?1 AND package = ?2")?;
+    let paths = stmt
+        .query_map(params![profile, name], |row| row.get::<_, String>(0))?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    for path in &paths {
+        let dest = profile_dir.join(path);
+        if dest.symlink_metadata().is_ok() {
+            fs::remove_file(&dest)?;
+            prune_empty_dirs(dest.parent(), profile_dir);
+        }
+    }
+    conn.execute(
+        "DELETE FROM profile_files WHERE profile = ?1 AND package = ?2",
+        params![profile, name],
+    )?;
+    Ok(())
+}
+
+fn prune_empty_dirs(mut dir: Option<&Path>, profile_dir: &Path) {
+    while let Some(current) = dir {
+        if current == profile_dir || !current.starts_with(profile_dir) || fs::remove_dir(current).is_err() {
+            break;
+        }
+        dir = current.parent();
+    }
+}
@@ -0,0 +1,69 @@
+use indicatif::{MultiProgress, ProgressBar, ProgressDrawTarget, ProgressStyle};
+use std::io::IsTerminal;
+use std::sync::OnceLock;
+use std::time::Duration;
+
+// Every bar and spinner texman draws goes through one MultiProgress: parallel downloads stack
+// instead of overwriting each other's line, and log lines and prompts are printed above them.
+
+const BAR_TEMPLATE: &str = "[{elapsed_precise}] {bar:40.green/yellow} {bytes}/{total_bytes} ({bytes_per_sec}, {eta}) {msg}";
+// Downloads whose size the server doesn't tell.
+const BYTES_TEMPLATE: &str = "[{elapsed_precise}] {spinner:.green} {bytes} ({bytes_per_sec}) {msg}";
+const SPINNER_TEMPLATE: &str = "{spinner:.green} {msg} ({elapsed})";
+
+// Bars are only drawn on a terminal that understands control sequences; pipes, CI logs, and
+// TERM=dumb get plain log lines instead.
+pub fn is_interactive() -> bool {
+    std::io::stderr().is_terminal() && std::env::var("TERM").as_deref() != Ok("dumb")
+}
+
+fn multi() -> &'static MultiProgress {
+    static MULTI: OnceLock<MultiProgress> = OnceLock::new();
+    MULTI.get_or_init(|| {
+        let target = if is_interactive() { ProgressDrawTarget::stderr() } else { ProgressDrawTarget::hidden() };
+        MultiProgress::with_draw_target(target)
+    })
+}
+
+fn style(template: &str) -> ProgressStyle {
+    ProgressStyle::with_template(template)
+        .unwrap_or_else(|_| ProgressStyle::default_bar())
+        .progress_chars("##-")
+}
+
+// `done` is nonzero when a download resumes part way through.
+pub fn download_bar(label: &str, total: Option<u64>, done: u64) -> ProgressBar {
+    let bar = match total {
+        Some(total) => ProgressBar::new(total).with_style(style(BAR_TEMPLATE)),
+        None => ProgressBar::no_length().with_style(style(BYTES_TEMPLATE)),
+    };
+    bar.set_message(label.to_string());
+    bar.set_position(done);
+    multi().add(bar)
+}
+
+// Shows that something of unknown length is running, e.g. parsing the TLPDB, until dropped.
+pub struct Spinner(Option<ProgressBar>);
+
+pub fn spinner(message: &str) -> Spinner {
+    if !is_interactive() {
+        return Spinner(None);
+    }
+    let bar = multi().add(ProgressBar::new_spinner().with_style(style(SPINNER_TEMPLATE)));
+    bar.set_message(message.to_string());
+    bar.enable_steady_tick(Duration::from_millis(100));
+    Spinner(Some(bar))
+}
+
+impl Drop for Spinner {
+    fn drop(&mut self) {
+        if let Some(bar) = self.0.take() {
+            bar.finish_and_clear();
+        }
+    }
+}
+
+// Runs `f` with the bars hidden, for output that must not land inside them.
+pub fn suspend<R>(f: impl FnOnce() -> R) -> R {
+    multi().suspend(f)
+}
@@ -0,0 +1,207 @@
+use crate::output::{self, OutputFormat, PackageRow};
+use crate::Package;
+use rusqlite::params;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::Path;
+
+// Packages every LaTeX run needs without a document naming them.
+const CORE_PACKAGES: &[&str] = &[
+    "latex", "latex-bin", "latexconfig", "l3kernel", "l3backend", "hyphen-base", "tex-ini-files",
+    "unicode-data", "knuth-lib", "kpathsea", "tex", "cm", "latex-fonts", "etex", "plain",
+];
+
+const SCANNED_EXTENSIONS: &[&str] = &["tex", "ltx", "sty", "cls", "clo", "def", "cfg", "dtx"];
+
+// Each command loading a file, with the prefix and extension that turn its argument into a file name.
+const LOADERS: &[(&str, &str, &str)] = &[
+    ("usepackage", "", ".sty"),
+    ("RequirePackage", "", ".sty"),
+    ("RequirePackageWithOptions", "", ".sty"),
+    ("documentclass", "", ".cls"),
+    ("LoadClass", "", ".cls"),
+    ("LoadClassWithOptions", "", ".cls"),
+    ("input", "", ""),
+    ("include", "", ".tex"),
+    ("InputIfFileExists", "", ""),
+    ("bibliographystyle", "", ".bst"),
+    ("usetheme", "beamertheme", ".sty"),
+    ("usecolortheme", "beamercolortheme", ".sty"),
+    ("usefonttheme", "beamerfonttheme", ".sty"),
+    ("useinnertheme", "beamerinnertheme", ".sty"),
+    ("useoutertheme", "beameroutertheme", ".sty"),
+    ("usetikzlibrary", "tikzlibrary", ".code.tex"),
+];
+
+// Returns the file names a TeX source asks for. Arguments may be comma-separated lists,
+// and `\input` also takes a bare word without braces.
+fn references(text: &str) -> Vec<String> {
+    let text: String = text
+        .lines()
+        .map(strip_comment)
+        .collect::<Vec<_>>()
+        .join("\n");
+    let mut files = Vec::new();
+    let mut rest = text.as_str();
+    while let Some(index) = rest.find('\\') {
+        rest = &rest[index + 1..];
+        let command_end = rest.find(|c: char| !c.is_ascii_alphabetic()).unwrap_or(rest.len());
+        let command = &rest[..command_end];
+        let Some((_, prefix, extension)) = LOADERS.iter().find(|(name, _, _)| *name == command) else {
+            continue;
+        };
+        let mut args = rest[command_end..].trim_start();
+        while let Some(optional) = args.strip_prefix('[') {
+            args = optional.split_once(']').map(|(_, after)| after.trim_start()).unwrap_or("");
+        }
+        let argument = if let Some(braced) = args.strip_prefix('{') {
+            braced.split_once('}').map(|(argument, _)| argument).unwrap_or("")
+        } else if command == "input" {
+            args.split(|c: char| c.is_whitespace() || c == '\\').next().unwrap_or("")
+        } else {
+            ""
+        };
+        for name in argument.split(',').map(str::trim).filter(|name| !name.is_empty() && !name.contains('#')) {
+            let file = format!("{}{}", prefix, name);
+            if extension.is_empty() || file.ends_with(extension) {
+                files.push(file);
+            } else {
+                files.push(format!("{}{}", file, extension));
+            }
+            // `\input{foo}` finds foo.tex as well as foo.
+            if command == "input" && !name.contains('.') {
+                files.push(format!("{}.tex", name));
+            }
+        }
+    }
+    files
+}
+
+fn strip_comment(line: &str) -> &str {
+    let mut escaped = false;
+    for (index, c) in line.char_indices() {
+        match c {
+            '\\' => escaped = !escaped,
+            '%' if !escaped => return &line[..index],
+            _ => escaped = false,
+        }
+    }
+    line
+}
+
+fn file_name(path: &str) -> &str {
+    path.rsplit('/').next().unwrap_or(path)
+}
+
+pub fn unused_packages(project: &Path, profile: Option<&str>, tlpdb: &HashMap<String, Package>, output: OutputFormat) -> anyhow::Result<()> {
+    let texman_dir = dirs::home_dir()
+        .ok_or_else(|| anyhow::anyhow!("Cannot find home directory"))?
+        .join(".texman");
+    let profile = match profile {
+        Some(profile) => profile.to_string(),
+        None => crate::active::name(&texman_dir)?,
+    };
+    let profile_dir = texman_dir.join("profiles").join(&profile);
+    let conn = crate::init_db(&texman_dir)?;
+
+    let mut stmt = conn.prepare("SELECT name, revision FROM installed_packages WHERE profile = ?1 ORDER BY name")?;
+    let installed = stmt
+        .query_map(params![profile], |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)))?
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut stmt = conn.prepare("SELECT path, package FROM profile_files WHERE profile = ?1")?;
+    let mut owners: HashMap<String, Vec<(String, String)>> = HashMap::new();
+    for row in stmt.query_map(params![profile], |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)))? {
+        let (path, package) = row?;
+        owners.entry(file_name(&path).to_string()).or_default().push((path, package));
+    }
+
+    let mut pending = Vec::new();
+    let mut scanned_files = 0;
+    for rel_path in crate::tree::walk_files(project)? {
+        let is_source = rel_path
+            .extension()
+            .and_then(|e| e.to_str())
+            .is_some_and(|e| SCANNED_EXTENSIONS.contains(&e));
+        if is_source {
+            pending.extend(references(&String::from_utf8_lossy(&fs::read(project.join(&rel_path))?)));
+            scanned_files += 1;
+        }
+    }
+    if scanned_files == 0 {
+        anyhow::bail!("No TeX sources found in {:?}", project);
+    }
+
+    // Follow references through the profile's own files, so packages loaded by other packages count too.
+    let mut used: HashSet<String> = CORE_PACKAGES.iter().map(|name| name.to_string()).collect();
+    let mut seen = HashSet::new();
+    while let Some(file) = pending.pop() {
+        let file = file_name(&file).to_string();
+        if !seen.insert(file.clone()) {
+            continue;
+        }
+        for (path, package) in owners.get(&file).into_iter().flatten() {
+            used.insert(package.clone());
+            let is_source = Path::new(path)
+                .extension()
+                .and_then(|e| e.to_str())
+                .is_some_and(|e| SCANNED_EXTENSIONS.contains(&e));
+            if is_source && let Ok(bytes) = fs::read(profile_dir.join(path)) {
+                pending.extend(references(&String::from_utf8_lossy(&bytes)));
+            }
+        }
+    }
+
+    let mut queue: Vec<String> = used.iter().cloned().collect();
+    while let Some(name) = queue.pop() {
+        for dep in tlpdb.get(&name).map(|pkg| crate::dependencies(pkg, tlpdb)).unwrap_or_default() {
+            if used.insert(dep.to_string()) {
+                queue.push(dep.to_string());
+            }
+        }
+    }
+
+    // Collections and schemes own no files; they only group other packages.
+    let candidates: Vec<PackageRow> = installed
+        .into_iter()
+        .filter(|(name, _)| !used.contains(name))
+        .filter(|(name, _)| {
+            !tlpdb
+                .get(name)
+                .and_then(|pkg| pkg.category.as_deref())
+                .is_some_and(|category| matches!(category, "Collection" | "Scheme"))
+        })
+        .map(|(name, revision)| {
+            let pkg = tlpdb.get(&name);
+            PackageRow {
+                size: pkg.and_then(|p| p.container_size),
+                shortdesc: pkg.and_then(|p| p.description.clone()),
+                name,
+                revision,
+            }
+        })
+        .collect();
+
+    match output {
+        OutputFormat::Table => output::print_table(&candidates),
+        OutputFormat::Csv => output::print_csv(&candidates),
+        OutputFormat::Json => output::print_json(&candidates)?,
+        OutputFormat::Text => {
+            if candidates.is_empty() {
+                println!("Every package in profile '{}' is used by {:?}.", profile, project);
+                return Ok(());
+            }
+            println!(
+                "{} packages in profile '{}' are not used by {:?} ({} source files scanned):",
+                candidates.len(),
+                profile,
+                project,
+                scanned_files
+            );
+            for pkg in &candidates {
+                println!("  {} r{}", pkg.name, pkg.revision);
+            }
+            println!("Remove them with 'texman remove <package>' after checking the document still builds.");
+        }
+    }
+    Ok(())
+}
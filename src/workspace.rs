@@ -0,0 +1,198 @@
+use crate::config::{ConflictPolicy, Config};
+use crate::Package;
+use rusqlite::{params, Connection};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// A workspace is a directory whose `texman-workspace.toml` lists project directories, each with
+// a `texman.toml` naming the packages it needs and the profile they go into. The projects share
+// the store and the profile pool in ~/.texman, so a package several of them use is downloaded
+// and unpacked once.
+pub const WORKSPACE_FILE: &str = "texman-workspace.toml";
+pub const PROJECT_FILE: &str = "texman.toml";
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct WorkspaceFile {
+    members: Vec<PathBuf>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ProjectFile {
+    // The directory name when not given.
+    profile: Option<String>,
+    #[serde(default)]
+    packages: Vec<String>,
+}
+
+struct Project {
+    name: String,
+    dir: PathBuf,
+    profile: String,
+    packages: Vec<String>,
+}
+
+// Where a project stands against its manifest and the TLPDB.
+struct Status {
+    missing: Vec<String>,
+    outdated: Vec<(String, String, String)>,
+}
+
+impl Status {
+    fn in_sync(&self) -> bool {
+        self.missing.is_empty() && self.outdated.is_empty()
+    }
+
+    fn to_install(&self) -> Vec<String> {
+        self.missing.iter().cloned().chain(self.outdated.iter().map(|(name, _, _)| name.clone())).collect()
+    }
+}
+
+// The nearest directory from `start` upwards with a workspace file.
+fn find_root(start: &Path) -> anyhow::Result<PathBuf> {
+    let start = fs::canonicalize(start).map_err(|e| anyhow::anyhow!("Cannot open {:?}: {}", start, e))?;
+    start
+        .ancestors()
+        .find(|dir| dir.join(WORKSPACE_FILE).is_file())
+        .map(Path::to_path_buf)
+        .ok_or_else(|| anyhow::anyhow!("No {} in {:?} or any directory above it", WORKSPACE_FILE, start))
+}
+
+fn load_project(root: &Path, member: &Path) -> anyhow::Result<Project> {
+    let dir = root.join(member);
+    let path = dir.join(PROJECT_FILE);
+    let text = fs::read_to_string(&path).map_err(|e| anyhow::anyhow!("Cannot read {:?}: {}", path, e))?;
+    let file: ProjectFile = toml::from_str(&text).map_err(|e| anyhow::anyhow!("Invalid project file {:?}: {}", path, e))?;
+    let name = member.to_string_lossy().trim_end_matches('/').to_string();
+    let profile = match file.profile {
+        Some(profile) => profile,
+        None => dir
+            .file_name()
+            .and_then(|name| name.to_str())
+            .map(str::to_string)
+            .ok_or_else(|| anyhow::anyhow!("Cannot name a profile after {:?}; set 'profile' in {:?}", dir, path))?,
+    };
+    Ok(Project { name, dir, profile, packages: file.packages })
+}
+
+fn load(start: &Path) -> anyhow::Result<(PathBuf, Vec<Project>)> {
+    let root = find_root(start)?;
+    let path = root.join(WORKSPACE_FILE);
+    let text = fs::read_to_string(&path)?;
+    let file: WorkspaceFile = toml::from_str(&text).map_err(|e| anyhow::anyhow!("Invalid workspace file {:?}: {}", path, e))?;
+    let projects = file.members.iter().map(|member| load_project(&root, member)).collect::<anyhow::Result<Vec<_>>>()?;
+    for (i, project) in projects.iter().enumerate() {
+        if let Some(other) = projects[..i].iter().find(|other| other.profile == project.profile && other.packages != project.packages) {
+            log::warn!(
+                "Projects {} and {} share profile '{}' with different package lists; it gets the packages of both",
+                other.name, project.name, project.profile
+            );
+        }
+    }
+    Ok((root, projects))
+}
+
+fn status(conn: &Connection, project: &Project, tlpdb: &HashMap<String, Package>, config: &Config) -> anyhow::Result<Status> {
+    let needed = crate::resolve_requested(&project.packages, tlpdb, config)?;
+    let mut stmt = conn.prepare("SELECT name, revision FROM installed_packages WHERE profile = ?1")?;
+    let installed: HashMap<String, String> = stmt
+        .query_map(params![project.profile], |row| Ok((row.get(0)?, row.get(1)?)))?
+        .collect::<Result<_, _>>()?;
+    let mut status = Status { missing: Vec::new(), outdated: Vec::new() };
+    for name in needed {
+        match installed.get(&name) {
+            None => status.missing.push(name),
+            Some(revision) if *revision != tlpdb[&name].revision => {
+                let latest = tlpdb[&name].revision.clone();
+                status.outdated.push((name, revision.clone(), latest));
+            }
+            Some(_) => {}
+        }
+    }
+    Ok(status)
+}
+
+// `texman workspace status`: whether each project's profile has every package its manifest
+// asks for, dependencies included, at the TLPDB's revision.
+pub fn print_status(start: &Path, tlpdb: &HashMap<String, Package>, config: &Config) -> anyhow::Result<()> {
+    let texman_dir = dirs::home_dir()
+        .ok_or_else(|| anyhow::anyhow!("Cannot find home directory"))?
+        .join(".texman");
+    let (root, projects) = load(start)?;
+    let conn = crate::init_db(&texman_dir)?;
+    println!("Workspace {:?} ({} projects):", root, projects.len());
+    let mut out_of_sync = 0;
+    for project in &projects {
+        let status = status(&conn, project, tlpdb, config)?;
+        if status.in_sync() {
+            println!("  {} (profile '{}'): up to date, {} packages", project.name, project.profile, project.packages.len());
+            continue;
+        }
+        out_of_sync += 1;
+        println!(
+            "  {} (profile '{}'): needs sync, {} missing, {} outdated",
+            project.name,
+            project.profile,
+            status.missing.len(),
+            status.outdated.len()
+        );
+        if !status.missing.is_empty() {
+            println!("    missing: {}", status.missing.join(", "));
+        }
+        for (name, installed, latest) in &status.outdated {
+            println!("    {} r{} -> r{}", name, installed, latest);
+        }
+    }
+    if out_of_sync > 0 {
+        println!("Run 'texman workspace sync --all' to bring {} projects up to date.", out_of_sync);
+    }
+    Ok(())
+}
+
+// `texman workspace sync`: installs what each selected project is missing or has outdated into
+// its profile. Projects that are in sync are left alone, and the store is shared, so what one
+// project downloaded is only linked for the next.
+pub async fn sync(
+    start: &Path,
+    members: &[String],
+    tlpdb: &HashMap<String, Package>,
+    config: &Config,
+    on_conflict: Option<ConflictPolicy>,
+    allow_scripts: bool,
+) -> anyhow::Result<Vec<(String, String)>> {
+    let texman_dir = dirs::home_dir()
+        .ok_or_else(|| anyhow::anyhow!("Cannot find home directory"))?
+        .join(".texman");
+    let (_, projects) = load(start)?;
+    for member in members {
+        if !projects.iter().any(|project| project.name == *member) {
+            anyhow::bail!("'{}' is not a member of the workspace", member);
+        }
+    }
+    let conn = crate::init_db(&texman_dir)?;
+    // Exactly the packages found missing or outdated; they already include the dependencies.
+    let mut install_config = config.clone();
+    install_config.no_depends_at_all = true;
+    let mut failed = Vec::new();
+    let mut synced = 0;
+    for project in projects.iter().filter(|project| members.is_empty() || members.contains(&project.name)) {
+        let to_install = status(&conn, project, tlpdb, config)?.to_install();
+        if to_install.is_empty() {
+            log::info!("{} is up to date", project.name);
+            continue;
+        }
+        println!("Syncing {} ({:?}): {} packages into profile '{}'", project.name, project.dir, to_install.len(), project.profile);
+        let transaction = crate::summary::Transaction::begin("sync", &project.profile, &texman_dir)?;
+        let profiles = [project.profile.clone()];
+        failed.extend(crate::install_package(&to_install, &profiles, tlpdb, &install_config, on_conflict, None, allow_scripts).await?);
+        transaction.finish(config, tlpdb)?;
+        synced += 1;
+    }
+    if synced == 0 {
+        println!("Every project is up to date.");
+    }
+    Ok(failed)
+}